@@ -0,0 +1,57 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Compensation for jobs which block, see [`ThreadPool::enter_blocking_section`].
+//!
+//! [`ThreadPool::enter_blocking_section`]: ../struct.ThreadPool.html#method.enter_blocking_section
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use thunk::Thunk;
+use {replenish, spawn_on_demand, Job, ThreadPoolSharedData};
+
+/// While it is alive, the pool runs an extra worker thread in place of one which blocks, see
+/// [`ThreadPool::enter_blocking_section`].
+///
+/// [`ThreadPool::enter_blocking_section`]: struct.ThreadPool.html#method.enter_blocking_section
+pub struct BlockingSection {
+    shared_data: Arc<ThreadPoolSharedData>,
+}
+
+impl BlockingSection {
+    pub(crate) fn enter(shared_data: &Arc<ThreadPoolSharedData>) -> BlockingSection {
+        shared_data.blocking_count.fetch_add(1, Ordering::SeqCst);
+        if shared_data.lazy_spawn {
+            spawn_on_demand(shared_data);
+        } else {
+            replenish(shared_data);
+        }
+        BlockingSection {
+            shared_data: shared_data.clone(),
+        }
+    }
+}
+
+impl Drop for BlockingSection {
+    fn drop(&mut self) {
+        let shared_data = &self.shared_data;
+        shared_data.blocking_count.fetch_sub(1, Ordering::SeqCst);
+        if shared_data.thread_count.load(Ordering::SeqCst) > shared_data.thread_limit() {
+            // A busy worker notices it is surplus once it finishes its job, an idle one needs a
+            // job to wake up for. It is not one of the jobs of the pool, so it is queued without
+            // being counted. Fails only if the pool has been shut down.
+            let job = Job {
+                wake: true,
+                ..Job::new(Thunk::new(|| ()), 0)
+            };
+            let _ = shared_data.job_queue.push(job);
+        }
+    }
+}
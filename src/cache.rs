@@ -0,0 +1,162 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Results of jobs kept for reuse, see [`ThreadPool::execute_cached`].
+//!
+//! [`ThreadPool::execute_cached`]: ../struct.ThreadPool.html#method.execute_cached
+
+use handle::{self, Completer, JobHandle, JoinError};
+use std::any::Any;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+enum State<T> {
+    /// The job is queued or running, the handles waiting for its result.
+    Running(Vec<Completer<T>>),
+    /// The job finished with this result.
+    Done(T),
+}
+
+type Entry<T> = Mutex<State<T>>;
+
+struct Slot {
+    /// An `Entry<T>` for the type of the result.
+    entry: Arc<dyn Any + Send + Sync>,
+    /// Until when the result may be reused, `None` while the job has not finished.
+    until: Option<Instant>,
+}
+
+type Slots = Arc<Mutex<HashMap<Arc<str>, Slot>>>;
+
+/// What a call to `ResultCache::get` asks the caller to do.
+pub(crate) enum Lookup<T> {
+    /// The handle is resolved already, or will be once the running job finishes.
+    Cached(JobHandle<T>),
+    /// Run the job and pass its result to the `Computation`.
+    Compute(JobHandle<T>, Computation<T>),
+}
+
+/// The results of the jobs of a pool by key.
+pub(crate) struct ResultCache {
+    slots: Slots,
+}
+
+impl ResultCache {
+    pub fn new() -> ResultCache {
+        ResultCache {
+            slots: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Look up the result for `key`, or register a computation of it which is valid for `ttl`.
+    pub fn get<T>(&self, key: Arc<str>, ttl: Duration) -> Lookup<T>
+    where
+        T: Clone + Send + 'static,
+    {
+        let mut slots = self
+            .slots
+            .lock()
+            .expect("ResultCache::get unable to lock slots");
+        let now = Instant::now();
+        // Forget the expired results, so that keys which are not asked for again do not pile up.
+        slots.retain(|_, slot| slot.until.map_or(true, |until| until > now));
+
+        let (completer, handle) = handle::pair();
+        // A key which was last used for a result of another type is computed anew.
+        let entry = slots
+            .get(&key)
+            .and_then(|slot| slot.entry.clone().downcast::<Entry<T>>().ok());
+        if let Some(entry) = entry {
+            match *entry.lock().expect("Unable to lock cache entry") {
+                State::Running(ref mut waiting) => waiting.push(completer),
+                State::Done(ref value) => completer.complete(Ok(value.clone())),
+            }
+            return Lookup::Cached(handle);
+        }
+
+        let entry: Arc<Entry<T>> = Arc::new(Mutex::new(State::Running(vec![completer])));
+        slots.insert(
+            key.clone(),
+            Slot {
+                entry: entry.clone(),
+                until: None,
+            },
+        );
+        Lookup::Compute(
+            handle,
+            Computation {
+                slots: self.slots.clone(),
+                key,
+                entry,
+                ttl,
+            },
+        )
+    }
+}
+
+/// Stores the result of a job in the cache and hands it to the waiting handles.
+pub(crate) struct Computation<T> {
+    slots: Slots,
+    key: Arc<str>,
+    entry: Arc<Entry<T>>,
+    ttl: Duration,
+}
+
+impl<T: Clone + Send + 'static> Computation<T> {
+    pub fn complete(self, result: Result<T, JoinError>) {
+        let mut slots = self
+            .slots
+            .lock()
+            .expect("Computation::complete unable to lock slots");
+        let entry: Arc<dyn Any + Send + Sync> = self.entry.clone();
+        let slot = slots
+            .get_mut(&self.key)
+            .filter(|slot| Arc::ptr_eq(&slot.entry, &entry));
+        let mut state = self.entry.lock().expect("Unable to lock cache entry");
+        let waiting = match result {
+            Ok(ref value) => {
+                if let Some(slot) = slot {
+                    slot.until = Some(Instant::now() + self.ttl);
+                }
+                mem::replace(&mut *state, State::Done(value.clone()))
+            }
+            // A failed job is not cached, the next call for the key runs it again.
+            Err(_) => {
+                if slot.is_some() {
+                    slots.remove(&self.key);
+                }
+                mem::replace(&mut *state, State::Running(Vec::new()))
+            }
+        };
+        drop(state);
+        drop(slots);
+
+        let waiting = match waiting {
+            State::Running(waiting) => waiting,
+            State::Done(_) => unreachable!("cache entry completed twice"),
+        };
+        match result {
+            Ok(value) => {
+                for completer in waiting {
+                    completer.complete(Ok(value.clone()));
+                }
+            }
+            // The handles which joined the computation are resolved as cancelled as their
+            // completers are dropped.
+            Err(error) => {
+                if let Some(first) = waiting.into_iter().next() {
+                    first.complete(Err(error));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Cooperative cancellation of running jobs.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+thread_local! {
+    // The token of the job running on this thread, if it has one.
+    static CURRENT: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+struct Inner {
+    cancelled: AtomicBool,
+    parents: Vec<CancellationToken>,
+}
+
+/// A flag which tells a job submitted with [`ThreadPool::execute_cancellable`] that it should stop
+/// early.
+///
+/// Cancellation is cooperative: the pool never interrupts a running job, the job has to check
+/// [`is_cancelled`](#method.is_cancelled) from time to time and return when it is set. Clones of
+/// a token share the same flag.
+///
+/// [`ThreadPool::execute_cancellable`]: struct.ThreadPool.html#method.execute_cancellable
+///
+/// # Examples
+///
+/// ```
+/// use threadpool::CancellationToken;
+///
+/// let token = CancellationToken::new();
+/// let clone = token.clone();
+/// assert!(!clone.is_cancelled());
+///
+/// token.cancel();
+/// assert!(clone.is_cancelled());
+/// ```
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+impl CancellationToken {
+    /// Create a new token which is not cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::with_parents(Vec::new())
+    }
+
+    /// Create a token which is cancelled together with `self`, but can also be cancelled on its
+    /// own without affecting `self`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::CancellationToken;
+    ///
+    /// let parent = CancellationToken::new();
+    /// let child = parent.child();
+    /// let grandchild = child.child();
+    ///
+    /// child.cancel();
+    /// assert!(grandchild.is_cancelled());
+    /// assert!(!parent.is_cancelled());
+    /// ```
+    pub fn child(&self) -> CancellationToken {
+        CancellationToken::with_parents(vec![self.clone()])
+    }
+
+    /// Returns the token of the job running on the calling thread, if it has one.
+    ///
+    /// Jobs submitted with [`ThreadPool::execute_cancellable`] have the token passed to them,
+    /// tagged jobs get a token which is cancelled by [`ThreadPool::cancel_tagged`] and
+    /// [`ThreadPool::shutdown_now`].
+    ///
+    /// [`ThreadPool::execute_cancellable`]: struct.ThreadPool.html#method.execute_cancellable
+    /// [`ThreadPool::cancel_tagged`]: struct.ThreadPool.html#method.cancel_tagged
+    /// [`ThreadPool::shutdown_now`]: struct.ThreadPool.html#method.shutdown_now
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::mpsc::channel;
+    /// use threadpool::{CancellationToken, ThreadPool};
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// let (tx, rx) = channel();
+    /// pool.execute_tagged("export", move || {
+    ///     let token = CancellationToken::current().unwrap();
+    ///     tx.send(token.is_cancelled()).unwrap();
+    /// });
+    /// assert_eq!(rx.recv().unwrap(), false);
+    /// assert!(CancellationToken::current().is_none());
+    /// ```
+    pub fn current() -> Option<CancellationToken> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Make `token` the token of the calling thread, returning the previous one.
+    pub(crate) fn replace_current(token: Option<CancellationToken>) -> Option<CancellationToken> {
+        CURRENT.with(|current| current.replace(token))
+    }
+
+    /// Create a token which is cancelled as soon as any of `parents` is.
+    pub(crate) fn with_parents(parents: Vec<CancellationToken>) -> CancellationToken {
+        CancellationToken {
+            inner: Arc::new(Inner {
+                cancelled: AtomicBool::new(false),
+                parents,
+            }),
+        }
+    }
+
+    /// Request cancellation of every job observing this token.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` once cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+            || self
+                .inner
+                .parents
+                .iter()
+                .any(CancellationToken::is_cancelled)
+    }
+}
+
+/// Returns `true` if `tag` matches `pattern`, which is either a tag or a prefix followed by `*`.
+pub(crate) fn tag_matches(pattern: &str, tag: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => tag.starts_with(prefix),
+        None => tag == pattern,
+    }
+}
+
+/// The numbers of jobs cancelled by [`ThreadPool::cancel_tagged`].
+///
+/// [`ThreadPool::cancel_tagged`]: struct.ThreadPool.html#method.cancel_tagged
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CancelledJobs {
+    pub(crate) queued: usize,
+    pub(crate) running: usize,
+}
+
+impl CancelledJobs {
+    /// The number of queued jobs which were removed without being run.
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+
+    /// The number of running jobs whose token was cancelled.
+    pub fn running(&self) -> usize {
+        self.running
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        CancellationToken::new()
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
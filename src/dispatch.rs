@@ -0,0 +1,226 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Handing jobs to the worker threads from a dedicated thread, see
+//! [`Builder::dispatcher_thread`].
+//!
+//! [`Builder::dispatcher_thread`]: ../struct.Builder.html#method.dispatcher_thread
+
+use queue::{JobQueue, PopError};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
+use {Job, ThreadPoolSharedData};
+
+/// Where the dispatcher puts the next job of one worker thread. Every worker thread keeps its
+/// own for as long as it lives, so that handing over a job does not allocate.
+pub(crate) struct Handoff {
+    job: Mutex<Option<Job>>,
+    condvar: Condvar,
+}
+
+impl Handoff {
+    pub fn new() -> Handoff {
+        Handoff {
+            job: Mutex::new(None),
+            condvar: Condvar::new(),
+        }
+    }
+}
+
+/// A worker thread waiting for its next job.
+struct Idle {
+    worker: usize,
+    handoff: Arc<Handoff>,
+}
+
+/// Takes the jobs from the queue of the pool, in the order of its discipline, and assigns each to
+/// an idle worker thread.
+///
+/// A job is only taken from the queue once a worker is idle, so the discipline decides on the
+/// jobs which are waiting at that moment. The job goes to the worker it prefers if that one is
+/// idle, otherwise to the worker which has been idle the longest.
+pub(crate) struct Dispatcher {
+    /// In the order the workers became idle.
+    idle: Mutex<VecDeque<Idle>>,
+    // Wakes the dispatching thread once a worker is idle.
+    idle_condvar: Condvar,
+    closed: AtomicBool,
+}
+
+impl Dispatcher {
+    pub fn new() -> Dispatcher {
+        Dispatcher {
+            idle: Mutex::new(VecDeque::new()),
+            idle_condvar: Condvar::new(),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Wait until a job is put into `handoff`, the one of `worker`, or `deadline` has passed.
+    pub fn take(
+        &self,
+        worker: usize,
+        handoff: &Arc<Handoff>,
+        deadline: Option<Instant>,
+    ) -> Result<Job, PopError> {
+        {
+            let mut idle = self.idle.lock().expect("Dispatcher unable to lock idle");
+            if self.closed.load(Ordering::SeqCst) {
+                return Err(PopError::Closed);
+            }
+            idle.push_back(Idle {
+                worker,
+                handoff: handoff.clone(),
+            });
+            self.idle_condvar.notify_one();
+        }
+        let mut job = handoff
+            .job
+            .lock()
+            .expect("Dispatcher unable to lock handoff");
+        loop {
+            if let Some(job) = job.take() {
+                return Ok(job);
+            }
+            // `close` notifies while holding the lock, so it can not slip in before the wait.
+            if self.closed.load(Ordering::SeqCst) {
+                return Err(PopError::Closed);
+            }
+            let deadline = match deadline {
+                Some(deadline) => deadline,
+                None => {
+                    job = handoff
+                        .condvar
+                        .wait(job)
+                        .expect("Dispatcher unable to wait on handoff");
+                    continue;
+                }
+            };
+            let now = Instant::now();
+            if now < deadline {
+                job = handoff
+                    .condvar
+                    .wait_timeout(job, deadline - now)
+                    .expect("Dispatcher unable to wait on handoff")
+                    .0;
+                continue;
+            }
+            drop(job);
+            let mut idle = self.idle.lock().expect("Dispatcher unable to lock idle");
+            return match idle
+                .iter()
+                .position(|idle| Arc::ptr_eq(&idle.handoff, handoff))
+            {
+                Some(position) => {
+                    idle.remove(position);
+                    Err(PopError::TimedOut)
+                }
+                // Picked by the dispatcher in the meantime, which puts the job into the handoff
+                // before it lets go of the idle workers.
+                None => handoff
+                    .job
+                    .lock()
+                    .expect("Dispatcher unable to lock handoff")
+                    .take()
+                    .ok_or(PopError::Closed),
+            };
+        }
+    }
+
+    /// Returns the number of worker threads waiting for a job.
+    pub fn idle_count(&self) -> usize {
+        self.idle
+            .lock()
+            .expect("Dispatcher unable to lock idle")
+            .len()
+    }
+
+    /// Returns the worker which has been idle the longest, blocking until there is one. Returns
+    /// `None` once `queue` has been closed and drained, even if no worker ever became idle.
+    fn wait_for_idle(&self, queue: &JobQueue<Job>) -> Option<usize> {
+        let mut idle = self.idle.lock().expect("Dispatcher unable to lock idle");
+        while idle.is_empty() && !self.closed.load(Ordering::SeqCst) {
+            if queue.is_closed() && queue.is_empty() {
+                return None;
+            }
+            idle = self
+                .idle_condvar
+                .wait(idle)
+                .expect("Dispatcher unable to wait for idle workers");
+        }
+        idle.front().map(|idle| idle.worker)
+    }
+
+    fn assign(&self, job: Job) {
+        let mut idle = self.idle.lock().expect("Dispatcher unable to lock idle");
+        // The worker waited for may have timed out in the meantime, see `take`.
+        while idle.is_empty() {
+            idle = self
+                .idle_condvar
+                .wait(idle)
+                .expect("Dispatcher unable to wait for idle workers");
+        }
+        let preferred = job
+            .affinity
+            .and_then(|worker| idle.iter().position(|idle| idle.worker == worker));
+        let Idle { handoff, .. } = idle
+            .remove(preferred.unwrap_or(0))
+            .expect("Dispatcher lost an idle worker");
+        *handoff
+            .job
+            .lock()
+            .expect("Dispatcher unable to lock handoff") = Some(job);
+        handoff.condvar.notify_one();
+    }
+
+    /// Wake the dispatching thread, to let it see that the queue of the pool has been closed.
+    pub fn wake(&self) {
+        let _idle = self.idle.lock().expect("Dispatcher unable to lock idle");
+        self.idle_condvar.notify_all();
+    }
+
+    fn close(&self) {
+        let mut idle = self.idle.lock().expect("Dispatcher unable to lock idle");
+        self.closed.store(true, Ordering::SeqCst);
+        for Idle { handoff, .. } in idle.drain(..) {
+            let _job = handoff
+                .job
+                .lock()
+                .expect("Dispatcher unable to lock handoff");
+            handoff.condvar.notify_one();
+        }
+    }
+}
+
+/// Spawn the thread dispatching the jobs of the pool, which runs until the queue of the pool has
+/// been closed and drained.
+pub(crate) fn spawn_dispatcher(shared_data: Arc<ThreadPoolSharedData>) -> io::Result<()> {
+    thread::Builder::new()
+        .name("threadpool-dispatcher".into())
+        .spawn(move || {
+            let dispatcher = shared_data
+                .dispatcher
+                .as_ref()
+                .expect("spawn_dispatcher called on a pool without dispatcher");
+            while let Some(worker) = dispatcher.wait_for_idle(&shared_data.job_queue) {
+                // Lets the discipline pick the jobs routed to that worker first.
+                match shared_data.job_queue.pop(worker) {
+                    Some(job) => dispatcher.assign(job),
+                    None => break,
+                }
+            }
+            dispatcher.close();
+        })
+        .map(|_| ())
+}
@@ -0,0 +1,135 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Pool configuration from environment variables, see [`Builder::from_env`].
+//!
+//! [`Builder::from_env`]: ../struct.Builder.html#method.from_env
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use Builder;
+
+/// The error returned by [`Builder::from_env`] if an environment variable has an invalid value.
+///
+/// [`Builder::from_env`]: struct.Builder.html#method.from_env
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnvError {
+    variable: String,
+    value: String,
+    expected: &'static str,
+}
+
+impl EnvError {
+    /// The name of the offending variable.
+    pub fn variable(&self) -> &str {
+        &self.variable
+    }
+
+    /// The value of the offending variable, lossily converted to UTF-8.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl fmt::Display for EnvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid value {:?} for {}: expected {}",
+            self.value, self.variable, self.expected
+        )
+    }
+}
+
+impl Error for EnvError {}
+
+/// Read the variable `{prefix}_{suffix}`, `None` if it is not set.
+fn read(prefix: &str, suffix: &str, expected: &'static str) -> Result<Option<String>, EnvError> {
+    let variable = format!("{}_{}", prefix, suffix);
+    match env::var(&variable) {
+        Ok(value) => Ok(Some(value)),
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(env::VarError::NotUnicode(value)) => Err(EnvError {
+            variable,
+            value: value.to_string_lossy().into_owned(),
+            expected,
+        }),
+    }
+}
+
+/// Read the variable `{prefix}_{suffix}` as a positive number.
+fn read_positive(prefix: &str, suffix: &str) -> Result<Option<usize>, EnvError> {
+    const EXPECTED: &str = "a positive integer";
+    match read(prefix, suffix, EXPECTED)? {
+        None => Ok(None),
+        Some(value) => match value.trim().parse() {
+            Ok(n) if n > 0 => Ok(Some(n)),
+            _ => Err(EnvError {
+                variable: format!("{}_{}", prefix, suffix),
+                value,
+                expected: EXPECTED,
+            }),
+        },
+    }
+}
+
+pub(crate) fn configure(mut builder: Builder, prefix: &str) -> Result<Builder, EnvError> {
+    if let Some(num_threads) = read_positive(prefix, "THREADS")? {
+        builder = builder.num_threads(num_threads);
+    }
+    if let Some(name) = read(prefix, "NAME", "a UTF-8 string")? {
+        builder = builder.thread_name(name);
+    }
+    if let Some(size) = read_positive(prefix, "STACK_SIZE")? {
+        builder = builder.thread_stack_size(size);
+    }
+    if let Some(bytes) = read_positive(prefix, "QUEUE_BYTES")? {
+        builder = builder.queue_byte_limit(bytes);
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::thread;
+    use {available_parallelism, Builder};
+
+    #[test]
+    fn test_from_env() {
+        env::set_var("THREADPOOL_TEST_ENV_THREADS", "3");
+        env::set_var("THREADPOOL_TEST_ENV_NAME", "from-env");
+        let pool = Builder::from_env("THREADPOOL_TEST_ENV").unwrap().build();
+        assert_eq!(pool.max_count(), 3);
+        let name = pool
+            .spawn(|| thread::current().name().map(str::to_owned))
+            .join()
+            .unwrap();
+        assert_eq!(name.as_deref(), Some("from-env"));
+
+        let defaults = Builder::from_env("THREADPOOL_TEST_ENV_UNSET")
+            .unwrap()
+            .build();
+        assert_eq!(defaults.max_count(), available_parallelism());
+    }
+
+    #[test]
+    fn test_from_env_invalid() {
+        env::set_var("THREADPOOL_TEST_INVALID_THREADS", "0");
+        let error = Builder::from_env("THREADPOOL_TEST_INVALID").err().unwrap();
+        assert_eq!(error.variable(), "THREADPOOL_TEST_INVALID_THREADS");
+        assert_eq!(error.value(), "0");
+        assert_eq!(
+            error.to_string(),
+            "invalid value \"0\" for THREADPOOL_TEST_INVALID_THREADS: expected a positive integer"
+        );
+    }
+}
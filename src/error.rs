@@ -0,0 +1,46 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Errors of creating a pool.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// The error returned by [`ThreadPool::try_new`] and [`Builder::try_build`].
+///
+/// [`ThreadPool::try_new`]: struct.ThreadPool.html#method.try_new
+/// [`Builder::try_build`]: struct.Builder.html#method.try_build
+#[derive(Debug)]
+pub enum PoolError {
+    /// The pool was asked to have zero threads.
+    ZeroThreads,
+    /// The operating system refused to spawn a worker thread, e.g. because of resource
+    /// exhaustion.
+    Spawn(io::Error),
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PoolError::ZeroThreads => f.write_str("a thread pool needs at least one thread"),
+            PoolError::Spawn(ref error) => write!(f, "unable to spawn worker thread: {}", error),
+        }
+    }
+}
+
+impl Error for PoolError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PoolError::ZeroThreads => None,
+            PoolError::Spawn(ref error) => Some(error),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Creation of the worker threads.
+
+use std::io;
+use std::thread;
+
+/// Creates the worker threads of a pool, see [`Builder::thread_factory`].
+///
+/// The pool calls [`spawn`](#tymethod.spawn) whenever it needs a worker thread: when it is
+/// built, when it grows and when a worker thread is replaced.
+///
+/// [`Builder::thread_factory`]: struct.Builder.html#method.thread_factory
+///
+/// # Examples
+///
+/// Count the worker threads a pool ever spawned:
+///
+/// ```
+/// use std::io;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use std::thread;
+/// use threadpool::{DefaultThreadFactory, ThreadFactory};
+///
+/// struct Counting(Arc<AtomicUsize>);
+///
+/// impl ThreadFactory for Counting {
+///     fn spawn(&self, builder: thread::Builder, main: Box<dyn FnOnce() + Send>) -> io::Result<()> {
+///         self.0.fetch_add(1, Ordering::SeqCst);
+///         DefaultThreadFactory.spawn(builder, main)
+///     }
+/// }
+///
+/// let spawned = Arc::new(AtomicUsize::new(0));
+/// let pool = threadpool::Builder::new()
+///     .num_threads(3)
+///     .thread_factory(Counting(spawned.clone()))
+///     .build();
+/// assert_eq!(spawned.load(Ordering::SeqCst), 3);
+/// ```
+pub trait ThreadFactory: Send + Sync {
+    /// Run `main` on a new thread. `builder` carries the name and stack size configured for the
+    /// pool.
+    ///
+    /// `main` returns once the worker thread is no longer needed. A thread which is not created
+    /// with `builder`, e.g. one which was created in advance, can run it just the same.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread could not be created, `main` must be dropped without being
+    /// called in that case.
+    fn spawn(&self, builder: thread::Builder, main: Box<dyn FnOnce() + Send>) -> io::Result<()>;
+}
+
+/// The [`ThreadFactory`] used unless another one is set, it spawns every worker thread with
+/// [`std::thread::Builder::spawn`].
+///
+/// [`ThreadFactory`]: trait.ThreadFactory.html
+/// [`std::thread::Builder::spawn`]: https://doc.rust-lang.org/std/thread/struct.Builder.html#method.spawn
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultThreadFactory;
+
+impl ThreadFactory for DefaultThreadFactory {
+    fn spawn(&self, builder: thread::Builder, main: Box<dyn FnOnce() + Send>) -> io::Result<()> {
+        builder.spawn(main).map(|_| ())
+    }
+}
@@ -0,0 +1,89 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The process-wide default pool.
+
+use std::sync::OnceLock;
+use {Builder, ThreadPool};
+
+static GLOBAL: OnceLock<ThreadPool> = OnceLock::new();
+
+/// Returns the process-wide default pool, creating it on first use.
+///
+/// This lets libraries offload work without asking their users for a pool to run it on. Unless
+/// another pool was installed with [`set_global`] beforehand, the pool has
+/// [`available_parallelism`] threads named `threadpool-global`. It lives until the process
+/// exits.
+///
+/// [`set_global`]: fn.set_global.html
+/// [`available_parallelism`]: fn.available_parallelism.html
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::mpsc::channel;
+///
+/// let (tx, rx) = channel();
+/// threadpool::global().execute(move || tx.send(1 + 1).unwrap());
+/// assert_eq!(rx.recv().unwrap(), 2);
+/// assert_eq!(threadpool::global().max_count(), threadpool::available_parallelism());
+/// ```
+pub fn global() -> &'static ThreadPool {
+    GLOBAL.get_or_init(|| {
+        Builder::new()
+            .thread_name("threadpool-global".into())
+            .build()
+    })
+}
+
+/// Installs `pool` as the process-wide default pool returned by [`global`].
+///
+/// This is meant to be called once at startup, typically by the application rather than by a
+/// library, to configure the pool the libraries use.
+///
+/// [`global`]: fn.global.html
+///
+/// # Errors
+///
+/// Hands `pool` back if the default pool exists already, because it was installed before or
+/// [`global`] was called.
+///
+/// # Examples
+///
+/// ```
+/// use threadpool::ThreadPool;
+///
+/// assert!(threadpool::set_global(ThreadPool::new(2)).is_ok());
+/// assert_eq!(threadpool::global().max_count(), 2);
+/// assert!(threadpool::set_global(ThreadPool::new(4)).is_err());
+/// ```
+pub fn set_global(pool: ThreadPool) -> Result<(), ThreadPool> {
+    GLOBAL.set(pool)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{global, set_global};
+    use std::sync::mpsc::channel;
+    use ThreadPool;
+
+    #[test]
+    fn test_global() {
+        let (tx, rx) = channel();
+        global().execute(move || tx.send(ThreadPool::current().is_some()).unwrap());
+        assert!(rx.recv().unwrap());
+        assert_eq!(global(), global());
+
+        let pool = ThreadPool::new(1);
+        let rejected = set_global(pool.clone()).unwrap_err();
+        assert_eq!(rejected, pool);
+        assert!(*global() != pool);
+    }
+}
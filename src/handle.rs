@@ -0,0 +1,569 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Handles to the results of jobs submitted with [`ThreadPool::spawn`].
+//!
+//! [`ThreadPool::spawn`]: ../struct.ThreadPool.html#method.spawn
+
+use panics;
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant};
+use ThreadPoolSharedData;
+
+enum Repr {
+    Panic(Box<dyn Any + Send + 'static>),
+    Cancelled,
+    TimedOut,
+}
+
+/// The error returned by [`JobHandle::join`] if the job did not produce a value.
+///
+/// [`JobHandle::join`]: struct.JobHandle.html#method.join
+pub struct JoinError {
+    repr: Repr,
+}
+
+impl JoinError {
+    pub(crate) fn panic(payload: Box<dyn Any + Send + 'static>) -> JoinError {
+        JoinError {
+            repr: Repr::Panic(payload),
+        }
+    }
+
+    pub(crate) fn cancelled() -> JoinError {
+        JoinError {
+            repr: Repr::Cancelled,
+        }
+    }
+
+    pub(crate) fn timed_out() -> JoinError {
+        JoinError {
+            repr: Repr::TimedOut,
+        }
+    }
+
+    /// Returns `true` if the job panicked.
+    pub fn is_panic(&self) -> bool {
+        match self.repr {
+            Repr::Panic(_) => true,
+            Repr::Cancelled | Repr::TimedOut => false,
+        }
+    }
+
+    /// Returns `true` if the job was dropped before it could run.
+    pub fn is_cancelled(&self) -> bool {
+        match self.repr {
+            Repr::Cancelled => true,
+            Repr::Panic(_) | Repr::TimedOut => false,
+        }
+    }
+
+    /// Returns `true` if the job was skipped because it did not start before its start timeout,
+    /// see [`ThreadPool::spawn_with_start_timeout`].
+    ///
+    /// [`ThreadPool::spawn_with_start_timeout`]: struct.ThreadPool.html#method.spawn_with_start_timeout
+    pub fn is_timed_out(&self) -> bool {
+        match self.repr {
+            Repr::TimedOut => true,
+            Repr::Panic(_) | Repr::Cancelled => false,
+        }
+    }
+
+    /// Consumes the error, returning the panic payload of the job.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the job did not panic, see [`is_panic`](#method.is_panic).
+    pub fn into_panic(self) -> Box<dyn Any + Send + 'static> {
+        self.try_into_panic()
+            .expect("JoinError::into_panic called on a job which did not panic")
+    }
+
+    /// Consumes the error, returning the panic payload of the job if it panicked, or the error
+    /// itself otherwise.
+    pub fn try_into_panic(self) -> Result<Box<dyn Any + Send + 'static>, JoinError> {
+        match self.repr {
+            Repr::Panic(payload) => Ok(payload),
+            Repr::Cancelled | Repr::TimedOut => Err(self),
+        }
+    }
+
+    fn panic_message(&self) -> Option<&str> {
+        match self.repr {
+            Repr::Panic(ref payload) => panics::payload_message(&**payload),
+            Repr::Cancelled | Repr::TimedOut => None,
+        }
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.repr {
+            Repr::Panic(_) => f.write_str("JoinError::Panic(..)"),
+            Repr::Cancelled => f.write_str("JoinError::Cancelled"),
+            Repr::TimedOut => f.write_str("JoinError::TimedOut"),
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.repr {
+            Repr::Panic(_) => match self.panic_message() {
+                Some(message) => write!(f, "job panicked: {}", message),
+                None => f.write_str("job panicked"),
+            },
+            Repr::Cancelled => f.write_str("job was cancelled"),
+            Repr::TimedOut => f.write_str("job did not start before its start timeout"),
+        }
+    }
+}
+
+impl Error for JoinError {}
+
+/// Callback registered with [`Prerequisite::when_done`].
+///
+/// [`Prerequisite::when_done`]: trait.Prerequisite.html#tymethod.when_done
+pub type DoneCallback = Box<dyn FnOnce(bool) + Send + 'static>;
+
+/// Something a job submitted with [`ThreadPool::submit_after`] can wait for.
+///
+/// This is implemented by [`JobHandle`], so jobs can depend on other jobs regardless of the type
+/// of their results.
+///
+/// [`ThreadPool::submit_after`]: struct.ThreadPool.html#method.submit_after
+/// [`JobHandle`]: struct.JobHandle.html
+pub trait Prerequisite {
+    /// Arrange for `callback` to be called once `self` has completed, passing `true` if it
+    /// completed successfully. If `self` has already completed, `callback` is called right away.
+    fn when_done(&self, callback: DoneCallback);
+}
+
+struct State<T> {
+    result: Option<Result<T, JoinError>>,
+    // `Some(succeeded)` once the job has completed, the result may have been taken already.
+    done: Option<bool>,
+    listeners: Vec<DoneCallback>,
+}
+
+struct Packet<T> {
+    state: Mutex<State<T>>,
+    condvar: Condvar,
+}
+
+/// A completion callback which is called exactly once.
+///
+/// Dropping it without calling `call` reports the job as cancelled.
+pub(crate) struct OnDone<T, C>
+where
+    C: FnOnce(Result<T, JoinError>),
+{
+    callback: Option<C>,
+    // The callback is only ever called where it is dropped, so `T` need not be `Send`.
+    result: PhantomData<fn(T)>,
+}
+
+impl<T, C> OnDone<T, C>
+where
+    C: FnOnce(Result<T, JoinError>),
+{
+    pub fn new(callback: C) -> OnDone<T, C> {
+        OnDone {
+            callback: Some(callback),
+            result: PhantomData,
+        }
+    }
+
+    pub fn call(mut self, result: Result<T, JoinError>) {
+        if let Some(callback) = self.callback.take() {
+            callback(result);
+        }
+    }
+}
+
+impl<T, C> Drop for OnDone<T, C>
+where
+    C: FnOnce(Result<T, JoinError>),
+{
+    fn drop(&mut self) {
+        if let Some(callback) = self.callback.take() {
+            callback(Err(JoinError::cancelled()));
+        }
+    }
+}
+
+/// The sending half of a [`JobHandle`], owned by the job itself.
+///
+/// Dropping it without calling `complete` resolves the handle as cancelled, so a job which never
+/// runs can not leave its handle waiting forever.
+pub(crate) struct Completer<T> {
+    packet: Option<Arc<Packet<T>>>,
+}
+
+impl<T> Completer<T> {
+    pub fn complete(mut self, result: Result<T, JoinError>) {
+        if let Some(packet) = self.packet.take() {
+            Completer::store(&packet, result);
+        }
+    }
+
+    fn store(packet: &Packet<T>, result: Result<T, JoinError>) {
+        let succeeded = result.is_ok();
+        let listeners = {
+            let mut state = packet
+                .state
+                .lock()
+                .expect("Completer unable to lock job result");
+            state.result = Some(result);
+            state.done = Some(succeeded);
+            packet.condvar.notify_all();
+            mem::take(&mut state.listeners)
+        };
+        for listener in listeners {
+            listener(succeeded);
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        if let Some(packet) = self.packet.take() {
+            Completer::store(&packet, Err(JoinError::cancelled()));
+        }
+    }
+}
+
+/// An owned permission to wait for the result of a job submitted with [`ThreadPool::spawn`].
+///
+/// [`ThreadPool::spawn`]: struct.ThreadPool.html#method.spawn
+pub struct JobHandle<T> {
+    packet: Arc<Packet<T>>,
+    queued: Option<Queued>,
+}
+
+/// Finds the job of a handle in the queue of its pool.
+struct Queued {
+    shared_data: Weak<ThreadPoolSharedData>,
+    /// The id of the job in the pending jobs of the pool.
+    id: u64,
+}
+
+pub(crate) fn pair<T>() -> (Completer<T>, JobHandle<T>) {
+    let packet = Arc::new(Packet {
+        state: Mutex::new(State {
+            result: None,
+            done: None,
+            listeners: Vec::new(),
+        }),
+        condvar: Condvar::new(),
+    });
+    (
+        Completer {
+            packet: Some(packet.clone()),
+        },
+        JobHandle {
+            packet,
+            queued: None,
+        },
+    )
+}
+
+impl<T> JobHandle<T> {
+    /// Block the current thread until the job has finished and return its result.
+    ///
+    /// If the job panicked, the returned [`JoinError`] carries the panic payload, similar to
+    /// [`std::thread::JoinHandle::join`].
+    ///
+    /// [`JoinError`]: struct.JoinError.html
+    /// [`std::thread::JoinHandle::join`]: https://doc.rust-lang.org/std/thread/struct.JoinHandle.html#method.join
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// let answer = pool.spawn(|| 6 * 7);
+    /// let failure = pool.spawn(|| -> u32 { panic!("no answer") });
+    ///
+    /// assert_eq!(42, answer.join().unwrap());
+    ///
+    /// let error = failure.join().unwrap_err();
+    /// assert!(error.is_panic());
+    /// assert_eq!(Some(&"no answer"), error.into_panic().downcast_ref::<&str>());
+    /// ```
+    pub fn join(self) -> Result<T, JoinError> {
+        let mut state = self
+            .packet
+            .state
+            .lock()
+            .expect("JobHandle::join unable to lock job result");
+        loop {
+            if let Some(result) = state.result.take() {
+                return result;
+            }
+            state = self
+                .packet
+                .condvar
+                .wait(state)
+                .expect("JobHandle::join unable to wait for job result");
+        }
+    }
+
+    /// Block the current thread until the job has finished or `deadline` has passed. Returns
+    /// `true` if the job has finished.
+    fn wait_until(&self, deadline: Instant) -> bool {
+        let mut state = self
+            .packet
+            .state
+            .lock()
+            .expect("JobHandle::wait_until unable to lock job result");
+        while state.done.is_none() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self
+                .packet
+                .condvar
+                .wait_timeout(state, deadline - now)
+                .expect("JobHandle::wait_until unable to wait for job result")
+                .0;
+        }
+        true
+    }
+
+    /// Change the priority of the job while it is still queued, see
+    /// [`Scheduling::Priority`].
+    ///
+    /// Returns `false` if the job has started already, or the pool does not order its jobs by
+    /// priority. Jobs kept aside for a worker thread by [`ThreadPool::execute_with_affinity`] or
+    /// NUMA placement keep their place.
+    ///
+    /// [`Scheduling::Priority`]: enum.Scheduling.html#variant.Priority
+    /// [`ThreadPool::execute_with_affinity`]: struct.ThreadPool.html#method.execute_with_affinity
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::Scheduling;
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(1)
+    ///     .scheduling(Scheduling::Priority)
+    ///     .build();
+    /// let (started_tx, started_rx) = channel();
+    /// let (release_tx, release_rx) = channel::<()>();
+    /// pool.execute(move || {
+    ///     started_tx.send(()).unwrap();
+    ///     release_rx.recv().unwrap();
+    /// });
+    /// started_rx.recv().unwrap();
+    ///
+    /// let (tx, rx) = channel();
+    /// let handles: Vec<_> = (0..3)
+    ///     .map(|i| {
+    ///         let tx = tx.clone();
+    ///         pool.spawn(move || tx.send(i).unwrap())
+    ///     })
+    ///     .collect();
+    /// assert!(handles[2].set_priority(10));
+    /// release_tx.send(()).unwrap();
+    ///
+    /// assert_eq!(rx.iter().take(3).collect::<Vec<_>>(), vec![2, 0, 1]);
+    /// assert!(!handles[2].set_priority(0));
+    /// ```
+    pub fn set_priority(&self, priority: i8) -> bool {
+        let queued = match self.queued {
+            Some(ref queued) => queued,
+            None => return false,
+        };
+        let shared_data = match queued.shared_data.upgrade() {
+            Some(shared_data) => shared_data,
+            None => return false,
+        };
+        let found = shared_data.job_queue.update(&mut |job| {
+            if job.pending.as_ref().map(|pending| pending.id()) != Some(queued.id) {
+                return false;
+            }
+            job.priority = priority;
+            true
+        });
+        if found {
+            shared_data.pending_jobs.set_priority(queued.id, priority);
+        }
+        found
+    }
+
+    /// Let [`set_priority`](#method.set_priority) find the job with the pending id `id` in the
+    /// queue of `shared_data`.
+    pub(crate) fn track(&mut self, shared_data: &Arc<ThreadPoolSharedData>, id: u64) {
+        self.queued = Some(Queued {
+            shared_data: Arc::downgrade(shared_data),
+            id,
+        });
+    }
+
+    /// Returns `true` if the job has finished, so that [`join`](#method.join) will not block.
+    pub fn is_finished(&self) -> bool {
+        self.packet
+            .state
+            .lock()
+            .expect("JobHandle::is_finished unable to lock job result")
+            .done
+            .is_some()
+    }
+}
+
+/// Block the current thread until all jobs of `handles` have finished and return their results,
+/// in the order of `handles`.
+///
+/// # Examples
+///
+/// ```
+/// use threadpool::{wait_all, ThreadPool};
+///
+/// let pool = ThreadPool::new(4);
+/// let handles = (0..4).map(|i| pool.spawn(move || i * i));
+///
+/// let squares = wait_all(handles)
+///     .into_iter()
+///     .collect::<Result<Vec<u32>, _>>()
+///     .unwrap();
+/// assert_eq!(vec![0, 1, 4, 9], squares);
+/// ```
+pub fn wait_all<T, I>(handles: I) -> Vec<Result<T, JoinError>>
+where
+    I: IntoIterator<Item = JobHandle<T>>,
+{
+    handles.into_iter().map(JobHandle::join).collect()
+}
+
+/// Like [`wait_all`], but gives up after `timeout`.
+///
+/// If not all jobs have finished by then, the handles are given back, so that they can be waited
+/// for again later.
+///
+/// [`wait_all`]: fn.wait_all.html
+///
+/// # Examples
+///
+/// ```
+/// use threadpool::{wait_all_timeout, ThreadPool};
+/// use std::thread::sleep;
+/// use std::time::Duration;
+///
+/// let pool = ThreadPool::new(2);
+/// let handles = vec![
+///     pool.spawn(|| 1),
+///     pool.spawn(|| {
+///         sleep(Duration::from_millis(200));
+///         2
+///     }),
+/// ];
+///
+/// let handles = wait_all_timeout(handles, Duration::from_millis(10)).unwrap_err();
+/// let results = wait_all_timeout(handles, Duration::from_secs(10)).unwrap();
+/// assert_eq!(results.len(), 2);
+/// ```
+pub fn wait_all_timeout<T, I>(
+    handles: I,
+    timeout: Duration,
+) -> Result<Vec<Result<T, JoinError>>, Vec<JobHandle<T>>>
+where
+    I: IntoIterator<Item = JobHandle<T>>,
+{
+    let deadline = Instant::now() + timeout;
+    let handles = handles.into_iter().collect::<Vec<_>>();
+    if handles.iter().all(|handle| handle.wait_until(deadline)) {
+        Ok(wait_all(handles))
+    } else {
+        Err(handles)
+    }
+}
+
+impl<T> Prerequisite for JobHandle<T> {
+    fn when_done(&self, callback: DoneCallback) {
+        let succeeded = {
+            let mut state = self
+                .packet
+                .state
+                .lock()
+                .expect("JobHandle::when_done unable to lock job result");
+            match state.done {
+                Some(succeeded) => succeeded,
+                None => {
+                    state.listeners.push(callback);
+                    return;
+                }
+            }
+        };
+        callback(succeeded);
+    }
+}
+
+impl<T> fmt::Debug for JobHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JobHandle")
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{wait_all, wait_all_timeout};
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+    use ThreadPool;
+
+    #[test]
+    fn test_wait_all() {
+        let pool = ThreadPool::new(4);
+        let handles = vec![
+            pool.spawn(|| 1),
+            pool.spawn(|| -> u32 { panic!("Ignore this panic, it must!") }),
+            pool.spawn(|| 3),
+        ];
+
+        let results = wait_all(handles);
+        assert_eq!(results[0].as_ref().ok(), Some(&1));
+        assert!(results[1].as_ref().unwrap_err().is_panic());
+        assert_eq!(results[2].as_ref().ok(), Some(&3));
+    }
+
+    #[test]
+    fn test_wait_all_timeout() {
+        let pool = ThreadPool::new(2);
+        let (tx, rx) = channel::<()>();
+        let handles = vec![
+            pool.spawn(|| 1),
+            pool.spawn(move || {
+                let _ = rx.recv();
+                2
+            }),
+        ];
+
+        let handles = wait_all_timeout(handles, Duration::from_millis(10)).unwrap_err();
+        assert_eq!(handles.len(), 2);
+        drop(tx);
+        let results = wait_all_timeout(handles, Duration::from_secs(60)).unwrap();
+        assert_eq!(
+            results.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+    }
+}
@@ -0,0 +1,35 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Liveness of the worker threads, see [`ThreadPool::health_check`].
+//!
+//! [`ThreadPool::health_check`]: ../struct.ThreadPool.html#method.health_check
+
+/// The outcome of [`ThreadPool::health_check`].
+///
+/// [`ThreadPool::health_check`]: struct.ThreadPool.html#method.health_check
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Health {
+    /// A worker thread started the probe job in time.
+    Healthy,
+    /// The probe job did not start in time, but other jobs finished meanwhile: the pool is
+    /// alive but overloaded, or some of its worker threads are stuck.
+    Degraded,
+    /// No job finished while waiting for the probe job, all worker threads seem to be stuck, or
+    /// the pool has been shut down.
+    Dead,
+}
+
+impl Health {
+    /// Returns `true` for [`Health::Healthy`](#variant.Healthy).
+    pub fn is_healthy(self) -> bool {
+        self == Health::Healthy
+    }
+}
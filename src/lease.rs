@@ -0,0 +1,175 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Worker threads reserved for the jobs of one holder, see [`ThreadPool::lease_worker`].
+//!
+//! [`ThreadPool::lease_worker`]: ../struct.ThreadPool.html#method.lease_worker
+
+use pending::{JobInfo, PendingJobs};
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use thunk::Thunk;
+use {run_job, submit_job, Job, ThreadPool, ThreadPoolSharedData, Worker};
+
+/// A worker thread of a pool reserved for the jobs submitted through the lease, see
+/// [`ThreadPool::lease_worker`].
+///
+/// The worker thread returns to the pool once the lease has been dropped and the jobs
+/// submitted through it have run.
+///
+/// [`ThreadPool::lease_worker`]: struct.ThreadPool.html#method.lease_worker
+pub struct WorkerLease {
+    pool: ThreadPool,
+    lease: Arc<Lease>,
+}
+
+struct State {
+    jobs: VecDeque<Job>,
+    /// The `WorkerLease` has been dropped.
+    released: bool,
+    /// No worker thread will ever serve the lease, the pool was shut down before one took it.
+    abandoned: bool,
+}
+
+struct Lease {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+impl WorkerLease {
+    pub(crate) fn new(pool: ThreadPool) -> WorkerLease {
+        let lease = Arc::new(Lease {
+            state: Mutex::new(State {
+                jobs: VecDeque::new(),
+                released: false,
+                abandoned: false,
+            }),
+            condvar: Condvar::new(),
+        });
+        let mut job = Job::new(Thunk::new(|| ()), pool.submitter);
+        job.lease = Some(Takeover {
+            lease: lease.clone(),
+            shared_data: Arc::downgrade(&pool.shared_data),
+        });
+        if submit_job(&pool.shared_data, job).is_err() {
+            panic!("ThreadPool::lease_worker unable to send lease into queue.");
+        }
+        WorkerLease { pool, lease }
+    }
+
+    /// Executes the function `job` on the leased worker thread. The jobs of a lease run one
+    /// after the other, in the order they were submitted.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool was shut down before a worker thread took the
+    /// lease.
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let shared_data = &self.pool.shared_data;
+        let mut job = Job::new(Thunk::new(job), self.pool.submitter);
+        job.contexts = Some(shared_data.capture_contexts());
+        job.pending = Some(PendingJobs::enter(
+            &shared_data.pending_jobs,
+            JobInfo::new(None, 0),
+        ));
+
+        let mut state = self
+            .lease
+            .state
+            .lock()
+            .expect("WorkerLease::execute unable to lock state");
+        if state.abandoned {
+            drop(state);
+            panic!("WorkerLease::execute unable to send job to the leased worker.");
+        }
+        shared_data.queued_count.fetch_add(1, Ordering::SeqCst);
+        state.jobs.push_back(job);
+        self.lease.condvar.notify_one();
+    }
+}
+
+impl Drop for WorkerLease {
+    fn drop(&mut self) {
+        self.lease
+            .state
+            .lock()
+            .expect("WorkerLease::drop unable to lock state")
+            .released = true;
+        self.lease.condvar.notify_one();
+    }
+}
+
+/// Carries a lease through the queue of the pool to the worker thread which serves it.
+pub(crate) struct Takeover {
+    lease: Arc<Lease>,
+    shared_data: Weak<ThreadPoolSharedData>,
+}
+
+impl Takeover {
+    /// Run the jobs of the lease on the calling thread as `worker`, until the lease has been
+    /// released and its jobs have run.
+    pub fn serve(self, shared_data: &Arc<ThreadPoolSharedData>, worker: &Worker) {
+        loop {
+            let job = {
+                let mut state = self
+                    .lease
+                    .state
+                    .lock()
+                    .expect("Takeover::serve unable to lock state");
+                loop {
+                    if let Some(job) = state.jobs.pop_front() {
+                        break job;
+                    }
+                    if state.released {
+                        return;
+                    }
+                    state = self
+                        .lease
+                        .condvar
+                        .wait(state)
+                        .expect("Takeover::serve unable to wait for jobs");
+                }
+            };
+            run_job(shared_data, worker, job);
+        }
+    }
+}
+
+impl Drop for Takeover {
+    fn drop(&mut self) {
+        let abandoned = {
+            let mut state = self
+                .lease
+                .state
+                .lock()
+                .expect("Takeover::drop unable to lock state");
+            state.abandoned = true;
+            mem::take(&mut state.jobs)
+        };
+        // The pool is gone, so is the accounting of its jobs.
+        let shared_data = match self.shared_data.upgrade() {
+            Some(shared_data) => shared_data,
+            None => return,
+        };
+        if abandoned.is_empty() {
+            return;
+        }
+        for job in abandoned {
+            shared_data.queued_count.fetch_sub(1, Ordering::SeqCst);
+            shared_data.release_bytes(job.size);
+        }
+        shared_data.no_work_notify_all();
+    }
+}
@@ -35,7 +35,7 @@
 //!     });
 //! }
 //!
-//! assert_eq!(rx.iter().take(n_jobs).fold(0, |a, b| a + b), 8);
+//! assert_eq!(rx.iter().take(n_jobs).sum::<usize>(), 8);
 //! ```
 //!
 //! ## Synchronized with a barrier
@@ -78,35 +78,493 @@
 //! assert_eq!(an_atomic.load(Ordering::SeqCst), /* n_jobs = */ 23);
 //! ```
 
+#[cfg(unix)]
+extern crate libc;
 extern crate num_cpus;
 
+mod blocking;
+mod cache;
+mod cancel;
+mod dispatch;
+mod env;
+mod error;
+mod factory;
+mod global;
+mod handle;
+mod health;
+mod lease;
+mod limit;
+mod metrics;
+mod numa;
+mod panics;
+mod pending;
+mod pipeline;
+mod priority;
+mod profile;
+mod qos;
+mod quarantine;
+mod queue;
+mod scheduling;
+mod shared;
+#[cfg(all(unix, feature = "signal"))]
+mod signal;
+mod stats;
+mod status;
+mod stream;
+mod task;
+mod thunk;
+mod wait;
+
+pub use blocking::BlockingSection;
+pub use cancel::{CancellationToken, CancelledJobs};
+pub use env::EnvError;
+pub use error::PoolError;
+pub use factory::{DefaultThreadFactory, ThreadFactory};
+pub use global::{global, set_global};
+pub use handle::{wait_all, wait_all_timeout, DoneCallback, JobHandle, JoinError, Prerequisite};
+pub use health::Health;
+pub use lease::WorkerLease;
+pub use metrics::prometheus_metrics;
+pub use numa::NumaPlacement;
+pub use panics::{JobPanic, PanicPolicy};
+pub use pending::JobInfo;
+pub use pipeline::{Pipeline, PipelineBuilder};
+pub use priority::ThreadPriority;
+pub use profile::{JobProfile, ProfileReport};
+pub use qos::QosClass;
+pub use scheduling::{QueuedJob, Scheduler, Scheduling};
+pub use shared::SharedWorkers;
+pub use stats::{IntervalStats, PriorityQueueStats, QueueWaitStats, WorkerStats};
+pub use status::{JobId, JobStatus};
+pub use stream::JobStream;
+pub use wait::WaitStrategy;
+
+use cache::{Lookup, ResultCache};
+use cancel::tag_matches;
+use dispatch::{spawn_dispatcher, Dispatcher, Handoff};
+use lease::Takeover;
+use limit::Limiter;
+use numa::Numa;
+use pending::{Pending, PendingJobs};
+use profile::Profiler;
+use quarantine::Quarantine;
+use queue::{JobQueue, PopError};
+use scheduling::Custom;
+use stats::QueueWaits;
+use status::StatusTable;
+use std::any::Any;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashSet, VecDeque};
 use std::fmt;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::{Arc, Condvar, Mutex};
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::mem;
+use std::panic;
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 use std::thread;
+use std::time::{Duration, Instant};
+use std::vec;
+use thunk::Thunk;
+
+/// A job waiting in the queue together with its scheduling information.
+struct Job {
+    thunk: Thunk,
+    deadline: Option<Instant>,
+    /// Identifies the `ThreadPool` handle which submitted the job.
+    submitter: usize,
+    tag: Option<Arc<str>>,
+    priority: i8,
+    /// Approximate memory held by the job, counted against `Builder::queue_byte_limit`.
+    size: usize,
+    /// Index of the worker which should preferably run the job.
+    affinity: Option<usize>,
+    /// Contexts of the submitting thread, `None` until they are captured.
+    contexts: Option<Vec<RestoreContext>>,
+    /// Entry in `ThreadPool::pending_jobs`, `None` until the job is submitted.
+    pending: Option<Pending>,
+    /// Set for the job which hands a worker over to a `WorkerLease`.
+    lease: Option<Takeover>,
+    /// Returned by `CancellationToken::current` while the job runs.
+    token: Option<CancellationToken>,
+    /// Set for the job which only wakes up an idle worker, so that it notices it is surplus.
+    wake: bool,
+}
+
+impl Job {
+    fn new(thunk: Thunk, submitter: usize) -> Job {
+        Job {
+            thunk,
+            deadline: None,
+            submitter,
+            tag: None,
+            priority: 0,
+            size: 0,
+            affinity: None,
+            contexts: None,
+            pending: None,
+            lease: None,
+            token: None,
+            wake: false,
+        }
+    }
+}
+
+type DeadlineMissHandler = Arc<dyn Fn(Duration) + Send + Sync>;
+
+type DeadLetterHandler = Arc<dyn Fn(&str, BoxedJob) + Send + Sync>;
+
+type IdleCallback = Arc<dyn Fn() + Send + Sync>;
+
+type JobWrapper = Arc<dyn Fn(&mut dyn FnMut()) + Send + Sync>;
+
+type CaptureContext = Arc<dyn Fn() -> RestoreContext + Send + Sync>;
+
+type RestoreContext = Box<dyn FnOnce(&mut dyn FnMut()) + Send>;
+
+type SchedulerFactory = Arc<dyn Fn() -> Box<dyn Scheduler> + Send + Sync>;
+
+type ShutdownHook = Box<dyn FnOnce() + Send + 'static>;
+
+/// A job which has been boxed already, see [`ThreadPool::execute_boxed`].
+///
+/// [`ThreadPool::execute_boxed`]: struct.ThreadPool.html#method.execute_boxed
+pub type BoxedJob = Box<dyn FnOnce() + Send + 'static>;
+
+/// How often spawning a worker thread is attempted before it is given up on.
+const SPAWN_ATTEMPTS: u32 = 5;
+
+/// Whether the target can spawn threads at all. WebAssembly without atomics can not, pools then
+/// run their jobs on the submitting thread, see `Builder::inline_fallback`.
+const THREADS_SUPPORTED: bool = !cfg!(all(target_family = "wasm", not(target_feature = "atomics")));
+
+/// Delay before the first retry of a failed spawn, doubled for every further retry.
+const SPAWN_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// A worker thread which dies within this time of the previous replacement is replaced with a
+/// delay, doubled for every further death up to `MAX_RESPAWN_DELAY`.
+const RESPAWN_STORM_WINDOW: Duration = Duration::from_secs(1);
+
+const FIRST_RESPAWN_DELAY: Duration = Duration::from_millis(10);
+
+const MAX_RESPAWN_DELAY: Duration = Duration::from_secs(1);
+
+/// Wrap `job` so that `on_done` is called with its result, see
+/// [`ThreadPool::execute_with_callback`].
+fn with_completion<F, T, C>(job: F, on_done: C) -> impl FnOnce() + Send + 'static
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: 'static,
+    C: FnOnce(Result<T, JoinError>) + Send + 'static,
+{
+    let on_done = handle::OnDone::new(on_done);
+    move || match panic::catch_unwind(panic::AssertUnwindSafe(job)) {
+        Ok(value) => on_done.call(Ok(value)),
+        Err(payload) => {
+            let message = panics::payload_message(&*payload)
+                .unwrap_or("job panicked, see its completion callback")
+                .to_owned();
+            on_done.call(Err(JoinError::panic(payload)));
+            // The payload now belongs to the callback, unwind with its message so the panic is
+            // accounted for like any other job panic.
+            panic::resume_unwind(Box::new(message));
+        }
+    }
+}
+
+/// The point every worker has to reach in `ThreadPool::sync_all`.
+struct SyncPoint {
+    remaining: Mutex<usize>,
+    condvar: Condvar,
+}
+
+impl SyncPoint {
+    fn wait(&self) {
+        let mut remaining = self
+            .remaining
+            .lock()
+            .expect("SyncPoint unable to lock count");
+        while *remaining > 0 {
+            remaining = self
+                .condvar
+                .wait(remaining)
+                .expect("SyncPoint unable to wait for workers");
+        }
+    }
+}
+
+/// Owned by a job of `ThreadPool::sync_all`, arrives at the sync point when dropped, so that a
+/// job discarded by `ThreadPool::shutdown_now` can not keep the others waiting.
+struct Arrival(Arc<SyncPoint>);
+
+impl Drop for Arrival {
+    fn drop(&mut self) {
+        let mut remaining = self
+            .0
+            .remaining
+            .lock()
+            .expect("Arrival unable to lock count");
+        *remaining -= 1;
+        if *remaining == 0 {
+            self.0.condvar.notify_all();
+        }
+    }
+}
+
+/// A group of jobs submitted with `ThreadPool::execute_gang`.
+struct Gang {
+    // Number of members which have started, and whether a member was dropped without starting.
+    state: Mutex<(usize, bool)>,
+    condvar: Condvar,
+    size: usize,
+}
+
+impl Gang {
+    /// Wait for all members to start, returns `false` if one of them never will.
+    fn wait(&self) -> bool {
+        let mut state = self.state.lock().expect("Gang unable to lock state");
+        while state.0 < self.size && !state.1 {
+            state = self
+                .condvar
+                .wait(state)
+                .expect("Gang unable to wait for members");
+        }
+        !state.1
+    }
+}
+
+/// Owned by a job of `ThreadPool::execute_gang` until it starts. Dropping it before breaks the
+/// gang, so the other members do not wait forever for a job discarded by
+/// `ThreadPool::shutdown_now`.
+struct GangMember {
+    gang: Arc<Gang>,
+    started: bool,
+}
+
+impl GangMember {
+    fn start(mut self) -> Arc<Gang> {
+        self.started = true;
+        let mut state = self.gang.state.lock().expect("Gang unable to lock state");
+        state.0 += 1;
+        if state.0 == self.gang.size {
+            self.gang.condvar.notify_all();
+        }
+        drop(state);
+        self.gang.clone()
+    }
+}
+
+impl Drop for GangMember {
+    fn drop(&mut self) {
+        if !self.started {
+            let mut state = self.gang.state.lock().expect("Gang unable to lock state");
+            state.1 = true;
+            self.gang.condvar.notify_all();
+        }
+    }
+}
+
+/// The instances of a job submitted with `ThreadPool::execute_n` which have not finished yet.
+struct Instances {
+    /// The number of instances left, the first error and the completer of the group's handle.
+    state: Mutex<(usize, Option<JoinError>, Option<handle::Completer<()>>)>,
+}
+
+impl Instances {
+    fn done(&self, result: Result<(), JoinError>) {
+        let mut state = self.state.lock().expect("Instances unable to lock state");
+        state.0 -= 1;
+        if let Err(error) = result {
+            state.1.get_or_insert(error);
+        }
+        if state.0 == 0 {
+            let result = state.1.take().map_or(Ok(()), Err);
+            if let Some(completer) = state.2.take() {
+                completer.complete(result);
+            }
+        }
+    }
+}
+
+/// The key of a job submitted with `ThreadPool::execute_dedup`, which is released once the job
+/// has run or was dropped.
+struct DedupKey {
+    keys: Arc<Mutex<HashSet<Arc<str>>>>,
+    key: Arc<str>,
+}
+
+impl Drop for DedupKey {
+    fn drop(&mut self) {
+        self.keys
+            .lock()
+            .expect("DedupKey unable to lock keys")
+            .remove(&self.key);
+    }
+}
+
+/// A job submitted with `ThreadPool::submit_after` waiting for its prerequisites.
+struct PendingJob {
+    remaining: AtomicUsize,
+    failed: AtomicBool,
+    job: Mutex<Option<(ThreadPool, Job)>>,
+}
+
+impl PendingJob {
+    fn done(&self, succeeded: bool) {
+        if !succeeded {
+            self.failed.store(true, Ordering::SeqCst);
+        }
+        if self.remaining.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let pending = self
+                .job
+                .lock()
+                .expect("PendingJob unable to lock job")
+                .take();
+            if let Some((pool, job)) = pending {
+                // Dropping the job reports it as cancelled, which also happens if the pool has
+                // been shut down in the meantime.
+                if !self.failed.load(Ordering::SeqCst) {
+                    let _ = pool.try_submit(job);
+                }
+            }
+        }
+    }
+}
+
+thread_local! {
+    // The pool a worker thread belongs to, `None` for all other threads.
+    static CURRENT_POOL: RefCell<Option<Arc<ThreadPoolSharedData>>> = const { RefCell::new(None) };
+    // What the continuations of the running job inherit, see `yield_now`.
+    static CURRENT_JOB: RefCell<Option<JobOrigin>> = const { RefCell::new(None) };
+}
+
+/// The scheduling information of a running job.
+#[derive(Clone)]
+struct JobOrigin {
+    submitter: usize,
+    tag: Option<Arc<str>>,
+    priority: i8,
+}
 
-trait FnBox {
-    fn call_box(self: Box<Self>);
+/// Bookkeeping of a single worker thread.
+struct Worker {
+    // Smallest number not used by another live worker, see `ThreadPool::execute_with_affinity`.
+    index: usize,
+    // Nanoseconds since the creation of the pool plus one at which the current job started, zero
+    // while the worker is idle.
+    busy_since: AtomicU64,
+    // Time spent on finished jobs.
+    busy_nanos: AtomicU64,
+    jobs_executed: AtomicU64,
+    panics: AtomicU64,
+    last_tag: Mutex<Option<Arc<str>>>,
+    // Tag and token of the current job, for `ThreadPool::cancel_tagged`.
+    running: Mutex<Option<(Arc<str>, CancellationToken)>>,
 }
 
-impl<F: FnOnce()> FnBox for F {
-    fn call_box(self: Box<F>) {
-        (*self)()
+impl Worker {
+    fn start_job(&self, now: u64, tag: &Option<Arc<str>>, token: &Option<CancellationToken>) {
+        self.busy_since.store(now + 1, Ordering::SeqCst);
+        *self.last_tag.lock().expect("Unable to lock last tag") = tag.clone();
+        if let (Some(tag), Some(token)) = (tag, token) {
+            *self.running.lock().expect("Unable to lock running job") =
+                Some((tag.clone(), token.clone()));
+        }
+    }
+
+    fn finish_job(&self, now: u64) {
+        self.running
+            .lock()
+            .expect("Unable to lock running job")
+            .take();
+        let since = self.busy_since.swap(0, Ordering::SeqCst);
+        if since > 0 {
+            self.busy_nanos
+                .fetch_add(now.saturating_sub(since - 1), Ordering::SeqCst);
+            self.jobs_executed.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn stats(&self, now: u64) -> WorkerStats {
+        WorkerStats {
+            index: self.index,
+            jobs_executed: self.jobs_executed.load(Ordering::SeqCst),
+            panics: self.panics.load(Ordering::SeqCst),
+            busy_time: nanos_to_duration(self.busy_nanos(now)),
+            current_job_time: match self.busy_since.load(Ordering::SeqCst) {
+                0 => None,
+                since => Some(nanos_to_duration(now.saturating_sub(since - 1))),
+            },
+            last_tag: self
+                .last_tag
+                .lock()
+                .expect("Unable to lock last tag")
+                .clone(),
+        }
+    }
+
+    /// Time spent on jobs, including the one currently running.
+    fn busy_nanos(&self, now: u64) -> u64 {
+        let busy = self.busy_nanos.load(Ordering::SeqCst);
+        match self.busy_since.load(Ordering::SeqCst) {
+            0 => busy,
+            since => busy + now.saturating_sub(since - 1),
+        }
+    }
+
+    /// Forget the statistics gathered so far. A running job only counts from `now` on.
+    fn reset_stats(&self, now: u64) {
+        self.jobs_executed.store(0, Ordering::SeqCst);
+        self.panics.store(0, Ordering::SeqCst);
+        self.busy_nanos.store(0, Ordering::SeqCst);
+        let since = self.busy_since.load(Ordering::SeqCst);
+        if since > 0 {
+            // Fails if the job has finished in the meantime, and its time is counted.
+            let _ = self.busy_since.compare_exchange(
+                since,
+                now + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            );
+        }
     }
 }
 
-type Thunk<'a> = Box<FnBox + Send + 'a>;
+/// When worker threads were last replaced, see `ThreadPoolSharedData::respawn_delay`.
+struct Respawn {
+    // The moment the last replacement is spawned, after its delay.
+    last: Option<Instant>,
+    delay: Duration,
+}
+
+/// The lifetime totals of the pool at the start of the current statistics interval, see
+/// `ThreadPool::reset_stats`.
+#[derive(Default)]
+struct IntervalStart {
+    // Nanoseconds since the creation of the pool.
+    at: u64,
+    completed_count: usize,
+    panic_count: usize,
+    respawn_count: usize,
+    busy_nanos: u64,
+}
 
 struct Sentinel<'a> {
     shared_data: &'a Arc<ThreadPoolSharedData>,
+    worker: Arc<Worker>,
     active: bool,
 }
 
 impl<'a> Sentinel<'a> {
     fn new(shared_data: &'a Arc<ThreadPoolSharedData>) -> Sentinel<'a> {
         Sentinel {
-            shared_data: shared_data,
+            shared_data,
+            worker: shared_data.register_worker(),
             active: true,
         }
     }
@@ -119,13 +577,22 @@ impl<'a> Sentinel<'a> {
 
 impl<'a> Drop for Sentinel<'a> {
     fn drop(&mut self) {
+        self.shared_data.retire_worker(&self.worker);
         if self.active {
-            self.shared_data.active_count.fetch_sub(1, Ordering::SeqCst);
+            // Count the panic before the job stops being active, so that it is visible once
+            // `join` returns.
             if thread::panicking() {
                 self.shared_data.panic_count.fetch_add(1, Ordering::SeqCst);
             }
-            self.shared_data.no_work_notify_all();
-            spawn_in_pool(self.shared_data.clone())
+            self.shared_data
+                .respawn_count
+                .fetch_add(1, Ordering::SeqCst);
+            self.shared_data.finish_active_job();
+            let delay = self.shared_data.respawn_delay();
+            if delay > Duration::from_secs(0) && !self.shared_data.job_queue.is_closed() {
+                thread::sleep(delay);
+            }
+            spawn_in_pool(self.shared_data.clone());
         }
     }
 }
@@ -133,15 +600,45 @@ impl<'a> Drop for Sentinel<'a> {
 /// [`ThreadPool`] factory, which can be used in order to configure the properties of the
 /// [`ThreadPool`].
 ///
-/// The three configuration options available:
+/// The configuration options available:
 ///
 /// * `num_threads`: maximum number of threads that will be alive at any given moment by the built
 ///   [`ThreadPool`]
 /// * `thread_name`: thread name for each of the threads spawned by the built [`ThreadPool`]
+/// * `thread_factory`: how the threads of the built [`ThreadPool`] are created
 /// * `thread_stack_size`: stack size (in bytes) for each of the threads spawned by the built
 ///   [`ThreadPool`]
+/// * `thread_priority`: operating system scheduling priority of the threads spawned by the built
+///   [`ThreadPool`]
+/// * `thread_qos`: quality of service class of the threads spawned by the built [`ThreadPool`]
+///   on Apple platforms
+/// * `numa_placement`: how the threads are placed on the NUMA nodes of the machine
+/// * `lazy_spawn`: spawn worker threads only once there are jobs for them
+/// * `deterministic`: run jobs on the calling thread of `run_pending` instead of worker threads
+/// * `scheduling`: the order in which queued jobs are started, e.g. by deadline or taking turns
+///   between the handles of the pool
+/// * `scheduler`: a custom order in which queued jobs are started, instead of `scheduling`
+/// * `priority_aging`: how fast queued jobs gain priority with [`Scheduling::Priority`]
+/// * `on_deadline_miss`: callback for jobs which finish after their deadline
+/// * `queue_byte_limit`: approximate memory the jobs submitted with
+///   [`ThreadPool::execute_sized`] may hold while they are queued
+/// * `panic_policy`: whether a panicking job replaces its worker thread or aborts the process
+/// * `quarantine_tags`: reject the jobs of tags which keep panicking
+/// * `on_quarantined_job`: callback receiving the jobs rejected because of their tag
+/// * `wait_strategy`: whether idle worker threads spin and yield before they park
+/// * `dequeue_batch`: how many jobs a worker thread takes from the queue at once
+/// * `dispatcher_thread`: hand the jobs to the worker threads from a dedicated thread
+/// * `max_jobs_per_thread`: replace a worker thread once it has executed this many jobs
+/// * `max_thread_lifetime`: replace a worker thread once it has run for this long
+/// * `wrap_jobs`: code which runs around every job, e.g. for logging or timing
+/// * `propagate_context`: thread-local state carried from the submitting thread to the job
+/// * `profile_jobs`: aggregate the execution times of the jobs by tag
+/// * `inline_fallback`: run jobs on the submitting thread while no worker thread can be spawned
+/// * `auto_resize`: follow the number of CPUs available to the process
 ///
 /// [`ThreadPool`]: struct.ThreadPool.html
+/// [`ThreadPool::execute_sized`]: struct.ThreadPool.html#method.execute_sized
+/// [`Scheduling::Priority`]: enum.Scheduling.html#variant.Priority
 ///
 /// # Examples
 ///
@@ -159,6 +656,30 @@ pub struct Builder {
     num_threads: Option<usize>,
     thread_name: Option<String>,
     thread_stack_size: Option<usize>,
+    thread_factory: Option<Arc<dyn ThreadFactory>>,
+    thread_priority: Option<ThreadPriority>,
+    thread_qos: Option<QosClass>,
+    numa_placement: Option<NumaPlacement>,
+    lazy_spawn: bool,
+    deterministic: bool,
+    scheduling: Option<Scheduling>,
+    scheduler: Option<SchedulerFactory>,
+    priority_aging: Option<Duration>,
+    deadline_miss_handler: Option<DeadlineMissHandler>,
+    queue_byte_limit: Option<usize>,
+    panic_policy: PanicPolicy,
+    quarantine: Option<(usize, Duration)>,
+    dead_letter_handler: Option<DeadLetterHandler>,
+    wait_strategy: WaitStrategy,
+    dequeue_batch: Option<usize>,
+    dispatcher_thread: bool,
+    max_jobs_per_thread: Option<usize>,
+    max_thread_lifetime: Option<Duration>,
+    job_wrappers: Vec<JobWrapper>,
+    context_captures: Vec<CaptureContext>,
+    profile_jobs: bool,
+    inline_fallback: bool,
+    auto_resize: Option<Duration>,
 }
 
 impl Builder {
@@ -176,13 +697,74 @@ impl Builder {
             num_threads: None,
             thread_name: None,
             thread_stack_size: None,
+            thread_factory: None,
+            thread_priority: None,
+            thread_qos: None,
+            numa_placement: None,
+            lazy_spawn: false,
+            deterministic: false,
+            scheduling: None,
+            scheduler: None,
+            priority_aging: None,
+            deadline_miss_handler: None,
+            queue_byte_limit: None,
+            panic_policy: PanicPolicy::Continue,
+            quarantine: None,
+            dead_letter_handler: None,
+            wait_strategy: WaitStrategy::park(),
+            dequeue_batch: None,
+            dispatcher_thread: false,
+            max_jobs_per_thread: None,
+            max_thread_lifetime: None,
+            job_wrappers: Vec::new(),
+            context_captures: Vec::new(),
+            profile_jobs: false,
+            inline_fallback: false,
+            auto_resize: None,
         }
     }
 
+    /// Initiate a new [`Builder`] configured from the environment variables starting with
+    /// `prefix`, so that the pool can be tuned per deployment without recompiling:
+    ///
+    /// * `{prefix}_THREADS`: [`num_threads`](#method.num_threads)
+    /// * `{prefix}_NAME`: [`thread_name`](#method.thread_name)
+    /// * `{prefix}_STACK_SIZE`: [`thread_stack_size`](#method.thread_stack_size), in bytes
+    /// * `{prefix}_QUEUE_BYTES`: [`queue_byte_limit`](#method.queue_byte_limit)
+    ///
+    /// Variables which are not set keep the defaults of [`Builder::new`]. Numbers have to be
+    /// positive integers.
+    ///
+    /// [`Builder`]: struct.Builder.html
+    /// [`Builder::new`]: struct.Builder.html#method.new
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EnvError`] naming the first variable which is set to an invalid value.
+    ///
+    /// [`EnvError`]: struct.EnvError.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::env;
+    ///
+    /// env::set_var("DECODER_THREADS", "4");
+    /// let pool = threadpool::Builder::from_env("DECODER")
+    ///     .expect("invalid pool configuration")
+    ///     .build();
+    /// assert_eq!(pool.max_count(), 4);
+    /// ```
+    pub fn from_env(prefix: &str) -> Result<Builder, EnvError> {
+        env::configure(Builder::new(), prefix)
+    }
+
     /// Set the maximum number of worker-threads that will be alive at any given moment by the built
-    /// [`ThreadPool`]. If not specified, defaults the number of threads to the number of CPUs.
+    /// [`ThreadPool`]. If not specified, defaults the number of threads to the number of CPUs
+    /// the process may use, see [`available_parallelism`].
     ///
     /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`available_parallelism`]: fn.available_parallelism.html
     ///
     /// # Panics
     ///
@@ -265,950 +847,7839 @@ impl Builder {
         self
     }
 
-    /// Finalize the [`Builder`] and build the [`ThreadPool`].
+    /// Create the worker threads of the built [`ThreadPool`] with `factory` instead of
+    /// [`DefaultThreadFactory`], e.g. to set platform-specific thread attributes or to
+    /// instrument the threads.
+    ///
+    /// The [`thread_name`](#method.thread_name) and
+    /// [`thread_stack_size`](#method.thread_stack_size) are handed to the factory, which may
+    /// ignore them.
     ///
-    /// [`Builder`]: struct.Builder.html
     /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`DefaultThreadFactory`]: struct.DefaultThreadFactory.html
     ///
     /// # Examples
     ///
-    /// ```
-    /// let pool = threadpool::Builder::new()
-    ///     .num_threads(8)
-    ///     .thread_stack_size(4_000_000)
-    ///     .build();
-    /// ```
-    pub fn build(self) -> ThreadPool {
-        let (tx, rx) = channel::<Thunk<'static>>();
-
-        let num_threads = self.num_threads.unwrap_or_else(num_cpus::get);
-
-        let shared_data = Arc::new(ThreadPoolSharedData {
-            name: self.thread_name,
-            job_receiver: Mutex::new(rx),
-            empty_condvar: Condvar::new(),
-            empty_trigger: Mutex::new(()),
-            join_generation: AtomicUsize::new(0),
-            queued_count: AtomicUsize::new(0),
-            active_count: AtomicUsize::new(0),
-            max_thread_count: AtomicUsize::new(num_threads),
-            panic_count: AtomicUsize::new(0),
-            stack_size: self.thread_stack_size,
-        });
-
-        // Threadpool threads
-        for _ in 0..num_threads {
-            spawn_in_pool(shared_data.clone());
-        }
-
-        ThreadPool {
-            jobs: tx,
-            shared_data: shared_data,
-        }
-    }
-}
-
-struct ThreadPoolSharedData {
-    name: Option<String>,
-    job_receiver: Mutex<Receiver<Thunk<'static>>>,
-    empty_trigger: Mutex<()>,
-    empty_condvar: Condvar,
-    join_generation: AtomicUsize,
-    queued_count: AtomicUsize,
-    active_count: AtomicUsize,
-    max_thread_count: AtomicUsize,
-    panic_count: AtomicUsize,
-    stack_size: Option<usize>,
-}
-
-impl ThreadPoolSharedData {
-    fn has_work(&self) -> bool {
-        self.queued_count.load(Ordering::SeqCst) > 0 || self.active_count.load(Ordering::SeqCst) > 0
-    }
-
-    /// Notify all observers joining this pool if there is no more work to do.
-    fn no_work_notify_all(&self) {
-        if !self.has_work() {
-            *self
-                .empty_trigger
-                .lock()
-                .expect("Unable to notify all joining threads");
-            self.empty_condvar.notify_all();
-        }
+    /// See [`ThreadFactory`](trait.ThreadFactory.html).
+    pub fn thread_factory<F>(mut self, factory: F) -> Builder
+    where
+        F: ThreadFactory + 'static,
+    {
+        self.thread_factory = Some(Arc::new(factory));
+        self
     }
-}
-
-/// Abstraction of a thread pool for basic parallelism.
-pub struct ThreadPool {
-    // How the threadpool communicates with subthreads.
-    //
-    // This is the only such Sender, so when it is dropped all subthreads will
-    // quit.
-    jobs: Sender<Thunk<'static>>,
-    shared_data: Arc<ThreadPoolSharedData>,
-}
 
-impl ThreadPool {
-    /// Creates a new thread pool capable of executing `num_threads` number of jobs concurrently.
+    /// Set the operating system scheduling priority for each of the threads spawned by the built
+    /// [`ThreadPool`]. If not specified, threads inherit the priority of the thread which spawned
+    /// them.
     ///
-    /// # Panics
+    /// Setting the priority is best effort: if the platform refuses the change, e.g. because
+    /// raising the priority requires privileges, the threads keep running with their inherited
+    /// priority. On Apple platforms the [`thread_qos`](#method.thread_qos) class matters more.
     ///
-    /// This function will panic if `num_threads` is 0.
+    /// [`ThreadPool`]: struct.ThreadPool.html
     ///
     /// # Examples
     ///
-    /// Create a new thread pool capable of executing four jobs concurrently:
+    /// A pool for background processing which yields to the rest of the application:
     ///
     /// ```
-    /// use threadpool::ThreadPool;
+    /// use threadpool::ThreadPriority;
     ///
-    /// let pool = ThreadPool::new(4);
+    /// let pool = threadpool::Builder::new()
+    ///     .thread_priority(ThreadPriority::Lowest)
+    ///     .build();
+    ///
+    /// pool.execute(|| println!("Hello from a low priority thread!"));
+    /// pool.join();
     /// ```
-    pub fn new(num_threads: usize) -> ThreadPool {
-        Builder::new().num_threads(num_threads).build()
+    pub fn thread_priority(mut self, priority: ThreadPriority) -> Builder {
+        self.thread_priority = Some(priority);
+        self
     }
 
-    /// Creates a new thread pool capable of executing `num_threads` number of jobs concurrently.
-    /// Each thread will have the [name][thread name] `name`.
-    ///
-    /// # Panics
+    /// Set the quality of service class for each of the threads spawned by the built
+    /// [`ThreadPool`] on macOS, iOS and the other Apple platforms, where it rather than the
+    /// priority decides how the threads are scheduled. If not specified, threads inherit the
+    /// class of the thread which spawned them. On other platforms the class is ignored.
     ///
-    /// This function will panic if `num_threads` is 0.
+    /// [`ThreadPool`]: struct.ThreadPool.html
     ///
     /// # Examples
     ///
-    /// ```rust
-    /// use std::thread;
-    /// use threadpool::ThreadPool;
+    /// A pool for indexing which does not compete with the user interface:
     ///
-    /// let pool = ThreadPool::with_name("worker".into(), 2);
-    /// for _ in 0..2 {
-    ///     pool.execute(|| {
-    ///         assert_eq!(
-    ///             thread::current().name(),
-    ///             Some("worker")
-    ///         );
-    ///     });
-    /// }
-    /// pool.join();
     /// ```
+    /// use threadpool::QosClass;
     ///
-    /// [thread name]: https://doc.rust-lang.org/std/thread/struct.Thread.html#method.name
-    pub fn with_name(name: String, num_threads: usize) -> ThreadPool {
-        Builder::new()
-            .num_threads(num_threads)
-            .thread_name(name)
-            .build()
-    }
-
-    /// **Deprecated: Use [`ThreadPool::with_name`](#method.with_name)**
-    #[inline(always)]
-    #[deprecated(since = "1.4.0", note = "use ThreadPool::with_name")]
-    pub fn new_with_name(name: String, num_threads: usize) -> ThreadPool {
-        Self::with_name(name, num_threads)
+    /// let pool = threadpool::Builder::new()
+    ///     .thread_qos(QosClass::Background)
+    ///     .build();
+    ///
+    /// pool.execute(|| println!("Hello from a background thread!"));
+    /// pool.join();
+    /// ```
+    pub fn thread_qos(mut self, qos: QosClass) -> Builder {
+        self.thread_qos = Some(qos);
+        self
     }
 
-    /// Executes the function `job` on a thread in the pool.
+    /// Bind the worker threads of the built [`ThreadPool`] to the NUMA nodes of the machine as
+    /// given by `placement`. If not specified, the threads run wherever the operating system
+    /// puts them.
     ///
-    /// # Examples
+    /// Jobs whose data lives in the memory of one node run faster on the CPUs of that node, see
+    /// [`NumaPlacement`] for the options.
     ///
-    /// Execute four jobs on a thread pool that can run two jobs concurrently:
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`NumaPlacement`]: enum.NumaPlacement.html
+    ///
+    /// # Examples
     ///
     /// ```
-    /// use threadpool::ThreadPool;
+    /// use threadpool::NumaPlacement;
     ///
-    /// let pool = ThreadPool::new(2);
-    /// pool.execute(|| println!("hello"));
-    /// pool.execute(|| println!("world"));
-    /// pool.execute(|| println!("foo"));
-    /// pool.execute(|| println!("bar"));
+    /// let pool = threadpool::Builder::new()
+    ///     .numa_placement(NumaPlacement::Node(0))
+    ///     .build();
+    ///
+    /// pool.execute(|| println!("Hello from node 0!"));
     /// pool.join();
     /// ```
-    pub fn execute<F>(&self, job: F)
-    where
-        F: FnOnce() + Send + 'static,
-    {
-        self.shared_data.queued_count.fetch_add(1, Ordering::SeqCst);
-        self.jobs
-            .send(Box::new(job))
-            .expect("ThreadPool::execute unable to send job into queue.");
+    pub fn numa_placement(mut self, placement: NumaPlacement) -> Builder {
+        self.numa_placement = Some(placement);
+        self
     }
 
-    /// Returns the number of jobs waiting to executed in the pool.
+    /// Spawn the worker threads of the built [`ThreadPool`] on demand. If not specified, all
+    /// threads are spawned when the pool is built.
+    ///
+    /// A lazy pool starts without any threads. Whenever a job is submitted and no idle thread is
+    /// left to pick it up, a new thread is spawned, up to the maximum set with
+    /// [`num_threads`](#method.num_threads). Threads stay alive once they have been spawned.
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
     ///
     /// # Examples
     ///
+    /// This pool only spawns a thread once it is used:
+    ///
     /// ```
-    /// use threadpool::ThreadPool;
-    /// use std::time::Duration;
-    /// use std::thread::sleep;
-    ///
-    /// let pool = ThreadPool::new(2);
-    /// for _ in 0..10 {
-    ///     pool.execute(|| {
-    ///         sleep(Duration::from_secs(100));
-    ///     });
-    /// }
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(8)
+    ///     .lazy_spawn(true)
+    ///     .build();
     ///
-    /// sleep(Duration::from_secs(1)); // wait for threads to start
-    /// assert_eq!(8, pool.queued_count());
+    /// pool.execute(|| println!("Hello from the first worker thread!"));
+    /// pool.join();
     /// ```
-    pub fn queued_count(&self) -> usize {
-        self.shared_data.queued_count.load(Ordering::Relaxed)
+    pub fn lazy_spawn(mut self, lazy: bool) -> Builder {
+        self.lazy_spawn = lazy;
+        self
     }
 
-    /// Returns the number of currently active threads.
+    /// Build a [`ThreadPool`] without worker threads, for testing code which uses a pool. If not
+    /// specified, the pool has worker threads.
+    ///
+    /// Jobs submitted to a deterministic pool stay queued until
+    /// [`ThreadPool::run_pending`] or [`ThreadPool::join`] runs them one after the other on the
+    /// calling thread, in the order given by the [`scheduling`](#method.scheduling). Tests can
+    /// therefore check the state before and after the jobs ran without sleeping.
+    ///
+    /// Jobs which wait for other jobs of the same pool to run, e.g. through a [`JobHandle`],
+    /// never return in a deterministic pool.
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`ThreadPool::run_pending`]: struct.ThreadPool.html#method.run_pending
+    /// [`ThreadPool::join`]: struct.ThreadPool.html#method.join
+    /// [`JobHandle`]: struct.JobHandle.html
     ///
     /// # Examples
     ///
     /// ```
-    /// use threadpool::ThreadPool;
-    /// use std::time::Duration;
-    /// use std::thread::sleep;
-    ///
-    /// let pool = ThreadPool::new(4);
-    /// for _ in 0..10 {
-    ///     pool.execute(move || {
-    ///         sleep(Duration::from_secs(100));
-    ///     });
-    /// }
+    /// let pool = threadpool::Builder::new()
+    ///     .deterministic(true)
+    ///     .build();
     ///
-    /// sleep(Duration::from_secs(1)); // wait for threads to start
-    /// assert_eq!(4, pool.active_count());
+    /// pool.execute(|| println!("Hello from the calling thread!"));
+    /// assert_eq!(pool.queued_count(), 1);
+    /// pool.join();
+    /// assert_eq!(pool.queued_count(), 0);
     /// ```
-    pub fn active_count(&self) -> usize {
-        self.shared_data.active_count.load(Ordering::SeqCst)
+    pub fn deterministic(mut self, deterministic: bool) -> Builder {
+        self.deterministic = deterministic;
+        self
     }
 
-    /// Returns the maximum number of threads the pool will execute concurrently.
+    /// Set the order in which the built [`ThreadPool`] starts its queued jobs. If not specified,
+    /// jobs are started in the order they were submitted by each thread, see
+    /// [`Scheduling::Fifo`].
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`Scheduling::Fifo`]: enum.Scheduling.html#variant.Fifo
     ///
     /// # Examples
     ///
+    /// The job with the closest deadline is started first:
+    ///
     /// ```
-    /// use threadpool::ThreadPool;
+    /// use std::time::{Duration, Instant};
+    /// use threadpool::Scheduling;
     ///
-    /// let mut pool = ThreadPool::new(4);
-    /// assert_eq!(4, pool.max_count());
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(1)
+    ///     .scheduling(Scheduling::EarliestDeadlineFirst)
+    ///     .build();
     ///
-    /// pool.set_num_threads(8);
-    /// assert_eq!(8, pool.max_count());
+    /// let now = Instant::now();
+    /// pool.execute_with_deadline(now + Duration::from_secs(2), || println!("second"));
+    /// pool.execute_with_deadline(now + Duration::from_secs(1), || println!("first"));
+    /// pool.join();
     /// ```
-    pub fn max_count(&self) -> usize {
-        self.shared_data.max_thread_count.load(Ordering::Relaxed)
+    pub fn scheduling(mut self, scheduling: Scheduling) -> Builder {
+        self.scheduling = Some(scheduling);
+        self
     }
 
-    /// Returns the number of panicked threads over the lifetime of the pool.
+    /// Order the queued jobs of the built [`ThreadPool`] with a custom [`Scheduler`], created by
+    /// calling `make` once per built pool. This takes precedence over
+    /// [`scheduling`](#method.scheduling).
     ///
-    /// # Examples
+    /// Jobs submitted with [`ThreadPool::execute_with_affinity`] bypass the scheduler while they
+    /// wait for their preferred worker thread.
     ///
-    /// ```
-    /// use threadpool::ThreadPool;
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`Scheduler`]: trait.Scheduler.html
+    /// [`ThreadPool::execute_with_affinity`]: struct.ThreadPool.html#method.execute_with_affinity
     ///
-    /// let pool = ThreadPool::new(4);
-    /// for n in 0..10 {
-    ///     pool.execute(move || {
-    ///         // simulate a panic
-    ///         if n % 2 == 0 {
-    ///             panic!()
-    ///         }
-    ///     });
-    /// }
-    /// pool.join();
+    /// # Examples
     ///
-    /// assert_eq!(5, pool.panic_count());
-    /// ```
-    pub fn panic_count(&self) -> usize {
-        self.shared_data.panic_count.load(Ordering::Relaxed)
-    }
-
-    /// **Deprecated: Use [`ThreadPool::set_num_threads`](#method.set_num_threads)**
-    #[deprecated(since = "1.3.0", note = "use ThreadPool::set_num_threads")]
-    pub fn set_threads(&mut self, num_threads: usize) {
-        self.set_num_threads(num_threads)
+    /// See [`Scheduler`] for a scheduler starting the most recent job first.
+    pub fn scheduler<F, S>(mut self, make: F) -> Builder
+    where
+        F: Fn() -> S + Send + Sync + 'static,
+        S: Scheduler + 'static,
+    {
+        self.scheduler = Some(Arc::new(move || Box::new(make()) as Box<dyn Scheduler>));
+        self
     }
 
-    /// Sets the number of worker-threads to use as `num_threads`.
-    /// Can be used to change the threadpool size during runtime.
-    /// Will not abort already running or waiting threads.
+    /// With [`Scheduling::Priority`], raise the priority of queued jobs by one level for every
+    /// `interval` they have been waiting, so that low priority jobs are eventually started even
+    /// while high priority jobs keep coming in.
+    ///
+    /// [`Scheduling::Priority`]: enum.Scheduling.html#variant.Priority
     ///
     /// # Panics
     ///
-    /// This function will panic if `num_threads` is 0.
+    /// This method will panic if `interval` is zero.
     ///
     /// # Examples
     ///
+    /// Raise the priority of waiting jobs by one level every 200 milliseconds:
+    ///
     /// ```
-    /// use threadpool::ThreadPool;
     /// use std::time::Duration;
-    /// use std::thread::sleep;
+    /// use threadpool::Scheduling;
     ///
-    /// let mut pool = ThreadPool::new(4);
-    /// for _ in 0..10 {
-    ///     pool.execute(move || {
-    ///         sleep(Duration::from_secs(100));
-    ///     });
-    /// }
+    /// let pool = threadpool::Builder::new()
+    ///     .scheduling(Scheduling::Priority)
+    ///     .priority_aging(Duration::from_millis(200))
+    ///     .build();
     ///
-    /// sleep(Duration::from_secs(1)); // wait for threads to start
-    /// assert_eq!(4, pool.active_count());
-    /// assert_eq!(6, pool.queued_count());
+    /// pool.execute_with_priority(-1, || println!("background"));
+    /// pool.execute_with_priority(3, || println!("interactive"));
+    /// pool.join();
+    /// ```
+    pub fn priority_aging(mut self, interval: Duration) -> Builder {
+        assert!(interval > Duration::from_secs(0));
+        self.priority_aging = Some(interval);
+        self
+    }
+
+    /// Set a callback which is invoked whenever a job submitted with
+    /// [`ThreadPool::execute_with_deadline`] finishes after its deadline. The callback runs on the
+    /// worker thread and receives how late the job was. A panic of the callback is ignored.
     ///
-    /// // Increase thread capacity of the pool
-    /// pool.set_num_threads(8);
+    /// [`ThreadPool::execute_with_deadline`]: struct.ThreadPool.html#method.execute_with_deadline
     ///
-    /// sleep(Duration::from_secs(1)); // wait for new threads to start
-    /// assert_eq!(8, pool.active_count());
-    /// assert_eq!(2, pool.queued_count());
+    /// # Examples
     ///
-    /// // Decrease thread capacity of the pool
-    /// // No active threads are killed
-    /// pool.set_num_threads(4);
+    /// ```
+    /// use std::time::Instant;
     ///
-    /// assert_eq!(8, pool.active_count());
-    /// assert_eq!(2, pool.queued_count());
+    /// let pool = threadpool::Builder::new()
+    ///     .on_deadline_miss(|lateness| println!("a job was {:?} late", lateness))
+    ///     .build();
+    ///
+    /// pool.execute_with_deadline(Instant::now(), || println!("too late"));
+    /// pool.join();
     /// ```
-    pub fn set_num_threads(&mut self, num_threads: usize) {
-        assert!(num_threads >= 1);
-        let prev_num_threads = self
-            .shared_data
-            .max_thread_count
-            .swap(num_threads, Ordering::Release);
-        if let Some(num_spawn) = num_threads.checked_sub(prev_num_threads) {
-            // Spawn new threads
-            for _ in 0..num_spawn {
-                spawn_in_pool(self.shared_data.clone());
-            }
-        }
+    pub fn on_deadline_miss<F>(mut self, handler: F) -> Builder
+    where
+        F: Fn(Duration) + Send + Sync + 'static,
+    {
+        self.deadline_miss_handler = Some(Arc::new(handler));
+        self
     }
 
-    /// Block the current thread until all jobs in the pool have been executed.
+    /// Run every job of the built [`ThreadPool`] through `wrapper`, which receives the job and
+    /// is expected to call it once. This gives one place to add timing, logging or metrics to
+    /// all jobs instead of wrapping each closure where it is submitted.
     ///
-    /// Calling `join` on an empty pool will cause an immediate return.
-    /// `join` may be called from multiple threads concurrently.
-    /// A `join` is an atomic point in time. All threads joining before the join
-    /// event will exit together even if the pool is processing new jobs by the
-    /// time they get scheduled.
+    /// Wrappers run on the worker thread, the one added first is the outermost. A wrapper which
+    /// does not call the job skips it, calling the job more than once has no effect. Panics of a
+    /// wrapper are treated like panics of the job.
     ///
-    /// Calling `join` from a thread within the pool will cause a deadlock. This
-    /// behavior is considered safe.
+    /// [`ThreadPool`]: struct.ThreadPool.html
     ///
     /// # Examples
     ///
     /// ```
-    /// use threadpool::ThreadPool;
-    /// use std::sync::Arc;
-    /// use std::sync::atomic::{AtomicUsize, Ordering};
-    ///
-    /// let pool = ThreadPool::new(8);
-    /// let test_count = Arc::new(AtomicUsize::new(0));
+    /// use std::time::Instant;
     ///
-    /// for _ in 0..42 {
-    ///     let test_count = test_count.clone();
-    ///     pool.execute(move || {
-    ///         test_count.fetch_add(1, Ordering::Relaxed);
-    ///     });
-    /// }
+    /// let pool = threadpool::Builder::new()
+    ///     .wrap_jobs(|job| {
+    ///         let start = Instant::now();
+    ///         job();
+    ///         println!("job took {:?}", start.elapsed());
+    ///     })
+    ///     .build();
     ///
+    /// pool.execute(|| println!("hello"));
     /// pool.join();
-    /// assert_eq!(42, test_count.load(Ordering::Relaxed));
     /// ```
-    pub fn join(&self) {
-        // fast path requires no mutex
-        if self.shared_data.has_work() == false {
-            return ();
-        }
-
-        let generation = self.shared_data.join_generation.load(Ordering::SeqCst);
-        let mut lock = self.shared_data.empty_trigger.lock().unwrap();
-
-        while generation == self.shared_data.join_generation.load(Ordering::Relaxed)
-            && self.shared_data.has_work()
-        {
-            lock = self.shared_data.empty_condvar.wait(lock).unwrap();
-        }
-
-        // increase generation if we are the first thread to come out of the loop
-        self.shared_data.join_generation.compare_and_swap(
-            generation,
-            generation.wrapping_add(1),
-            Ordering::SeqCst,
-        );
+    pub fn wrap_jobs<F>(mut self, wrapper: F) -> Builder
+    where
+        F: Fn(&mut dyn FnMut()) + Send + Sync + 'static,
+    {
+        self.job_wrappers.push(Arc::new(wrapper));
+        self
     }
-}
 
-impl Clone for ThreadPool {
-    /// Cloning a pool will create a new handle to the pool.
-    /// The behavior is similar to [Arc](https://doc.rust-lang.org/stable/std/sync/struct.Arc.html).
+    /// Carry thread-local state, like a request ID or a logging context, from the thread
+    /// submitting a job to the worker thread running it.
     ///
-    /// We could for example submit jobs from multiple threads concurrently.
+    /// `capture` is called on the submitting thread when a job is queued and returns a closure
+    /// which receives the job on the worker thread. That closure is expected to install the
+    /// captured state, call the job once and restore the previous state. Contexts are installed
+    /// around the wrappers added with [`wrap_jobs`](#method.wrap_jobs), the one added first is
+    /// the outermost.
+    ///
+    /// The futures of [`ThreadPool::spawn_future`] are polled without the contexts.
+    ///
+    /// [`ThreadPool::spawn_future`]: struct.ThreadPool.html#method.spawn_future
+    ///
+    /// # Examples
     ///
     /// ```
-    /// use threadpool::ThreadPool;
-    /// use std::thread;
+    /// use std::cell::Cell;
     /// use std::sync::mpsc::channel;
     ///
-    /// let pool = ThreadPool::with_name("clone example".into(), 2);
+    /// thread_local!(static REQUEST_ID: Cell<u64> = Cell::new(0));
     ///
-    /// let results = (0..2)
-    ///     .map(|i| {
-    ///         let pool = pool.clone();
-    ///         thread::spawn(move || {
-    ///             let (tx, rx) = channel();
-    ///             for i in 1..12 {
-    ///                 let tx = tx.clone();
-    ///                 pool.execute(move || {
-    ///                     tx.send(i).expect("channel will be waiting");
-    ///                 });
-    ///             }
-    ///             drop(tx);
-    ///             if i == 0 {
-    ///                 rx.iter().fold(0, |accumulator, element| accumulator + element)
-    ///             } else {
-    ///                 rx.iter().fold(1, |accumulator, element| accumulator * element)
-    ///             }
-    ///         })
+    /// let pool = threadpool::Builder::new()
+    ///     .propagate_context(|| {
+    ///         let id = REQUEST_ID.with(Cell::get);
+    ///         move |job: &mut dyn FnMut()| {
+    ///             let previous = REQUEST_ID.with(|current| current.replace(id));
+    ///             job();
+    ///             REQUEST_ID.with(|current| current.set(previous));
+    ///         }
     ///     })
-    ///     .map(|join_handle| join_handle.join().expect("collect results from threads"))
-    ///     .collect::<Vec<usize>>();
+    ///     .build();
     ///
-    /// assert_eq!(vec![66, 39916800], results);
+    /// REQUEST_ID.with(|id| id.set(42));
+    /// let (tx, rx) = channel();
+    /// pool.execute(move || tx.send(REQUEST_ID.with(Cell::get)).unwrap());
+    /// assert_eq!(rx.recv(), Ok(42));
     /// ```
-    fn clone(&self) -> ThreadPool {
-        ThreadPool {
-            jobs: self.jobs.clone(),
-            shared_data: self.shared_data.clone(),
-        }
-    }
-}
-
-/// Create a thread pool with one thread per CPU.
-/// On machines with hyperthreading,
-/// this will create one thread per hyperthread.
-impl Default for ThreadPool {
-    fn default() -> Self {
-        ThreadPool::new(num_cpus::get())
-    }
-}
-
-impl fmt::Debug for ThreadPool {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("ThreadPool")
-            .field("name", &self.shared_data.name)
-            .field("queued_count", &self.queued_count())
-            .field("active_count", &self.active_count())
-            .field("max_count", &self.max_count())
-            .finish()
+    pub fn propagate_context<F, R>(mut self, capture: F) -> Builder
+    where
+        F: Fn() -> R + Send + Sync + 'static,
+        R: FnOnce(&mut dyn FnMut()) + Send + 'static,
+    {
+        self.context_captures
+            .push(Arc::new(move || Box::new(capture()) as RestoreContext));
+        self
     }
-}
 
-impl PartialEq for ThreadPool {
-    /// Check if you are working with the same pool
+    /// Limit the approximate memory held by queued jobs to `bytes`, as estimated by the sizes
+    /// passed to [`ThreadPool::execute_sized`]. Jobs submitted without a size are not limited.
     ///
-    /// ```
-    /// use threadpool::ThreadPool;
+    /// A single job larger than the limit is still accepted once no other sized jobs are queued.
     ///
-    /// let a = ThreadPool::new(2);
-    /// let b = ThreadPool::new(2);
+    /// [`ThreadPool::execute_sized`]: struct.ThreadPool.html#method.execute_sized
     ///
-    /// assert_eq!(a, a);
-    /// assert_eq!(b, b);
+    /// # Examples
+    ///
+    /// Keep at most 64 MiB of buffers waiting in the queue:
     ///
-    /// # // TODO: change this to assert_ne in the future
-    /// assert!(a != b);
-    /// assert!(b != a);
     /// ```
-    fn eq(&self, other: &ThreadPool) -> bool {
-        let a: &ThreadPoolSharedData = &*self.shared_data;
-        let b: &ThreadPoolSharedData = &*other.shared_data;
-        a as *const ThreadPoolSharedData == b as *const ThreadPoolSharedData
-        // with rust 1.17 and late:
-        // Arc::ptr_eq(&self.shared_data, &other.shared_data)
+    /// let pool = threadpool::Builder::new()
+    ///     .queue_byte_limit(64 << 20)
+    ///     .build();
+    ///
+    /// let buffer = vec![0u8; 1 << 20];
+    /// pool.execute_sized(buffer.len(), move || println!("{} bytes", buffer.len()));
+    /// pool.join();
+    /// ```
+    pub fn queue_byte_limit(mut self, bytes: usize) -> Builder {
+        self.queue_byte_limit = Some(bytes);
+        self
+    }
+
+    /// Set what happens when a job of the built [`ThreadPool`] panics. Defaults to
+    /// [`PanicPolicy::Continue`].
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`PanicPolicy::Continue`]: enum.PanicPolicy.html#variant.Continue
+    ///
+    /// # Examples
+    ///
+    /// Abort the process instead of running more jobs on possibly corrupted state:
+    ///
+    /// ```
+    /// use threadpool::PanicPolicy;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .panic_policy(PanicPolicy::Abort)
+    ///     .build();
+    /// ```
+    pub fn panic_policy(mut self, policy: PanicPolicy) -> Builder {
+        self.panic_policy = policy;
+        self
+    }
+
+    /// Quarantine a tag once its jobs have panicked `panics` times within `window`: further jobs
+    /// submitted with the tag are rejected until the quarantine is lifted with
+    /// [`ThreadPool::release_quarantine`]. If not specified, tags are never quarantined.
+    ///
+    /// This keeps one buggy kind of job from chewing through the pool. A rejected job is handed
+    /// to the callback set with [`on_quarantined_job`](#method.on_quarantined_job), or dropped
+    /// without running if there is none, a [`JobHandle`] of it then reports it as cancelled.
+    /// [`ThreadPool::try_execute_tagged`] hands a rejected job back instead, and
+    /// [`ThreadPool::rejected_count`] counts them all. Jobs which were queued before the tag was
+    /// quarantined still run.
+    ///
+    /// [`ThreadPool::release_quarantine`]: struct.ThreadPool.html#method.release_quarantine
+    /// [`JobHandle`]: struct.JobHandle.html
+    /// [`ThreadPool::try_execute_tagged`]: struct.ThreadPool.html#method.try_execute_tagged
+    /// [`ThreadPool::rejected_count`]: struct.ThreadPool.html#method.rejected_count
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if `panics` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::mpsc::channel;
+    /// use std::time::Duration;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .quarantine_tags(3, Duration::from_secs(60))
+    ///     .build();
+    ///
+    /// for _ in 0..3 {
+    ///     pool.execute_tagged("flaky", || panic!("Ignore this panic, it must!"));
+    ///     pool.join();
+    /// }
+    /// assert_eq!(pool.quarantined_tags(), ["flaky"]);
+    ///
+    /// let (tx, rx) = channel();
+    /// pool.execute_tagged("flaky", move || tx.send("ran").unwrap());
+    /// pool.join();
+    /// assert!(rx.recv().is_err());
+    /// ```
+    pub fn quarantine_tags(mut self, panics: usize, window: Duration) -> Builder {
+        assert!(
+            panics > 0,
+            "a tag needs to panic at least once to be quarantined"
+        );
+        self.quarantine = Some((panics, window));
+        self
+    }
+
+    /// Set a callback which receives the jobs rejected because their tag is quarantined, see
+    /// [`quarantine_tags`](#method.quarantine_tags), along with the tag. If not specified,
+    /// rejected jobs are dropped.
+    ///
+    /// The callback runs on the thread submitting the job, which may e.g. store the job in a
+    /// dead-letter queue to run it once the tag has been released. Running the job completes
+    /// its [`JobHandle`], if it has one.
+    ///
+    /// [`JobHandle`]: struct.JobHandle.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use std::time::Duration;
+    ///
+    /// let dead_letters = Arc::new(Mutex::new(Vec::new()));
+    /// let pool = {
+    ///     let dead_letters = dead_letters.clone();
+    ///     threadpool::Builder::new()
+    ///         .quarantine_tags(1, Duration::from_secs(60))
+    ///         .on_quarantined_job(move |tag, job| {
+    ///             dead_letters.lock().unwrap().push((tag.to_string(), job))
+    ///         })
+    ///         .build()
+    /// };
+    ///
+    /// pool.execute_tagged("import", || panic!("Ignore this panic, it must!"));
+    /// pool.join();
+    /// pool.execute_tagged("import", || println!("importing"));
+    ///
+    /// // Retry the rejected jobs once the bug is fixed.
+    /// pool.release_quarantine("import");
+    /// for (tag, job) in dead_letters.lock().unwrap().drain(..) {
+    ///     pool.execute_tagged(tag, job);
+    /// }
+    /// ```
+    pub fn on_quarantined_job<F>(mut self, handler: F) -> Builder
+    where
+        F: Fn(&str, BoxedJob) + Send + Sync + 'static,
+    {
+        self.dead_letter_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Set how the idle worker threads of the built [`ThreadPool`] wait for the next job.
+    /// Defaults to [`WaitStrategy::park`].
+    ///
+    /// Spinning lowers the latency between submitting a job and a worker thread starting it, but
+    /// keeps a CPU busy per idle worker thread until it parks.
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`WaitStrategy::park`]: struct.WaitStrategy.html#method.park
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::WaitStrategy;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .wait_strategy(WaitStrategy::park().spin(10_000).yield_now(100))
+    ///     .build();
+    /// ```
+    pub fn wait_strategy(mut self, strategy: WaitStrategy) -> Builder {
+        self.wait_strategy = strategy;
+        self
+    }
+
+    /// Let each worker thread take up to `jobs` jobs from the queue at once and run them back to
+    /// back. If not specified, a worker thread takes one job at a time.
+    ///
+    /// For very short jobs this amortizes the synchronization of taking a job over the whole
+    /// batch. In return the jobs of a batch no longer go to the next idle worker thread, a later
+    /// job with a higher priority does not overtake them, and a worker thread finishes its batch
+    /// before it exits when the pool shrinks. The jobs held by a worker thread are still counted
+    /// by [`ThreadPool::queued_count`], but discarded without being counted by
+    /// [`ThreadPool::shutdown_now`].
+    ///
+    /// [`ThreadPool::queued_count`]: struct.ThreadPool.html#method.queued_count
+    /// [`ThreadPool::shutdown_now`]: struct.ThreadPool.html#method.shutdown_now
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if `jobs` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let pool = threadpool::Builder::new().dequeue_batch(64).build();
+    /// let sum = Arc::new(AtomicUsize::new(0));
+    /// for i in 0..10_000 {
+    ///     let sum = sum.clone();
+    ///     pool.execute(move || {
+    ///         sum.fetch_add(i, Ordering::Relaxed);
+    ///     });
+    /// }
+    /// pool.join();
+    /// assert_eq!(sum.load(Ordering::Relaxed), 49_995_000);
+    /// ```
+    pub fn dequeue_batch(mut self, jobs: usize) -> Builder {
+        assert!(
+            jobs > 0,
+            "a worker thread needs to take at least one job at once"
+        );
+        self.dequeue_batch = Some(jobs);
+        self
+    }
+
+    /// Let a dedicated dispatcher thread take the jobs from the queue and assign each to an idle
+    /// worker thread, instead of the worker threads taking them from the shared queue themselves.
+    /// If not specified, the worker threads share the queue.
+    ///
+    /// The dispatcher takes a job only once a worker thread is idle, in the order decided by the
+    /// [`scheduling`](#method.scheduling) of the pool, and prefers the worker thread the job has
+    /// an affinity with, see [`ThreadPool::execute_with_affinity`]. Centralizing these decisions
+    /// keeps the worker threads from contending on the queue, at the cost of an extra thread
+    /// and a hand-over per job. Idle worker threads wait for their next job without the
+    /// [`wait_strategy`](#method.wait_strategy) of the pool and take one job at a time, whatever
+    /// [`dequeue_batch`](#method.dequeue_batch) says. [Deterministic] pools have no dispatcher.
+    ///
+    /// [`ThreadPool::execute_with_affinity`]: struct.ThreadPool.html#method.execute_with_affinity
+    /// [Deterministic]: #method.deterministic
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::mpsc::channel;
+    /// use threadpool::Scheduling;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(4)
+    ///     .scheduling(Scheduling::Fair)
+    ///     .dispatcher_thread(true)
+    ///     .build();
+    ///
+    /// let (tx, rx) = channel();
+    /// for i in 0..8 {
+    ///     let tx = tx.clone();
+    ///     pool.execute(move || tx.send(i).unwrap());
+    /// }
+    /// drop(tx);
+    /// assert_eq!(rx.iter().sum::<i32>(), 28);
+    /// ```
+    pub fn dispatcher_thread(mut self, dispatcher: bool) -> Builder {
+        self.dispatcher_thread = dispatcher;
+        self
+    }
+
+    /// Replace a worker thread with a new one once it has executed `jobs` jobs. If not
+    /// specified, worker threads run jobs for as long as the pool needs them.
+    ///
+    /// Recycling bounds what third-party code running in the jobs can leave behind on a long
+    /// lived thread, such as leaked thread-local state or a fragmented thread cache of the
+    /// allocator. A worker thread is only replaced between jobs, and is not counted by
+    /// [`ThreadPool::respawn_count`].
+    ///
+    /// [`ThreadPool::respawn_count`]: struct.ThreadPool.html#method.respawn_count
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if `jobs` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    /// use std::sync::mpsc::channel;
+    /// use std::thread;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(1)
+    ///     .max_jobs_per_thread(2)
+    ///     .build();
+    ///
+    /// let (tx, rx) = channel();
+    /// for _ in 0..6 {
+    ///     let tx = tx.clone();
+    ///     pool.execute(move || tx.send(thread::current().id()).unwrap());
+    /// }
+    /// drop(tx);
+    /// assert_eq!(rx.iter().collect::<HashSet<_>>().len(), 3);
+    /// ```
+    pub fn max_jobs_per_thread(mut self, jobs: usize) -> Builder {
+        assert!(
+            jobs > 0,
+            "a worker thread needs to execute at least one job"
+        );
+        self.max_jobs_per_thread = Some(jobs);
+        self
+    }
+
+    /// Replace a worker thread with a new one once it has run for `lifetime`. If not specified,
+    /// worker threads run for as long as the pool needs them.
+    ///
+    /// The limit only applies between jobs: a job is never interrupted, so a worker thread
+    /// running a long job outlives its lifetime until the job has finished. An idle worker
+    /// thread is replaced as soon as its lifetime has passed, so an idle pool keeps replacing
+    /// its threads at that pace. See [`max_jobs_per_thread`](#method.max_jobs_per_thread) for
+    /// why to recycle worker threads.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .max_thread_lifetime(Duration::from_secs(3600))
+    ///     .build();
+    /// pool.execute(|| println!("hello"));
+    /// pool.join();
+    /// ```
+    pub fn max_thread_lifetime(mut self, lifetime: Duration) -> Builder {
+        self.max_thread_lifetime = Some(lifetime);
+        self
+    }
+
+    /// Aggregate the number of jobs and the time they spent executing by tag, for
+    /// [`ThreadPool::profile_report`]. If not specified, jobs are not profiled.
+    ///
+    /// Profiling costs two clock readings and a lock per job.
+    ///
+    /// [`ThreadPool::profile_report`]: struct.ThreadPool.html#method.profile_report
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let pool = threadpool::Builder::new().profile_jobs(true).build();
+    ///
+    /// pool.execute_tagged("thumbnail", || println!("resizing"));
+    /// pool.join();
+    /// print!("{}", pool.profile_report().unwrap());
+    /// ```
+    pub fn profile_jobs(mut self, profile: bool) -> Builder {
+        self.profile_jobs = profile;
+        self
+    }
+
+    /// Run the submitted jobs on the submitting thread while the built [`ThreadPool`] has no
+    /// worker thread, because the operating system refuses to spawn them. If not specified, the
+    /// jobs stay queued until a worker thread can be spawned, see
+    /// [`ThreadPool::spawn_error`].
+    ///
+    /// This keeps a program limping along under resource exhaustion instead of stalling. Jobs
+    /// which wait for other jobs of the pool deadlock when they run on the submitting thread,
+    /// and jobs submitted by a job running inline are run once it has returned.
+    ///
+    /// On targets which can not spawn threads at all, i.e. WebAssembly without the `atomics`
+    /// target feature, this is always enabled, so that crates can use a pool whatever their
+    /// target: the pool spawns its threads lazily, and every job runs on the thread which
+    /// submits it. [`Builder::auto_resize`] has no effect there.
+    ///
+    /// [`Builder::auto_resize`]: #method.auto_resize
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`ThreadPool::spawn_error`]: struct.ThreadPool.html#method.spawn_error
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(4)
+    ///     .inline_fallback(true)
+    ///     .build();
+    /// ```
+    pub fn inline_fallback(mut self, inline: bool) -> Builder {
+        self.inline_fallback = inline;
+        self
+    }
+
+    /// Check every `interval` whether the number of CPUs available to the process has changed,
+    /// and resize the built [`ThreadPool`] to it like [`ThreadPool::resize_auto`] does. If not
+    /// specified, the pool keeps its size.
+    ///
+    /// The available CPUs change e.g. when CPUs of a virtual machine are hot-plugged, when the
+    /// CPU quota of a container is updated or when the affinity of the process is changed with
+    /// `taskset`. The check runs on a background thread which exits once the pool has shut down.
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`ThreadPool::resize_auto`]: struct.ThreadPool.html#method.resize_auto
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .auto_resize(Duration::from_secs(30))
+    ///     .build();
+    /// ```
+    pub fn auto_resize(mut self, interval: Duration) -> Builder {
+        self.auto_resize = Some(interval);
+        self
+    }
+
+    /// Finalize the [`Builder`] and build the [`ThreadPool`].
+    ///
+    /// [`Builder`]: struct.Builder.html
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if a worker thread can not be spawned, see
+    /// [`try_build`](#method.try_build) for a non-panicking variant.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(8)
+    ///     .thread_stack_size(4_000_000)
+    ///     .build();
+    /// ```
+    pub fn build(self) -> ThreadPool {
+        self.try_build()
+            .expect("Builder::build unable to spawn worker threads")
+    }
+
+    /// Finalize the [`Builder`] and build the [`ThreadPool`], returning an error instead of
+    /// panicking if the operating system can not spawn the worker threads.
+    ///
+    /// Worker threads spawned later on, e.g. with [`lazy_spawn`](#method.lazy_spawn) or when the
+    /// pool grows, do not panic if spawning fails. Spawning them is retried with increasing delays,
+    /// and the pool reports the failure through [`ThreadPool::spawn_error`] meanwhile.
+    ///
+    /// [`Builder`]: struct.Builder.html
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`ThreadPool::spawn_error`]: struct.ThreadPool.html#method.spawn_error
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PoolError::Spawn`] with the error of the operating system. The threads which
+    /// were spawned already exit again.
+    ///
+    /// [`PoolError::Spawn`]: enum.PoolError.html#variant.Spawn
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let pool = match threadpool::Builder::new().num_threads(8).try_build() {
+    ///     Ok(pool) => pool,
+    ///     Err(error) => panic!("no pool for us: {}", error),
+    /// };
+    /// ```
+    pub fn try_build(self) -> Result<ThreadPool, PoolError> {
+        let num_threads = self.num_threads.unwrap_or_else(available_parallelism);
+
+        let shared_data = Arc::new(ThreadPoolSharedData {
+            name: Mutex::new(self.thread_name),
+            job_queue: scheduling::job_queue(match self.scheduler {
+                Some(ref make) => Box::new(Custom(make())),
+                None => self
+                    .scheduling
+                    .unwrap_or(Scheduling::Fifo)
+                    .discipline(num_threads, self.priority_aging),
+            })
+            .with_wait_strategy(self.wait_strategy),
+            handle_count: AtomicUsize::new(1),
+            next_submitter: AtomicUsize::new(1),
+            cancellation: CancellationToken::new(),
+            empty_condvar: Condvar::new(),
+            empty_trigger: Mutex::new(()),
+            join_generation: AtomicUsize::new(0),
+            queued_count: AtomicUsize::new(0),
+            active_count: AtomicUsize::new(0),
+            max_thread_count: AtomicUsize::new(num_threads),
+            blocking_count: AtomicUsize::new(0),
+            workers: Mutex::new(Vec::new()),
+            queue_waits: QueueWaits::new(),
+            profiler: if self.profile_jobs {
+                Some(Profiler::new())
+            } else {
+                None
+            },
+            retired_busy_nanos: AtomicU64::new(0),
+            created_at: Instant::now(),
+            utilization_sample: Mutex::new((0, 0)),
+            interval_start: Mutex::new(IntervalStart::default()),
+            thread_count: AtomicUsize::new(0),
+            // A deterministic pool never spawns, its threads are only ever spawned on demand. Nor
+            // do pools on targets without threads, whose jobs run inline once spawning failed.
+            lazy_spawn: self.lazy_spawn || self.deterministic || !THREADS_SUPPORTED,
+            deterministic: self.deterministic,
+            panic_count: AtomicUsize::new(0),
+            completed_count: AtomicUsize::new(0),
+            stack_size: self.thread_stack_size,
+            thread_factory: self
+                .thread_factory
+                .unwrap_or_else(|| Arc::new(DefaultThreadFactory)),
+            priority: self.thread_priority,
+            qos: self.thread_qos,
+            numa: self.numa_placement.and_then(Numa::new),
+            deadline_miss_handler: self.deadline_miss_handler,
+            job_wrappers: self.job_wrappers,
+            context_captures: self.context_captures,
+            idle_callbacks: Mutex::new(Vec::new()),
+            shutdown_hooks: Mutex::new(Vec::new()),
+            job_statuses: Arc::new(StatusTable::new()),
+            pending_jobs: Arc::new(PendingJobs::new()),
+            gang_submission: Mutex::new(()),
+            dedup_keys: Arc::new(Mutex::new(HashSet::new())),
+            result_cache: ResultCache::new(),
+            last_panic: Mutex::new(None),
+            respawn_count: AtomicUsize::new(0),
+            respawn: Mutex::new(Respawn {
+                last: None,
+                delay: Duration::from_secs(0),
+            }),
+            spawn_failed: AtomicBool::new(false),
+            spawn_error: Mutex::new(None),
+            inline_fallback: self.inline_fallback || !THREADS_SUPPORTED,
+            queued_bytes: Mutex::new(0),
+            queued_bytes_condvar: Condvar::new(),
+            queue_byte_limit: self.queue_byte_limit,
+            panic_policy: self.panic_policy,
+            quarantine: self
+                .quarantine
+                .map(|(panics, window)| Quarantine::new(panics, window)),
+            dead_letter_handler: self.dead_letter_handler,
+            dequeue_batch: self.dequeue_batch.unwrap_or(1),
+            dispatcher: if self.dispatcher_thread && !self.deterministic && THREADS_SUPPORTED {
+                Some(Dispatcher::new())
+            } else {
+                None
+            },
+            max_jobs_per_thread: self.max_jobs_per_thread,
+            max_thread_lifetime: self.max_thread_lifetime,
+        });
+
+        let pool = ThreadPool {
+            shared_data,
+            submitter: 0,
+            limiter: None,
+            overflow: None,
+        };
+
+        // Started first, so that it can let the worker threads exit if spawning them fails.
+        if pool.shared_data.dispatcher.is_some() {
+            spawn_dispatcher(pool.shared_data.clone()).map_err(PoolError::Spawn)?;
+        }
+
+        // Threadpool threads
+        if !pool.shared_data.lazy_spawn {
+            pool.shared_data
+                .thread_count
+                .fetch_add(num_threads, Ordering::SeqCst);
+            for spawned in 0..num_threads {
+                if let Err(error) = try_spawn_in_pool(pool.shared_data.clone()) {
+                    pool.shared_data
+                        .thread_count
+                        .fetch_sub(num_threads - spawned, Ordering::SeqCst);
+                    // Dropping the only handle closes the queue and lets the spawned threads exit.
+                    return Err(PoolError::Spawn(error));
+                }
+            }
+        }
+        if let Some(interval) = self.auto_resize.filter(|_| THREADS_SUPPORTED) {
+            spawn_auto_resize(&pool.shared_data, interval).map_err(PoolError::Spawn)?;
+        }
+
+        Ok(pool)
     }
 }
-impl Eq for ThreadPool {}
 
-fn spawn_in_pool(shared_data: Arc<ThreadPoolSharedData>) {
-    let mut builder = thread::Builder::new();
-    if let Some(ref name) = shared_data.name {
-        builder = builder.name(name.clone());
+struct ThreadPoolSharedData {
+    // Name of the threads spawned from now on, see `ThreadPool::set_name`.
+    name: Mutex<Option<String>>,
+    job_queue: JobQueue<Job>,
+    handle_count: AtomicUsize,
+    next_submitter: AtomicUsize,
+    cancellation: CancellationToken,
+    empty_trigger: Mutex<()>,
+    empty_condvar: Condvar,
+    join_generation: AtomicUsize,
+    queued_count: AtomicUsize,
+    active_count: AtomicUsize,
+    max_thread_count: AtomicUsize,
+    // Jobs in a `BlockingSection`, the pool runs as many extra worker threads.
+    blocking_count: AtomicUsize,
+    workers: Mutex<Vec<Arc<Worker>>>,
+    queue_waits: QueueWaits,
+    profiler: Option<Profiler>,
+    // Busy time of the workers which have exited, and of the live ones before their statistics
+    // were reset.
+    retired_busy_nanos: AtomicU64,
+    created_at: Instant,
+    // Time since creation and busy time at the last call to `ThreadPool::utilization`.
+    utilization_sample: Mutex<(u64, u64)>,
+    interval_start: Mutex<IntervalStart>,
+    // Number of worker threads which are alive.
+    thread_count: AtomicUsize,
+    lazy_spawn: bool,
+    deterministic: bool,
+    panic_count: AtomicUsize,
+    // Jobs which have finished, including the ones which panicked.
+    completed_count: AtomicUsize,
+    stack_size: Option<usize>,
+    thread_factory: Arc<dyn ThreadFactory>,
+    priority: Option<ThreadPriority>,
+    qos: Option<QosClass>,
+    numa: Option<Numa>,
+    deadline_miss_handler: Option<DeadlineMissHandler>,
+    job_wrappers: Vec<JobWrapper>,
+    context_captures: Vec<CaptureContext>,
+    idle_callbacks: Mutex<Vec<IdleCallback>>,
+    shutdown_hooks: Mutex<Vec<ShutdownHook>>,
+    job_statuses: Arc<StatusTable>,
+    pending_jobs: Arc<PendingJobs>,
+    // Held while the members of a gang are queued, so that they are not interleaved with others.
+    gang_submission: Mutex<()>,
+    // Keys of the jobs submitted with `execute_dedup` which are queued or running.
+    dedup_keys: Arc<Mutex<HashSet<Arc<str>>>>,
+    // Results of the jobs submitted with `execute_cached`.
+    result_cache: ResultCache,
+    last_panic: Mutex<Option<JobPanic>>,
+    respawn_count: AtomicUsize,
+    respawn: Mutex<Respawn>,
+    // Set while the pool is short of worker threads because spawning one failed for good.
+    spawn_failed: AtomicBool,
+    spawn_error: Mutex<Option<io::Error>>,
+    inline_fallback: bool,
+    // Sum of the sizes of the queued jobs.
+    queued_bytes: Mutex<usize>,
+    queued_bytes_condvar: Condvar,
+    queue_byte_limit: Option<usize>,
+    panic_policy: PanicPolicy,
+    quarantine: Option<Quarantine>,
+    // Receives the jobs rejected by `quarantine`, see `Builder::on_quarantined_job`.
+    dead_letter_handler: Option<DeadLetterHandler>,
+    // The most jobs a worker thread takes from the queue at once.
+    dequeue_batch: usize,
+    // Assigns the jobs to the worker threads, see `Builder::dispatcher_thread`.
+    dispatcher: Option<Dispatcher>,
+    // When a worker thread is replaced, see `Builder::max_jobs_per_thread`.
+    max_jobs_per_thread: Option<usize>,
+    max_thread_lifetime: Option<Duration>,
+}
+
+impl ThreadPoolSharedData {
+    fn name(&self) -> Option<String> {
+        self.name.lock().expect("Unable to lock name").clone()
+    }
+
+    /// The number of worker threads the pool runs, including the ones replacing blocked jobs.
+    fn thread_limit(&self) -> usize {
+        self.max_thread_count.load(Ordering::SeqCst) + self.blocking_count.load(Ordering::SeqCst)
+    }
+
+    /// Count the calling worker thread out if the pool runs more threads than it should.
+    fn retire_surplus_thread(&self) -> bool {
+        let mut thread_count = self.thread_count.load(Ordering::SeqCst);
+        while thread_count > self.thread_limit() {
+            match self.thread_count.compare_exchange(
+                thread_count,
+                thread_count - 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return true,
+                Err(count) => thread_count = count,
+            }
+        }
+        false
+    }
+
+    /// Whether a worker thread which has executed `executed` jobs since `started` should be
+    /// replaced.
+    fn is_recycling_due(&self, executed: usize, started: Instant) -> bool {
+        self.max_jobs_per_thread
+            .is_some_and(|jobs| executed >= jobs)
+            || self
+                .max_thread_lifetime
+                .is_some_and(|lifetime| started.elapsed() >= lifetime)
+    }
+
+    fn nanos_since_creation(&self) -> u64 {
+        let elapsed = self.created_at.elapsed();
+        elapsed.as_secs() * 1_000_000_000 + u64::from(elapsed.subsec_nanos())
+    }
+
+    fn register_worker(&self) -> Arc<Worker> {
+        let mut workers = self.workers.lock().expect("Unable to lock workers");
+        let index = (0..)
+            .find(|&index| workers.iter().all(|w| w.index != index))
+            .unwrap();
+        let worker = Arc::new(Worker {
+            index,
+            busy_since: AtomicU64::new(0),
+            busy_nanos: AtomicU64::new(0),
+            jobs_executed: AtomicU64::new(0),
+            panics: AtomicU64::new(0),
+            last_tag: Mutex::new(None),
+            running: Mutex::new(None),
+        });
+        workers.push(worker.clone());
+        worker
+    }
+
+    fn retire_worker(&self, worker: &Arc<Worker>) {
+        let mut workers = self.workers.lock().expect("Unable to lock workers");
+        workers.retain(|w| !Arc::ptr_eq(w, worker));
+        // A worker which panicked is retired in the middle of its job.
+        worker.finish_job(self.nanos_since_creation());
+        self.retired_busy_nanos
+            .fetch_add(worker.busy_nanos.load(Ordering::SeqCst), Ordering::SeqCst);
+    }
+
+    /// Total time all workers spent executing jobs, in nanoseconds.
+    fn busy_nanos(&self) -> u64 {
+        let workers = self.workers.lock().expect("Unable to lock workers");
+        let now = self.nanos_since_creation();
+        workers
+            .iter()
+            .fold(self.retired_busy_nanos.load(Ordering::SeqCst), |busy, w| {
+                busy + w.busy_nanos(now)
+            })
+    }
+
+    /// Wait until a job of `bytes` bytes fits into the queue byte limit, or give up at
+    /// `deadline`. Returns the locked queued bytes if it fits.
+    fn wait_for_bytes(
+        &self,
+        bytes: usize,
+        deadline: Option<Instant>,
+    ) -> Option<MutexGuard<'_, usize>> {
+        let mut queued = self
+            .queued_bytes
+            .lock()
+            .expect("Unable to lock queued bytes");
+        if let Some(limit) = self.queue_byte_limit {
+            // Always admit a job into an empty queue, even if it is too large on its own.
+            while *queued > 0 && queued.saturating_add(bytes) > limit {
+                queued = match deadline {
+                    None => self
+                        .queued_bytes_condvar
+                        .wait(queued)
+                        .expect("Unable to wait for queued bytes"),
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            return None;
+                        }
+                        self.queued_bytes_condvar
+                            .wait_timeout(queued, deadline - now)
+                            .expect("Unable to wait for queued bytes")
+                            .0
+                    }
+                };
+            }
+        }
+        Some(queued)
+    }
+
+    /// Account for a job of `bytes` bytes entering the queue. If that exceeds the byte limit,
+    /// wait for other jobs to leave the queue when `block` is set, or return `false` otherwise.
+    fn reserve_bytes(&self, bytes: usize, block: bool) -> bool {
+        let deadline = if block { None } else { Some(Instant::now()) };
+        match self.wait_for_bytes(bytes, deadline) {
+            Some(mut queued) => {
+                *queued += bytes;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Account for a job of `bytes` bytes leaving the queue.
+    fn release_bytes(&self, bytes: usize) {
+        if bytes == 0 {
+            return;
+        }
+        let mut queued = self
+            .queued_bytes
+            .lock()
+            .expect("Unable to lock queued bytes");
+        *queued -= bytes;
+        self.queued_bytes_condvar.notify_all();
+    }
+
+    /// Returns how long to wait before replacing a worker thread which died, so that threads
+    /// dying in a tight loop do not hammer the operating system with spawns.
+    fn respawn_delay(&self) -> Duration {
+        let mut respawn = self.respawn.lock().expect("Unable to lock respawn state");
+        let now = Instant::now();
+        respawn.delay = match respawn.last {
+            Some(last) if now < last + RESPAWN_STORM_WINDOW => cmp::min(
+                cmp::max(respawn.delay * 2, FIRST_RESPAWN_DELAY),
+                MAX_RESPAWN_DELAY,
+            ),
+            _ => Duration::from_secs(0),
+        };
+        respawn.last = Some(now + respawn.delay);
+        respawn.delay
+    }
+
+    fn has_work(&self) -> bool {
+        self.queued_count.load(Ordering::SeqCst) > 0 || self.active_count.load(Ordering::SeqCst) > 0
+    }
+
+    /// Run the shutdown hooks if the pool has terminated, that is its queue is closed and the last
+    /// worker has exited.
+    fn check_terminated(&self) {
+        if !self.job_queue.is_closed() || self.thread_count.load(Ordering::SeqCst) > 0 {
+            return;
+        }
+        // Both the last worker and the thread closing the queue may get here, only one of them
+        // finds the hooks.
+        let hooks = mem::take(
+            &mut *self
+                .shutdown_hooks
+                .lock()
+                .expect("Unable to lock shutdown hooks"),
+        );
+        for hook in hooks {
+            hook();
+        }
+    }
+
+    /// Capture the contexts of the current thread, see `Builder::propagate_context`.
+    fn capture_contexts(&self) -> Vec<RestoreContext> {
+        self.context_captures
+            .iter()
+            .map(|capture| capture())
+            .collect()
+    }
+
+    /// Account for a job which panicked with `payload`, or abort if that is the panic policy.
+    fn record_panic(&self, payload: &(dyn Any + Send), tag: Option<Arc<str>>) {
+        if let (Some(quarantine), Some(tag)) = (self.quarantine.as_ref(), tag.as_ref()) {
+            quarantine.record(tag);
+        }
+        let job_panic = JobPanic::new(payload, tag);
+        if self.panic_policy == PanicPolicy::Abort {
+            eprintln!("{}, aborting", job_panic);
+            process::abort();
+        }
+        *self.last_panic.lock().expect("Unable to lock last panic") = Some(job_panic);
+        self.panic_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Mark a job as no longer active, notifying the idle callbacks if it was the last one.
+    fn finish_active_job(&self) {
+        // Only the worker finishing the last active job sees the count drop to zero, so the
+        // callbacks fire once per transition to idle.
+        if self.active_count.fetch_sub(1, Ordering::SeqCst) == 1 && !self.has_work() {
+            let callbacks = self
+                .idle_callbacks
+                .lock()
+                .expect("Unable to lock idle callbacks")
+                .clone();
+            for callback in callbacks {
+                // A panicking callback must not take the worker down, it may be unwinding already.
+                let _ = panic::catch_unwind(panic::AssertUnwindSafe(|| callback()));
+            }
+        }
+        self.no_work_notify_all();
+    }
+
+    /// Close the queue of the pool, waking the dispatcher so that it exits even if no worker
+    /// thread ever waits for it.
+    fn close(&self) {
+        self.job_queue.close();
+        if let Some(ref dispatcher) = self.dispatcher {
+            dispatcher.wake();
+        }
+    }
+
+    /// Notify all observers joining this pool if there is no more work to do.
+    fn no_work_notify_all(&self) {
+        if !self.has_work() {
+            *self
+                .empty_trigger
+                .lock()
+                .expect("Unable to notify all joining threads");
+            self.empty_condvar.notify_all();
+        }
+    }
+}
+
+fn nanos_to_duration(nanos: u64) -> Duration {
+    Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+}
+
+/// Put `job` into the queue of the pool, giving it back if the pool is shutting down.
+fn submit_job(shared_data: &Arc<ThreadPoolSharedData>, job: Job) -> Result<(), Box<Job>> {
+    shared_data.queued_count.fetch_add(1, Ordering::SeqCst);
+    if let Err(job) = shared_data.job_queue.push(job) {
+        shared_data.queued_count.fetch_sub(1, Ordering::SeqCst);
+        shared_data.release_bytes(job.size);
+        return Err(Box::new(job));
+    }
+    if shared_data.lazy_spawn {
+        spawn_on_demand(shared_data);
+    } else if shared_data.spawn_failed.load(Ordering::SeqCst) {
+        replenish(shared_data);
+    }
+    if shared_data.inline_fallback
+        && shared_data.spawn_failed.load(Ordering::SeqCst)
+        && shared_data.thread_count.load(Ordering::SeqCst) == 0
+    {
+        // A job running inline already drains the queue once it returns.
+        let running_inline = CURRENT_POOL.with(|current| match *current.borrow() {
+            Some(ref current) => Arc::ptr_eq(current, shared_data),
+            None => false,
+        });
+        if !running_inline {
+            run_on_caller(shared_data);
+        }
+    }
+    Ok(())
+}
+
+/// Run the queued jobs on the calling thread until the queue is empty, returning how many ran.
+fn run_on_caller(shared_data: &Arc<ThreadPoolSharedData>) -> usize {
+    let worker = shared_data.register_worker();
+    let previous = CURRENT_POOL.with(|current| current.replace(Some(shared_data.clone())));
+    let mut count = 0;
+    while let Some(job) = shared_data.job_queue.try_pop() {
+        run_job(shared_data, &worker, job);
+        count += 1;
+    }
+    CURRENT_POOL.with(|current| *current.borrow_mut() = previous);
+    shared_data.retire_worker(&worker);
+    count
+}
+
+/// Change the maximum number of worker threads of a pool to `num_threads`.
+fn resize(shared_data: &Arc<ThreadPoolSharedData>, num_threads: usize) {
+    let prev_num_threads = shared_data
+        .max_thread_count
+        .swap(num_threads, Ordering::Release);
+    if shared_data.lazy_spawn {
+        spawn_on_demand(shared_data);
+    } else if let Some(num_spawn) = num_threads.checked_sub(prev_num_threads) {
+        // Spawn new threads
+        shared_data
+            .thread_count
+            .fetch_add(num_spawn, Ordering::SeqCst);
+        for spawned in 0..num_spawn {
+            if !spawn_in_pool(shared_data.clone()) {
+                // The remaining threads would most likely fail as well.
+                shared_data
+                    .thread_count
+                    .fetch_sub(num_spawn - spawned - 1, Ordering::SeqCst);
+                break;
+            }
+        }
+    }
+}
+
+/// Spawn the thread of `Builder::auto_resize`, which follows the available parallelism until the
+/// pool shuts down.
+fn spawn_auto_resize(
+    shared_data: &Arc<ThreadPoolSharedData>,
+    interval: Duration,
+) -> io::Result<()> {
+    let shared_data = Arc::downgrade(shared_data);
+    thread::Builder::new()
+        .name("threadpool-auto-resize".into())
+        .spawn(move || loop {
+            thread::sleep(interval);
+            let shared_data = match shared_data.upgrade() {
+                Some(shared_data) => shared_data,
+                None => return,
+            };
+            if shared_data.job_queue.is_closed() {
+                return;
+            }
+            let num_threads = available_parallelism();
+            if num_threads != shared_data.max_thread_count.load(Ordering::SeqCst) {
+                resize(&shared_data, num_threads);
+            }
+        })
+        .map(|_| ())
+}
+
+/// Spawn a thread calling `callback` whenever the oldest pending job of the pool has waited
+/// longer than `threshold`, see `ThreadPool::on_queue_latency_exceeded`.
+fn spawn_latency_alarm(
+    shared_data: &Arc<ThreadPoolSharedData>,
+    threshold: Duration,
+    callback: Box<dyn Fn(Duration) + Send>,
+) -> io::Result<()> {
+    let shared_data = Arc::downgrade(shared_data);
+    thread::Builder::new()
+        .name("threadpool-latency-alarm".into())
+        .spawn(move || {
+            // Fire once per breach, then wait until the queue has caught up again.
+            let mut armed = true;
+            let mut next_check = threshold;
+            loop {
+                thread::sleep(next_check);
+                let shared_data = match shared_data.upgrade() {
+                    Some(shared_data) => shared_data,
+                    None => return,
+                };
+                if shared_data.job_queue.is_closed() {
+                    return;
+                }
+                let waited = shared_data
+                    .pending_jobs
+                    .oldest()
+                    .map(|enqueued_at| enqueued_at.elapsed());
+                next_check = match waited {
+                    Some(waited) if waited > threshold => {
+                        if armed {
+                            armed = false;
+                            let _ =
+                                panic::catch_unwind(panic::AssertUnwindSafe(|| callback(waited)));
+                        }
+                        cmp::max(threshold / 4, Duration::from_millis(1))
+                    }
+                    // No job can exceed the threshold before the oldest one does.
+                    Some(waited) => {
+                        armed = true;
+                        threshold - waited + Duration::from_millis(1)
+                    }
+                    None => {
+                        armed = true;
+                        threshold
+                    }
+                };
+            }
+        })
+        .map(|_| ())
+}
+
+/// Spawn the worker threads a pool which does not spawn lazily is short of, because spawning
+/// them failed before.
+fn replenish(shared_data: &Arc<ThreadPoolSharedData>) {
+    loop {
+        let thread_count = shared_data.thread_count.load(Ordering::SeqCst);
+        if thread_count >= shared_data.thread_limit() {
+            return;
+        }
+        if shared_data
+            .thread_count
+            .compare_exchange(
+                thread_count,
+                thread_count + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_ok()
+            && !spawn_in_pool(shared_data.clone())
+        {
+            return;
+        }
+    }
+}
+
+/// Spawn worker threads until there is one for every queued or running job, without exceeding
+/// the maximum number of threads.
+fn spawn_on_demand(shared_data: &Arc<ThreadPoolSharedData>) {
+    if shared_data.deterministic {
+        return;
+    }
+    loop {
+        let thread_count = shared_data.thread_count.load(Ordering::SeqCst);
+        let outstanding = shared_data.queued_count.load(Ordering::SeqCst)
+            + shared_data.active_count.load(Ordering::SeqCst);
+        if thread_count >= outstanding || thread_count >= shared_data.thread_limit() {
+            return;
+        }
+        if shared_data
+            .thread_count
+            .compare_exchange(
+                thread_count,
+                thread_count + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_ok()
+            && !spawn_in_pool(shared_data.clone())
+        {
+            return;
+        }
+    }
+}
+
+/// Abstraction of a thread pool for basic parallelism.
+pub struct ThreadPool {
+    // How the threadpool communicates with subthreads.
+    //
+    // Every handle is counted in `handle_count`, once the last one is dropped the job queue is
+    // closed and all subthreads will quit after draining it.
+    shared_data: Arc<ThreadPoolSharedData>,
+    // Tags the jobs submitted through this handle, see `Scheduling::Fair`.
+    submitter: usize,
+    // Caps the jobs of this handle and its clones, see `handle_with_limit`.
+    limiter: Option<Arc<Limiter>>,
+    // Takes the sized jobs which do not fit into the queue, see `with_overflow`.
+    overflow: Option<Box<ThreadPool>>,
+}
+
+impl ThreadPool {
+    /// Creates a new thread pool capable of executing `num_threads` number of jobs concurrently.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `num_threads` is 0.
+    ///
+    /// # Examples
+    ///
+    /// Create a new thread pool capable of executing four jobs concurrently:
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// ```
+    pub fn new(num_threads: usize) -> ThreadPool {
+        Builder::new().num_threads(num_threads).build()
+    }
+
+    /// Creates a new thread pool capable of executing `num_threads` number of jobs concurrently,
+    /// returning an error instead of panicking if that is not possible.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PoolError::ZeroThreads`] if `num_threads` is 0, and [`PoolError::Spawn`] if the
+    /// operating system can not spawn the worker threads, see [`Builder::try_build`].
+    ///
+    /// [`PoolError::ZeroThreads`]: enum.PoolError.html#variant.ZeroThreads
+    /// [`PoolError::Spawn`]: enum.PoolError.html#variant.Spawn
+    /// [`Builder::try_build`]: struct.Builder.html#method.try_build
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::{PoolError, ThreadPool};
+    ///
+    /// assert!(ThreadPool::try_new(4).is_ok());
+    /// match ThreadPool::try_new(0) {
+    ///     Err(PoolError::ZeroThreads) => (),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    pub fn try_new(num_threads: usize) -> Result<ThreadPool, PoolError> {
+        if num_threads == 0 {
+            return Err(PoolError::ZeroThreads);
+        }
+        Builder::new().num_threads(num_threads).try_build()
+    }
+
+    /// Creates a new thread pool configured from the environment variables starting with
+    /// `prefix`, see [`Builder::from_env`].
+    ///
+    /// [`Builder::from_env`]: struct.Builder.html#method.from_env
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EnvError`] naming the first variable which is set to an invalid value.
+    ///
+    /// [`EnvError`]: struct.EnvError.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = match ThreadPool::from_env("WORKERS") {
+    ///     Ok(pool) => pool,
+    ///     Err(error) => {
+    ///         eprintln!("{}, using the default pool", error);
+    ///         ThreadPool::default()
+    ///     }
+    /// };
+    /// ```
+    pub fn from_env(prefix: &str) -> Result<ThreadPool, EnvError> {
+        Builder::from_env(prefix).map(Builder::build)
+    }
+
+    /// Creates a new thread pool capable of executing `num_threads` number of jobs concurrently.
+    /// Each thread will have the [name][thread name] `name`.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `num_threads` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::with_name("worker".into(), 2);
+    /// for _ in 0..2 {
+    ///     pool.execute(|| {
+    ///         assert_eq!(
+    ///             thread::current().name(),
+    ///             Some("worker")
+    ///         );
+    ///     });
+    /// }
+    /// pool.join();
+    /// ```
+    ///
+    /// [thread name]: https://doc.rust-lang.org/std/thread/struct.Thread.html#method.name
+    pub fn with_name(name: String, num_threads: usize) -> ThreadPool {
+        Builder::new()
+            .num_threads(num_threads)
+            .thread_name(name)
+            .build()
+    }
+
+    /// Creates a new thread pool without worker threads, whose jobs only run when
+    /// [`run_pending`](#method.run_pending) or [`join`](#method.join) is called, see
+    /// [`Builder::deterministic`].
+    ///
+    /// [`Builder::deterministic`]: struct.Builder.html#method.deterministic
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new_deterministic();
+    /// let counter = Arc::new(AtomicUsize::new(0));
+    /// for _ in 0..3 {
+    ///     let counter = counter.clone();
+    ///     pool.execute(move || {
+    ///         counter.fetch_add(1, Ordering::SeqCst);
+    ///     });
+    /// }
+    ///
+    /// assert_eq!(counter.load(Ordering::SeqCst), 0);
+    /// assert_eq!(pool.run_pending(), 3);
+    /// assert_eq!(counter.load(Ordering::SeqCst), 3);
+    /// ```
+    pub fn new_deterministic() -> ThreadPool {
+        Builder::new().num_threads(1).deterministic(true).build()
+    }
+
+    /// Returns a handle to the pool the calling thread belongs to, or `None` if it is not a
+    /// worker thread of any pool.
+    ///
+    /// This lets code running inside a job submit follow-up work without being handed the pool.
+    /// Once every other handle of the pool has been dropped, no new handles are returned either.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::mpsc::channel;
+    ///
+    /// assert!(ThreadPool::current().is_none());
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// let (tx, rx) = channel();
+    /// pool.execute(move || {
+    ///     let pool = ThreadPool::current().expect("running on a worker thread");
+    ///     pool.execute(move || tx.send("follow-up").unwrap());
+    /// });
+    ///
+    /// assert_eq!("follow-up", rx.recv().unwrap());
+    /// ```
+    pub fn current() -> Option<ThreadPool> {
+        CURRENT_POOL.with(|current| {
+            let current = current.borrow();
+            let shared_data = current.as_ref()?;
+            let mut handle_count = shared_data.handle_count.load(Ordering::SeqCst);
+            loop {
+                if handle_count == 0 {
+                    return None;
+                }
+                match shared_data.handle_count.compare_exchange(
+                    handle_count,
+                    handle_count + 1,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                ) {
+                    Ok(_) => return Some(ThreadPool::new_handle(shared_data.clone())),
+                    Err(count) => handle_count = count,
+                }
+            }
+        })
+    }
+
+    /// Wrap `shared_data` in a new handle, which must already be counted in `handle_count`.
+    fn new_handle(shared_data: Arc<ThreadPoolSharedData>) -> ThreadPool {
+        let submitter = shared_data.next_submitter.fetch_add(1, Ordering::Relaxed);
+        ThreadPool {
+            shared_data,
+            submitter,
+            limiter: None,
+            overflow: None,
+        }
+    }
+
+    /// Returns a new handle to the pool whose jobs never occupy more than `limit` worker threads
+    /// at once, however many threads the pool has. Further jobs submitted through the handle or
+    /// its clones wait until one of its jobs has finished, they are counted by
+    /// [`queued_count`](#method.queued_count) in the meantime.
+    ///
+    /// This lets one pool serve several tenants without one of them taking all the threads. The
+    /// limit of the new handle is independent of a limit `self` may have. Jobs which wait for
+    /// each other, e.g. the members of [`execute_gang`](#method.execute_gang), deadlock if they
+    /// need more than `limit` threads.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `limit` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(8);
+    /// let tenant = pool.handle_with_limit(2);
+    ///
+    /// let running = Arc::new(AtomicUsize::new(0));
+    /// let most = Arc::new(AtomicUsize::new(0));
+    /// for _ in 0..6 {
+    ///     let (running, most) = (running.clone(), most.clone());
+    ///     tenant.execute(move || {
+    ///         most.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+    ///         sleep(Duration::from_millis(10));
+    ///         running.fetch_sub(1, Ordering::SeqCst);
+    ///     });
+    /// }
+    ///
+    /// pool.join();
+    /// assert!(most.load(Ordering::SeqCst) <= 2);
+    /// ```
+    pub fn handle_with_limit(&self, limit: usize) -> ThreadPool {
+        assert!(
+            limit >= 1,
+            "handle_with_limit needs a limit of at least one"
+        );
+        self.shared_data.handle_count.fetch_add(1, Ordering::SeqCst);
+        let mut handle = ThreadPool::new_handle(self.shared_data.clone());
+        handle.limiter = Some(Arc::new(Limiter::new(limit)));
+        handle
+    }
+
+    /// Returns a new handle to the pool which passes sized jobs on to `overflow` when they would
+    /// exceed the limit set with [`Builder::queue_byte_limit`], instead of blocking in
+    /// [`execute_sized`](#method.execute_sized) or being handed back by
+    /// [`try_execute_sized`](#method.try_execute_sized).
+    ///
+    /// This degrades gracefully under load spikes, e.g. by spilling to a larger pool of lower
+    /// priority threads. Jobs spill only if the queue is full when they are submitted, and run on
+    /// `overflow` like the jobs submitted to it directly, which may in turn spill to an overflow
+    /// of its own. The new handle shares the limit of `self` set with
+    /// [`handle_with_limit`](#method.handle_with_limit), if any.
+    ///
+    /// [`Builder::queue_byte_limit`]: struct.Builder.html#method.queue_byte_limit
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::mpsc::channel;
+    /// use threadpool::ThreadPool;
+    ///
+    /// let primary = threadpool::Builder::new()
+    ///     .num_threads(1)
+    ///     .queue_byte_limit(100)
+    ///     .build();
+    /// let secondary = ThreadPool::new(4);
+    /// let pool = primary.with_overflow(&secondary);
+    ///
+    /// // Keep the only worker of the primary pool busy, so that sized jobs stay queued.
+    /// let (tx, rx) = channel::<()>();
+    /// pool.execute(move || {
+    ///     let _ = rx.recv();
+    /// });
+    ///
+    /// pool.execute_sized(60, || ());
+    /// assert!(pool.try_execute_sized(60, || ()).is_ok());
+    /// assert_eq!(primary.queued_bytes(), 60);
+    /// secondary.join();
+    /// assert_eq!(secondary.completed_count(), 1);
+    /// drop(tx);
+    /// ```
+    pub fn with_overflow(&self, overflow: &ThreadPool) -> ThreadPool {
+        let mut handle = self.clone();
+        handle.overflow = Some(Box::new(overflow.clone()));
+        handle
+    }
+
+    /// Reserves a worker thread for the jobs submitted through the returned [`WorkerLease`].
+    ///
+    /// The first worker thread to pick the lease up from the queue stops taking other jobs of
+    /// the pool and runs only the jobs of the lease, one after the other, until the lease is
+    /// dropped. This gives jobs exclusive access to per-thread resources, e.g. thread local
+    /// state, without a separate pool. Jobs submitted through the lease before a worker thread
+    /// has picked it up wait for it.
+    ///
+    /// The lease keeps the pool alive like a handle. Leasing all worker threads leaves none for
+    /// the other jobs of the pool.
+    ///
+    /// [`WorkerLease`]: struct.WorkerLease.html
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool is [deterministic](struct.Builder.html#method.deterministic)
+    /// or has been shut down.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::cell::Cell;
+    /// use std::sync::mpsc::channel;
+    /// use threadpool::ThreadPool;
+    ///
+    /// thread_local!(static CONNECTION: Cell<u32> = Cell::new(0));
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let lease = pool.lease_worker();
+    /// let (tx, rx) = channel();
+    /// for _ in 0..3 {
+    ///     let tx = tx.clone();
+    ///     lease.execute(move || {
+    ///         let uses = CONNECTION.with(|uses| {
+    ///             uses.set(uses.get() + 1);
+    ///             uses.get()
+    ///         });
+    ///         tx.send(uses).unwrap();
+    ///     });
+    /// }
+    /// drop(lease);
+    ///
+    /// pool.join();
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+    /// ```
+    pub fn lease_worker(&self) -> WorkerLease {
+        assert!(
+            !self.shared_data.deterministic,
+            "lease_worker cannot be used with a deterministic pool"
+        );
+        self.shared_data.handle_count.fetch_add(1, Ordering::SeqCst);
+        WorkerLease::new(ThreadPool::new_handle(self.shared_data.clone()))
+    }
+
+    /// Declare that the calling job is about to block, e.g. on I/O, so that the pool runs an
+    /// extra worker thread in the meantime. The extra thread is retired once the returned
+    /// [`BlockingSection`] has been dropped and it has finished its current job.
+    ///
+    /// This keeps the other jobs going while jobs which mix computation and blocking calls wait,
+    /// without sizing the pool for the worst case. During the section
+    /// [`active_count`](#method.active_count) may exceed [`max_count`](#method.max_count).
+    ///
+    /// [`BlockingSection`]: struct.BlockingSection.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// let (tx, rx) = channel::<()>();
+    /// let (done_tx, done_rx) = channel();
+    /// {
+    ///     let pool = pool.clone();
+    ///     let done_tx = done_tx.clone();
+    ///     pool.clone().execute(move || {
+    ///         let _section = pool.enter_blocking_section();
+    ///         rx.recv().unwrap();
+    ///         done_tx.send("blocking").unwrap();
+    ///     });
+    /// }
+    /// // Runs although the only worker thread is blocked.
+    /// pool.execute(move || done_tx.send("computing").unwrap());
+    /// assert_eq!(done_rx.recv(), Ok("computing"));
+    ///
+    /// tx.send(()).unwrap();
+    /// assert_eq!(done_rx.recv(), Ok("blocking"));
+    /// ```
+    pub fn enter_blocking_section(&self) -> BlockingSection {
+        BlockingSection::enter(&self.shared_data)
+    }
+
+    /// Returns `true` if the calling thread is a worker thread of this pool.
+    ///
+    /// This can be used to detect re-entrancy, e.g. to avoid calling [`join`](#method.join)
+    /// from within the pool.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// let other = ThreadPool::new(2);
+    /// assert!(!pool.is_worker_thread());
+    ///
+    /// let (inside, elsewhere) = (pool.clone(), other.clone());
+    /// let handle = pool.spawn(move || (inside.is_worker_thread(), elsewhere.is_worker_thread()));
+    /// assert_eq!((true, false), handle.join().unwrap());
+    /// ```
+    pub fn is_worker_thread(&self) -> bool {
+        CURRENT_POOL.with(|current| {
+            current
+                .borrow()
+                .as_ref()
+                .is_some_and(|shared_data| Arc::ptr_eq(shared_data, &self.shared_data))
+        })
+    }
+
+    /// Panic with a clear message instead of deadlocking when `method`, which waits for the
+    /// jobs of the pool, is called by one of these jobs.
+    fn assert_not_worker_thread(&self, method: &str) {
+        assert!(
+            !self.is_worker_thread(),
+            "ThreadPool::{} called from a thread within the pool, it would wait for the calling \
+             job itself and deadlock",
+            method
+        );
+    }
+
+    /// **Deprecated: Use [`ThreadPool::with_name`](#method.with_name)**
+    #[inline(always)]
+    #[deprecated(since = "1.4.0", note = "use ThreadPool::with_name")]
+    pub fn new_with_name(name: String, num_threads: usize) -> ThreadPool {
+        Self::with_name(name, num_threads)
+    }
+
+    /// Executes the function `job` on a thread in the pool.
+    ///
+    /// # Examples
+    ///
+    /// Execute four jobs on a thread pool that can run two jobs concurrently:
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// pool.execute(|| println!("hello"));
+    /// pool.execute(|| println!("world"));
+    /// pool.execute(|| println!("foo"));
+    /// pool.execute(|| println!("bar"));
+    /// pool.join();
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.submit(Job::new(Thunk::new(job), self.submitter));
+    }
+
+    /// Executes the boxed function `job` on a thread in the pool.
+    ///
+    /// Frameworks which keep their jobs boxed already can submit them like this without the
+    /// pool allocating a second box: the box itself is stored inside the pool's job, and unboxed
+    /// right before it is called.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::{BoxedJob, ThreadPool};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// let (tx, rx) = channel();
+    /// let jobs: Vec<BoxedJob> = (0..4)
+    ///     .map(|i| {
+    ///         let tx = tx.clone();
+    ///         Box::new(move || tx.send(i).unwrap()) as BoxedJob
+    ///     })
+    ///     .collect();
+    ///
+    /// for job in jobs {
+    ///     pool.execute_boxed(job);
+    /// }
+    /// assert_eq!(rx.iter().take(4).sum::<i32>(), 6);
+    /// ```
+    pub fn execute_boxed(&self, job: BoxedJob) {
+        self.submit(Job::new(Thunk::new(job), self.submitter));
+    }
+
+    /// Executes the function `job` on a thread in the pool, which should finish it before
+    /// `deadline`.
+    ///
+    /// With [`Scheduling::EarliestDeadlineFirst`] the queued job with the closest deadline is
+    /// started first, otherwise the deadline does not change the order of the jobs. If the job
+    /// finishes late, the callback set with [`Builder::on_deadline_miss`] is invoked.
+    ///
+    /// [`Scheduling::EarliestDeadlineFirst`]: enum.Scheduling.html#variant.EarliestDeadlineFirst
+    /// [`Builder::on_deadline_miss`]: struct.Builder.html#method.on_deadline_miss
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use std::thread::sleep;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let misses = Arc::new(AtomicUsize::new(0));
+    /// let pool = {
+    ///     let misses = misses.clone();
+    ///     threadpool::Builder::new()
+    ///         .on_deadline_miss(move |_| {
+    ///             misses.fetch_add(1, Ordering::SeqCst);
+    ///         })
+    ///         .build()
+    /// };
+    ///
+    /// let deadline = Instant::now() + Duration::from_millis(50);
+    /// pool.execute_with_deadline(deadline, || sleep(Duration::from_millis(100)));
+    /// pool.join();
+    /// assert_eq!(1, misses.load(Ordering::SeqCst));
+    /// ```
+    pub fn execute_with_deadline<F>(&self, deadline: Instant, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.submit(Job {
+            deadline: Some(deadline),
+            ..Job::new(Thunk::new(job), self.submitter)
+        });
+    }
+
+    /// Executes the function `job` on a thread in the pool with the given priority.
+    ///
+    /// With [`Scheduling::Priority`] queued jobs with a higher priority are started first, jobs
+    /// submitted without a priority have priority 0. Other scheduling disciplines ignore the
+    /// priority.
+    ///
+    /// [`Scheduling::Priority`]: enum.Scheduling.html#variant.Priority
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::Scheduling;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(1)
+    ///     .scheduling(Scheduling::Priority)
+    ///     .build();
+    ///
+    /// pool.execute_with_priority(-10, || println!("later"));
+    /// pool.execute_with_priority(10, || println!("sooner"));
+    /// pool.join();
+    /// ```
+    pub fn execute_with_priority<F>(&self, priority: i8, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.submit(Job {
+            priority,
+            ..Job::new(Thunk::new(job), self.submitter)
+        });
+    }
+
+    /// Executes the function `job` on a thread in the pool, preferably the same thread as the
+    /// other jobs submitted with the same `key`.
+    ///
+    /// The key is hashed to one of the worker threads, which runs the job before taking other
+    /// queued jobs, so jobs working on the same data tend to find it in that thread's CPU
+    /// caches. Once an idle worker has no other jobs left, it steals jobs waiting for a busy
+    /// worker, so affinity never leaves the pool idle. Jobs with the same key are not guaranteed
+    /// to run in the order they were submitted.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// for shard in 0..16 {
+    ///     let key = shard % 4;
+    ///     pool.execute_with_affinity(key, move || println!("updating shard {}", shard));
+    /// }
+    /// pool.join();
+    /// ```
+    pub fn execute_with_affinity<K, F>(&self, key: K, job: F)
+    where
+        K: Hash,
+        F: FnOnce() + Send + 'static,
+    {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let workers = cmp::max(self.max_count(), 1) as u64;
+        self.submit(Job {
+            affinity: Some((hasher.finish() % workers) as usize),
+            ..Job::new(Thunk::new(job), self.submitter)
+        });
+    }
+
+    /// Executes the function `job` on a thread in the pool, labelled with `tag`.
+    ///
+    /// The tag identifies the job in the [`JobPanic`] reported by
+    /// [`last_panic`](#method.last_panic) if the job panics.
+    ///
+    /// [`JobPanic`]: struct.JobPanic.html
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// for n in 0..4 {
+    ///     pool.execute_tagged(format!("batch-{}", n), move || println!("batch {}", n));
+    /// }
+    /// pool.join();
+    /// ```
+    pub fn execute_tagged<T, F>(&self, tag: T, job: F)
+    where
+        T: Into<String>,
+        F: FnOnce() + Send + 'static,
+    {
+        self.submit(Job {
+            tag: Some(Arc::from(tag.into())),
+            ..Job::new(Thunk::new(job), self.submitter)
+        });
+    }
+
+    /// Executes the function `job` on a thread in the pool, labelled with `tag`, unless the tag
+    /// is quarantined, see [`Builder::quarantine_tags`]. Then `job` is handed back, without
+    /// going to the callback set with [`Builder::on_quarantined_job`].
+    ///
+    /// [`Builder::quarantine_tags`]: struct.Builder.html#method.quarantine_tags
+    /// [`Builder::on_quarantined_job`]: struct.Builder.html#method.on_quarantined_job
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .quarantine_tags(1, Duration::from_secs(60))
+    ///     .build();
+    ///
+    /// assert!(pool
+    ///     .try_execute_tagged("import", || panic!("Ignore this panic, it must!"))
+    ///     .is_ok());
+    /// pool.join();
+    /// assert!(pool.try_execute_tagged("import", || ()).is_err());
+    /// assert_eq!(pool.rejected_count(), 1);
+    /// ```
+    pub fn try_execute_tagged<T, F>(&self, tag: T, job: F) -> Result<(), F>
+    where
+        T: Into<String>,
+        F: FnOnce() + Send + 'static,
+    {
+        let tag: Arc<str> = Arc::from(tag.into());
+        if let Some(ref quarantine) = self.shared_data.quarantine {
+            if quarantine.reject(&tag) {
+                return Err(job);
+            }
+        }
+        self.submit(Job {
+            tag: Some(tag),
+            ..Job::new(Thunk::new(job), self.submitter)
+        });
+        Ok(())
+    }
+
+    /// Executes the function `job` on a thread in the pool, unless a job submitted with the same
+    /// `key` is still queued or running. Returns `false` if `job` was dropped for that reason.
+    ///
+    /// This keeps identical jobs, e.g. refreshing a cache entry, from piling up when they are
+    /// requested faster than they run. The key also tags the job like
+    /// [`execute_tagged`](#method.execute_tagged) does.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::{Arc, Barrier};
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// let barrier = Arc::new(Barrier::new(2));
+    /// let refresh = barrier.clone();
+    /// assert!(pool.execute_dedup("refresh", move || {
+    ///     refresh.wait();
+    /// }));
+    /// assert!(!pool.execute_dedup("refresh", || unreachable!()));
+    ///
+    /// barrier.wait();
+    /// pool.join();
+    /// assert!(pool.execute_dedup("refresh", || println!("refreshing again")));
+    /// ```
+    pub fn execute_dedup<K, F>(&self, key: K, job: F) -> bool
+    where
+        K: Into<String>,
+        F: FnOnce() + Send + 'static,
+    {
+        let key = Arc::<str>::from(key.into());
+        let guard = {
+            let mut keys = self
+                .shared_data
+                .dedup_keys
+                .lock()
+                .expect("ThreadPool::execute_dedup unable to lock keys");
+            if !keys.insert(key.clone()) {
+                return false;
+            }
+            DedupKey {
+                keys: self.shared_data.dedup_keys.clone(),
+                key: key.clone(),
+            }
+        };
+        self.submit(Job {
+            tag: Some(key),
+            ..Job::new(
+                Thunk::new(move || {
+                    let _guard = guard;
+                    job();
+                }),
+                self.submitter,
+            )
+        });
+        true
+    }
+
+    /// Executes the function `job` on a thread in the pool and returns a [`JobHandle`] for its
+    /// result, unless a job submitted with the same `key` completed less than `ttl` ago. The
+    /// handle then returns a clone of that result without running `job`.
+    ///
+    /// While a job for `key` is still queued or running, the handle waits for its result as
+    /// well. A job which panics or is cancelled is not cached, the next call for `key` runs
+    /// `job` again; the handles which waited for it in the meantime report it as cancelled.
+    /// The key also tags the job like [`execute_tagged`](#method.execute_tagged) does.
+    ///
+    /// This suits idempotent jobs whose results stay valid for a while, e.g. loading a
+    /// configuration.
+    ///
+    /// [`JobHandle`]: struct.JobHandle.html
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// let loads = Arc::new(AtomicUsize::new(0));
+    /// let load = || {
+    ///     let loads = loads.clone();
+    ///     move || loads.fetch_add(1, Ordering::SeqCst) + 40
+    /// };
+    ///
+    /// let ttl = Duration::from_secs(60);
+    /// assert_eq!(pool.execute_cached("config", ttl, load()).join().unwrap(), 40);
+    /// assert_eq!(pool.execute_cached("config", ttl, load()).join().unwrap(), 40);
+    /// assert_eq!(loads.load(Ordering::SeqCst), 1);
+    /// ```
+    pub fn execute_cached<K, T, F>(&self, key: K, ttl: Duration, job: F) -> JobHandle<T>
+    where
+        K: Into<String>,
+        T: Clone + Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let key = Arc::<str>::from(key.into());
+        let (handle, computation) = match self.shared_data.result_cache.get(key.clone(), ttl) {
+            Lookup::Cached(handle) => return handle,
+            Lookup::Compute(handle, computation) => (handle, computation),
+        };
+        self.submit(Job {
+            tag: Some(key),
+            ..Job::new(
+                Thunk::new(with_completion(job, move |result| {
+                    computation.complete(result)
+                })),
+                self.submitter,
+            )
+        });
+        handle
+    }
+
+    /// Executes the function `job` on a thread in the pool and returns an id which can be used
+    /// to query its status with [`job_status`](#method.job_status).
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::{JobStatus, ThreadPool};
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// let id = pool.execute_tracked(|| println!("tracked"));
+    /// pool.join();
+    /// assert_eq!(JobStatus::Completed, pool.job_status(id));
+    /// ```
+    pub fn execute_tracked<F>(&self, job: F) -> JobId
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut tracker = StatusTable::track(&self.shared_data.job_statuses);
+        let id = tracker.id();
+        self.execute(move || {
+            tracker.start();
+            job();
+            tracker.complete();
+        });
+        id
+    }
+
+    /// Returns the status of the job `id` submitted with
+    /// [`execute_tracked`](#method.execute_tracked).
+    ///
+    /// The pool remembers the statuses of the 1024 most recently finished tracked jobs, older
+    /// ones are reported as [`JobStatus::Unknown`].
+    ///
+    /// [`JobStatus::Unknown`]: enum.JobStatus.html#variant.Unknown
+    pub fn job_status(&self, id: JobId) -> JobStatus {
+        self.shared_data.job_statuses.get(id)
+    }
+
+    fn submit(&self, job: Job) {
+        if self.try_submit(job).is_err() {
+            panic!("ThreadPool::execute unable to send job into queue.");
+        }
+    }
+
+    /// Queue `job`, handing it back if the pool has been shut down.
+    fn try_submit(&self, mut job: Job) -> Result<(), Box<Job>> {
+        if let (Some(quarantine), Some(tag)) = (&self.shared_data.quarantine, job.tag.clone()) {
+            if quarantine.reject(&tag) {
+                self.shared_data.release_bytes(job.size);
+                // Dropping the job instead cancels its handle.
+                if let Some(ref handler) = self.shared_data.dead_letter_handler {
+                    let thunk = job.thunk;
+                    handler(&tag, Box::new(move || thunk.call()));
+                }
+                return Ok(());
+            }
+        }
+        if job.contexts.is_none() {
+            job.contexts = Some(self.shared_data.capture_contexts());
+        }
+        if job.token.is_none() && job.tag.is_some() {
+            job.token = Some(self.shared_data.cancellation.child());
+        }
+        if job.pending.is_none() {
+            let info = JobInfo::new(job.tag.clone(), job.priority);
+            job.pending = Some(PendingJobs::enter(&self.shared_data.pending_jobs, info));
+        }
+        if job.affinity.is_none() {
+            if let Some(ref numa) = self.shared_data.numa {
+                job.affinity = numa.preferred_worker(self.max_count());
+            }
+        }
+        match self.limiter {
+            Some(ref limiter) => limiter.submit(&self.shared_data, job),
+            None => submit_job(&self.shared_data, job),
+        }
+    }
+
+    /// Executes the function `job` on a thread in the pool, passing it a [`CancellationToken`]
+    /// which is also returned to the caller.
+    ///
+    /// The token is cancelled when either the returned token is cancelled or the pool is shut
+    /// down with [`shutdown_now`](#method.shutdown_now). A job whose token is cancelled before it
+    /// starts is not run at all, a running job has to check the token and return early itself.
+    ///
+    /// [`CancellationToken`]: struct.CancellationToken.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::mpsc::channel;
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// let (tx, rx) = channel();
+    /// let token = pool.execute_cancellable(move |token| {
+    ///     let mut rounds = 0;
+    ///     while !token.is_cancelled() {
+    ///         rounds += 1;
+    ///         sleep(Duration::from_millis(10));
+    ///     }
+    ///     tx.send(rounds).unwrap();
+    /// });
+    ///
+    /// sleep(Duration::from_millis(100));
+    /// token.cancel();
+    /// assert!(rx.recv().unwrap() > 0);
+    /// ```
+    pub fn execute_cancellable<F>(&self, job: F) -> CancellationToken
+    where
+        F: FnOnce(&CancellationToken) + Send + 'static,
+    {
+        self.execute_with_token(self.shared_data.cancellation.child(), job)
+    }
+
+    /// Executes the function `job` on a thread in the pool like
+    /// [`execute_cancellable`](#method.execute_cancellable), with a token which is a child of
+    /// `parent`.
+    ///
+    /// Cancelling `parent` therefore cancels the job as well: it is not run if it has not
+    /// started yet, or sees its token cancelled if it is running. A job can pass its own token
+    /// as `parent` to the jobs it submits, so that cancelling it cancels the whole tree of jobs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// let (tx, rx) = channel();
+    /// let children = pool.clone();
+    /// let parent = pool.execute_cancellable(move |token| {
+    ///     for i in 0..3 {
+    ///         let tx = tx.clone();
+    ///         children.execute_cancellable_child(token, move |_| tx.send(i).unwrap());
+    ///     }
+    ///     token.cancel();
+    /// });
+    ///
+    /// pool.join();
+    /// assert!(parent.is_cancelled());
+    /// assert!(rx.try_recv().is_err());
+    /// ```
+    pub fn execute_cancellable_child<F>(
+        &self,
+        parent: &CancellationToken,
+        job: F,
+    ) -> CancellationToken
+    where
+        F: FnOnce(&CancellationToken) + Send + 'static,
+    {
+        let token = CancellationToken::with_parents(vec![
+            parent.clone(),
+            self.shared_data.cancellation.clone(),
+        ]);
+        self.execute_with_token(token, job)
+    }
+
+    fn execute_with_token<F>(&self, token: CancellationToken, job: F) -> CancellationToken
+    where
+        F: FnOnce(&CancellationToken) + Send + 'static,
+    {
+        let job_token = token.clone();
+        let mut job = Job::new(
+            Thunk::new(move || {
+                if !job_token.is_cancelled() {
+                    job(&job_token)
+                }
+            }),
+            self.submitter,
+        );
+        job.token = Some(token.clone());
+        self.submit(job);
+        token
+    }
+
+    /// Executes the function `job` on a thread in the pool and returns a [`JobHandle`] which can
+    /// be used to wait for its result.
+    ///
+    /// If `job` panics, the payload is handed to the [`JobHandle`]. The panic is still counted
+    /// by [`panic_count`](#method.panic_count), just like for jobs submitted with
+    /// [`execute`](#method.execute).
+    ///
+    /// [`JobHandle`]: struct.JobHandle.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// let handles: Vec<_> = (0..4).map(|i| pool.spawn(move || i * i)).collect();
+    ///
+    /// let squares: Vec<u32> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    /// assert_eq!(vec![0, 1, 4, 9], squares);
+    /// ```
+    pub fn spawn<F, T>(&self, job: F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (completer, mut handle) = handle::pair();
+        let mut job = Job::new(
+            Thunk::new(with_completion(job, move |result| {
+                completer.complete(result)
+            })),
+            self.submitter,
+        );
+        let pending = PendingJobs::enter(&self.shared_data.pending_jobs, JobInfo::new(None, 0));
+        handle.track(&self.shared_data, pending.id());
+        job.pending = Some(pending);
+        self.submit(job);
+        handle
+    }
+
+    /// Executes the function `job` on a thread in the pool, unless it is still queued after
+    /// `timeout`. Then `on_expired` is called on the worker thread instead of running stale work,
+    /// e.g. a request whose client has given up waiting already.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// pool.execute(|| sleep(Duration::from_millis(100)));
+    /// pool.execute_with_start_timeout(
+    ///     Duration::from_millis(10),
+    ///     || println!("handling request"),
+    ///     || println!("request expired in the queue"),
+    /// );
+    /// pool.join();
+    /// ```
+    pub fn execute_with_start_timeout<F, E>(&self, timeout: Duration, job: F, on_expired: E)
+    where
+        F: FnOnce() + Send + 'static,
+        E: FnOnce() + Send + 'static,
+    {
+        let start_deadline = Instant::now() + timeout;
+        self.execute(move || {
+            if Instant::now() > start_deadline {
+                on_expired();
+            } else {
+                job();
+            }
+        });
+    }
+
+    /// Like [`spawn`](#method.spawn), but skips `job` if it is still queued after `timeout`. The
+    /// handle then reports a [`JoinError`] for which
+    /// [`is_timed_out`](struct.JoinError.html#method.is_timed_out) returns `true`.
+    ///
+    /// [`JoinError`]: struct.JoinError.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// pool.execute(|| sleep(Duration::from_millis(100)));
+    /// let handle = pool.spawn_with_start_timeout(Duration::from_millis(10), || 42);
+    ///
+    /// assert!(handle.join().unwrap_err().is_timed_out());
+    /// ```
+    pub fn spawn_with_start_timeout<F, T>(&self, timeout: Duration, job: F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (completer, handle) = handle::pair();
+        let start_deadline = Instant::now() + timeout;
+        self.execute(move || {
+            if Instant::now() > start_deadline {
+                completer.complete(Err(JoinError::timed_out()));
+            } else {
+                with_completion(job, move |result| completer.complete(result))();
+            }
+        });
+        handle
+    }
+
+    /// Runs the future `future` on the pool and returns a [`JobHandle`] for its output.
+    ///
+    /// The future is polled by a worker thread whenever it is woken, it does not occupy a thread
+    /// while it is pending. A panic while polling is reported like a panic of
+    /// [`spawn`](#method.spawn). If the pool shuts down while the future is pending, the future is
+    /// dropped and the handle reports it as cancelled.
+    ///
+    /// [`JobHandle`]: struct.JobHandle.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::future;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// let answer = pool.spawn_future(future::ready(6 * 7));
+    /// assert_eq!(42, answer.join().unwrap());
+    /// ```
+    pub fn spawn_future<F>(&self, future: F) -> JobHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        task::Task::spawn(&self.shared_data, self.submitter, future)
+    }
+
+    /// Executes the functions yielded by `jobs` on the pool and returns an iterator over their
+    /// results, in the order the jobs finish.
+    ///
+    /// Jobs are submitted as the results are consumed, keeping about twice as many jobs queued or
+    /// running as the pool has threads, so `jobs` may be a long or even infinite iterator. A job
+    /// which panics resumes its panic on the thread consuming the results.
+    ///
+    /// # Panics
+    ///
+    /// The returned iterator will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now) before all jobs were submitted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let lengths = pool.execute_stream((1..=100).map(|n| move || n.to_string().len()));
+    ///
+    /// assert_eq!(192, lengths.sum::<usize>());
+    /// ```
+    pub fn execute_stream<I, F, T>(&self, jobs: I) -> JobStream<I::IntoIter, T>
+    where
+        I: IntoIterator<Item = F>,
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let window = 2 * cmp::max(self.max_count(), 1);
+        JobStream::new(self.clone(), jobs.into_iter(), window)
+    }
+
+    /// Executes the function `job` on a thread in the pool and calls `on_done` with its result
+    /// once it has finished.
+    ///
+    /// `on_done` runs on the worker thread right after `job`, and is called exactly once: with
+    /// the value returned by `job`, with a [`JoinError`] carrying the panic payload if `job`
+    /// panicked, or with a cancelled [`JoinError`] if `job` is discarded before it could run, see
+    /// [`shutdown_now`](#method.shutdown_now). A panicking job is still counted by
+    /// [`panic_count`](#method.panic_count).
+    ///
+    /// This is lighter than [`spawn`](#method.spawn) when nobody needs to block on the result.
+    ///
+    /// [`JoinError`]: struct.JoinError.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let total = Arc::new(AtomicUsize::new(0));
+    /// let failures = Arc::new(AtomicUsize::new(0));
+    ///
+    /// for i in 0..8 {
+    ///     let (total, failures) = (total.clone(), failures.clone());
+    ///     pool.execute_with_callback(
+    ///         move || if i == 3 { panic!("unlucky") } else { i },
+    ///         move |result| match result {
+    ///             Ok(i) => {
+    ///                 total.fetch_add(i, Ordering::SeqCst);
+    ///             }
+    ///             Err(_) => {
+    ///                 failures.fetch_add(1, Ordering::SeqCst);
+    ///             }
+    ///         },
+    ///     );
+    /// }
+    /// pool.join();
+    ///
+    /// assert_eq!(25, total.load(Ordering::SeqCst));
+    /// assert_eq!(1, failures.load(Ordering::SeqCst));
+    /// ```
+    pub fn execute_with_callback<F, T, C>(&self, job: F, on_done: C)
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: 'static,
+        C: FnOnce(Result<T, JoinError>) + Send + 'static,
+    {
+        self.execute(with_completion(job, on_done));
+    }
+
+    /// Executes the function `job` on a thread in the pool once all of its `prerequisites` have
+    /// completed, and returns a [`JobHandle`] for its result.
+    ///
+    /// If any of the prerequisites fails, i.e. panics or is cancelled, `job` is not run and its
+    /// handle reports it as cancelled, which in turn cancels the jobs depending on it. This way
+    /// whole graphs of jobs can be submitted up front, e.g. for build-system-like pipelines.
+    ///
+    /// [`JobHandle`]: struct.JobHandle.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let log = Arc::new(Mutex::new(Vec::new()));
+    ///
+    /// let step = |name: &'static str| {
+    ///     let log = log.clone();
+    ///     move || log.lock().unwrap().push(name)
+    /// };
+    /// let compile_a = pool.spawn(step("compile a"));
+    /// let compile_b = pool.spawn(step("compile b"));
+    /// let link = pool.submit_after(&[&compile_a, &compile_b], step("link"));
+    /// link.join().unwrap();
+    ///
+    /// assert_eq!(Some(&"link"), log.lock().unwrap().last());
+    /// ```
+    pub fn submit_after<F, T>(&self, prerequisites: &[&dyn Prerequisite], job: F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (completer, handle) = handle::pair();
+        // The job is queued later on by another thread, capture the contexts of this one.
+        let job = Job {
+            contexts: Some(self.shared_data.capture_contexts()),
+            ..Job::new(
+                Thunk::new(with_completion(job, move |result| {
+                    completer.complete(result)
+                })),
+                self.submitter,
+            )
+        };
+        // One extra count keeps the job from being released while we are still registering.
+        let pending = Arc::new(PendingJob {
+            remaining: AtomicUsize::new(prerequisites.len() + 1),
+            failed: AtomicBool::new(false),
+            job: Mutex::new(Some((self.clone(), job))),
+        });
+        for prerequisite in prerequisites {
+            let pending = pending.clone();
+            prerequisite.when_done(Box::new(move |succeeded| pending.done(succeeded)));
+        }
+        pending.done(true);
+        handle
+    }
+
+    /// Executes the function `job` on a thread in the pool, estimating the memory it holds while
+    /// it is queued as `bytes`.
+    ///
+    /// If queueing the job would exceed the limit set with [`Builder::queue_byte_limit`], this
+    /// blocks until enough sized jobs have left the queue. Calling it from a job of the same pool
+    /// may therefore deadlock, see [`try_execute_sized`](#method.try_execute_sized) for a
+    /// non-blocking variant and [`with_overflow`](#method.with_overflow) for passing the job on
+    /// to another pool instead.
+    ///
+    /// [`Builder::queue_byte_limit`]: struct.Builder.html#method.queue_byte_limit
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(2)
+    ///     .queue_byte_limit(4096)
+    ///     .build();
+    ///
+    /// for _ in 0..8 {
+    ///     let buffer = vec![1u8; 1024];
+    ///     pool.execute_sized(buffer.len(), move || assert_eq!(1024, buffer.len()));
+    /// }
+    /// pool.join();
+    /// ```
+    pub fn execute_sized<F>(&self, bytes: usize, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if let Some(ref overflow) = self.overflow {
+            if !self.shared_data.reserve_bytes(bytes, false) {
+                return overflow.execute_sized(bytes, job);
+            }
+        } else {
+            self.shared_data.reserve_bytes(bytes, true);
+        }
+        self.submit(Job {
+            size: bytes,
+            ..Job::new(Thunk::new(job), self.submitter)
+        });
+    }
+
+    /// Like [`execute_sized`](#method.execute_sized), but gives `job` back instead of blocking if
+    /// the queue byte limit would be exceeded.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(1)
+    ///     .queue_byte_limit(100)
+    ///     .build();
+    ///
+    /// // Keep the only worker busy, so that sized jobs stay queued.
+    /// let (tx, rx) = channel::<()>();
+    /// pool.execute(move || {
+    ///     let _ = rx.recv();
+    /// });
+    ///
+    /// assert!(pool.try_execute_sized(60, || ()).is_ok());
+    /// assert!(pool.try_execute_sized(60, || ()).is_err());
+    /// drop(tx);
+    /// ```
+    pub fn try_execute_sized<F>(&self, bytes: usize, job: F) -> Result<(), F>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if !self.shared_data.reserve_bytes(bytes, false) {
+            return match self.overflow {
+                Some(ref overflow) => overflow.try_execute_sized(bytes, job),
+                None => Err(job),
+            };
+        }
+        self.submit(Job {
+            size: bytes,
+            ..Job::new(Thunk::new(job), self.submitter)
+        });
+        Ok(())
+    }
+
+    /// Block the current thread until a job of `bytes` bytes could be queued without exceeding the
+    /// limit set with [`Builder::queue_byte_limit`]. Returns immediately if the pool has no limit.
+    ///
+    /// This lets a producer wait once for room for a whole batch of jobs instead of blocking in
+    /// [`execute_sized`](#method.execute_sized) for every single one. The room is not reserved,
+    /// other producers may take it before the batch is submitted.
+    ///
+    /// [`Builder::queue_byte_limit`]: struct.Builder.html#method.queue_byte_limit
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(2)
+    ///     .queue_byte_limit(4096)
+    ///     .build();
+    ///
+    /// for _ in 0..4 {
+    ///     let batch = vec![vec![1u8; 256]; 8];
+    ///     pool.wait_for_capacity(batch.len() * 256);
+    ///     for buffer in batch {
+    ///         // Does not block unless another producer took the room.
+    ///         pool.execute_sized(buffer.len(), move || assert_eq!(256, buffer.len()));
+    ///     }
+    /// }
+    /// pool.join();
+    /// ```
+    pub fn wait_for_capacity(&self, bytes: usize) {
+        self.shared_data.wait_for_bytes(bytes, None);
+    }
+
+    /// Like [`wait_for_capacity`](#method.wait_for_capacity), but gives up after `timeout`.
+    /// Returns `true` if there is room for `bytes` bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::mpsc::channel;
+    /// use std::time::Duration;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .num_threads(1)
+    ///     .queue_byte_limit(100)
+    ///     .build();
+    ///
+    /// // Keep the only worker busy, so that sized jobs stay queued.
+    /// let (tx, rx) = channel::<()>();
+    /// pool.execute(move || {
+    ///     let _ = rx.recv();
+    /// });
+    /// pool.execute_sized(60, || ());
+    ///
+    /// assert!(pool.wait_for_capacity_timeout(40, Duration::from_millis(10)));
+    /// assert!(!pool.wait_for_capacity_timeout(60, Duration::from_millis(10)));
+    /// drop(tx);
+    /// ```
+    pub fn wait_for_capacity_timeout(&self, bytes: usize, timeout: Duration) -> bool {
+        self.shared_data
+            .wait_for_bytes(bytes, Some(Instant::now() + timeout))
+            .is_some()
+    }
+
+    /// Returns the sum of the sizes of the jobs submitted with
+    /// [`execute_sized`](#method.execute_sized) which are waiting in the queue.
+    pub fn queued_bytes(&self) -> usize {
+        *self
+            .shared_data
+            .queued_bytes
+            .lock()
+            .expect("ThreadPool::queued_bytes unable to lock queued bytes")
+    }
+
+    /// Returns the number of jobs waiting to executed in the pool.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::time::Duration;
+    /// use std::thread::sleep;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// for _ in 0..10 {
+    ///     pool.execute(|| {
+    ///         sleep(Duration::from_secs(100));
+    ///     });
+    /// }
+    ///
+    /// sleep(Duration::from_secs(1)); // wait for threads to start
+    /// assert_eq!(8, pool.queued_count());
+    /// ```
+    pub fn queued_count(&self) -> usize {
+        self.shared_data.queued_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the jobs which have been submitted but not started yet, oldest first.
+    ///
+    /// Unlike [`queued_count`](#method.queued_count) this tells what is waiting: the tag, the
+    /// time of submission and the priority of every job.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// let (started_tx, started_rx) = channel();
+    /// let (tx, rx) = channel::<()>();
+    /// pool.execute(move || {
+    ///     started_tx.send(()).unwrap();
+    ///     rx.recv().unwrap();
+    /// });
+    /// started_rx.recv().unwrap();
+    /// pool.execute_tagged("report", || println!("report"));
+    /// pool.execute_with_priority(5, || println!("urgent"));
+    ///
+    /// let pending = pool.pending_jobs();
+    /// assert_eq!(pending.len(), 2);
+    /// assert_eq!(pending[0].tag(), Some("report"));
+    /// assert_eq!(pending[1].priority(), 5);
+    /// tx.send(()).unwrap();
+    /// ```
+    pub fn pending_jobs(&self) -> Vec<JobInfo> {
+        self.shared_data.pending_jobs.snapshot()
+    }
+
+    /// Cancels the jobs whose tag matches `pattern`: the tag itself, or a prefix followed by
+    /// `*` like `"export-*"`.
+    ///
+    /// Matching jobs which have not started are never run, they no longer show up in
+    /// [`pending_jobs`](#method.pending_jobs) but are counted by
+    /// [`queued_count`](#method.queued_count) until a worker thread has dropped them. Matching
+    /// jobs which are running have their token cancelled, they see it through
+    /// [`CancellationToken::current`] and have to return early themselves.
+    ///
+    /// Returns how many queued and running jobs were cancelled.
+    ///
+    /// [`CancellationToken::current`]: struct.CancellationToken.html#method.current
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::mpsc::channel;
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// let (started_tx, started_rx) = channel();
+    /// let (tx, rx) = channel::<()>();
+    /// pool.execute(move || {
+    ///     started_tx.send(()).unwrap();
+    ///     rx.recv().unwrap();
+    /// });
+    /// started_rx.recv().unwrap();
+    ///
+    /// pool.execute_tagged("export-orders", || panic!("cancelled"));
+    /// pool.execute_tagged("export-users", || panic!("cancelled"));
+    /// pool.execute_tagged("import", || println!("importing"));
+    ///
+    /// let cancelled = pool.cancel_tagged("export-*");
+    /// assert_eq!(cancelled.queued(), 2);
+    /// tx.send(()).unwrap();
+    /// pool.join();
+    /// assert_eq!(pool.panic_count(), 0);
+    /// ```
+    pub fn cancel_tagged(&self, pattern: &str) -> CancelledJobs {
+        let queued = self.shared_data.pending_jobs.cancel_tagged(pattern);
+        let mut running = 0;
+        let workers = self
+            .shared_data
+            .workers
+            .lock()
+            .expect("ThreadPool::cancel_tagged unable to lock workers");
+        for worker in workers.iter() {
+            let current = worker
+                .running
+                .lock()
+                .expect("ThreadPool::cancel_tagged unable to lock running job");
+            if let Some((ref tag, ref token)) = *current {
+                if tag_matches(pattern, tag) {
+                    token.cancel();
+                    running += 1;
+                }
+            }
+        }
+        CancelledJobs { queued, running }
+    }
+
+    /// Returns the number of currently active threads.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::time::Duration;
+    /// use std::thread::sleep;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// for _ in 0..10 {
+    ///     pool.execute(move || {
+    ///         sleep(Duration::from_secs(100));
+    ///     });
+    /// }
+    ///
+    /// sleep(Duration::from_secs(1)); // wait for threads to start
+    /// assert_eq!(4, pool.active_count());
+    /// ```
+    pub fn active_count(&self) -> usize {
+        self.shared_data.active_count.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of worker threads waiting for a job because the queue is empty.
+    ///
+    /// Together with [`active_count`](#method.active_count) this is the number of worker threads
+    /// alive, except for threads which are starting up or about to exit, e.g. after
+    /// [`set_num_threads`](#method.set_num_threads) made the pool smaller.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::thread;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// while pool.idle_count() < 4 {
+    ///     thread::yield_now(); // wait for threads to start
+    /// }
+    ///
+    /// pool.execute(|| loop {
+    ///     thread::park();
+    /// });
+    /// while pool.active_count() < 1 {
+    ///     thread::yield_now();
+    /// }
+    /// assert_eq!(3, pool.idle_count());
+    /// ```
+    pub fn idle_count(&self) -> usize {
+        match self.shared_data.dispatcher {
+            Some(ref dispatcher) => dispatcher.idle_count(),
+            None => self.shared_data.job_queue.waiting(),
+        }
+    }
+
+    /// Returns the maximum number of threads the pool will execute concurrently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let mut pool = ThreadPool::new(4);
+    /// assert_eq!(4, pool.max_count());
+    ///
+    /// pool.set_num_threads(8);
+    /// assert_eq!(8, pool.max_count());
+    /// ```
+    pub fn max_count(&self) -> usize {
+        self.shared_data.max_thread_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of panicked jobs over the lifetime of the pool.
+    ///
+    /// Panics are caught by the worker threads, which go on with the next job.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// for n in 0..10 {
+    ///     pool.execute(move || {
+    ///         // simulate a panic
+    ///         if n % 2 == 0 {
+    ///             panic!()
+    ///         }
+    ///     });
+    /// }
+    /// pool.join();
+    ///
+    /// assert_eq!(5, pool.panic_count());
+    /// ```
+    pub fn panic_count(&self) -> usize {
+        self.shared_data.panic_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of worker threads which died and were replaced over the lifetime of
+    /// the pool.
+    ///
+    /// Panicking jobs do not take their worker thread down, but panics outside of a job, e.g. in
+    /// the `Drop` implementation of the payload of a job panic, do.
+    pub fn respawn_count(&self) -> usize {
+        self.shared_data.respawn_count.load(Ordering::SeqCst)
+    }
+
+    /// Returns `true` while worker threads die so quickly after each other that their
+    /// replacements are delayed.
+    ///
+    /// A worker thread which dies within a second of the previous replacement is only replaced
+    /// after a delay, starting at 10 milliseconds and doubling with every further death up to
+    /// one second. While this happens the pool runs with fewer threads, and whatever kills them
+    /// should be fixed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// assert!(!pool.respawn_storm());
+    /// ```
+    pub fn respawn_storm(&self) -> bool {
+        let respawn = self
+            .shared_data
+            .respawn
+            .lock()
+            .expect("ThreadPool::respawn_storm unable to lock respawn state");
+        match respawn.last {
+            Some(last) => {
+                respawn.delay > Duration::from_secs(0)
+                    && Instant::now() < last + RESPAWN_STORM_WINDOW
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the number of jobs which have finished over the lifetime of the pool, including
+    /// the ones which panicked.
+    ///
+    /// Together with [`queued_count`](#method.queued_count) and
+    /// [`active_count`](#method.active_count) this tells how far a batch of jobs has progressed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// for _ in 0..10 {
+    ///     pool.execute(|| ());
+    /// }
+    ///
+    /// let total = pool.completed_count() + pool.active_count() + pool.queued_count();
+    /// println!("{} of {} jobs done", pool.completed_count(), total);
+    ///
+    /// pool.join();
+    /// assert_eq!(10, pool.completed_count());
+    /// ```
+    pub fn completed_count(&self) -> usize {
+        self.shared_data.completed_count.load(Ordering::SeqCst)
+    }
+
+    /// Returns the most recent panic of a job in the pool, if any job has panicked yet.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// assert_eq!(None, pool.last_panic());
+    ///
+    /// pool.execute_tagged("import", || panic!("corrupt record"));
+    /// pool.join();
+    ///
+    /// let last_panic = pool.last_panic().unwrap();
+    /// assert_eq!("corrupt record", last_panic.message());
+    /// assert_eq!(Some("import"), last_panic.tag());
+    /// ```
+    pub fn last_panic(&self) -> Option<JobPanic> {
+        self.shared_data
+            .last_panic
+            .lock()
+            .expect("ThreadPool::last_panic unable to lock last panic")
+            .clone()
+    }
+
+    /// Returns the tags whose jobs are rejected because they kept panicking, in alphabetical
+    /// order, see [`Builder::quarantine_tags`].
+    ///
+    /// [`Builder::quarantine_tags`]: struct.Builder.html#method.quarantine_tags
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .quarantine_tags(1, Duration::from_secs(60))
+    ///     .build();
+    /// assert!(pool.quarantined_tags().is_empty());
+    ///
+    /// pool.execute_tagged("import", || panic!("Ignore this panic, it must!"));
+    /// pool.join();
+    /// assert_eq!(pool.quarantined_tags(), ["import"]);
+    /// ```
+    pub fn quarantined_tags(&self) -> Vec<String> {
+        match self.shared_data.quarantine {
+            Some(ref quarantine) => quarantine.tags(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Accept the jobs tagged `tag` again after they were quarantined, e.g. once the bug making
+    /// them panic has been fixed. The tag then has to reach the threshold of
+    /// [`Builder::quarantine_tags`] anew to be quarantined again. Returns `false` if `tag` was
+    /// not quarantined.
+    ///
+    /// [`Builder::quarantine_tags`]: struct.Builder.html#method.quarantine_tags
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .quarantine_tags(1, Duration::from_secs(60))
+    ///     .build();
+    /// pool.execute_tagged("import", || panic!("Ignore this panic, it must!"));
+    /// pool.join();
+    ///
+    /// assert!(pool.release_quarantine("import"));
+    /// assert!(pool.quarantined_tags().is_empty());
+    /// assert!(!pool.release_quarantine("import"));
+    /// ```
+    pub fn release_quarantine(&self, tag: &str) -> bool {
+        match self.shared_data.quarantine {
+            Some(ref quarantine) => quarantine.release(tag),
+            None => false,
+        }
+    }
+
+    /// Returns the number of jobs rejected over the lifetime of the pool because their tag was
+    /// quarantined, see [`Builder::quarantine_tags`].
+    ///
+    /// [`Builder::quarantine_tags`]: struct.Builder.html#method.quarantine_tags
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let pool = threadpool::Builder::new()
+    ///     .quarantine_tags(1, Duration::from_secs(60))
+    ///     .build();
+    /// pool.execute_tagged("import", || panic!("Ignore this panic, it must!"));
+    /// pool.join();
+    ///
+    /// pool.execute_tagged("import", || ());
+    /// assert_eq!(pool.rejected_count(), 1);
+    /// ```
+    pub fn rejected_count(&self) -> usize {
+        match self.shared_data.quarantine {
+            Some(ref quarantine) => quarantine.rejected(),
+            None => 0,
+        }
+    }
+
+    /// Returns the error of the operating system if the pool is short of worker threads, because
+    /// spawning one kept failing.
+    ///
+    /// A worker thread whose spawning fails, e.g. because of a transient resource exhaustion, is
+    /// retried a few times with increasing delays first. If it still cannot be spawned the pool
+    /// goes on with the threads it has, and tries to spawn the missing ones again whenever a job
+    /// is submitted. This returns `None` again once that succeeds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// assert!(pool.spawn_error().is_none());
+    /// ```
+    pub fn spawn_error(&self) -> Option<PoolError> {
+        if !self.shared_data.spawn_failed.load(Ordering::SeqCst) {
+            return None;
+        }
+        self.shared_data
+            .spawn_error
+            .lock()
+            .expect("ThreadPool::spawn_error unable to lock spawn error")
+            .as_ref()
+            .map(|error| PoolError::Spawn(io::Error::new(error.kind(), error.to_string())))
+    }
+
+    /// Submits a job doing nothing and waits up to `timeout` for a worker thread to start it,
+    /// e.g. for the liveness endpoint of a service.
+    ///
+    /// The probe job is queued behind the jobs waiting already, so a long queue reports the pool
+    /// as [`Degraded`] as long as jobs are still finishing, and only as [`Dead`] once none has
+    /// finished during `timeout`. A probe which did not start in time stays queued and runs
+    /// later. A [deterministic] pool runs its pending jobs first, like [`join`](#method.join).
+    ///
+    /// [`Degraded`]: enum.Health.html#variant.Degraded
+    /// [`Dead`]: enum.Health.html#variant.Dead
+    /// [deterministic]: struct.Builder.html#method.deterministic
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::{Health, ThreadPool};
+    /// use std::sync::mpsc::channel;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// let (tx, rx) = channel::<()>();
+    /// pool.execute(move || rx.recv().unwrap());
+    /// assert_eq!(pool.health_check(Duration::from_millis(50)), Health::Dead);
+    ///
+    /// tx.send(()).unwrap();
+    /// assert_eq!(pool.health_check(Duration::from_secs(1)), Health::Healthy);
+    /// ```
+    pub fn health_check(&self, timeout: Duration) -> Health {
+        let completed = self.completed_count();
+        let (tx, rx) = mpsc::channel();
+        let probe = Job::new(
+            Thunk::new(move || {
+                let _ = tx.send(());
+            }),
+            self.submitter,
+        );
+        if self.try_submit(probe).is_err() {
+            return Health::Dead;
+        }
+        if self.shared_data.deterministic {
+            self.run_pending();
+        }
+        match rx.recv_timeout(timeout) {
+            Ok(()) => Health::Healthy,
+            Err(_) if self.completed_count() != completed => Health::Degraded,
+            Err(_) => Health::Dead,
+        }
+    }
+
+    /// Returns the moment the pool was created.
+    ///
+    /// All handles of a pool share it, so comparing it tells whether a pool has been rebuilt, e.g.
+    /// after a configuration change.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::time::Instant;
+    ///
+    /// let before = Instant::now();
+    /// let pool = ThreadPool::new(2);
+    /// assert!(pool.created_at() >= before);
+    /// assert_eq!(pool.created_at(), pool.clone().created_at());
+    /// ```
+    pub fn created_at(&self) -> Instant {
+        self.shared_data.created_at
+    }
+
+    /// Returns the time which has passed since the pool was created.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// sleep(Duration::from_millis(10));
+    /// assert!(pool.uptime() >= Duration::from_millis(10));
+    /// ```
+    pub fn uptime(&self) -> Duration {
+        self.shared_data.created_at.elapsed()
+    }
+
+    /// Returns the total time the worker threads have spent executing jobs over the lifetime of
+    /// the pool, including the jobs which are running right now.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// for _ in 0..4 {
+    ///     pool.execute(|| sleep(Duration::from_millis(100)));
+    /// }
+    /// pool.join();
+    ///
+    /// assert!(pool.busy_time() >= Duration::from_millis(400));
+    /// ```
+    pub fn busy_time(&self) -> Duration {
+        nanos_to_duration(self.shared_data.busy_nanos())
+    }
+
+    /// Returns the statistics of every live worker thread, ordered by
+    /// [`WorkerStats::index`](struct.WorkerStats.html#method.index).
+    ///
+    /// Unlike the totals of [`busy_time`](#method.busy_time) or
+    /// [`panic_count`](#method.panic_count), these show a single worker which is stuck on a job
+    /// or which executes far fewer jobs than the others.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// for n in 0..8 {
+    ///     pool.execute_tagged(format!("job-{}", n), || ());
+    /// }
+    /// pool.join();
+    ///
+    /// let stats = pool.worker_stats();
+    /// assert_eq!(stats.len(), 2);
+    /// assert_eq!(stats.iter().map(|w| w.jobs_executed()).sum::<u64>(), 8);
+    /// for worker in stats {
+    ///     println!("worker {}: last job {:?}", worker.index(), worker.last_tag());
+    /// }
+    /// ```
+    pub fn worker_stats(&self) -> Vec<WorkerStats> {
+        let now = self.shared_data.nanos_since_creation();
+        let mut stats = self
+            .shared_data
+            .workers
+            .lock()
+            .expect("ThreadPool::worker_stats unable to lock workers")
+            .iter()
+            .map(|worker| worker.stats(now))
+            .collect::<Vec<_>>();
+        stats.sort_by_key(|worker| worker.index);
+        stats
+    }
+
+    /// Returns how long the jobs of the pool waited between being submitted and being started
+    /// by a worker thread.
+    ///
+    /// A job waiting for the [prerequisites](#method.submit_after) it was submitted with only
+    /// counts as submitted once they have finished.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// for _ in 0..4 {
+    ///     pool.execute(|| sleep(Duration::from_millis(10)));
+    /// }
+    /// pool.join();
+    ///
+    /// let waits = pool.queue_wait_stats();
+    /// assert_eq!(waits.count(), 4);
+    /// assert!(waits.quantile(1.0).unwrap() >= Duration::from_millis(30));
+    /// println!("mean wait {:?}", waits.mean().unwrap());
+    /// ```
+    pub fn queue_wait_stats(&self) -> QueueWaitStats {
+        self.shared_data.queue_waits.snapshot()
+    }
+
+    /// Returns the number of jobs which have not been started yet and the wait of the oldest of
+    /// them for every priority with such jobs, highest priority first.
+    ///
+    /// Jobs are counted like by [`pending_jobs`](#method.pending_jobs). Unlike the total of
+    /// [`queued_count`](#method.queued_count), this shows a low priority backlog which is
+    /// starved by a steady stream of higher priority jobs, see [`Scheduling::Priority`].
+    ///
+    /// [`Scheduling::Priority`]: enum.Scheduling.html#variant.Priority
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// let (tx, rx) = channel::<()>();
+    /// let (started_tx, started_rx) = channel();
+    /// pool.execute(move || {
+    ///     started_tx.send(()).unwrap();
+    ///     rx.recv().unwrap();
+    /// });
+    /// started_rx.recv().unwrap();
+    ///
+    /// pool.execute_with_priority(5, || ());
+    /// pool.execute_with_priority(-1, || ());
+    /// pool.execute_with_priority(-1, || ());
+    ///
+    /// let stats = pool.priority_queue_stats();
+    /// assert_eq!(stats.len(), 2);
+    /// assert_eq!((stats[0].priority(), stats[0].queued()), (5, 1));
+    /// assert_eq!((stats[1].priority(), stats[1].queued()), (-1, 2));
+    /// println!("backlog of priority -1 waits for {:?}", stats[1].oldest_wait());
+    /// tx.send(()).unwrap();
+    /// ```
+    pub fn priority_queue_stats(&self) -> Vec<PriorityQueueStats> {
+        self.shared_data.pending_jobs.by_priority()
+    }
+
+    /// Returns the number of finished jobs and the time they spent executing by tag, or `None`
+    /// if the pool was not built with [`Builder::profile_jobs`].
+    ///
+    /// [`Builder::profile_jobs`]: struct.Builder.html#method.profile_jobs
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    ///
+    /// let pool = threadpool::Builder::new().profile_jobs(true).build();
+    /// for _ in 0..3 {
+    ///     pool.execute_tagged("slow", || sleep(Duration::from_millis(20)));
+    ///     pool.execute_tagged("fast", || ());
+    /// }
+    /// pool.join();
+    ///
+    /// let report = pool.profile_report().unwrap();
+    /// assert_eq!(report.profiles()[0].tag(), Some("slow"));
+    /// assert_eq!(report.get(Some("fast")).unwrap().count(), 3);
+    /// assert!(report.get(Some("slow")).unwrap().mean_time() >= Duration::from_millis(20));
+    /// ```
+    pub fn profile_report(&self) -> Option<ProfileReport> {
+        self.shared_data
+            .profiler
+            .as_ref()
+            .map(|profiler| profiler.report())
+    }
+
+    /// Starts a new statistics interval, so that dashboards can tell the rates of the pool per
+    /// interval instead of over its lifetime.
+    ///
+    /// This starts the interval of [`interval_stats`](#method.interval_stats) anew, and resets
+    /// [`worker_stats`](#method.worker_stats), [`queue_wait_stats`](#method.queue_wait_stats),
+    /// [`profile_report`](#method.profile_report) and the sampling window of
+    /// [`utilization`](#method.utilization) for all handles of the pool. The lifetime totals,
+    /// e.g. [`completed_count`](#method.completed_count) and [`busy_time`](#method.busy_time),
+    /// keep counting. Jobs finishing while the statistics are reset may be counted in either
+    /// interval.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// for _ in 0..10 {
+    ///     pool.execute(|| ());
+    /// }
+    /// pool.join();
+    /// assert_eq!(pool.interval_stats().completed_count(), 10);
+    ///
+    /// pool.reset_stats();
+    /// pool.execute(|| ());
+    /// pool.join();
+    /// assert_eq!(pool.interval_stats().completed_count(), 1);
+    /// assert_eq!(pool.completed_count(), 11);
+    /// ```
+    pub fn reset_stats(&self) {
+        let shared_data = &self.shared_data;
+        let now = shared_data.nanos_since_creation();
+        for worker in shared_data
+            .workers
+            .lock()
+            .expect("ThreadPool::reset_stats unable to lock workers")
+            .iter()
+        {
+            // The busy time of the pool keeps what the worker forgets.
+            shared_data
+                .retired_busy_nanos
+                .fetch_add(worker.busy_nanos(now), Ordering::SeqCst);
+            worker.reset_stats(now);
+        }
+        let busy = shared_data.busy_nanos();
+        *shared_data
+            .utilization_sample
+            .lock()
+            .expect("ThreadPool::reset_stats unable to lock sample") = (now, busy);
+        *shared_data
+            .interval_start
+            .lock()
+            .expect("ThreadPool::reset_stats unable to lock interval") = IntervalStart {
+            at: now,
+            completed_count: shared_data.completed_count.load(Ordering::SeqCst),
+            panic_count: shared_data.panic_count.load(Ordering::SeqCst),
+            respawn_count: shared_data.respawn_count.load(Ordering::SeqCst),
+            busy_nanos: busy,
+        };
+        shared_data.queue_waits.reset();
+        if let Some(ref profiler) = shared_data.profiler {
+            profiler.reset();
+        }
+    }
+
+    /// Returns the statistics of the pool since [`reset_stats`](#method.reset_stats) was last
+    /// called on any handle of this pool, or since the creation of the pool.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// pool.execute(|| panic!("boom"));
+    /// pool.join();
+    ///
+    /// pool.reset_stats();
+    /// pool.execute(|| ());
+    /// pool.join();
+    ///
+    /// let stats = pool.interval_stats();
+    /// assert_eq!(stats.completed_count(), 1);
+    /// assert_eq!(stats.panic_count(), 0);
+    /// assert_eq!(pool.panic_count(), 1);
+    /// ```
+    pub fn interval_stats(&self) -> IntervalStats {
+        let shared_data = &self.shared_data;
+        let start = shared_data
+            .interval_start
+            .lock()
+            .expect("ThreadPool::interval_stats unable to lock interval");
+        let now = shared_data.nanos_since_creation();
+        let since =
+            |total: &AtomicUsize, start: usize| total.load(Ordering::SeqCst).saturating_sub(start);
+        IntervalStats {
+            elapsed: nanos_to_duration(now.saturating_sub(start.at)),
+            completed_count: since(&shared_data.completed_count, start.completed_count),
+            panic_count: since(&shared_data.panic_count, start.panic_count),
+            respawn_count: since(&shared_data.respawn_count, start.respawn_count),
+            busy_time: nanos_to_duration(shared_data.busy_nanos().saturating_sub(start.busy_nanos)),
+        }
+    }
+
+    /// Returns the fraction of time the worker threads spent executing jobs since the previous
+    /// call to `utilization` on any handle of this pool, or since the creation of the pool for the
+    /// first call.
+    ///
+    /// The value is between `0.0` (all threads were idle) and `1.0` (all
+    /// [`max_count`](#method.max_count) threads were busy during the whole sampling window).
+    /// Calling it periodically tells whether the pool is over- or under-provisioned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(2);
+    /// pool.utilization(); // start a new sampling window
+    ///
+    /// pool.execute(|| sleep(Duration::from_millis(200)));
+    /// sleep(Duration::from_millis(200));
+    ///
+    /// // One of two threads was busy most of the time.
+    /// let utilization = pool.utilization();
+    /// assert!(utilization > 0.25 && utilization <= 0.5);
+    /// ```
+    pub fn utilization(&self) -> f64 {
+        let mut sample = self
+            .shared_data
+            .utilization_sample
+            .lock()
+            .expect("ThreadPool::utilization unable to lock sample");
+        let now = self.shared_data.nanos_since_creation();
+        let busy = self.shared_data.busy_nanos();
+        let (window, busy_in_window) = (now - sample.0, busy.saturating_sub(sample.1));
+        *sample = (now, busy);
+
+        let capacity = window as f64 * self.max_count() as f64;
+        if capacity > 0.0 {
+            (busy_in_window as f64 / capacity).min(1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// **Deprecated: Use [`ThreadPool::set_num_threads`](#method.set_num_threads)**
+    #[deprecated(since = "1.3.0", note = "use ThreadPool::set_num_threads")]
+    pub fn set_threads(&mut self, num_threads: usize) {
+        self.set_num_threads(num_threads)
+    }
+
+    /// Sets the number of worker-threads to use as `num_threads`.
+    /// Can be used to change the threadpool size during runtime.
+    /// Will not abort already running or waiting threads.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `num_threads` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::time::Duration;
+    /// use std::thread::sleep;
+    ///
+    /// let mut pool = ThreadPool::new(4);
+    /// for _ in 0..10 {
+    ///     pool.execute(move || {
+    ///         sleep(Duration::from_secs(100));
+    ///     });
+    /// }
+    ///
+    /// sleep(Duration::from_secs(1)); // wait for threads to start
+    /// assert_eq!(4, pool.active_count());
+    /// assert_eq!(6, pool.queued_count());
+    ///
+    /// // Increase thread capacity of the pool
+    /// pool.set_num_threads(8);
+    ///
+    /// sleep(Duration::from_secs(1)); // wait for new threads to start
+    /// assert_eq!(8, pool.active_count());
+    /// assert_eq!(2, pool.queued_count());
+    ///
+    /// // Decrease thread capacity of the pool
+    /// // No active threads are killed
+    /// pool.set_num_threads(4);
+    ///
+    /// assert_eq!(8, pool.active_count());
+    /// assert_eq!(2, pool.queued_count());
+    /// ```
+    pub fn set_num_threads(&mut self, num_threads: usize) {
+        assert!(num_threads >= 1);
+        resize(&self.shared_data, num_threads);
+    }
+
+    /// Sets the number of worker threads to the number of CPUs currently available to the
+    /// process, see [`available_parallelism`], and returns it.
+    ///
+    /// The available CPUs change e.g. when CPUs of a virtual machine are hot-plugged or the CPU
+    /// quota of a container is updated. See [`Builder::auto_resize`] to do this periodically.
+    ///
+    /// [`available_parallelism`]: fn.available_parallelism.html
+    /// [`Builder::auto_resize`]: struct.Builder.html#method.auto_resize
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let mut pool = ThreadPool::new(1);
+    /// let num_threads = pool.resize_auto();
+    /// assert_eq!(num_threads, threadpool::available_parallelism());
+    /// assert_eq!(pool.max_count(), num_threads);
+    /// ```
+    pub fn resize_auto(&mut self) -> usize {
+        let num_threads = available_parallelism();
+        self.set_num_threads(num_threads);
+        num_threads
+    }
+
+    /// Resize the pool to the number of threads returned by `target` whenever the process
+    /// receives `signal`, e.g. `SIGHUP`, so that operators can tune a live service without
+    /// restarting it. The pool keeps its size if `target` returns `None`, 0 or panics.
+    ///
+    /// `target` runs on a thread dedicated to dispatching the signals, not in the signal handler,
+    /// so it may do anything, like reading a configuration file or an environment variable.
+    /// Several pools may resize on the same signal. Once registered, the handler stays
+    /// installed for the lifetime of the process, the default action of `signal`, e.g. terminating
+    /// the process, is not taken any more.
+    ///
+    /// This is only available on Unix platforms with the `signal` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the handler could not be installed, e.g. for `SIGKILL`, or the
+    /// dispatching thread could not be spawned. Returns an error of kind
+    /// [`AlreadyExists`](https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.AlreadyExists)
+    /// if a handler other than the default action or ignoring the signal is installed for
+    /// `signal` already, which would never be called again otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate libc;
+    /// extern crate threadpool;
+    ///
+    /// use std::env;
+    /// use threadpool::ThreadPool;
+    ///
+    /// # fn main() {
+    /// let pool = ThreadPool::new(4);
+    /// pool.resize_on_signal(libc::SIGHUP, || {
+    ///     env::var("WORKERS").ok().and_then(|workers| workers.parse().ok())
+    /// })
+    /// .unwrap();
+    /// # }
+    /// ```
+    #[cfg(all(unix, feature = "signal"))]
+    pub fn resize_on_signal<F>(&self, signal: libc::c_int, target: F) -> io::Result<()>
+    where
+        F: Fn() -> Option<usize> + Send + Sync + 'static,
+    {
+        signal::register(&self.shared_data, signal, Arc::new(target))
+    }
+
+    /// Returns the name given to the worker threads of the pool, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::with_name("worker".into(), 2);
+    /// assert_eq!(pool.name().as_deref(), Some("worker"));
+    /// assert_eq!(ThreadPool::new(2).name(), None);
+    /// ```
+    pub fn name(&self) -> Option<String> {
+        self.shared_data.name()
+    }
+
+    /// Renames the pool.
+    ///
+    /// The new name is given to all worker threads spawned from now on, including the ones
+    /// replacing exited threads and the ones added by [`set_num_threads`](#method.set_num_threads),
+    /// and is reported by [`name`](#method.name) and [`prometheus_metrics`]. Threads which are
+    /// already running keep their name.
+    ///
+    /// [`prometheus_metrics`]: fn.prometheus_metrics.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::thread;
+    ///
+    /// let mut pool = ThreadPool::with_name("old".into(), 1);
+    /// pool.set_name("new".into());
+    /// pool.set_num_threads(2);
+    /// assert_eq!(pool.name().as_deref(), Some("new"));
+    /// ```
+    pub fn set_name(&mut self, name: String) {
+        *self
+            .shared_data
+            .name
+            .lock()
+            .expect("ThreadPool::set_name unable to lock name") = Some(name);
+    }
+
+    /// Shut the pool down without waiting for its jobs.
+    ///
+    /// All jobs still waiting in the queue are dropped without being run and the
+    /// [`CancellationToken`]s of jobs submitted with
+    /// [`execute_cancellable`](#method.execute_cancellable) are cancelled. Running jobs are not
+    /// interrupted, the worker threads exit as soon as they have finished them.
+    ///
+    /// Returns the number of jobs which were discarded. Submitting new jobs afterwards panics.
+    ///
+    /// [`CancellationToken`]: struct.CancellationToken.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// pool.execute_cancellable(|token| {
+    ///     while !token.is_cancelled() {
+    ///         sleep(Duration::from_millis(10));
+    ///     }
+    /// });
+    /// for _ in 0..4 {
+    ///     pool.execute(|| println!("never printed"));
+    /// }
+    ///
+    /// sleep(Duration::from_millis(100)); // wait for the first job to start
+    /// assert_eq!(4, pool.shutdown_now());
+    /// pool.join();
+    /// ```
+    pub fn shutdown_now(&self) -> usize {
+        self.shared_data.cancellation.cancel();
+        self.shared_data.close();
+
+        let mut discarded = 0;
+        while let Some(job) = self.shared_data.job_queue.try_pop() {
+            if job.wake {
+                continue;
+            }
+            self.shared_data.queued_count.fetch_sub(1, Ordering::SeqCst);
+            self.shared_data.release_bytes(job.size);
+            drop(job);
+            discarded += 1;
+        }
+        self.shared_data.no_work_notify_all();
+        self.shared_data.check_terminated();
+        discarded
+    }
+
+    /// Shut the pool down, giving its jobs up to `timeout` to finish.
+    ///
+    /// No new jobs are accepted from now on, submitting them panics. The queued and running jobs
+    /// get until `timeout` has passed to finish, then the pool is shut down like with
+    /// [`shutdown_now`](#method.shutdown_now): the jobs still queued are dropped without being
+    /// run and the jobs still running see their [`CancellationToken`]s cancelled.
+    ///
+    /// Returns the number of jobs which were discarded, `0` if all jobs finished in time.
+    ///
+    /// [`CancellationToken`]: struct.CancellationToken.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// for _ in 0..4 {
+    ///     pool.execute(|| sleep(Duration::from_millis(100)));
+    /// }
+    ///
+    /// let discarded = pool.shutdown_timeout(Duration::from_millis(250));
+    /// assert!(discarded >= 1);
+    /// ```
+    pub fn shutdown_timeout(&self, timeout: Duration) -> usize {
+        let deadline = Instant::now() + timeout;
+        self.shared_data.close();
+        if self.shared_data.deterministic {
+            self.run_pending();
+        }
+
+        let mut lock = self
+            .shared_data
+            .empty_trigger
+            .lock()
+            .expect("ThreadPool::shutdown_timeout unable to lock empty_trigger");
+        while self.shared_data.has_work() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            lock = self
+                .shared_data
+                .empty_condvar
+                .wait_timeout(lock, deadline - now)
+                .expect("ThreadPool::shutdown_timeout unable to wait for jobs")
+                .0;
+        }
+        drop(lock);
+        self.shutdown_now()
+    }
+
+    /// Register `hook` to be called once the pool has terminated: its last handle has been dropped
+    /// or it has been shut down with [`shutdown_now`](#method.shutdown_now), and the last worker
+    /// thread has exited.
+    ///
+    /// The hooks run exactly once, on the last worker thread to exit, or on the thread shutting
+    /// down the pool if no workers are left at that point. A hook registered after the pool has
+    /// terminated runs right away.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let (tx, rx) = channel();
+    /// pool.on_shutdown(move || tx.send("terminated").unwrap());
+    ///
+    /// pool.execute(|| println!("last job"));
+    /// drop(pool);
+    /// assert_eq!(Ok("terminated"), rx.recv());
+    /// ```
+    pub fn on_shutdown<F>(&self, hook: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.shared_data
+            .shutdown_hooks
+            .lock()
+            .expect("ThreadPool::on_shutdown unable to lock shutdown hooks")
+            .push(Box::new(hook));
+        self.shared_data.check_terminated();
+    }
+
+    /// Executes `job` on `n` threads of the pool at the same time, passing each of them its index
+    /// in the group, from `0` to `n - 1`.
+    ///
+    /// The group is held back until it has `n` workers: the members occupy their worker threads as
+    /// soon as they are dequeued and only start `job` once all of them are there. This allows
+    /// jobs to synchronize with each other, e.g. with a [`Barrier`], without deadlocking when
+    /// only part of the group could start.
+    ///
+    /// The members are queued one after another, so with [`Scheduling::Fifo`] groups submitted
+    /// from the same thread are formed in the order they were submitted. Groups submitted
+    /// concurrently from different threads, or with other scheduling disciplines, may have their
+    /// members interleaved, which can deadlock if the groups together need more threads than the
+    /// pool has. If the pool is shut down with
+    /// [`shutdown_now`](#method.shutdown_now) before all members have started, none of them runs
+    /// `job`.
+    ///
+    /// [`Barrier`]: https://doc.rust-lang.org/std/sync/struct.Barrier.html
+    /// [`Scheduling::Fifo`]: enum.Scheduling.html#variant.Fifo
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `n` is 0 or larger than [`max_count`](#method.max_count), or if
+    /// the pool has been shut down with [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::{Arc, Barrier};
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let barrier = Arc::new(Barrier::new(3));
+    /// pool.execute_gang(3, move |index| {
+    ///     println!("member {} before the barrier", index);
+    ///     barrier.wait();
+    ///     println!("member {} after the barrier", index);
+    /// });
+    /// pool.join();
+    /// ```
+    pub fn execute_gang<F>(&self, n: usize, job: F)
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        assert!(n >= 1, "execute_gang needs at least one member");
+        assert!(
+            n <= self.max_count(),
+            "execute_gang needs more threads than the pool has"
+        );
+        let gang = Arc::new(Gang {
+            state: Mutex::new((0, false)),
+            condvar: Condvar::new(),
+            size: n,
+        });
+        let job = Arc::new(job);
+        let _submitting = self
+            .shared_data
+            .gang_submission
+            .lock()
+            .expect("ThreadPool::execute_gang unable to lock gang submission");
+        for index in 0..n {
+            let member = GangMember {
+                gang: gang.clone(),
+                started: false,
+            };
+            let job = job.clone();
+            self.execute(move || {
+                if member.start().wait() {
+                    job(index);
+                }
+            });
+        }
+    }
+
+    /// Executes `job` `n` times on the pool, passing each instance its index from `0` to `n - 1`,
+    /// and returns a [`JobHandle`] which finishes once all instances have finished.
+    ///
+    /// This suits homogeneous worker loops, e.g. `n` instances draining a shared queue. Up to
+    /// [`max_count`](#method.max_count) instances run at the same time, see
+    /// [`execute_gang`](#method.execute_gang) for instances which have to run together.
+    ///
+    /// If instances panic, the handle reports the panic of the first of them to finish, after all
+    /// instances have finished. Each panic is still counted by
+    /// [`panic_count`](#method.panic_count).
+    ///
+    /// [`JobHandle`]: struct.JobHandle.html
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::mpsc::channel;
+    /// use std::sync::{Arc, Mutex};
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let (tx, rx) = channel();
+    /// for i in 0..100 {
+    ///     tx.send(i).unwrap();
+    /// }
+    /// drop(tx);
+    ///
+    /// let rx = Arc::new(Mutex::new(rx));
+    /// let (results_tx, results_rx) = channel();
+    /// let results_tx = Mutex::new(results_tx);
+    /// let drained = pool.execute_n(4, move |_| {
+    ///     while let Ok(i) = rx.lock().unwrap().recv() {
+    ///         results_tx.lock().unwrap().send(i * 2).unwrap();
+    ///     }
+    /// });
+    ///
+    /// drained.join().unwrap();
+    /// assert_eq!(results_rx.try_iter().sum::<i32>(), 9900);
+    /// ```
+    pub fn execute_n<F>(&self, n: usize, job: F) -> JobHandle<()>
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        let (completer, handle) = handle::pair();
+        if n == 0 {
+            completer.complete(Ok(()));
+            return handle;
+        }
+        let instances = Arc::new(Instances {
+            state: Mutex::new((n, None, Some(completer))),
+        });
+        let job = Arc::new(job);
+        for index in 0..n {
+            let job = job.clone();
+            let instances = instances.clone();
+            self.execute_with_callback(move || job(index), move |result| instances.done(result));
+        }
+        handle
+    }
+
+    /// Applies `f` to the items received from `rx` on all worker threads of the pool, until the
+    /// channel is closed, and returns a [`JobHandle`] which finishes once all items are done.
+    ///
+    /// One loop per [`max_count`](#method.max_count) worker threads takes turns receiving from
+    /// `rx` and runs `f` concurrently with the others, so the pool is fully occupied until all
+    /// senders are dropped. If `f` panics, the loop which ran it stops, the others go on; the
+    /// handle reports the panic once they are done.
+    ///
+    /// [`JobHandle`]: struct.JobHandle.html
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::mpsc::channel;
+    /// use std::sync::Arc;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let (tx, rx) = channel();
+    /// let total = Arc::new(AtomicUsize::new(0));
+    /// let consumed = {
+    ///     let total = total.clone();
+    ///     pool.consume(rx, move |n| {
+    ///         total.fetch_add(n, Ordering::SeqCst);
+    ///     })
+    /// };
+    ///
+    /// for n in 1..=100 {
+    ///     tx.send(n).unwrap();
+    /// }
+    /// drop(tx);
+    ///
+    /// consumed.join().unwrap();
+    /// assert_eq!(total.load(Ordering::SeqCst), 5050);
+    /// ```
+    pub fn consume<T, F>(&self, rx: Receiver<T>, f: F) -> JobHandle<()>
+    where
+        T: Send + 'static,
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        let rx = Mutex::new(rx);
+        self.execute_n(cmp::max(self.max_count(), 1), move |_| loop {
+            // The lock is released before `f` runs, so the next loop can receive meanwhile.
+            let item = rx
+                .lock()
+                .expect("ThreadPool::consume unable to lock receiver")
+                .recv();
+            match item {
+                Ok(item) => f(item),
+                Err(_) => return,
+            }
+        })
+    }
+
+    /// Applies `map` to every item of `items` on the pool and combines the results with
+    /// `reduce`, starting from `identity()`.
+    ///
+    /// Every worker thread folds the items it takes into its own partial result, so `reduce` runs
+    /// concurrently and only the [`max_count`](#method.max_count) partial results are combined on
+    /// the calling thread at the end. Items are handed out one at a time in the order of
+    /// `items`, but may be combined in any order, so `reduce` has to be associative and
+    /// commutative, with `identity()` as its neutral element.
+    ///
+    /// If `map` or `reduce` panics, the panic is resumed on the calling thread once all worker
+    /// threads are done. Calling `map_reduce` from a thread within the pool may deadlock.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let words = vec!["map", "reduce", "on", "a", "thread", "pool"];
+    ///
+    /// let letters = pool.map_reduce(words, |word| word.len(), || 0, |a, b| a + b);
+    /// assert_eq!(letters, 22);
+    /// ```
+    pub fn map_reduce<I, M, T, Z, R>(&self, items: I, map: M, identity: Z, reduce: R) -> T
+    where
+        I: IntoIterator,
+        I::IntoIter: Send + 'static,
+        M: Fn(I::Item) -> T + Send + Sync + 'static,
+        T: Send + 'static,
+        Z: Fn() -> T + Send + Sync + 'static,
+        R: Fn(T, T) -> T + Send + Sync + 'static,
+    {
+        let items = Arc::new(Mutex::new(items.into_iter()));
+        let map = Arc::new(map);
+        let identity = Arc::new(identity);
+        let reduce = Arc::new(reduce);
+        let partials: Vec<_> = (0..cmp::max(self.max_count(), 1))
+            .map(|_| {
+                let (items, map, identity, reduce) =
+                    (items.clone(), map.clone(), identity.clone(), reduce.clone());
+                self.spawn(move || {
+                    let mut partial = identity();
+                    loop {
+                        let item = items
+                            .lock()
+                            .expect("ThreadPool::map_reduce unable to lock items")
+                            .next();
+                        match item {
+                            Some(item) => partial = reduce(partial, map(item)),
+                            None => return partial,
+                        }
+                    }
+                })
+            })
+            .collect();
+        if self.shared_data.deterministic {
+            self.run_pending();
+        }
+
+        let mut result = Some(identity());
+        let mut panic = None;
+        for partial in partials {
+            match partial.join() {
+                Ok(partial) => result = result.map(|result| reduce(result, partial)),
+                Err(error) => {
+                    result = None;
+                    if panic.is_none() && error.is_panic() {
+                        panic = Some(error.into_panic());
+                    }
+                }
+            }
+        }
+        match (result, panic) {
+            (Some(result), _) => result,
+            (None, Some(payload)) => panic::resume_unwind(payload),
+            (None, None) => panic!("ThreadPool::map_reduce jobs were cancelled"),
+        }
+    }
+
+    /// Block the current thread until every worker thread of the pool has passed a
+    /// synchronization point.
+    ///
+    /// A job is delivered to each of the [`max_count`](#method.max_count) workers, and no worker
+    /// continues with other jobs until all of them have arrived. Afterwards every worker has
+    /// finished the jobs it was running when `sync_all` was called and observes all changes made
+    /// before the call. Jobs dropped by [`shutdown_now`](#method.shutdown_now) count as arrived.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been shut down with
+    /// [`shutdown_now`](#method.shutdown_now), or if it is called from a thread within the pool,
+    /// which would deadlock otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let batch_size = Arc::new(AtomicUsize::new(16));
+    ///
+    /// batch_size.store(64, Ordering::Relaxed);
+    /// pool.sync_all();
+    /// // Every worker now sees the new batch size.
+    /// ```
+    pub fn sync_all(&self) {
+        self.assert_not_worker_thread("sync_all");
+        let workers = self.max_count();
+        let point = Arc::new(SyncPoint {
+            remaining: Mutex::new(workers),
+            condvar: Condvar::new(),
+        });
+        for _ in 0..workers {
+            let arrival = Arrival(point.clone());
+            self.execute(move || {
+                let point = arrival.0.clone();
+                drop(arrival);
+                point.wait();
+            });
+        }
+        point.wait();
+    }
+
+    /// Register `callback` to be called whenever the pool runs out of work, that is when the last
+    /// active job finishes and no jobs are queued.
+    ///
+    /// The callback runs on the worker thread which finished the last job, so it may submit more
+    /// jobs to the pool, e.g. to start the next phase of a batch. Panics of the callback are
+    /// ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::mpsc::channel;
+    /// use std::sync::Mutex;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let (tx, rx) = channel();
+    /// let tx = Mutex::new(tx);
+    /// pool.on_idle(move || tx.lock().unwrap().send(()).unwrap());
+    ///
+    /// for _ in 0..8 {
+    ///     pool.execute(|| {});
+    /// }
+    /// rx.recv().unwrap();
+    /// ```
+    pub fn on_idle<F>(&self, callback: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.shared_data
+            .idle_callbacks
+            .lock()
+            .expect("ThreadPool::on_idle unable to lock idle callbacks")
+            .push(Arc::new(callback));
+    }
+
+    /// Register `callback` to be called when the oldest job which has not started yet has waited
+    /// longer than `threshold`, with the time it has waited so far.
+    ///
+    /// This is the signal to add capacity or to shed load upstream. The callback runs on a
+    /// separate thread watching the queue, so it is called even while all worker threads are
+    /// stuck. It fires once when the threshold is exceeded and again only after the queue has
+    /// caught up in between. Panics of the callback are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the watching thread could not be spawned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::mpsc::channel;
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    ///
+    /// let pool = ThreadPool::new(1);
+    /// let (tx, rx) = channel();
+    /// pool.on_queue_latency_exceeded(Duration::from_millis(50), move |waited| {
+    ///     tx.send(waited).unwrap();
+    /// })
+    /// .unwrap();
+    ///
+    /// pool.execute(|| sleep(Duration::from_millis(200)));
+    /// pool.execute(|| ());
+    /// assert!(rx.recv().unwrap() > Duration::from_millis(50));
+    /// ```
+    pub fn on_queue_latency_exceeded<F>(
+        &self,
+        threshold: Duration,
+        callback: F,
+    ) -> Result<(), PoolError>
+    where
+        F: Fn(Duration) + Send + 'static,
+    {
+        spawn_latency_alarm(&self.shared_data, threshold, Box::new(callback))
+            .map_err(PoolError::Spawn)
+    }
+
+    /// Run the queued jobs of a [deterministic] pool on the calling thread, until no job is left,
+    /// and return how many jobs ran. This includes the jobs submitted by the jobs which ran.
+    ///
+    /// The jobs run as they would on a worker thread: their panics are caught and counted and
+    /// [`ThreadPool::current`] returns this pool.
+    ///
+    /// [deterministic]: struct.Builder.html#method.deterministic
+    /// [`ThreadPool::current`]: #method.current
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the pool is not deterministic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::mpsc::channel;
+    /// use threadpool::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new_deterministic();
+    /// let (tx, rx) = channel();
+    /// pool.execute(move || tx.send("ran").unwrap());
+    ///
+    /// assert!(rx.try_recv().is_err());
+    /// pool.run_pending();
+    /// assert_eq!(rx.try_recv(), Ok("ran"));
+    /// ```
+    pub fn run_pending(&self) -> usize {
+        assert!(
+            self.shared_data.deterministic,
+            "ThreadPool::run_pending called on a pool which is not deterministic"
+        );
+        run_on_caller(&self.shared_data)
+    }
+
+    /// Block the current thread until all jobs in the pool have been executed.
+    ///
+    /// Calling `join` on an empty pool will cause an immediate return.
+    /// `join` may be called from multiple threads concurrently.
+    /// A `join` is an atomic point in time. All threads joining before the join
+    /// event will exit together even if the pool is processing new jobs by the
+    /// time they get scheduled.
+    ///
+    /// A job cannot wait for the pool it runs on to finish, as it keeps the pool busy itself.
+    ///
+    /// A [deterministic] pool first runs its queued jobs on the calling thread, see
+    /// [`run_pending`](#method.run_pending).
+    ///
+    /// [deterministic]: struct.Builder.html#method.deterministic
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if it is called from a thread within the pool, which would
+    /// deadlock otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// let pool = ThreadPool::new(8);
+    /// let test_count = Arc::new(AtomicUsize::new(0));
+    ///
+    /// for _ in 0..42 {
+    ///     let test_count = test_count.clone();
+    ///     pool.execute(move || {
+    ///         test_count.fetch_add(1, Ordering::Relaxed);
+    ///     });
+    /// }
+    ///
+    /// pool.join();
+    /// assert_eq!(42, test_count.load(Ordering::Relaxed));
+    /// ```
+    pub fn join(&self) {
+        self.assert_not_worker_thread("join");
+        if self.shared_data.deterministic {
+            self.run_pending();
+        }
+        // fast path requires no mutex
+        if !self.shared_data.has_work() {
+            return;
+        }
+
+        let generation = self.shared_data.join_generation.load(Ordering::SeqCst);
+        let mut lock = self.shared_data.empty_trigger.lock().unwrap();
+
+        while generation == self.shared_data.join_generation.load(Ordering::Relaxed)
+            && self.shared_data.has_work()
+        {
+            lock = self.shared_data.empty_condvar.wait(lock).unwrap();
+        }
+
+        // increase generation if we are the first thread to come out of the loop
+        let _ = self.shared_data.join_generation.compare_exchange(
+            generation,
+            generation.wrapping_add(1),
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+    }
+}
+
+impl Clone for ThreadPool {
+    /// Cloning a pool will create a new handle to the pool.
+    /// The behavior is similar to [Arc](https://doc.rust-lang.org/stable/std/sync/struct.Arc.html).
+    ///
+    /// We could for example submit jobs from multiple threads concurrently.
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::thread;
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let pool = ThreadPool::with_name("clone example".into(), 2);
+    ///
+    /// let results = (0..2)
+    ///     .map(|i| {
+    ///         let pool = pool.clone();
+    ///         thread::spawn(move || {
+    ///             let (tx, rx) = channel();
+    ///             for i in 1..12 {
+    ///                 let tx = tx.clone();
+    ///                 pool.execute(move || {
+    ///                     tx.send(i).expect("channel will be waiting");
+    ///                 });
+    ///             }
+    ///             drop(tx);
+    ///             if i == 0 {
+    ///                 rx.iter().fold(0, |accumulator, element| accumulator + element)
+    ///             } else {
+    ///                 rx.iter().fold(1, |accumulator, element| accumulator * element)
+    ///             }
+    ///         })
+    ///     })
+    ///     .map(|join_handle| join_handle.join().expect("collect results from threads"))
+    ///     .collect::<Vec<usize>>();
+    ///
+    /// assert_eq!(vec![66, 39916800], results);
+    /// ```
+    ///
+    /// With [`Scheduling::Fair`](enum.Scheduling.html#variant.Fair) the new handle gets its own
+    /// turn when the workers pick the next job. Clones of a handle created with
+    /// [`handle_with_limit`](#method.handle_with_limit) share its limit.
+    fn clone(&self) -> ThreadPool {
+        self.shared_data.handle_count.fetch_add(1, Ordering::SeqCst);
+        let mut handle = ThreadPool::new_handle(self.shared_data.clone());
+        handle.limiter = self.limiter.clone();
+        handle.overflow = self.overflow.clone();
+        handle
+    }
+}
+
+impl<F> Extend<F> for ThreadPool
+where
+    F: FnOnce() + Send + 'static,
+{
+    /// Executes every job of `jobs` on the pool, like calling [`execute`](#method.execute) for
+    /// each of them.
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let mut pool = ThreadPool::new(4);
+    /// let (tx, rx) = channel();
+    /// pool.extend((0..8).map(|i| {
+    ///     let tx = tx.clone();
+    ///     move || tx.send(i * i).unwrap()
+    /// }));
+    /// drop(tx);
+    ///
+    /// assert_eq!(rx.iter().sum::<i32>(), 140);
+    /// ```
+    fn extend<I: IntoIterator<Item = F>>(&mut self, jobs: I) {
+        for job in jobs {
+            self.execute(job);
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        if self.shared_data.handle_count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.shared_data.close();
+            self.shared_data.check_terminated();
+        }
+    }
+}
+
+/// Returns the number of CPUs the current process may use, which is the number of threads of
+/// pools whose size is not given explicitly.
+///
+/// Besides the logical cores of the machine this takes the CPU affinity of the process and, on
+/// Linux, the CPU quota of its cgroup into account. A container limited to two CPUs therefore
+/// gets pools of two threads, however many cores the host has. A fractional quota is rounded up.
+///
+/// # Examples
+///
+/// ```
+/// let pool = threadpool::ThreadPool::default();
+/// assert_eq!(pool.max_count(), threadpool::available_parallelism());
+/// ```
+pub fn available_parallelism() -> usize {
+    num_cpus::get()
+}
+
+/// Lets other jobs run before the rest of the current job: `continuation` is queued behind the
+/// jobs which are waiting already, and the current job should return right after.
+///
+/// This keeps a long running job from monopolizing its worker thread, by splitting it into
+/// steps which take turns with the other jobs. The continuation inherits the tag, priority and
+/// [`CancellationToken`] of the current job. Called outside of a job, e.g. after the pool has
+/// been dropped, `continuation` runs right away on the calling thread. If the pool is shut down
+/// with [`ThreadPool::shutdown_now`], it is discarded like the other queued jobs.
+///
+/// [`CancellationToken`]: struct.CancellationToken.html
+/// [`ThreadPool::shutdown_now`]: struct.ThreadPool.html#method.shutdown_now
+///
+/// # Examples
+///
+/// ```
+/// use threadpool::ThreadPool;
+/// use std::sync::mpsc::{channel, Sender};
+///
+/// fn count_down(n: u32, tx: Sender<u32>) {
+///     tx.send(n).unwrap();
+///     if n > 0 {
+///         threadpool::yield_now(move || count_down(n - 1, tx));
+///     }
+/// }
+///
+/// let pool = ThreadPool::new(1);
+/// let (tx, rx) = channel();
+/// {
+///     let tx = tx.clone();
+///     pool.execute(move || count_down(2, tx));
+/// }
+/// pool.execute(move || tx.send(100).unwrap());
+///
+/// assert_eq!(rx.iter().take(4).collect::<Vec<_>>(), vec![2, 100, 1, 0]);
+/// ```
+pub fn yield_now<F>(continuation: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let origin = CURRENT_JOB.with(|current| current.borrow().clone());
+    let (pool, origin) = match (ThreadPool::current(), origin) {
+        (Some(pool), Some(origin)) => (pool, origin),
+        _ => return continuation(),
+    };
+    let mut job = Job {
+        tag: origin.tag,
+        priority: origin.priority,
+        ..Job::new(Thunk::new(continuation), origin.submitter)
+    };
+    job.token = CancellationToken::current();
+    // Only fails once the pool has been shut down, which discards queued jobs.
+    let _ = pool.try_submit(job);
+}
+
+/// Create a thread pool with one thread per CPU the process may use, see
+/// [`available_parallelism`]. On machines with hyperthreading, this will create one thread per
+/// hyperthread.
+///
+/// [`available_parallelism`]: fn.available_parallelism.html
+impl Default for ThreadPool {
+    fn default() -> Self {
+        ThreadPool::new(available_parallelism())
+    }
+}
+
+impl fmt::Debug for ThreadPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ThreadPool")
+            .field("name", &self.shared_data.name())
+            .field("queued_count", &self.queued_count())
+            .field("active_count", &self.active_count())
+            .field("max_count", &self.max_count())
+            .finish()
+    }
+}
+
+impl PartialEq for ThreadPool {
+    /// Check if you are working with the same pool
+    ///
+    /// ```
+    /// use threadpool::ThreadPool;
+    ///
+    /// let a = ThreadPool::new(2);
+    /// let b = ThreadPool::new(2);
+    ///
+    /// assert_eq!(a, a);
+    /// assert_eq!(b, b);
+    ///
+    /// # // TODO: change this to assert_ne in the future
+    /// assert!(a != b);
+    /// assert!(b != a);
+    /// ```
+    fn eq(&self, other: &ThreadPool) -> bool {
+        Arc::ptr_eq(&self.shared_data, &other.shared_data)
+    }
+}
+impl Eq for ThreadPool {}
+
+/// Spawn a worker thread which has been counted in `thread_count` already, retrying with
+/// increasing delays while the operating system refuses. Returns `false` if the thread was given
+/// up on, the error is then reported by `ThreadPool::spawn_error`.
+fn spawn_in_pool(shared_data: Arc<ThreadPoolSharedData>) -> bool {
+    let mut delay = SPAWN_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match try_spawn_in_pool(shared_data.clone()) {
+            Ok(()) => {
+                shared_data.spawn_failed.store(false, Ordering::SeqCst);
+                return true;
+            }
+            Err(_) if attempt < SPAWN_ATTEMPTS && THREADS_SUPPORTED => {
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(error) => {
+                *shared_data
+                    .spawn_error
+                    .lock()
+                    .expect("Unable to lock spawn error") = Some(error);
+                shared_data.spawn_failed.store(true, Ordering::SeqCst);
+                shared_data.thread_count.fetch_sub(1, Ordering::SeqCst);
+                shared_data.check_terminated();
+                return false;
+            }
+        }
+    }
+}
+
+fn try_spawn_in_pool(shared_data: Arc<ThreadPoolSharedData>) -> io::Result<()> {
+    let mut builder = thread::Builder::new();
+    if let Some(name) = shared_data.name() {
+        builder = builder.name(name);
+    }
+    if let Some(ref stack_size) = shared_data.stack_size {
+        builder = builder.stack_size(stack_size.to_owned());
+    }
+    let factory = shared_data.thread_factory.clone();
+    factory.spawn(
+        builder,
+        Box::new(move || {
+            // Will spawn a new thread on panic unless it is cancelled.
+            let sentinel = Sentinel::new(&shared_data);
+            let worker = sentinel.worker.clone();
+            CURRENT_POOL.with(|current| *current.borrow_mut() = Some(shared_data.clone()));
+
+            if let Some(priority) = shared_data.priority {
+                priority.apply();
+            }
+            if let Some(qos) = shared_data.qos {
+                qos.apply();
+            }
+            if let Some(ref numa) = shared_data.numa {
+                numa.bind(worker.index);
+            }
+
+            // Jobs taken from the queue along with the one running, see `Builder::dequeue_batch`.
+            let mut batch = VecDeque::new();
+            // Where the dispatcher puts the jobs of this thread, see `Builder::dispatcher_thread`.
+            let handoff = Arc::new(Handoff::new());
+            let mut retired = false;
+            // Jobs executed and the start of this thread, to recycle it.
+            let mut executed = 0;
+            let started = Instant::now();
+            let mut recycled = false;
+            loop {
+                let mut job = match batch.pop_front() {
+                    // Jobs held back while the pool was shut down with `shutdown_now`.
+                    Some(job) if shared_data.cancellation.is_cancelled() => {
+                        discard_job(&shared_data, job);
+                        continue;
+                    }
+                    Some(job) => job,
+                    None => {
+                        // Shutdown this thread if the pool has become smaller
+                        let thread_counter_val = shared_data.active_count.load(Ordering::Acquire);
+                        let max_thread_count_val = shared_data.thread_limit();
+                        if thread_counter_val >= max_thread_count_val {
+                            break;
+                        }
+                        if shared_data.retire_surplus_thread() {
+                            retired = true;
+                            break;
+                        }
+                        if shared_data.is_recycling_due(executed, started) {
+                            recycled = true;
+                            break;
+                        }
+                        // An idle thread is recycled once its lifetime has passed, too.
+                        let deadline = shared_data
+                            .max_thread_lifetime
+                            .map(|lifetime| started + lifetime);
+                        let taken = match shared_data.dispatcher {
+                            Some(ref dispatcher) => dispatcher
+                                .take(worker.index, &handoff, deadline)
+                                .map(|job| batch.push_back(job)),
+                            None => shared_data.job_queue.pop_batch(
+                                worker.index,
+                                shared_data.dequeue_batch,
+                                &mut batch,
+                                deadline,
+                            ),
+                        };
+                        match taken {
+                            Ok(()) => {}
+                            Err(PopError::TimedOut) => {
+                                recycled = true;
+                                break;
+                            }
+                            // The ThreadPool was dropped.
+                            Err(PopError::Closed) => break,
+                        }
+                        batch
+                            .pop_front()
+                            .expect("JobQueue::pop_batch returned without a job")
+                    }
+                };
+                match job.lease.take() {
+                    Some(takeover) => {
+                        shared_data.queued_count.fetch_sub(1, Ordering::SeqCst);
+                        shared_data.no_work_notify_all();
+                        drop(job);
+                        // The lease may hold this thread for long, let the others run the batch.
+                        for job in mem::take(&mut batch) {
+                            if let Err(job) = shared_data.job_queue.push(job) {
+                                batch.push_back(job);
+                            }
+                        }
+                        takeover.serve(&shared_data, &worker);
+                    }
+                    None => {
+                        run_job(&shared_data, &worker, job);
+                        executed += 1;
+                    }
+                }
+            }
+
+            if recycled && !shared_data.job_queue.is_closed() {
+                // Free the index of the worker for its replacement, which takes over its slot
+                // in `thread_count`.
+                sentinel.cancel();
+                spawn_in_pool(shared_data.clone());
+                return;
+            }
+
+            if !retired {
+                shared_data.thread_count.fetch_sub(1, Ordering::SeqCst);
+            }
+            sentinel.cancel();
+            shared_data.check_terminated();
+        }),
+    )
+}
+
+/// Drop `job`, which has been taken from the queue, without running it.
+fn discard_job(shared_data: &ThreadPoolSharedData, job: Job) {
+    // Jobs which only wake up a worker are not counted, see `BlockingSection`.
+    if !job.wake {
+        shared_data.queued_count.fetch_sub(1, Ordering::SeqCst);
+    }
+    shared_data.release_bytes(job.size);
+    drop(job);
+    shared_data.no_work_notify_all();
+}
+
+/// Run `job`, which has just been taken from the queue, on the calling thread as `worker`.
+fn run_job(shared_data: &Arc<ThreadPoolSharedData>, worker: &Worker, job: Job) {
+    if job.wake || job.pending.as_ref().is_some_and(Pending::is_cancelled) {
+        // Cancelled by `ThreadPool::cancel_tagged` while it was queued, or only queued to wake up
+        // a worker.
+        discard_job(shared_data, job);
+        return;
+    }
+
+    // Do not allow IR around the job execution
+    shared_data.active_count.fetch_add(1, Ordering::SeqCst);
+    shared_data.queued_count.fetch_sub(1, Ordering::SeqCst);
+    shared_data.release_bytes(job.size);
+
+    let Job {
+        thunk,
+        deadline,
+        submitter,
+        tag,
+        priority,
+        contexts,
+        pending,
+        token,
+        ..
+    } = job;
+    if let Some(pending) = pending {
+        shared_data
+            .queue_waits
+            .record(pending.enqueued_at().elapsed());
+    }
+    worker.start_job(shared_data.nanos_since_creation(), &tag, &token);
+    let previous_token = CancellationToken::replace_current(token);
+    let origin = JobOrigin {
+        submitter,
+        tag: tag.clone(),
+        priority,
+    };
+    let previous_origin = CURRENT_JOB.with(|current| current.replace(Some(origin)));
+    // The worker survives panics of its jobs, the sentinel only covers panics of the pool itself.
+    let mut thunk = Some(thunk);
+    let mut job = || {
+        if let Some(thunk) = thunk.take() {
+            thunk.call();
+        }
+    };
+    let started = shared_data.profiler.as_ref().map(|_| Instant::now());
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        call_in_contexts(contexts.unwrap_or_default().into_iter(), &mut || {
+            call_wrapped(&shared_data.job_wrappers, &mut job)
+        })
+    }));
+    CURRENT_JOB.with(|current| *current.borrow_mut() = previous_origin);
+    CancellationToken::replace_current(previous_token);
+    if let (Some(profiler), Some(started)) = (shared_data.profiler.as_ref(), started) {
+        profiler.record(&tag, started.elapsed(), result.is_err());
+    }
+    if let Err(payload) = result {
+        shared_data.record_panic(&*payload, tag);
+        worker.panics.fetch_add(1, Ordering::SeqCst);
+    }
+    worker.finish_job(shared_data.nanos_since_creation());
+    shared_data.completed_count.fetch_add(1, Ordering::SeqCst);
+    if let Some(deadline) = deadline {
+        let now = Instant::now();
+        if now > deadline {
+            if let Some(ref handler) = shared_data.deadline_miss_handler {
+                // The job itself succeeded, a panicking handler must not take the worker down.
+                let _ = panic::catch_unwind(panic::AssertUnwindSafe(|| handler(now - deadline)));
+            }
+        }
+    }
+
+    shared_data.finish_active_job();
+}
+
+/// Call `job` with the captured `contexts` installed, the first of them being the outermost.
+fn call_in_contexts(mut contexts: vec::IntoIter<RestoreContext>, job: &mut dyn FnMut()) {
+    match contexts.next() {
+        Some(restore) => {
+            let mut inner = Some(contexts);
+            restore(&mut || {
+                if let Some(inner) = inner.take() {
+                    call_in_contexts(inner, job);
+                }
+            })
+        }
+        None => job(),
+    }
+}
+
+/// Call `job` through `wrappers`, the first of them being the outermost.
+fn call_wrapped(wrappers: &[JobWrapper], job: &mut dyn FnMut()) {
+    match wrappers.split_first() {
+        Some((wrapper, inner)) => wrapper(&mut || call_wrapped(inner, job)),
+        None => job(),
+    }
+}
+
+#[cfg(test)]
+// The original tests predate these lints and are kept as they were written.
+#[allow(
+    unused_must_use,
+    clippy::assertions_on_constants,
+    clippy::identity_op,
+    clippy::unnecessary_fold
+)]
+mod test {
+    use super::{
+        Builder, CancellationToken, Health, JobStatus, NumaPlacement, PanicPolicy, PoolError,
+        QueuedJob, Scheduler, Scheduling, SharedWorkers, ThreadFactory, ThreadPool, WaitStrategy,
+    };
+    use std::cell::Cell;
+    use std::cmp;
+    use std::env;
+    use std::io;
+    use std::panic;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{channel, sync_channel};
+    use std::sync::{Arc, Barrier, Mutex};
+    use std::thread::{self, sleep};
+    use std::time::{Duration, Instant};
+
+    const TEST_TASKS: usize = 4;
+
+    #[test]
+    fn test_set_num_threads_increasing() {
+        let new_thread_amount = TEST_TASKS + 8;
+        let mut pool = ThreadPool::new(TEST_TASKS);
+        for _ in 0..TEST_TASKS {
+            pool.execute(move || sleep(Duration::from_secs(23)));
+        }
+        sleep(Duration::from_secs(1));
+        assert_eq!(pool.active_count(), TEST_TASKS);
+
+        pool.set_num_threads(new_thread_amount);
+
+        for _ in 0..(new_thread_amount - TEST_TASKS) {
+            pool.execute(move || sleep(Duration::from_secs(23)));
+        }
+        sleep(Duration::from_secs(1));
+        assert_eq!(pool.active_count(), new_thread_amount);
+
+        pool.join();
+    }
+
+    #[test]
+    fn test_set_num_threads_decreasing() {
+        let new_thread_amount = 2;
+        let mut pool = ThreadPool::new(TEST_TASKS);
+        for _ in 0..TEST_TASKS {
+            pool.execute(move || {
+                assert_eq!(1, 1);
+            });
+        }
+        pool.set_num_threads(new_thread_amount);
+        for _ in 0..new_thread_amount {
+            pool.execute(move || sleep(Duration::from_secs(23)));
+        }
+        sleep(Duration::from_secs(1));
+        assert_eq!(pool.active_count(), new_thread_amount);
+
+        pool.join();
+    }
+
+    #[test]
+    fn test_active_count() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        for _ in 0..2 * TEST_TASKS {
+            pool.execute(move || loop {
+                sleep(Duration::from_secs(10))
+            });
+        }
+        sleep(Duration::from_secs(1));
+        let active_count = pool.active_count();
+        assert_eq!(active_count, TEST_TASKS);
+        let initialized_count = pool.max_count();
+        assert_eq!(initialized_count, TEST_TASKS);
+    }
+
+    #[test]
+    fn test_works() {
+        let pool = ThreadPool::new(TEST_TASKS);
+
+        let (tx, rx) = channel();
+        for _ in 0..TEST_TASKS {
+            let tx = tx.clone();
+            pool.execute(move || {
+                tx.send(1).unwrap();
+            });
+        }
+
+        assert_eq!(rx.iter().take(TEST_TASKS).fold(0, |a, b| a + b), TEST_TASKS);
+    }
+
+    #[test]
+    fn test_completed_count() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let (tx, rx) = channel::<()>();
+        pool.execute(move || {
+            let _ = rx.recv();
+        });
+        for n in 0..20 {
+            pool.execute(move || {
+                if n % 4 == 0 {
+                    panic!("Ignore this panic, it must!");
+                }
+            });
+        }
+        while pool.queued_count() > 0 || pool.active_count() > 1 {
+            sleep(Duration::from_millis(1));
+        }
+        assert_eq!(pool.completed_count(), 20);
+
+        drop(tx);
+        pool.join();
+        assert_eq!(pool.completed_count(), 21);
+        assert_eq!(pool.panic_count(), 5);
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut pool = ThreadPool::new(TEST_TASKS);
+        let counter = Arc::new(AtomicUsize::new(0));
+        let jobs = (0..100).map(|_| {
+            let counter = counter.clone();
+            move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        pool.extend(jobs);
+        pool.join();
+        assert_eq!(counter.load(Ordering::SeqCst), 100);
+    }
+
+    #[test]
+    fn test_try_new() {
+        assert_eq!(ThreadPool::try_new(2).unwrap().max_count(), 2);
+        match ThreadPool::try_new(0) {
+            Err(PoolError::ZeroThreads) => (),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn test_try_build_spawn_failure() {
+        // No address space can hold a stack this large.
+        let result = Builder::new()
+            .num_threads(2)
+            .thread_stack_size(1 << 60)
+            .try_build();
+        match result {
+            Err(PoolError::Spawn(_)) => (),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_thread_factory() {
+        struct Counting(Arc<AtomicUsize>);
+
+        impl ThreadFactory for Counting {
+            fn spawn(
+                &self,
+                builder: thread::Builder,
+                main: Box<dyn FnOnce() + Send>,
+            ) -> io::Result<()> {
+                if self.0.fetch_add(1, Ordering::SeqCst) == 3 {
+                    return Err(io::Error::other("no more threads"));
+                }
+                builder.spawn(main).map(|_| ())
+            }
+        }
+
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let pool = Builder::new()
+            .num_threads(2)
+            .thread_name("factory".into())
+            .thread_factory(Counting(spawned.clone()))
+            .build();
+        let name = pool.spawn(|| thread::current().name().map(str::to_owned));
+        assert_eq!(name.join().unwrap(), Some("factory".to_owned()));
+        assert_eq!(spawned.load(Ordering::SeqCst), 2);
+
+        let result = Builder::new()
+            .num_threads(2)
+            .thread_factory(Counting(spawned.clone()))
+            .try_build();
+        match result {
+            Err(PoolError::Spawn(ref error)) => assert_eq!(error.to_string(), "no more threads"),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_spawn_retry() {
+        struct Flaky {
+            spawned: Arc<AtomicUsize>,
+            // Number of spawns which fail from now on.
+            failures: Arc<AtomicUsize>,
+        }
+
+        impl ThreadFactory for Flaky {
+            fn spawn(
+                &self,
+                builder: thread::Builder,
+                main: Box<dyn FnOnce() + Send>,
+            ) -> io::Result<()> {
+                let failures = self.failures.load(Ordering::SeqCst);
+                if failures > 0 {
+                    self.failures.store(failures - 1, Ordering::SeqCst);
+                    return Err(io::Error::new(io::ErrorKind::WouldBlock, "try again"));
+                }
+                self.spawned.fetch_add(1, Ordering::SeqCst);
+                builder.spawn(main).map(|_| ())
+            }
+        }
+
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let failures = Arc::new(AtomicUsize::new(0));
+        let mut pool = Builder::new()
+            .num_threads(1)
+            .thread_factory(Flaky {
+                spawned: spawned.clone(),
+                failures: failures.clone(),
+            })
+            .build();
+
+        // Transient failures are retried.
+        failures.store(2, Ordering::SeqCst);
+        pool.set_num_threads(2);
+        assert_eq!(spawned.load(Ordering::SeqCst), 2);
+        assert!(pool.spawn_error().is_none());
+
+        // Persistent ones are reported, until the missing threads are spawned after all.
+        failures.store(usize::MAX, Ordering::SeqCst);
+        pool.set_num_threads(4);
+        assert_eq!(spawned.load(Ordering::SeqCst), 2);
+        match pool.spawn_error() {
+            Some(PoolError::Spawn(ref error)) => {
+                assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+                assert_eq!(error.to_string(), "try again");
+            }
+            other => panic!("unexpected spawn error {:?}", other),
+        }
+
+        failures.store(0, Ordering::SeqCst);
+        let (tx, rx) = channel();
+        pool.execute(move || tx.send(()).unwrap());
+        rx.recv().unwrap();
+        assert_eq!(spawned.load(Ordering::SeqCst), 4);
+        assert!(pool.spawn_error().is_none());
+        pool.join();
+    }
+
+    #[test]
+    fn test_inline_fallback() {
+        struct Refusing;
+
+        impl ThreadFactory for Refusing {
+            fn spawn(&self, _: thread::Builder, _: Box<dyn FnOnce() + Send>) -> io::Result<()> {
+                Err(io::Error::new(io::ErrorKind::WouldBlock, "no threads"))
+            }
+        }
+
+        let stalled = Builder::new()
+            .lazy_spawn(true)
+            .thread_factory(Refusing)
+            .build();
+        stalled.execute(|| ());
+        assert_eq!(stalled.queued_count(), 1);
+        assert!(stalled.spawn_error().is_some());
+
+        let pool = Builder::new()
+            .lazy_spawn(true)
+            .thread_factory(Refusing)
+            .inline_fallback(true)
+            .build();
+        let (tx, rx) = channel();
+        let pool_ = pool.clone();
+        pool.execute(move || {
+            let tx_ = tx.clone();
+            pool_.execute(move || tx_.send((2, thread::current().id())).unwrap());
+            tx.send((1, thread::current().id())).unwrap();
+        });
+        let ran: Vec<_> = rx.try_iter().collect();
+        assert_eq!(
+            ran,
+            vec![(1, thread::current().id()), (2, thread::current().id())]
+        );
+        assert_eq!(pool.queued_count(), 0);
+        assert_eq!(pool.completed_count(), 2);
+    }
+
+    #[test]
+    fn test_auto_resize() {
+        let parallelism = super::available_parallelism();
+        let mut pool = ThreadPool::new(parallelism + 2);
+        assert_eq!(pool.resize_auto(), parallelism);
+        assert_eq!(pool.max_count(), parallelism);
+
+        let pool = Builder::new()
+            .num_threads(parallelism + 2)
+            .auto_resize(Duration::from_millis(10))
+            .build();
+        let start = Instant::now();
+        while pool.max_count() != parallelism {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_tasks_panic() {
+        ThreadPool::new(0);
+    }
+
+    #[test]
+    fn test_worker_survives_panic() {
+        let pool = ThreadPool::new(1);
+        let before = pool.spawn(|| thread::current().id()).join().unwrap();
+        pool.execute(|| panic!("Ignore this panic, it must!"));
+        let after = pool.spawn(|| thread::current().id()).join().unwrap();
+
+        assert_eq!(before, after);
+        assert_eq!(pool.panic_count(), 1);
+    }
+
+    #[test]
+    fn test_recovery_from_subtask_panic() {
+        let pool = ThreadPool::new(TEST_TASKS);
+
+        // Panic all the existing threads.
+        for _ in 0..TEST_TASKS {
+            pool.execute(move || panic!("Ignore this panic, it must!"));
+        }
+        pool.join();
+
+        assert_eq!(pool.panic_count(), TEST_TASKS);
+
+        // Ensure new threads were spawned to compensate.
+        let (tx, rx) = channel();
+        for _ in 0..TEST_TASKS {
+            let tx = tx.clone();
+            pool.execute(move || {
+                tx.send(1).unwrap();
+            });
+        }
+
+        assert_eq!(rx.iter().take(TEST_TASKS).fold(0, |a, b| a + b), TEST_TASKS);
+    }
+
+    #[test]
+    fn test_should_not_panic_on_drop_if_subtasks_panic_after_drop() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let waiter = Arc::new(Barrier::new(TEST_TASKS + 1));
+
+        // Panic all the existing threads in a bit.
+        for _ in 0..TEST_TASKS {
+            let waiter = waiter.clone();
+            pool.execute(move || {
+                waiter.wait();
+                panic!("Ignore this panic, it should!");
+            });
+        }
+
+        drop(pool);
+
+        // Kick off the failure.
+        waiter.wait();
+    }
+
+    #[test]
+    fn test_massive_task_creation() {
+        let test_tasks = 4_200_000;
+
+        let pool = ThreadPool::new(TEST_TASKS);
+        let b0 = Arc::new(Barrier::new(TEST_TASKS + 1));
+        let b1 = Arc::new(Barrier::new(TEST_TASKS + 1));
+
+        let (tx, rx) = channel();
+
+        for i in 0..test_tasks {
+            let tx = tx.clone();
+            let (b0, b1) = (b0.clone(), b1.clone());
+
+            pool.execute(move || {
+                // Wait until the pool has been filled once.
+                if i < TEST_TASKS {
+                    b0.wait();
+                    // wait so the pool can be measured
+                    b1.wait();
+                }
+
+                tx.send(1).is_ok();
+            });
+        }
+
+        b0.wait();
+        assert_eq!(pool.active_count(), TEST_TASKS);
+        b1.wait();
+
+        assert_eq!(rx.iter().take(test_tasks).fold(0, |a, b| a + b), test_tasks);
+        pool.join();
+
+        let atomic_active_count = pool.active_count();
+        assert!(
+            atomic_active_count == 0,
+            "atomic_active_count: {}",
+            atomic_active_count
+        );
+    }
+
+    #[test]
+    fn test_shrink() {
+        let test_tasks_begin = TEST_TASKS + 2;
+
+        let mut pool = ThreadPool::new(test_tasks_begin);
+        let b0 = Arc::new(Barrier::new(test_tasks_begin + 1));
+        let b1 = Arc::new(Barrier::new(test_tasks_begin + 1));
+
+        for _ in 0..test_tasks_begin {
+            let (b0, b1) = (b0.clone(), b1.clone());
+            pool.execute(move || {
+                b0.wait();
+                b1.wait();
+            });
+        }
+
+        let b2 = Arc::new(Barrier::new(TEST_TASKS + 1));
+        let b3 = Arc::new(Barrier::new(TEST_TASKS + 1));
+
+        for _ in 0..TEST_TASKS {
+            let (b2, b3) = (b2.clone(), b3.clone());
+            pool.execute(move || {
+                b2.wait();
+                b3.wait();
+            });
+        }
+
+        b0.wait();
+        pool.set_num_threads(TEST_TASKS);
+
+        assert_eq!(pool.active_count(), test_tasks_begin);
+        b1.wait();
+
+        b2.wait();
+        assert_eq!(pool.active_count(), TEST_TASKS);
+        b3.wait();
+    }
+
+    #[test]
+    fn test_lazy_spawn() {
+        let mut pool = Builder::new()
+            .num_threads(TEST_TASKS)
+            .lazy_spawn(true)
+            .build();
+        assert_eq!(pool.shared_data.thread_count.load(Ordering::SeqCst), 0);
+
+        pool.execute(|| {});
+        pool.join();
+        assert_eq!(pool.shared_data.thread_count.load(Ordering::SeqCst), 1);
+
+        // Blocked jobs need a thread each, but no more than the maximum.
+        let b0 = Arc::new(Barrier::new(TEST_TASKS + 1));
+        for _ in 0..TEST_TASKS {
+            let b0 = b0.clone();
+            pool.execute(move || {
+                b0.wait();
+            });
+        }
+        for _ in 0..TEST_TASKS {
+            pool.execute(|| {});
+        }
+        assert_eq!(
+            pool.shared_data.thread_count.load(Ordering::SeqCst),
+            TEST_TASKS
+        );
+        b0.wait();
+        pool.join();
+
+        // Growing a lazy pool does not spawn threads up front.
+        pool.set_num_threads(2 * TEST_TASKS);
+        assert_eq!(
+            pool.shared_data.thread_count.load(Ordering::SeqCst),
+            TEST_TASKS
+        );
+    }
+
+    #[test]
+    fn test_busy_time_and_utilization() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        assert_eq!(pool.busy_time(), Duration::from_secs(0));
+
+        for _ in 0..TEST_TASKS {
+            pool.execute(|| sleep(Duration::from_millis(200)));
+        }
+        // Jobs which panic still count, the busy time survives the worker.
+        pool.execute(|| {
+            sleep(Duration::from_millis(200));
+            panic!("Ignore this panic, it must!");
+        });
+        pool.join();
+
+        let busy = pool.busy_time();
+        assert!(busy >= Duration::from_millis(200 * (TEST_TASKS as u64 + 1)));
+        let utilization = pool.utilization();
+        assert!(utilization > 0.0 && utilization <= 1.0);
+
+        // Nothing happened during the next window.
+        sleep(Duration::from_millis(50));
+        assert_eq!(pool.utilization(), 0.0);
+        assert_eq!(pool.busy_time(), busy);
+    }
+
+    #[test]
+    fn test_current() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        assert!(ThreadPool::current().is_none());
+        assert!(!pool.is_worker_thread());
+
+        let (tx, rx) = channel();
+        let outer = pool.clone();
+        pool.execute(move || {
+            let current = ThreadPool::current().unwrap();
+            assert_eq!(current, outer);
+            assert!(current.is_worker_thread());
+            current.execute(move || {
+                tx.send(ThreadPool::current().unwrap().is_worker_thread())
+                    .unwrap();
+            });
+        });
+        assert!(rx.recv().unwrap());
+    }
+
+    #[test]
+    fn test_current_after_drop() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = channel();
+        let b0 = Arc::new(Barrier::new(2));
+        {
+            let b0 = b0.clone();
+            pool.execute(move || {
+                b0.wait();
+                tx.send(ThreadPool::current().is_none()).unwrap();
+            });
+        }
+        drop(pool);
+        b0.wait();
+        assert!(rx.recv().unwrap());
+    }
+
+    #[test]
+    fn test_name() {
+        let name = "test";
+        let mut pool = ThreadPool::with_name(name.to_owned(), 2);
+        let (tx, rx) = sync_channel(0);
+
+        // initial thread should share the name "test"
+        for _ in 0..2 {
+            let tx = tx.clone();
+            pool.execute(move || {
+                let name = thread::current().name().unwrap().to_owned();
+                tx.send(name).unwrap();
+            });
+        }
+
+        // new spawn thread should share the name "test" too.
+        pool.set_num_threads(3);
+        let tx_clone = tx.clone();
+        pool.execute(move || {
+            let name = thread::current().name().unwrap().to_owned();
+            tx_clone.send(name).unwrap();
+            panic!();
+        });
+
+        // recover thread should share the name "test" too.
+        pool.execute(move || {
+            let name = thread::current().name().unwrap().to_owned();
+            tx.send(name).unwrap();
+        });
+
+        for thread_name in rx.iter().take(4) {
+            assert_eq!(name, thread_name);
+        }
+    }
+
+    #[test]
+    fn test_set_name() {
+        let mut pool = Builder::new()
+            .num_threads(2)
+            .thread_name("old".into())
+            .lazy_spawn(true)
+            .build();
+        let (tx, rx) = channel::<()>();
+        let first = pool.spawn(move || {
+            let _ = rx.recv();
+            thread::current().name().map(str::to_owned)
+        });
+        while pool.active_count() == 0 {
+            thread::yield_now();
+        }
+
+        pool.set_name("new".into());
+        let second = pool.spawn(|| thread::current().name().map(str::to_owned));
+        assert_eq!(second.join().unwrap().as_deref(), Some("new"));
+        drop(tx);
+        assert_eq!(first.join().unwrap().as_deref(), Some("old"));
+        assert_eq!(pool.name().as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn test_debug() {
+        let pool = ThreadPool::new(4);
+        let debug = format!("{:?}", pool);
+        assert_eq!(
+            debug,
+            "ThreadPool { name: None, queued_count: 0, active_count: 0, max_count: 4 }"
+        );
+
+        let pool = ThreadPool::with_name("hello".into(), 4);
+        let debug = format!("{:?}", pool);
+        assert_eq!(
+            debug,
+            "ThreadPool { name: Some(\"hello\"), queued_count: 0, active_count: 0, max_count: 4 }"
+        );
+
+        let pool = ThreadPool::new(4);
+        pool.execute(move || sleep(Duration::from_secs(5)));
+        sleep(Duration::from_secs(1));
+        let debug = format!("{:?}", pool);
+        assert_eq!(
+            debug,
+            "ThreadPool { name: None, queued_count: 0, active_count: 1, max_count: 4 }"
+        );
+    }
+
+    #[test]
+    fn test_repeate_join() {
+        let pool = ThreadPool::with_name("repeate join test".into(), 8);
+        let test_count = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..42 {
+            let test_count = test_count.clone();
+            pool.execute(move || {
+                sleep(Duration::from_secs(2));
+                test_count.fetch_add(1, Ordering::Release);
+            });
+        }
+
+        println!("{:?}", pool);
+        pool.join();
+        assert_eq!(42, test_count.load(Ordering::Acquire));
+
+        for _ in 0..42 {
+            let test_count = test_count.clone();
+            pool.execute(move || {
+                sleep(Duration::from_secs(2));
+                test_count.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        pool.join();
+        assert_eq!(84, test_count.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_multi_join() {
+        use std::sync::mpsc::TryRecvError::*;
+
+        // Toggle the following lines to debug the deadlock
+        fn error(_s: String) {
+            //use ::std::io::Write;
+            //let stderr = ::std::io::stderr();
+            //let mut stderr = stderr.lock();
+            //stderr.write(&_s.as_bytes()).is_ok();
+        }
+
+        let pool0 = ThreadPool::with_name("multi join pool0".into(), 4);
+        let pool1 = ThreadPool::with_name("multi join pool1".into(), 4);
+        let (tx, rx) = channel();
+
+        for i in 0..8 {
+            let pool1 = pool1.clone();
+            let pool0_ = pool0.clone();
+            let tx = tx.clone();
+            pool0.execute(move || {
+                pool1.execute(move || {
+                    error(format!("p1: {} -=- {:?}\n", i, pool0_));
+                    pool0_.join();
+                    error(format!("p1: send({})\n", i));
+                    tx.send(i).expect("send i from pool1 -> main");
+                });
+                error(format!("p0: {}\n", i));
+            });
+        }
+        drop(tx);
+
+        assert_eq!(rx.try_recv(), Err(Empty));
+        error(format!("{:?}\n{:?}\n", pool0, pool1));
+        pool0.join();
+        error(format!("pool0.join() complete =-= {:?}", pool1));
+        pool1.join();
+        error("pool1.join() complete\n".into());
+        assert_eq!(
+            rx.iter().fold(0, |acc, i| acc + i),
+            0 + 1 + 2 + 3 + 4 + 5 + 6 + 7
+        );
+    }
+
+    #[test]
+    fn test_empty_pool() {
+        // Joining an empty pool must return imminently
+        let pool = ThreadPool::new(4);
+
+        pool.join();
+
+        assert!(true);
+    }
+
+    #[test]
+    fn test_no_fun_or_joy() {
+        // What happens when you keep adding jobs after a join
+
+        fn sleepy_function() {
+            sleep(Duration::from_secs(6));
+        }
+
+        let pool = ThreadPool::with_name("no fun or joy".into(), 8);
+
+        pool.execute(sleepy_function);
+
+        let p_t = pool.clone();
+        thread::spawn(move || {
+            (0..23).map(|_| p_t.execute(sleepy_function)).count();
+        });
+
+        pool.join();
+    }
+
+    #[test]
+    fn test_clone() {
+        let pool = ThreadPool::with_name("clone example".into(), 2);
+
+        // This batch of jobs will occupy the pool for some time
+        for _ in 0..6 {
+            pool.execute(move || {
+                sleep(Duration::from_secs(2));
+            });
+        }
+
+        // The following jobs will be inserted into the pool in a random fashion
+        let t0 = {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                // wait for the first batch of tasks to finish
+                pool.join();
+
+                let (tx, rx) = channel();
+                for i in 0..42 {
+                    let tx = tx.clone();
+                    pool.execute(move || {
+                        tx.send(i).expect("channel will be waiting");
+                    });
+                }
+                drop(tx);
+                rx.iter()
+                    .fold(0, |accumulator, element| accumulator + element)
+            })
+        };
+        let t1 = {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                // wait for the first batch of tasks to finish
+                pool.join();
+
+                let (tx, rx) = channel();
+                for i in 1..12 {
+                    let tx = tx.clone();
+                    pool.execute(move || {
+                        tx.send(i).expect("channel will be waiting");
+                    });
+                }
+                drop(tx);
+                rx.iter()
+                    .fold(1, |accumulator, element| accumulator * element)
+            })
+        };
+
+        assert_eq!(
+            861,
+            t0.join()
+                .expect("thread 0 will return after calculating additions",)
+        );
+        assert_eq!(
+            39916800,
+            t1.join()
+                .expect("thread 1 will return after calculating multiplications",)
+        );
+    }
+
+    #[test]
+    fn test_spawn_join() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let handles = (0..2 * TEST_TASKS)
+            .map(|i| pool.spawn(move || i * 2))
+            .collect::<Vec<_>>();
+        let results = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            (0..2 * TEST_TASKS).map(|i| i * 2).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_spawn_panic_payload() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let n = 23;
+        let handle = pool.spawn(move || -> usize { panic!("Ignore this panic, it must! {}", n) });
+
+        let error = handle.join().unwrap_err();
+        assert!(error.is_panic());
+        assert!(!error.is_cancelled());
+        assert_eq!(
+            error.to_string(),
+            "job panicked: Ignore this panic, it must! 23"
+        );
+        let payload = error.into_panic();
+        assert_eq!(
+            payload.downcast_ref::<String>().map(|s| &**s),
+            Some("Ignore this panic, it must! 23")
+        );
+
+        pool.join();
+        assert_eq!(pool.panic_count(), 1);
+
+        // The replacement worker still serves spawned jobs.
+        assert_eq!(pool.spawn(|| 42).join().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_cancel_before_start() {
+        let pool = ThreadPool::new(1);
+        let b0 = Arc::new(Barrier::new(2));
+        {
+            let b0 = b0.clone();
+            pool.execute(move || {
+                b0.wait();
+            });
+        }
+
+        let ran = Arc::new(AtomicUsize::new(0));
+        let token = {
+            let ran = ran.clone();
+            pool.execute_cancellable(move |_| {
+                ran.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+        token.cancel();
+        b0.wait();
+        pool.join();
+
+        assert!(token.is_cancelled());
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_shutdown_now() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let b0 = Arc::new(Barrier::new(TEST_TASKS + 1));
+        let (tx, rx) = channel();
+
+        for _ in 0..TEST_TASKS {
+            let b0 = b0.clone();
+            let tx = tx.clone();
+            pool.execute_cancellable(move |token| {
+                b0.wait();
+                while !token.is_cancelled() {
+                    sleep(Duration::from_millis(10));
+                }
+                tx.send(()).unwrap();
+            });
+        }
+        let handles = (0..TEST_TASKS)
+            .map(|i| pool.spawn(move || i))
+            .collect::<Vec<_>>();
+
+        b0.wait();
+        assert_eq!(pool.shutdown_now(), TEST_TASKS);
+        assert_eq!(rx.iter().take(TEST_TASKS).count(), TEST_TASKS);
+        for handle in handles {
+            assert!(handle.join().unwrap_err().is_cancelled());
+        }
+        pool.join();
+        assert_eq!(pool.queued_count(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_execute_after_shutdown_now_panics() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        pool.shutdown_now();
+        pool.execute(|| {});
+    }
+
+    #[test]
+    fn test_earliest_deadline_first() {
+        let pool = Builder::new()
+            .num_threads(1)
+            .scheduling(Scheduling::EarliestDeadlineFirst)
+            .build();
+        let b0 = Arc::new(Barrier::new(2));
+        {
+            let b0 = b0.clone();
+            pool.execute(move || {
+                b0.wait();
+            });
+        }
+
+        let (tx, rx) = channel();
+        let now = Instant::now();
+        for &(i, offset) in &[(3, 30), (1, 10), (4, 40), (2, 20)] {
+            let tx = tx.clone();
+            pool.execute_with_deadline(now + Duration::from_secs(offset), move || {
+                tx.send(i).unwrap();
+            });
+        }
+        let tx_last = tx.clone();
+        pool.execute(move || tx_last.send(5).unwrap());
+        drop(tx);
+
+        b0.wait();
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_priority_scheduling() {
+        let pool = Builder::new()
+            .num_threads(1)
+            .scheduling(Scheduling::Priority)
+            .build();
+        let b0 = Arc::new(Barrier::new(2));
+        {
+            let b0 = b0.clone();
+            pool.execute(move || {
+                b0.wait();
+            });
+        }
+
+        let (tx, rx) = channel();
+        for &(priority, name) in &[(0, "a"), (-3, "b"), (7, "c"), (0, "d"), (7, "e")] {
+            let tx = tx.clone();
+            pool.execute_with_priority(priority, move || tx.send(name).unwrap());
+        }
+        drop(tx);
+
+        b0.wait();
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec!["c", "e", "a", "d", "b"]);
+    }
+
+    #[test]
+    fn test_dispatcher_thread() {
+        let pool = Builder::new()
+            .num_threads(TEST_TASKS)
+            .scheduling(Scheduling::Priority)
+            .dispatcher_thread(true)
+            .build();
+
+        // The jobs stay queued while all workers are busy.
+        let barrier = Arc::new(Barrier::new(TEST_TASKS + 1));
+        for _ in 0..TEST_TASKS {
+            let barrier = barrier.clone();
+            pool.execute(move || {
+                barrier.wait();
+            });
+        }
+        while pool.active_count() < TEST_TASKS || pool.queued_count() > 0 {
+            thread::yield_now();
+        }
+        let runs: Arc<Vec<_>> = Arc::new((0..100).map(|_| AtomicUsize::new(0)).collect());
+        for i in 0..runs.len() {
+            let runs = runs.clone();
+            pool.execute_with_priority((i % 7) as i8 - 3, move || {
+                runs[i].fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        assert_eq!(pool.idle_count(), 0);
+        assert_eq!(pool.queued_count(), runs.len());
+
+        // Every job runs exactly once on whichever worker it is handed to.
+        barrier.wait();
+        pool.join();
+        assert!(runs.iter().all(|runs| runs.load(Ordering::SeqCst) == 1));
+        assert_eq!(pool.completed_count(), TEST_TASKS + runs.len());
+
+        // With a single worker the jobs run in the order of their priorities.
+        let pool = Builder::new()
+            .num_threads(1)
+            .scheduling(Scheduling::Priority)
+            .dispatcher_thread(true)
+            .build();
+        let barrier = Arc::new(Barrier::new(2));
+        let b0 = barrier.clone();
+        pool.execute(move || {
+            b0.wait();
+        });
+        while pool.active_count() < 1 || pool.queued_count() > 0 {
+            thread::yield_now();
+        }
+        let (tx, rx) = channel();
+        for &(priority, name) in &[(0, "a"), (-3, "b"), (7, "c")] {
+            let tx = tx.clone();
+            pool.execute_with_priority(priority, move || tx.send(name).unwrap());
+        }
+        drop(tx);
+        assert_eq!(pool.idle_count(), 0);
+        assert_eq!(pool.queued_count(), 3);
+        barrier.wait();
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec!["c", "a", "b"]);
+
+        // Dropping the pool lets the dispatcher and the workers exit.
+        let (tx, rx) = channel();
+        pool.on_shutdown(move || tx.send(()).unwrap());
+        drop(pool);
+        rx.recv().unwrap();
+
+        // Also if no worker was ever spawned, so none ever waited for the dispatcher.
+        let pool = Builder::new()
+            .num_threads(2)
+            .lazy_spawn(true)
+            .dispatcher_thread(true)
+            .build();
+        let shared_data = Arc::downgrade(&pool.shared_data);
+        let (tx, rx) = channel();
+        pool.on_shutdown(move || tx.send(()).unwrap());
+        drop(pool);
+        rx.recv().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while shared_data.upgrade().is_some() {
+            assert!(
+                Instant::now() < deadline,
+                "dispatcher thread kept the pool alive"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_priority_aging() {
+        let pool = Builder::new()
+            .num_threads(1)
+            .scheduling(Scheduling::Priority)
+            .priority_aging(Duration::from_millis(5))
+            .build();
+        let b0 = Arc::new(Barrier::new(2));
+        {
+            let b0 = b0.clone();
+            pool.execute(move || {
+                b0.wait();
+            });
+        }
+
+        let (tx, rx) = channel();
+        {
+            let tx = tx.clone();
+            pool.execute_with_priority(-2, move || tx.send("old").unwrap());
+        }
+        // Waiting for 20 intervals raises the old job above the new one.
+        sleep(Duration::from_millis(100));
+        pool.execute_with_priority(10, move || tx.send("new").unwrap());
+
+        b0.wait();
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec!["old", "new"]);
+    }
+
+    #[test]
+    fn test_set_priority() {
+        let pool = Builder::new()
+            .num_threads(1)
+            .scheduling(Scheduling::Priority)
+            .priority_aging(Duration::from_secs(60))
+            .build();
+        let b0 = Arc::new(Barrier::new(2));
+        {
+            let b0 = b0.clone();
+            pool.execute(move || {
+                b0.wait();
+            });
+        }
+
+        let (tx, rx) = channel();
+        let handles: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|&name| {
+                let tx = tx.clone();
+                pool.spawn(move || tx.send(name).unwrap())
+            })
+            .collect();
+        drop(tx);
+        assert!(handles[0].set_priority(-1));
+        assert!(handles[2].set_priority(5));
+        assert_eq!(
+            pool.pending_jobs()
+                .iter()
+                .map(|info| info.priority())
+                .collect::<Vec<_>>(),
+            vec![0, -1, 0, 5]
+        );
+
+        b0.wait();
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec!["c", "b", "a"]);
+        assert!(handles.iter().all(|handle| !handle.set_priority(1)));
+
+        let fifo = ThreadPool::new(1);
+        let (release_tx, release_rx) = channel::<()>();
+        fifo.execute(move || release_rx.recv().unwrap());
+        let queued = fifo.spawn(|| ());
+        assert!(!queued.set_priority(1));
+        release_tx.send(()).unwrap();
+        queued.join().unwrap();
+    }
+
+    #[test]
+    fn test_fair_scheduling() {
+        let pool = Builder::new()
+            .num_threads(1)
+            .scheduling(Scheduling::Fair)
+            .build();
+        let b0 = Arc::new(Barrier::new(2));
+        {
+            let b0 = b0.clone();
+            pool.execute(move || {
+                b0.wait();
+            });
+        }
+
+        let (tx, rx) = channel();
+        let chatty = pool.clone();
+        let quiet = pool.clone();
+        for i in 0..4 {
+            let tx = tx.clone();
+            chatty.execute(move || tx.send(("chatty", i)).unwrap());
+        }
+        for i in 0..2 {
+            let tx = tx.clone();
+            quiet.execute(move || tx.send(("quiet", i)).unwrap());
+        }
+        drop(tx);
+
+        b0.wait();
+        assert_eq!(
+            rx.iter().collect::<Vec<_>>(),
+            vec![
+                ("chatty", 0),
+                ("quiet", 0),
+                ("chatty", 1),
+                ("quiet", 1),
+                ("chatty", 2),
+                ("chatty", 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_custom_scheduler() {
+        #[derive(Default)]
+        struct ByTag(Mutex<Vec<QueuedJob>>);
+
+        impl Scheduler for ByTag {
+            fn push(&self, job: QueuedJob) {
+                self.0.lock().unwrap().push(job);
+            }
+
+            fn pop(&self) -> Option<QueuedJob> {
+                let mut jobs = self.0.lock().unwrap();
+                jobs.sort_by(|a, b| b.tag().cmp(&a.tag()));
+                jobs.pop()
+            }
+
+            fn len(&self) -> usize {
+                self.0.lock().unwrap().len()
+            }
+        }
+
+        let pool = Builder::new()
+            .num_threads(1)
+            .scheduling(Scheduling::Priority)
+            .scheduler(ByTag::default)
+            .build();
+        let (tx, rx) = channel();
+        {
+            let tx = tx.clone();
+            pool.execute(move || {
+                sleep(Duration::from_millis(50));
+                tx.send("blocker").unwrap();
+            });
+        }
+        while pool.queued_count() > 0 {
+            thread::yield_now();
+        }
+        for tag in &["c", "a", "b"] {
+            let tx = tx.clone();
+            pool.execute_tagged(*tag, move || tx.send(*tag).unwrap());
+        }
+        drop(tx);
+
+        assert_eq!(rx.iter().collect::<Vec<_>>(), ["blocker", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_wait_strategy() {
+        let pool = Builder::new()
+            .num_threads(2)
+            .wait_strategy(WaitStrategy::park().spin(1000).yield_now(10))
+            .build();
+        let (tx, rx) = channel();
+        for i in 0..TEST_TASKS {
+            let tx = tx.clone();
+            pool.execute(move || tx.send(i).unwrap());
+            if i % 2 == 0 {
+                sleep(Duration::from_millis(1));
+            }
+        }
+        drop(tx);
+        assert_eq!(rx.iter().sum::<usize>(), (0..TEST_TASKS).sum());
+
+        pool.join();
+        pool.shutdown_now();
+        assert_eq!(pool.active_count(), 0);
+    }
+
+    #[test]
+    fn test_dequeue_batch() {
+        let pool = Builder::new().num_threads(1).dequeue_batch(3).build();
+        let (tx, rx) = channel();
+        let (release_tx, release_rx) = channel::<()>();
+        let (started_tx, started_rx) = channel();
+        // Queued by a job, so that the only worker takes all three at once after it.
+        let inner = pool.clone();
+        pool.execute(move || {
+            inner.execute(move || {
+                started_tx.send(()).unwrap();
+                let _ = release_rx.recv();
+            });
+            for i in 0..2 {
+                let tx = tx.clone();
+                inner.execute(move || tx.send(i).unwrap());
+            }
+        });
+
+        started_rx.recv().unwrap();
+        assert_eq!(pool.queued_count(), 2);
+        // The jobs held by the worker are not in the queue any more.
+        assert_eq!(pool.shutdown_now(), 0);
+        drop(release_tx);
+        pool.join();
+        assert_eq!(pool.queued_count(), 0);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_recycle_workers() {
+        thread_local!(static JOBS: Cell<usize> = const { Cell::new(0) });
+        let pool = Builder::new().num_threads(1).max_jobs_per_thread(3).build();
+        let (tx, rx) = channel();
+        for _ in 0..7 {
+            let tx = tx.clone();
+            pool.execute(move || {
+                let jobs = JOBS.with(|jobs| jobs.get() + 1);
+                JOBS.with(|count| count.set(jobs));
+                tx.send(jobs).unwrap();
+            });
+        }
+        drop(tx);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![1, 2, 3, 1, 2, 3, 1]);
+        assert_eq!(pool.respawn_count(), 0);
+
+        // A job outliving the lifetime of its worker is not interrupted.
+        let pool = Builder::new()
+            .num_threads(1)
+            .max_thread_lifetime(Duration::from_millis(100))
+            .build();
+        let (tx, rx) = channel();
+        pool.execute(move || {
+            let id = thread::current().id();
+            sleep(Duration::from_millis(200));
+            tx.send(id).unwrap();
+        });
+        let first = rx.recv().unwrap();
+        let (tx, rx) = channel();
+        pool.execute(move || tx.send(thread::current().id()).unwrap());
+        assert_ne!(rx.recv().unwrap(), first);
+        assert_eq!(pool.max_count(), 1);
+
+        // Idle workers are recycled, whether they wait on the queue or for the dispatcher.
+        for &dispatcher in &[false, true] {
+            struct Counting(Arc<AtomicUsize>);
+
+            impl ThreadFactory for Counting {
+                fn spawn(
+                    &self,
+                    builder: thread::Builder,
+                    main: Box<dyn FnOnce() + Send>,
+                ) -> io::Result<()> {
+                    self.0.fetch_add(1, Ordering::SeqCst);
+                    builder.spawn(main).map(|_| ())
+                }
+            }
+
+            let spawned = Arc::new(AtomicUsize::new(0));
+            let pool = Builder::new()
+                .num_threads(2)
+                .max_thread_lifetime(Duration::from_millis(50))
+                .dispatcher_thread(dispatcher)
+                .thread_factory(Counting(spawned.clone()))
+                .build();
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while spawned.load(Ordering::SeqCst) < 6 {
+                assert!(Instant::now() < deadline, "idle workers were not recycled");
+                sleep(Duration::from_millis(10));
+            }
+            assert_eq!(pool.max_count(), 2);
+            let (tx, rx) = channel();
+            pool.execute(move || tx.send(()).unwrap());
+            rx.recv().unwrap();
+        }
     }
-    if let Some(ref stack_size) = shared_data.stack_size {
-        builder = builder.stack_size(stack_size.to_owned());
+
+    #[test]
+    fn test_wrap_jobs() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let (outer, inner) = (calls.clone(), calls.clone());
+        let pool = Builder::new()
+            .num_threads(1)
+            .wrap_jobs(move |job| {
+                outer.lock().unwrap().push("outer");
+                job();
+                job();
+            })
+            .wrap_jobs(move |job| {
+                inner.lock().unwrap().push("inner");
+                job();
+            })
+            .build();
+        let job_calls = calls.clone();
+        pool.execute(move || job_calls.lock().unwrap().push("job"));
+        pool.join();
+
+        assert_eq!(*calls.lock().unwrap(), ["outer", "inner", "job", "inner"]);
     }
-    builder
-        .spawn(move || {
-            // Will spawn a new thread on panic unless it is cancelled.
-            let sentinel = Sentinel::new(&shared_data);
 
-            loop {
-                // Shutdown this thread if the pool has become smaller
-                let thread_counter_val = shared_data.active_count.load(Ordering::Acquire);
-                let max_thread_count_val = shared_data.max_thread_count.load(Ordering::Relaxed);
-                if thread_counter_val >= max_thread_count_val {
-                    break;
+    #[test]
+    fn test_wrap_jobs_skip() {
+        let pool = Builder::new()
+            .num_threads(1)
+            .wrap_jobs(|_| panic!("Ignore this panic, it must!"))
+            .build();
+        let ran = Arc::new(AtomicUsize::new(0));
+        let job_ran = ran.clone();
+        pool.execute(move || {
+            job_ran.fetch_add(1, Ordering::SeqCst);
+        });
+        pool.join();
+
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+        assert_eq!(pool.panic_count(), 1);
+        assert_eq!(pool.completed_count(), 1);
+    }
+
+    #[test]
+    fn test_propagate_context() {
+        thread_local!(static REQUEST_ID: Cell<u64> = const { Cell::new(0) });
+
+        let pool = Builder::new()
+            .num_threads(1)
+            .propagate_context(|| {
+                let id = REQUEST_ID.with(Cell::get);
+                move |job: &mut dyn FnMut()| {
+                    let previous = REQUEST_ID.with(|current| current.replace(id));
+                    job();
+                    REQUEST_ID.with(|current| current.set(previous));
                 }
-                let message = {
-                    // Only lock jobs for the time it takes
-                    // to get a job, not run it.
-                    let lock = shared_data
-                        .job_receiver
-                        .lock()
-                        .expect("Worker thread unable to lock job_receiver");
-                    lock.recv()
-                };
+            })
+            .wrap_jobs(|job| {
+                assert_ne!(REQUEST_ID.with(Cell::get), 0);
+                job();
+            })
+            .build();
+        let (tx, rx) = channel();
+        let first = {
+            let tx = tx.clone();
+            REQUEST_ID.with(|id| id.set(1));
+            pool.spawn(move || tx.send(REQUEST_ID.with(Cell::get)).unwrap())
+        };
+        {
+            let tx = tx.clone();
+            REQUEST_ID.with(|id| id.set(2));
+            pool.submit_after(&[&first], move || {
+                tx.send(REQUEST_ID.with(Cell::get)).unwrap()
+            });
+        }
+        REQUEST_ID.with(|id| id.set(3));
+        pool.execute(move || tx.send(REQUEST_ID.with(Cell::get)).unwrap());
+        pool.join();
 
-                let job = match message {
-                    Ok(job) => job,
-                    // The ThreadPool was dropped.
-                    Err(..) => break,
-                };
-                // Do not allow IR around the job execution
-                shared_data.active_count.fetch_add(1, Ordering::SeqCst);
-                shared_data.queued_count.fetch_sub(1, Ordering::SeqCst);
+        let mut ids = rx.iter().collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, [1, 2, 3]);
+        assert_eq!(pool.panic_count(), 0);
+    }
+
+    #[test]
+    fn test_execute_cancellable_child() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = channel();
+        let parent = CancellationToken::new();
+        pool.execute(|| sleep(Duration::from_millis(50)));
+
+        let child = {
+            let tx = tx.clone();
+            pool.execute_cancellable_child(&parent, move |_| tx.send("child").unwrap())
+        };
+        let grandchild = {
+            let tx = tx.clone();
+            pool.execute_cancellable_child(&child, move |_| tx.send("grandchild").unwrap())
+        };
+        let sibling = pool.execute_cancellable(move |_| tx.send("sibling").unwrap());
+        parent.cancel();
+        assert!(child.is_cancelled() && grandchild.is_cancelled());
+        assert!(!sibling.is_cancelled());
+        pool.join();
+        assert_eq!(rx.iter().collect::<Vec<_>>(), ["sibling"]);
+
+        let parent = CancellationToken::new();
+        let (tx, rx) = channel();
+        let (started_tx, started_rx) = channel();
+        pool.execute_cancellable_child(&parent, move |token| {
+            started_tx.send(()).unwrap();
+            while !token.is_cancelled() {
+                sleep(Duration::from_millis(1));
+            }
+            tx.send("running").unwrap();
+        });
+        started_rx.recv().unwrap();
+        parent.cancel();
+        assert_eq!(rx.recv(), Ok("running"));
+    }
+
+    #[test]
+    fn test_numa_placement() {
+        for placement in &[NumaPlacement::Spread, NumaPlacement::Node(0)] {
+            let pool = Builder::new()
+                .num_threads(2)
+                .numa_placement(*placement)
+                .build();
+            let (tx, rx) = channel();
+            for i in 0..TEST_TASKS {
+                let tx = tx.clone();
+                pool.execute(move || tx.send(i).unwrap());
+            }
+            drop(tx);
+            assert_eq!(rx.iter().sum::<usize>(), (0..TEST_TASKS).sum());
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let pool = Builder::new()
+            .num_threads(4)
+            .deterministic(true)
+            .scheduling(Scheduling::Priority)
+            .build();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for priority in 0..3 {
+            let order = order.clone();
+            pool.execute_with_priority(priority, move || {
+                order.lock().unwrap().push(priority);
+                if priority == 0 {
+                    let order = order.clone();
+                    ThreadPool::current()
+                        .unwrap()
+                        .execute(move || order.lock().unwrap().push(-1));
+                }
+            });
+        }
+        pool.execute(|| panic!("Ignore this panic, it must!"));
+
+        assert_eq!(pool.queued_count(), 4);
+        assert!(order.lock().unwrap().is_empty());
+        assert_eq!(pool.run_pending(), 5);
+        assert_eq!(*order.lock().unwrap(), [2, 1, 0, -1]);
+        assert_eq!(pool.panic_count(), 1);
+        assert_eq!(pool.run_pending(), 0);
+        assert!(pool.worker_stats().is_empty());
+        assert!(ThreadPool::current().is_none());
+
+        let mut pool = pool;
+        pool.set_num_threads(8);
+        pool.execute(|| ());
+        pool.join();
+        assert_eq!(pool.completed_count(), 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "not deterministic")]
+    fn test_run_pending_without_deterministic() {
+        ThreadPool::new(1).run_pending();
+    }
+
+    #[test]
+    fn test_idle_count() {
+        let pool = Builder::new().num_threads(3).lazy_spawn(true).build();
+        assert_eq!(pool.idle_count(), 0);
+
+        let barrier = Arc::new(Barrier::new(2));
+        {
+            let barrier = barrier.clone();
+            pool.execute(move || {
+                barrier.wait();
+            });
+        }
+        pool.execute(|| ());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.idle_count() < 1 || pool.active_count() < 1 {
+            assert!(Instant::now() < deadline);
+            thread::yield_now();
+        }
+        assert_eq!(pool.idle_count(), 1);
+
+        barrier.wait();
+        while pool.idle_count() < 2 {
+            assert!(Instant::now() < deadline);
+            thread::yield_now();
+        }
+        assert_eq!(pool.active_count(), 0);
+    }
+
+    #[test]
+    fn test_execute_n() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let indices = Arc::new(Mutex::new(Vec::new()));
+        let job_indices = indices.clone();
+        let handle = pool.execute_n(2 * TEST_TASKS, move |index| {
+            job_indices.lock().unwrap().push(index);
+        });
+        assert!(handle.join().is_ok());
+        let mut indices = indices.lock().unwrap().clone();
+        indices.sort();
+        assert_eq!(indices, (0..2 * TEST_TASKS).collect::<Vec<_>>());
+
+        let handle = pool.execute_n(3, |index| {
+            if index == 1 {
+                panic!("Ignore this panic, it must!");
+            }
+        });
+        assert!(handle.join().unwrap_err().is_panic());
+        pool.join();
+        assert_eq!(pool.panic_count(), 1);
+
+        assert!(pool.execute_n(0, |_| unreachable!()).join().is_ok());
+    }
+
+    #[test]
+    fn test_consume() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let (tx, rx) = channel();
+        let (results_tx, results_rx) = channel();
+        let results_tx = Mutex::new(results_tx);
+        let consumed = pool.consume(rx, move |n: usize| {
+            if n == 3 {
+                panic!("Ignore this panic, it must!");
+            }
+            results_tx.lock().unwrap().send(n).unwrap();
+        });
+        for n in 0..10 * TEST_TASKS {
+            tx.send(n).unwrap();
+        }
+        assert!(!consumed.is_finished());
+        drop(tx);
+
+        assert!(consumed.join().unwrap_err().is_panic());
+        let mut results: Vec<_> = results_rx.try_iter().collect();
+        results.sort();
+        assert_eq!(
+            results,
+            (0..10 * TEST_TASKS).filter(|&n| n != 3).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_yield_now() {
+        let (tx, rx) = channel();
+        {
+            let tx = tx.clone();
+            super::yield_now(move || tx.send("inline").unwrap());
+        }
+        assert_eq!(rx.try_recv(), Ok("inline"));
+
+        let pool = ThreadPool::new(1);
+        let (started_tx, started_rx) = channel();
+        let (release_tx, release_rx) = channel::<()>();
+        {
+            let tx = tx.clone();
+            pool.execute_tagged("steps", move || {
+                started_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                tx.send("first step").unwrap();
+                super::yield_now(move || tx.send("second step").unwrap());
+            });
+        }
+        started_rx.recv().unwrap();
+        pool.execute(move || tx.send("other").unwrap());
+        release_tx.send(()).unwrap();
+        pool.join();
+
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec!["first step", "other", "second step"]
+        );
+        assert_eq!(pool.completed_count(), 3);
+        assert_eq!(pool.worker_stats()[0].last_tag(), Some("steps"));
+    }
+
+    #[test]
+    fn test_map_reduce() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let sum = pool.map_reduce(1..=1000u64, |n| n * n, || 0, |a, b| a + b);
+        assert_eq!(sum, (1..=1000u64).map(|n| n * n).sum::<u64>());
+        assert_eq!(
+            pool.map_reduce(Vec::<u64>::new(), |n| n, || 1, |a, b| a * b),
+            1
+        );
+
+        let deterministic = ThreadPool::new_deterministic();
+        let longest = deterministic.map_reduce(vec!["a", "abc", "ab"], str::len, || 0, cmp::max);
+        assert_eq!(longest, 3);
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            pool.map_reduce(
+                0..100,
+                |n| {
+                    if n == 42 {
+                        panic!("Ignore this panic, it must!");
+                    }
+                    n
+                },
+                || 0,
+                |a, b| a + b,
+            )
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_health_check() {
+        let pool = ThreadPool::new(1);
+        assert_eq!(pool.health_check(Duration::from_secs(5)), Health::Healthy);
+
+        for _ in 0..20 {
+            pool.execute(|| sleep(Duration::from_millis(20)));
+        }
+        assert_eq!(
+            pool.health_check(Duration::from_millis(100)),
+            Health::Degraded
+        );
+        pool.join();
+
+        let (tx, rx) = channel::<()>();
+        pool.execute(move || rx.recv().unwrap());
+        assert_eq!(pool.health_check(Duration::from_millis(50)), Health::Dead);
+        tx.send(()).unwrap();
+        pool.join();
+        assert!(pool.health_check(Duration::from_secs(5)).is_healthy());
+
+        let deterministic = ThreadPool::new_deterministic();
+        assert_eq!(
+            deterministic.health_check(Duration::from_millis(10)),
+            Health::Healthy
+        );
+
+        pool.shutdown_now();
+        assert_eq!(pool.health_check(Duration::from_secs(5)), Health::Dead);
+    }
+
+    #[test]
+    fn test_blocking_section() {
+        let pool = ThreadPool::new(2);
+        let (release_tx, release_rx) = channel::<()>();
+        let release_rx = Arc::new(Mutex::new(release_rx));
+        let (blocked_tx, blocked_rx) = channel();
+        for _ in 0..2 {
+            let (pool_, release_rx, blocked_tx) =
+                (pool.clone(), release_rx.clone(), blocked_tx.clone());
+            pool.execute(move || {
+                let _section = pool_.enter_blocking_section();
+                blocked_tx.send(()).unwrap();
+                release_rx.lock().unwrap().recv().unwrap();
+            });
+        }
+        blocked_rx.iter().take(2).for_each(drop);
+
+        let (tx, rx) = channel();
+        for i in 0..TEST_TASKS {
+            let tx = tx.clone();
+            pool.execute(move || tx.send(i).unwrap());
+        }
+        // Both original workers are blocked, the jobs run on the extra ones.
+        assert_eq!(rx.iter().take(TEST_TASKS).count(), TEST_TASKS);
+
+        release_tx.send(()).unwrap();
+        release_tx.send(()).unwrap();
+        pool.join();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.worker_stats().len() > 2 && Instant::now() < deadline {
+            sleep(Duration::from_millis(10));
+        }
+        assert_eq!(pool.worker_stats().len(), 2);
+        assert_eq!(pool.completed_count(), 2 + TEST_TASKS);
+    }
+
+    #[test]
+    fn test_blocking_section_wake_job() {
+        let pool = ThreadPool::new(1);
+        let (release_tx, release_rx) = channel::<()>();
+        let (started_tx, started_rx) = channel();
+        let inner = pool.clone();
+        pool.execute(move || {
+            let section = inner.enter_blocking_section();
+            // Occupies the extra worker, so that the job waking up a worker stays queued.
+            inner.execute(move || {
+                started_tx.send(()).unwrap();
+                let _ = release_rx.recv();
+            });
+            started_rx.recv().unwrap();
+            drop(section);
+        });
+        while pool.completed_count() < 1 {
+            thread::yield_now();
+        }
+
+        // It is neither counted as queued nor as discarded.
+        assert_eq!(pool.queued_count(), 0);
+        assert_eq!(pool.shutdown_now(), 0);
+        drop(release_tx);
+        pool.join();
+        assert_eq!(pool.completed_count(), 2);
+    }
+
+    #[test]
+    fn test_lease_worker() {
+        let pool = ThreadPool::new(2);
+        let lease = pool.lease_worker();
+        let (tx, rx) = channel();
+        for _ in 0..TEST_TASKS {
+            let tx = tx.clone();
+            lease.execute(move || tx.send(thread::current().id()).unwrap());
+        }
+        pool.join();
+        let leased: Vec<_> = rx.try_iter().collect();
+        assert_eq!(leased.len(), TEST_TASKS);
+        assert!(leased.iter().all(|&id| id == leased[0]));
+
+        for _ in 0..TEST_TASKS {
+            let tx = tx.clone();
+            pool.execute(move || {
+                sleep(Duration::from_millis(10));
+                tx.send(thread::current().id()).unwrap();
+            });
+        }
+        pool.join();
+        assert!(rx.try_iter().all(|id| id != leased[0]));
+
+        drop(lease);
+        let (started_tx, started_rx) = channel();
+        let (release_tx, release_rx) = channel::<()>();
+        let release_rx = Arc::new(Mutex::new(release_rx));
+        for _ in 0..2 {
+            let (started_tx, release_rx) = (started_tx.clone(), release_rx.clone());
+            pool.execute(move || {
+                started_tx.send(()).unwrap();
+                let _ = release_rx.lock().unwrap().recv();
+            });
+        }
+        // Both workers are back in general dispatch.
+        started_rx.recv().unwrap();
+        started_rx.recv().unwrap();
+        drop(release_tx);
+        pool.join();
+    }
+
+    #[test]
+    fn test_lease_worker_shutdown_now() {
+        let pool = ThreadPool::new(1);
+        let (started_tx, started_rx) = channel();
+        let (tx, rx) = channel::<()>();
+        pool.execute(move || {
+            started_tx.send(()).unwrap();
+            rx.recv().unwrap();
+        });
+        started_rx.recv().unwrap();
+        let lease = pool.lease_worker();
+        lease.execute(|| ());
+        assert_eq!(pool.shutdown_now(), 1);
+        tx.send(()).unwrap();
+        pool.join();
+        assert_eq!(pool.queued_count(), 0);
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| lease.execute(|| ()))).is_err());
+    }
+
+    #[test]
+    fn test_shared_workers() {
+        let workers = SharedWorkers::new(2);
+        assert_eq!(workers.num_threads(), 2);
+        let busy = workers.pool(1);
+        let other = workers.pool(1);
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        for _ in 0..TEST_TASKS {
+            let (running, most) = (running.clone(), most.clone());
+            busy.execute(move || {
+                most.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                sleep(Duration::from_millis(50));
+                running.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+
+        // The other pool gets a worker while the busy one still has jobs queued.
+        let (tx, rx) = channel();
+        other.execute(move || tx.send(()).unwrap());
+        rx.recv().unwrap();
+        assert!(busy.queued_count() > 0);
+
+        busy.join();
+        assert_eq!(most.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_handle_with_limit() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let tenant = pool.handle_with_limit(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let (tx, rx) = channel();
+        for i in 0..3 * TEST_TASKS {
+            let (running, most, tx) = (running.clone(), most.clone(), tx.clone());
+            // Clones share the limit of the handle.
+            tenant.clone().execute(move || {
+                most.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                sleep(Duration::from_millis(10));
+                running.fetch_sub(1, Ordering::SeqCst);
+                tx.send(i).unwrap();
+            });
+        }
+        assert!(pool.queued_count() >= 3 * TEST_TASKS - 2);
+        pool.join();
+
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+        assert_eq!(rx.try_iter().count(), 3 * TEST_TASKS);
+        assert_eq!(pool.queued_count(), 0);
+    }
+
+    #[test]
+    fn test_handle_with_limit_shutdown_now() {
+        let pool = ThreadPool::new(2);
+        let tenant = pool.handle_with_limit(1);
+        let (tx, rx) = channel();
+        let (started_tx, started_rx) = channel();
+        tenant.execute(move || {
+            started_tx.send(()).unwrap();
+            sleep(Duration::from_millis(50));
+        });
+        for _ in 0..TEST_TASKS {
+            let tx = tx.clone();
+            tenant.execute(move || tx.send(()).unwrap());
+        }
+        drop(tx);
+        started_rx.recv().unwrap();
+        assert_eq!(pool.queued_count(), TEST_TASKS);
+
+        pool.shutdown_now();
+        pool.join();
+        assert_eq!(pool.queued_count(), 0);
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn test_execute_dedup() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = channel();
+        let (release_tx, release_rx) = channel::<()>();
+        pool.execute(move || {
+            release_rx.recv().unwrap();
+        });
+        for i in 0..3 {
+            let tx = tx.clone();
+            assert_eq!(
+                pool.execute_dedup("a", move || tx.send(("a", i)).unwrap()),
+                i == 0
+            );
+        }
+        {
+            let tx = tx.clone();
+            assert!(pool.execute_dedup("b", move || tx.send(("b", 0)).unwrap()));
+        }
+        release_tx.send(()).unwrap();
+        pool.join();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [("a", 0), ("b", 0)]);
+
+        // A panicking job releases its key as well.
+        assert!(pool.execute_dedup("a", || panic!("Ignore this panic, it must!")));
+        pool.join();
+        assert!(pool.execute_dedup("a", move || tx.send(("a", 3)).unwrap()));
+        pool.join();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [("a", 3)]);
+    }
+
+    #[test]
+    fn test_execute_cached() {
+        let pool = ThreadPool::new(1);
+        let runs = Arc::new(AtomicUsize::new(0));
+        let (release_tx, release_rx) = channel::<()>();
+        let ttl = Duration::from_secs(60);
+
+        // Handles asking while the job is queued wait for its result.
+        let first = {
+            let runs = runs.clone();
+            pool.execute_cached("a", ttl, move || {
+                release_rx.recv().unwrap();
+                runs.fetch_add(1, Ordering::SeqCst)
+            })
+        };
+        let second = pool.execute_cached("a", ttl, || -> usize { unreachable!() });
+        release_tx.send(()).unwrap();
+        assert_eq!(first.join().unwrap(), 0);
+        assert_eq!(second.join().unwrap(), 0);
+        assert_eq!(pool.execute_cached("a", ttl, || 7_usize).join().unwrap(), 0);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // Expired results and results of another type are computed anew.
+        assert_eq!(
+            pool.execute_cached("b", Duration::from_millis(0), || 1)
+                .join()
+                .unwrap(),
+            1
+        );
+        assert_eq!(pool.execute_cached("b", ttl, || 2).join().unwrap(), 2);
+        assert_eq!(
+            pool.execute_cached("b", ttl, || "two").join().unwrap(),
+            "two"
+        );
+
+        // Failures are not cached.
+        let panicked = pool.execute_cached("c", ttl, || -> i32 {
+            panic!("Ignore this panic, it must!")
+        });
+        assert!(panicked.join().unwrap_err().is_panic());
+        assert_eq!(pool.execute_cached("c", ttl, || 3).join().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_shutdown_timeout() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = channel();
+        for i in 0..TEST_TASKS {
+            let tx = tx.clone();
+            pool.execute(move || {
+                sleep(Duration::from_millis(10));
+                tx.send(i).unwrap();
+            });
+        }
+        assert_eq!(pool.shutdown_timeout(Duration::from_secs(10)), 0);
+        assert_eq!(rx.try_iter().count(), TEST_TASKS);
 
-                job.call_box();
+        let pool = ThreadPool::new(1);
+        let (release_tx, release_rx) = channel::<()>();
+        let token = pool.execute_cancellable(move |_| {
+            release_rx.recv().unwrap();
+        });
+        for i in 0..TEST_TASKS {
+            let tx = tx.clone();
+            pool.execute(move || tx.send(i).unwrap());
+        }
+        let start = Instant::now();
+        assert_eq!(pool.shutdown_timeout(Duration::from_millis(50)), TEST_TASKS);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(token.is_cancelled());
 
-                shared_data.active_count.fetch_sub(1, Ordering::SeqCst);
-                shared_data.no_work_notify_all();
-            }
+        release_tx.send(()).unwrap();
+        pool.join();
+        drop(tx);
+        assert!(rx.recv().is_err());
+    }
 
-            sentinel.cancel();
-        })
-        .unwrap();
-}
+    #[test]
+    fn test_deadline_miss_handler() {
+        let (tx, rx) = channel();
+        let tx = Mutex::new(tx);
+        let pool = Builder::new()
+            .num_threads(TEST_TASKS)
+            .on_deadline_miss(move |lateness| tx.lock().unwrap().send(lateness).unwrap())
+            .build();
+
+        pool.execute_with_deadline(Instant::now() + Duration::from_secs(60), || {});
+        pool.execute_with_deadline(Instant::now(), || sleep(Duration::from_millis(20)));
+        pool.join();
+        drop(pool);
 
-#[cfg(test)]
-mod test {
-    use super::{Builder, ThreadPool};
-    use std::sync::atomic::{AtomicUsize, Ordering};
-    use std::sync::mpsc::{channel, sync_channel};
-    use std::sync::{Arc, Barrier};
-    use std::thread::{self, sleep};
-    use std::time::Duration;
+        let misses = rx.iter().collect::<Vec<_>>();
+        assert_eq!(misses.len(), 1);
+        assert!(misses[0] >= Duration::from_millis(20));
 
-    const TEST_TASKS: usize = 4;
+        // A panicking handler neither fails the job nor kills its worker.
+        let pool = Builder::new()
+            .num_threads(1)
+            .on_deadline_miss(|_| panic!("Ignore this panic, it must!"))
+            .build();
+        pool.execute_with_deadline(Instant::now(), || sleep(Duration::from_millis(1)));
+        pool.join();
+        assert_eq!(pool.completed_count(), 1);
+        assert_eq!(pool.panic_count(), 0);
+        assert_eq!(pool.respawn_count(), 0);
+    }
 
     #[test]
-    fn test_set_num_threads_increasing() {
-        let new_thread_amount = TEST_TASKS + 8;
-        let mut pool = ThreadPool::new(TEST_TASKS);
-        for _ in 0..TEST_TASKS {
-            pool.execute(move || sleep(Duration::from_secs(23)));
+    fn test_respawn_backoff() {
+        // The worker drops the payload of a panic outside of the job, a panic there kills it.
+        struct PanicOnDrop;
+
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                panic!("Ignore this panic, it must!");
+            }
         }
-        sleep(Duration::from_secs(1));
-        assert_eq!(pool.active_count(), TEST_TASKS);
 
-        pool.set_num_threads(new_thread_amount);
+        let pool = ThreadPool::new(1);
+        assert!(!pool.respawn_storm());
 
-        for _ in 0..(new_thread_amount - TEST_TASKS) {
-            pool.execute(move || sleep(Duration::from_secs(23)));
+        let start = Instant::now();
+        for _ in 0..TEST_TASKS {
+            pool.execute(|| panic::panic_any(PanicOnDrop));
         }
-        sleep(Duration::from_secs(1));
-        assert_eq!(pool.active_count(), new_thread_amount);
-
         pool.join();
+
+        // The first replacement is immediate, the following ones wait 10 and 20 milliseconds
+        // before the next job can run.
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(pool.respawn_count(), TEST_TASKS);
+        assert!(pool.respawn_storm());
     }
 
     #[test]
-    fn test_set_num_threads_decreasing() {
-        let new_thread_amount = 2;
-        let mut pool = ThreadPool::new(TEST_TASKS);
-        for _ in 0..TEST_TASKS {
-            pool.execute(move || {
-                assert_eq!(1, 1);
-            });
-        }
-        pool.set_num_threads(new_thread_amount);
-        for _ in 0..new_thread_amount {
-            pool.execute(move || sleep(Duration::from_secs(23)));
+    fn test_execute_with_callback() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let (tx, rx) = channel();
+
+        for i in 0..TEST_TASKS {
+            let tx = tx.clone();
+            pool.execute_with_callback(
+                move || i,
+                move |result| {
+                    let on_worker = ThreadPool::current().is_some();
+                    tx.send((result.unwrap(), on_worker)).unwrap();
+                },
+            );
         }
-        sleep(Duration::from_secs(1));
-        assert_eq!(pool.active_count(), new_thread_amount);
+        let mut results = rx.iter().take(TEST_TASKS).collect::<Vec<_>>();
+        results.sort();
+        assert_eq!(
+            results,
+            (0..TEST_TASKS).map(|i| (i, true)).collect::<Vec<_>>()
+        );
 
+        let tx_panic = tx.clone();
+        pool.execute_with_callback(
+            || panic!("Ignore this panic, it must!"),
+            move |result: Result<(), _>| tx_panic.send((result.is_err() as usize, true)).unwrap(),
+        );
+        assert_eq!(rx.recv().unwrap(), (1, true));
         pool.join();
+        assert_eq!(pool.panic_count(), 1);
     }
 
     #[test]
-    fn test_active_count() {
-        let pool = ThreadPool::new(TEST_TASKS);
-        for _ in 0..2 * TEST_TASKS {
-            pool.execute(move || loop {
-                sleep(Duration::from_secs(10))
+    fn test_execute_with_callback_cancelled() {
+        let pool = ThreadPool::new(1);
+        let b0 = Arc::new(Barrier::new(2));
+        let b1 = Arc::new(Barrier::new(2));
+        {
+            let (b0, b1) = (b0.clone(), b1.clone());
+            pool.execute(move || {
+                b0.wait();
+                b1.wait();
             });
         }
-        sleep(Duration::from_secs(1));
-        let active_count = pool.active_count();
-        assert_eq!(active_count, TEST_TASKS);
-        let initialized_count = pool.max_count();
-        assert_eq!(initialized_count, TEST_TASKS);
+
+        let (tx, rx) = channel();
+        b0.wait();
+        pool.execute_with_callback(|| 1, move |result| tx.send(result).unwrap());
+        assert_eq!(pool.shutdown_now(), 1);
+        assert!(rx.recv().unwrap().unwrap_err().is_cancelled());
+        b1.wait();
     }
 
     #[test]
-    fn test_works() {
+    fn test_submit_after() {
         let pool = ThreadPool::new(TEST_TASKS);
-
         let (tx, rx) = channel();
-        for _ in 0..TEST_TASKS {
+        let b0 = Arc::new(Barrier::new(2));
+
+        let first = {
+            let (tx, b0) = (tx.clone(), b0.clone());
+            pool.spawn(move || {
+                b0.wait();
+                tx.send("first").unwrap();
+                1
+            })
+        };
+        let second = {
             let tx = tx.clone();
-            pool.execute(move || {
-                tx.send(1).unwrap();
-            });
-        }
+            pool.spawn(move || {
+                tx.send("second").unwrap();
+                "two"
+            })
+        };
+        let last = {
+            let tx = tx.clone();
+            pool.submit_after(&[&first, &second], move || tx.send("last").unwrap())
+        };
+        // Jobs without prerequisites start right away.
+        let now = pool.submit_after(&[], || "now");
+        assert_eq!(now.join().unwrap(), "now");
+        assert!(!last.is_finished());
 
-        assert_eq!(rx.iter().take(TEST_TASKS).fold(0, |a, b| a + b), TEST_TASKS);
+        b0.wait();
+        last.join().unwrap();
+        let mut order = rx.iter().take(3).collect::<Vec<_>>();
+        assert_eq!(order.pop(), Some("last"));
+        assert_eq!(first.join().unwrap(), 1);
+        assert_eq!(second.join().unwrap(), "two");
     }
 
     #[test]
-    #[should_panic]
-    fn test_zero_tasks_panic() {
-        ThreadPool::new(0);
+    fn test_submit_after_failed_prerequisite() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        let failing = pool.spawn(|| panic!("Ignore this panic, it must!"));
+        let dependent = {
+            let ran = ran.clone();
+            pool.submit_after(&[&failing], move || ran.fetch_add(1, Ordering::SeqCst))
+        };
+        let transitive = {
+            let ran = ran.clone();
+            pool.submit_after(&[&dependent], move || ran.fetch_add(1, Ordering::SeqCst))
+        };
+
+        assert!(transitive.join().unwrap_err().is_cancelled());
+        assert!(dependent.join().unwrap_err().is_cancelled());
+        assert!(failing.join().unwrap_err().is_panic());
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
     }
 
     #[test]
-    fn test_recovery_from_subtask_panic() {
+    fn test_last_panic() {
         let pool = ThreadPool::new(TEST_TASKS);
+        assert_eq!(pool.last_panic(), None);
 
-        // Panic all the existing threads.
-        for _ in 0..TEST_TASKS {
-            pool.execute(move || panic!("Ignore this panic, it must!"));
-        }
+        let n = 23;
+        let error = pool.spawn(move || -> () { panic!("spawned job {}", n) });
+        assert!(error.join().unwrap_err().is_panic());
         pool.join();
+        let last_panic = pool.last_panic().unwrap();
+        assert_eq!(last_panic.message(), "spawned job 23");
+        assert_eq!(last_panic.tag(), None);
 
-        assert_eq!(pool.panic_count(), TEST_TASKS);
+        pool.execute_tagged("reindex", move || panic!("tagged job {}", n));
+        pool.join();
+        let last_panic = pool.last_panic().unwrap();
+        assert_eq!(last_panic.message(), "tagged job 23");
+        assert_eq!(last_panic.tag(), Some("reindex"));
+        assert_eq!(
+            last_panic.to_string(),
+            "job 'reindex' panicked: tagged job 23"
+        );
+        assert_eq!(pool.panic_count(), 2);
+    }
 
-        // Ensure new threads were spawned to compensate.
+    #[test]
+    fn test_quarantine_tags() {
+        let pool = Builder::new()
+            .num_threads(2)
+            .quarantine_tags(2, Duration::from_secs(60))
+            .build();
+        for _ in 0..2 {
+            pool.execute_tagged("flaky", || panic!("Ignore this panic, it must!"));
+            pool.join();
+        }
+        assert_eq!(pool.quarantined_tags(), ["flaky"]);
+
+        let rejected = pool.execute_cached("flaky", Duration::from_secs(60), || 1);
+        assert!(rejected.join().unwrap_err().is_cancelled());
+        assert!(pool.try_execute_tagged("flaky", || ()).is_err());
+        assert_eq!(pool.rejected_count(), 2);
+        // Other tags and untagged jobs still run.
         let (tx, rx) = channel();
-        for _ in 0..TEST_TASKS {
+        {
             let tx = tx.clone();
-            pool.execute(move || {
-                tx.send(1).unwrap();
-            });
+            pool.execute(move || tx.send("untagged").unwrap());
         }
+        pool.execute_tagged("stable", move || tx.send("stable").unwrap());
+        pool.join();
+        assert_eq!(rx.iter().count(), 2);
 
-        assert_eq!(rx.iter().take(TEST_TASKS).fold(0, |a, b| a + b), TEST_TASKS);
+        assert!(pool.release_quarantine("flaky"));
+        assert_eq!(
+            pool.execute_cached("flaky", Duration::from_secs(60), || 1)
+                .join()
+                .unwrap(),
+            1
+        );
+        assert_eq!(pool.panic_count(), 2);
+        assert_eq!(pool.rejected_count(), 2);
     }
 
     #[test]
-    fn test_should_not_panic_on_drop_if_subtasks_panic_after_drop() {
-        let pool = ThreadPool::new(TEST_TASKS);
-        let waiter = Arc::new(Barrier::new(TEST_TASKS + 1));
+    fn test_quarantine_dead_letter() {
+        let dead_letters = Arc::new(Mutex::new(Vec::new()));
+        let pool = {
+            let dead_letters = dead_letters.clone();
+            Builder::new()
+                .quarantine_tags(1, Duration::from_secs(60))
+                .on_quarantined_job(move |tag, job| {
+                    dead_letters.lock().unwrap().push((tag.to_string(), job))
+                })
+                .build()
+        };
+        pool.execute_tagged("flaky", || panic!("Ignore this panic, it must!"));
+        pool.join();
 
-        // Panic all the existing threads in a bit.
-        for _ in 0..TEST_TASKS {
-            let waiter = waiter.clone();
-            pool.execute(move || {
-                waiter.wait();
-                panic!("Ignore this panic, it should!");
-            });
+        let rejected = pool.execute_cached("flaky", Duration::from_secs(60), || 1);
+        assert_eq!(pool.rejected_count(), 1);
+        let (tag, job) = dead_letters.lock().unwrap().pop().unwrap();
+        assert_eq!(tag, "flaky");
+        // Running the dead letter completes its handle.
+        job();
+        assert_eq!(rejected.join().unwrap(), 1);
+
+        // Jobs handed back are not dead letters.
+        assert!(pool.try_execute_tagged("flaky", || ()).is_err());
+        assert!(dead_letters.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_panic_policy_abort() {
+        const CHILD: &str = "THREADPOOL_TEST_ABORT_CHILD";
+        if env::var_os(CHILD).is_some() {
+            let pool = Builder::new().panic_policy(PanicPolicy::Abort).build();
+            pool.execute_tagged("fatal", || panic!("Ignore this panic, it must!"));
+            pool.join();
+            return;
         }
 
-        drop(pool);
+        // Run this test again in a child process, which is expected to abort.
+        let output = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "test::test_panic_policy_abort", "--nocapture"])
+            .env(CHILD, "1")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("job 'fatal' panicked: Ignore this panic, it must!, aborting"));
+    }
 
-        // Kick off the failure.
-        waiter.wait();
+    #[test]
+    fn test_queue_byte_limit() {
+        let pool = Builder::new().num_threads(1).queue_byte_limit(100).build();
+        let (tx, rx) = channel::<()>();
+        pool.execute(move || {
+            let _ = rx.recv();
+        });
+
+        pool.execute_sized(60, || ());
+        assert_eq!(pool.queued_bytes(), 60);
+        assert!(pool.try_execute_sized(60, || ()).is_err());
+        pool.execute(|| ());
+
+        let submitted = Arc::new(AtomicUsize::new(0));
+        let submitter = {
+            let pool = pool.clone();
+            let submitted = submitted.clone();
+            thread::spawn(move || {
+                pool.execute_sized(60, || ());
+                submitted.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+        sleep(Duration::from_millis(100));
+        assert_eq!(submitted.load(Ordering::SeqCst), 0);
+
+        drop(tx);
+        submitter.join().unwrap();
+        pool.join();
+        assert_eq!(submitted.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.queued_bytes(), 0);
     }
 
     #[test]
-    fn test_massive_task_creation() {
-        let test_tasks = 4_200_000;
+    fn test_start_timeout() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = channel::<()>();
+        pool.execute(move || {
+            let _ = rx.recv();
+        });
+        let fresh = pool.spawn_with_start_timeout(Duration::from_secs(60), || 1);
+        let stale = pool.spawn_with_start_timeout(Duration::from_millis(10), || 2);
+        let (expired_tx, expired_rx) = channel();
+        pool.execute_with_start_timeout(
+            Duration::from_millis(10),
+            || panic!("stale job must not run"),
+            move || expired_tx.send(()).unwrap(),
+        );
+        sleep(Duration::from_millis(50));
+        drop(tx);
 
-        let pool = ThreadPool::new(TEST_TASKS);
-        let b0 = Arc::new(Barrier::new(TEST_TASKS + 1));
-        let b1 = Arc::new(Barrier::new(TEST_TASKS + 1));
+        assert_eq!(fresh.join().unwrap(), 1);
+        let error = stale.join().unwrap_err();
+        assert!(error.is_timed_out());
+        assert!(!error.is_cancelled());
+        assert_eq!(
+            error.to_string(),
+            "job did not start before its start timeout"
+        );
+        expired_rx.recv().unwrap();
+        pool.join();
+        assert_eq!(pool.panic_count(), 0);
+    }
 
-        let (tx, rx) = channel();
+    #[test]
+    fn test_wait_for_capacity() {
+        let pool = Builder::new().num_threads(1).queue_byte_limit(100).build();
+        let (tx, rx) = channel::<()>();
+        pool.execute(move || {
+            let _ = rx.recv();
+        });
+        pool.execute_sized(80, || ());
+        assert!(!pool.wait_for_capacity_timeout(50, Duration::from_millis(10)));
 
-        for i in 0..test_tasks {
-            let tx = tx.clone();
-            let (b0, b1) = (b0.clone(), b1.clone());
+        let waiter = {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                pool.wait_for_capacity(50);
+                pool.queued_bytes()
+            })
+        };
+        sleep(Duration::from_millis(50));
+        drop(tx);
+        assert_eq!(waiter.join().unwrap(), 0);
 
+        let unlimited = ThreadPool::new(1);
+        assert!(unlimited.wait_for_capacity_timeout(usize::MAX, Duration::from_millis(0)));
+        pool.join();
+    }
+
+    #[test]
+    fn test_with_overflow() {
+        let (tx, rx) = channel::<()>();
+        let rx = Arc::new(Mutex::new(rx));
+        let blocked = || {
+            let pool = Builder::new().num_threads(1).queue_byte_limit(100).build();
+            let rx = rx.clone();
             pool.execute(move || {
-                // Wait until the pool has been filled once.
-                if i < TEST_TASKS {
-                    b0.wait();
-                    // wait so the pool can be measured
-                    b1.wait();
-                }
+                let _ = rx.lock().unwrap().recv();
+            });
+            pool
+        };
+        let (primary, secondary, tertiary) = (blocked(), blocked(), ThreadPool::new(1));
 
-                tx.send(1).is_ok();
+        let pool = primary.with_overflow(&secondary).clone();
+        assert!(pool.try_execute_sized(60, || ()).is_ok());
+        assert!(pool.try_execute_sized(60, || ()).is_ok());
+        assert!(pool.try_execute_sized(60, || ()).is_err());
+        assert_eq!((primary.queued_bytes(), secondary.queued_bytes()), (60, 60));
+
+        // The overflow of the overflow takes what neither pool has room for.
+        let chained = primary.with_overflow(&secondary.with_overflow(&tertiary));
+        chained.execute_sized(60, || ());
+        tertiary.join();
+        assert_eq!(tertiary.completed_count(), 1);
+        assert!(primary.try_execute_sized(60, || ()).is_err());
+
+        drop(tx);
+        primary.join();
+        secondary.join();
+    }
+
+    #[test]
+    fn test_sync_all_waits_for_busy_workers() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let finished = Arc::new(AtomicUsize::new(0));
+        for _ in 0..TEST_TASKS - 1 {
+            let finished = finished.clone();
+            pool.execute(move || {
+                sleep(Duration::from_millis(100));
+                finished.fetch_add(1, Ordering::SeqCst);
             });
         }
 
-        b0.wait();
-        assert_eq!(pool.active_count(), TEST_TASKS);
-        b1.wait();
+        pool.sync_all();
+        assert_eq!(finished.load(Ordering::SeqCst), TEST_TASKS - 1);
+        assert_eq!(pool.queued_count(), 0);
+    }
 
-        assert_eq!(rx.iter().take(test_tasks).fold(0, |a, b| a + b), test_tasks);
-        pool.join();
+    #[test]
+    fn test_sync_all_shutdown_now() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = channel::<()>();
+        pool.execute(move || {
+            let _ = rx.recv();
+        });
+        while pool.active_count() == 0 {
+            thread::yield_now();
+        }
 
-        let atomic_active_count = pool.active_count();
-        assert!(
-            atomic_active_count == 0,
-            "atomic_active_count: {}",
-            atomic_active_count
-        );
+        let syncing = {
+            let pool = pool.clone();
+            thread::spawn(move || pool.sync_all())
+        };
+        while pool.queued_count() == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(pool.shutdown_now(), 1);
+        syncing.join().unwrap();
+        drop(tx);
     }
 
     #[test]
-    fn test_shrink() {
-        let test_tasks_begin = TEST_TASKS + 2;
-
-        let mut pool = ThreadPool::new(test_tasks_begin);
-        let b0 = Arc::new(Barrier::new(test_tasks_begin + 1));
-        let b1 = Arc::new(Barrier::new(test_tasks_begin + 1));
+    fn test_join_from_worker_panics() {
+        let pool = ThreadPool::new(2);
+        let inner = pool.clone();
+        let error = pool.spawn(move || inner.join()).join().unwrap_err();
+        let message = error.into_panic().downcast::<String>().unwrap();
+        assert!(message.starts_with("ThreadPool::join called from a thread within the pool"));
+
+        // Joining another pool is fine.
+        let other = ThreadPool::new(1);
+        pool.spawn(move || other.join()).join().unwrap();
+        pool.join();
+    }
 
-        for _ in 0..test_tasks_begin {
-            let (b0, b1) = (b0.clone(), b1.clone());
-            pool.execute(move || {
-                b0.wait();
-                b1.wait();
-            });
+    #[test]
+    fn test_on_shutdown() {
+        let (tx, rx) = channel();
+        let pool = ThreadPool::new(TEST_TASKS);
+        {
+            let tx = tx.clone();
+            pool.on_shutdown(move || tx.send("dropped").unwrap());
+        }
+        for _ in 0..TEST_TASKS {
+            pool.execute(|| sleep(Duration::from_millis(10)));
         }
+        drop(pool);
+        assert_eq!(rx.recv(), Ok("dropped"));
 
-        let b2 = Arc::new(Barrier::new(TEST_TASKS + 1));
-        let b3 = Arc::new(Barrier::new(TEST_TASKS + 1));
+        // Without any worker threads.
+        let pool = Builder::new().lazy_spawn(true).build();
+        {
+            let tx = tx.clone();
+            pool.on_shutdown(move || tx.send("lazy").unwrap());
+        }
+        drop(pool);
+        assert_eq!(rx.try_recv(), Ok("lazy"));
 
-        for _ in 0..TEST_TASKS {
-            let (b2, b3) = (b2.clone(), b3.clone());
-            pool.execute(move || {
-                b2.wait();
-                b3.wait();
-            });
+        let pool = ThreadPool::new(TEST_TASKS);
+        {
+            let tx = tx.clone();
+            pool.on_shutdown(move || tx.send("shut down").unwrap());
         }
+        pool.shutdown_now();
+        assert_eq!(rx.recv(), Ok("shut down"));
+        pool.on_shutdown(move || tx.send("late").unwrap());
+        assert_eq!(rx.try_recv(), Ok("late"));
+        assert!(rx.try_recv().is_err());
+    }
 
-        b0.wait();
-        pool.set_num_threads(TEST_TASKS);
+    #[test]
+    fn test_profile_jobs() {
+        assert!(ThreadPool::new(1).profile_report().is_none());
 
-        assert_eq!(pool.active_count(), test_tasks_begin);
-        b1.wait();
+        let pool = Builder::new().num_threads(2).profile_jobs(true).build();
+        for i in 0..TEST_TASKS {
+            pool.execute_tagged("even", move || assert!(i % 2 == 0));
+            pool.execute(|| sleep(Duration::from_millis(10)));
+        }
+        pool.join();
 
-        b2.wait();
-        assert_eq!(pool.active_count(), TEST_TASKS);
-        b3.wait();
+        let report = pool.profile_report().unwrap();
+        assert_eq!(report.profiles().len(), 2);
+        let untagged = report.get(None).unwrap();
+        assert_eq!(untagged.count(), TEST_TASKS as u64);
+        assert!(untagged.total_time() >= Duration::from_millis(10 * TEST_TASKS as u64));
+        let even = report.get(Some("even")).unwrap();
+        assert_eq!(even.count(), TEST_TASKS as u64);
+        assert_eq!(even.panics(), TEST_TASKS as u64 / 2);
     }
 
     #[test]
-    fn test_name() {
-        let name = "test";
-        let mut pool = ThreadPool::with_name(name.to_owned(), 2);
-        let (tx, rx) = sync_channel(0);
-
-        // initial thread should share the name "test"
-        for _ in 0..2 {
-            let tx = tx.clone();
+    fn test_reset_stats() {
+        let pool = Builder::new().num_threads(2).profile_jobs(true).build();
+        for i in 0..TEST_TASKS {
             pool.execute(move || {
-                let name = thread::current().name().unwrap().to_owned();
-                tx.send(name).unwrap();
+                sleep(Duration::from_millis(10));
+                assert!(i % 2 == 0);
             });
         }
+        pool.join();
+        assert_eq!(pool.panic_count(), TEST_TASKS / 2);
+        assert!(pool.busy_time() >= Duration::from_millis(10 * TEST_TASKS as u64));
 
-        // new spawn thread should share the name "test" too.
-        pool.set_num_threads(3);
-        let tx_clone = tx.clone();
+        let (release_tx, release_rx) = channel::<()>();
+        let (started_tx, started_rx) = channel();
         pool.execute(move || {
-            let name = thread::current().name().unwrap().to_owned();
-            tx_clone.send(name).unwrap();
-            panic!();
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
         });
+        started_rx.recv().unwrap();
+        pool.reset_stats();
+        let stats = pool.interval_stats();
+        assert_eq!(stats.completed_count(), 0);
+        assert_eq!(stats.panic_count(), 0);
+        assert!(stats.busy_time() < Duration::from_millis(10 * TEST_TASKS as u64));
+        assert_eq!(pool.queue_wait_stats().count(), 0);
+        assert!(pool.profile_report().unwrap().profiles().is_empty());
+        // The lifetime totals are kept.
+        assert_eq!(pool.completed_count(), TEST_TASKS);
+        assert_eq!(pool.panic_count(), TEST_TASKS / 2);
+        assert!(pool.last_panic().is_some());
+        assert!(pool.busy_time() >= Duration::from_millis(10 * TEST_TASKS as u64));
+
+        release_tx.send(()).unwrap();
+        pool.join();
+        assert_eq!(pool.interval_stats().completed_count(), 1);
+        assert_eq!(pool.completed_count(), TEST_TASKS + 1);
+        let stats = pool.worker_stats();
+        assert_eq!(stats.iter().map(|w| w.jobs_executed()).sum::<u64>(), 1);
+        assert_eq!(stats.iter().map(|w| w.panics()).sum::<u64>(), 0);
+        assert_eq!(pool.profile_report().unwrap().get(None).unwrap().count(), 1);
+    }
 
-        // recover thread should share the name "test" too.
-        pool.execute(move || {
-            let name = thread::current().name().unwrap().to_owned();
-            tx.send(name).unwrap();
-        });
+    #[test]
+    fn test_queue_latency_alarm() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = channel();
+        pool.on_queue_latency_exceeded(Duration::from_millis(50), move |waited| {
+            tx.send(waited).unwrap();
+        })
+        .unwrap();
 
-        for thread_name in rx.iter().take(4) {
-            assert_eq!(name, thread_name);
+        for _ in 0..2 {
+            pool.execute(|| sleep(Duration::from_millis(300)));
+            pool.execute(|| ());
+            let waited = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert!(waited > Duration::from_millis(50));
+            pool.join();
+            // Fired once for the breach, re-armed once the queue is empty.
+            assert!(rx.try_recv().is_err());
+            sleep(Duration::from_millis(100));
         }
+
+        drop(pool);
+        assert!(rx.recv_timeout(Duration::from_secs(1)).is_err());
     }
 
     #[test]
-    fn test_debug() {
-        let pool = ThreadPool::new(4);
-        let debug = format!("{:?}", pool);
-        assert_eq!(
-            debug,
-            "ThreadPool { name: None, queued_count: 0, active_count: 0, max_count: 4 }"
-        );
-
-        let pool = ThreadPool::with_name("hello".into(), 4);
-        let debug = format!("{:?}", pool);
-        assert_eq!(
-            debug,
-            "ThreadPool { name: Some(\"hello\"), queued_count: 0, active_count: 0, max_count: 4 }"
-        );
+    fn test_cancel_tagged() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = channel();
+        let (started_tx, started_rx) = channel();
+        let tx_ = tx.clone();
+        pool.execute_tagged("export-0", move || {
+            started_tx.send(()).unwrap();
+            let token = CancellationToken::current().unwrap();
+            while !token.is_cancelled() {
+                sleep(Duration::from_millis(1));
+            }
+            tx_.send("export-0 cancelled").unwrap();
+        });
+        started_rx.recv().unwrap();
+        for tag in &["export-1", "export-2", "exports", "import"] {
+            let tx = tx.clone();
+            pool.execute_tagged(*tag, move || tx.send(*tag).unwrap());
+        }
 
-        let pool = ThreadPool::new(4);
-        pool.execute(move || sleep(Duration::from_secs(5)));
-        sleep(Duration::from_secs(1));
-        let debug = format!("{:?}", pool);
+        let cancelled = pool.cancel_tagged("export-*");
+        assert_eq!((cancelled.queued(), cancelled.running()), (2, 1));
+        assert_eq!(pool.pending_jobs().len(), 2);
+        assert_eq!(pool.cancel_tagged("import").queued(), 1);
+        pool.join();
+        drop(tx);
         assert_eq!(
-            debug,
-            "ThreadPool { name: None, queued_count: 0, active_count: 1, max_count: 4 }"
+            rx.iter().collect::<Vec<_>>(),
+            vec!["export-0 cancelled", "exports"]
         );
+        assert_eq!(pool.queued_count(), 0);
+        assert_eq!(pool.completed_count(), 2);
     }
 
     #[test]
-    fn test_repeate_join() {
-        let pool = ThreadPool::with_name("repeate join test".into(), 8);
-        let test_count = Arc::new(AtomicUsize::new(0));
+    fn test_pending_jobs() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = channel::<()>();
+        let (started_tx, started_rx) = channel();
+        pool.execute_tagged("blocker", move || {
+            started_tx.send(()).unwrap();
+            rx.recv().unwrap();
+        });
+        started_rx.recv().unwrap();
+        assert!(pool.pending_jobs().is_empty());
 
-        for _ in 0..42 {
-            let test_count = test_count.clone();
-            pool.execute(move || {
-                sleep(Duration::from_secs(2));
-                test_count.fetch_add(1, Ordering::Release);
-            });
+        let before = Instant::now();
+        for i in 0..TEST_TASKS {
+            pool.execute_tagged(format!("job-{}", i), || ());
         }
-
-        println!("{:?}", pool);
+        let pending = pool.pending_jobs();
+        let tags: Vec<_> = pending.iter().map(|job| job.tag().unwrap()).collect();
+        assert_eq!(tags, vec!["job-0", "job-1", "job-2", "job-3"]);
+        assert!(pending.iter().all(|job| job.enqueued_at() >= before));
+        assert!(pending.iter().all(|job| job.priority() == 0));
+
+        assert_eq!(pool.shutdown_now(), TEST_TASKS);
+        assert!(pool.pending_jobs().is_empty());
+        tx.send(()).unwrap();
         pool.join();
-        assert_eq!(42, test_count.load(Ordering::Acquire));
+    }
 
-        for _ in 0..42 {
-            let test_count = test_count.clone();
-            pool.execute(move || {
-                sleep(Duration::from_secs(2));
-                test_count.fetch_add(1, Ordering::Relaxed);
-            });
+    #[test]
+    fn test_job_status() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = channel::<()>();
+        let running = pool.execute_tracked(move || {
+            let _ = rx.recv();
+        });
+        let queued = pool.execute_tracked(|| panic!("Ignore this panic, it must!"));
+        while pool.job_status(running) == JobStatus::Queued {
+            thread::yield_now();
         }
+        assert_eq!(pool.job_status(running), JobStatus::Running);
+        assert_eq!(pool.job_status(queued), JobStatus::Queued);
+
+        drop(tx);
         pool.join();
-        assert_eq!(84, test_count.load(Ordering::Relaxed));
+        assert_eq!(pool.job_status(running), JobStatus::Completed);
+        assert_eq!(pool.job_status(queued), JobStatus::Panicked);
+
+        let other = ThreadPool::new(1);
+        let blocker = {
+            let (tx, rx) = channel::<()>();
+            other.execute(move || {
+                let _ = rx.recv();
+            });
+            tx
+        };
+        while other.active_count() == 0 {
+            thread::yield_now();
+        }
+        let cancelled = other.execute_tracked(|| ());
+        other.shutdown_now();
+        assert_eq!(other.job_status(cancelled), JobStatus::Cancelled);
+        drop(blocker);
     }
 
     #[test]
-    fn test_multi_join() {
-        use std::sync::mpsc::TryRecvError::*;
-
-        // Toggle the following lines to debug the deadlock
-        fn error(_s: String) {
-            //use ::std::io::Write;
-            //let stderr = ::std::io::stderr();
-            //let mut stderr = stderr.lock();
-            //stderr.write(&_s.as_bytes()).is_ok();
+    fn test_worker_stats() {
+        let pool = ThreadPool::new(2);
+        let (tx, rx) = channel::<()>();
+        pool.execute_tagged("stuck", move || {
+            let _ = rx.recv();
+        });
+        pool.execute_tagged("panicking", || panic!("Ignore this panic, it must!"));
+        for _ in 0..10 {
+            pool.execute(|| ());
+        }
+        while pool.queued_count() > 0 || pool.active_count() > 1 {
+            sleep(Duration::from_millis(1));
         }
 
-        let pool0 = ThreadPool::with_name("multi join pool0".into(), 4);
-        let pool1 = ThreadPool::with_name("multi join pool1".into(), 4);
-        let (tx, rx) = channel();
+        let stats = pool.worker_stats();
+        assert_eq!(
+            stats.iter().map(|w| w.index()).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        let (stuck, other): (Vec<_>, Vec<_>) = stats
+            .into_iter()
+            .partition(|w| w.current_job_time().is_some());
+        assert_eq!(stuck.len(), 1);
+        assert_eq!(stuck[0].last_tag(), Some("stuck"));
+        assert_eq!(stuck[0].jobs_executed(), 0);
+        assert_eq!(other[0].jobs_executed(), 11);
+        assert_eq!(other[0].panics(), 1);
+        assert_eq!(other[0].last_tag(), None);
 
-        for i in 0..8 {
-            let pool1 = pool1.clone();
-            let pool0_ = pool0.clone();
+        drop(tx);
+        pool.join();
+        let stats = pool.worker_stats();
+        assert_eq!(stats.iter().map(|w| w.jobs_executed()).sum::<u64>(), 12);
+        assert!(stats.iter().all(|w| w.current_job_time().is_none()));
+    }
+
+    #[test]
+    fn test_execute_with_affinity() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let (tx, rx) = channel();
+        for i in 0..100 {
             let tx = tx.clone();
-            pool0.execute(move || {
-                pool1.execute(move || {
-                    error(format!("p1: {} -=- {:?}\n", i, pool0_));
-                    pool0_.join();
-                    error(format!("p1: send({})\n", i));
-                    tx.send(i).expect("send i from pool1 -> main");
-                });
-                error(format!("p0: {}\n", i));
+            pool.execute_with_affinity(i % 3, move || {
+                tx.send(i).unwrap();
             });
         }
         drop(tx);
 
-        assert_eq!(rx.try_recv(), Err(Empty));
-        error(format!("{:?}\n{:?}\n", pool0, pool1));
-        pool0.join();
-        error(format!("pool0.join() complete =-= {:?}", pool1));
-        pool1.join();
-        error("pool1.join() complete\n".into());
-        assert_eq!(
-            rx.iter().fold(0, |acc, i| acc + i),
-            0 + 1 + 2 + 3 + 4 + 5 + 6 + 7
-        );
+        let mut done = rx.iter().collect::<Vec<_>>();
+        done.sort();
+        assert_eq!(done, (0..100).collect::<Vec<_>>());
     }
 
     #[test]
-    fn test_empty_pool() {
-        // Joining an empty pool must return imminently
-        let pool = ThreadPool::new(4);
+    fn test_affinity_steals_from_busy_worker() {
+        let pool = ThreadPool::new(2);
+        let (started_tx, started_rx) = channel();
+        let (release_tx, release_rx) = channel::<()>();
+        // Occupy both workers, so the jobs below are only dequeued once they are released.
+        let release_rx = Arc::new(Mutex::new(release_rx));
+        for _ in 0..2 {
+            let started_tx = started_tx.clone();
+            let release_rx = release_rx.clone();
+            pool.execute(move || {
+                started_tx.send(thread::current().id()).unwrap();
+                let _ = release_rx.lock().unwrap().recv();
+            });
+        }
+        let workers = [started_rx.recv().unwrap(), started_rx.recv().unwrap()];
 
+        let (tx, rx) = channel();
+        for _ in 0..20 {
+            let tx = tx.clone();
+            pool.execute_with_affinity("key", move || {
+                tx.send(thread::current().id()).unwrap();
+            });
+        }
+        drop(tx);
+        // Release one worker, which either is the preferred one or steals all jobs from it.
+        release_tx.send(()).unwrap();
+        let ran_on = rx.iter().take(20).collect::<Vec<_>>();
+        drop(release_tx);
         pool.join();
 
-        assert!(true);
+        assert!(workers.contains(&ran_on[0]));
+        assert!(ran_on.iter().all(|id| *id == ran_on[0]));
     }
 
     #[test]
-    fn test_no_fun_or_joy() {
-        // What happens when you keep adding jobs after a join
-
-        fn sleepy_function() {
-            sleep(Duration::from_secs(6));
+    fn test_execute_gang() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let (tx, rx) = channel();
+        for gang in 0..8 {
+            let barrier = Arc::new(Barrier::new(TEST_TASKS - 1));
+            let tx = Mutex::new(tx.clone());
+            pool.execute_gang(TEST_TASKS - 1, move |index| {
+                barrier.wait();
+                tx.lock().unwrap().send((gang, index)).unwrap();
+            });
+            pool.execute(|| sleep(Duration::from_millis(1)));
         }
+        drop(tx);
 
-        let pool = ThreadPool::with_name("no fun or joy".into(), 8);
-
-        pool.execute(sleepy_function);
-
-        let p_t = pool.clone();
-        thread::spawn(move || {
-            (0..23).map(|_| p_t.execute(sleepy_function)).count();
-        });
-
-        pool.join();
+        let mut members = rx.iter().collect::<Vec<_>>();
+        members.sort();
+        let expected = (0..8)
+            .flat_map(|gang| (0..TEST_TASKS - 1).map(move |index| (gang, index)))
+            .collect::<Vec<_>>();
+        assert_eq!(members, expected);
     }
 
     #[test]
-    fn test_clone() {
-        let pool = ThreadPool::with_name("clone example".into(), 2);
-
-        // This batch of jobs will occupy the pool for some time
-        for _ in 0..6 {
-            pool.execute(move || {
-                sleep(Duration::from_secs(2));
+    fn test_execute_gang_shutdown_now() {
+        let pool = ThreadPool::new(2);
+        let (tx, rx) = channel::<()>();
+        pool.execute(move || {
+            let _ = rx.recv();
+        });
+        let ran = Arc::new(AtomicUsize::new(0));
+        {
+            let ran = ran.clone();
+            pool.execute_gang(2, move |_| {
+                ran.fetch_add(1, Ordering::SeqCst);
             });
         }
+        while pool.active_count() < 2 {
+            thread::yield_now();
+        }
 
-        // The following jobs will be inserted into the pool in a random fashion
-        let t0 = {
-            let pool = pool.clone();
-            thread::spawn(move || {
-                // wait for the first batch of tasks to finish
-                pool.join();
+        // One member is waiting for the busy worker, the other one is dropped.
+        assert_eq!(pool.shutdown_now(), 1);
+        drop(tx);
+        pool.join();
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+    }
 
-                let (tx, rx) = channel();
-                for i in 0..42 {
-                    let tx = tx.clone();
-                    pool.execute(move || {
-                        tx.send(i).expect("channel will be waiting");
-                    });
-                }
-                drop(tx);
-                rx.iter()
-                    .fold(0, |accumulator, element| accumulator + element)
-            })
-        };
-        let t1 = {
-            let pool = pool.clone();
-            thread::spawn(move || {
-                // wait for the first batch of tasks to finish
-                pool.join();
+    #[test]
+    #[should_panic]
+    fn test_execute_gang_too_large() {
+        ThreadPool::new(2).execute_gang(3, |_| ());
+    }
 
-                let (tx, rx) = channel();
-                for i in 1..12 {
-                    let tx = tx.clone();
-                    pool.execute(move || {
-                        tx.send(i).expect("channel will be waiting");
-                    });
-                }
-                drop(tx);
-                rx.iter()
-                    .fold(1, |accumulator, element| accumulator * element)
-            })
-        };
+    #[test]
+    fn test_on_idle() {
+        let pool = ThreadPool::new(TEST_TASKS);
+        let (tx, rx) = channel();
+        let tx = Mutex::new(tx);
+        pool.on_idle(move || tx.lock().unwrap().send(()).unwrap());
 
-        assert_eq!(
-            861,
-            t0.join()
-                .expect("thread 0 will return after calculating additions",)
-        );
-        assert_eq!(
-            39916800,
-            t1.join()
-                .expect("thread 1 will return after calculating multiplications",)
-        );
+        for _ in 0..2 {
+            let barrier = Arc::new(Barrier::new(TEST_TASKS + 1));
+            for _ in 0..TEST_TASKS {
+                let barrier = barrier.clone();
+                pool.execute(move || {
+                    barrier.wait();
+                });
+            }
+            barrier.wait();
+            rx.recv().unwrap();
+            pool.join();
+            assert!(rx.try_recv().is_err());
+        }
     }
 
     #[test]
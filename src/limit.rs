@@ -0,0 +1,135 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Caps on the worker threads occupied by the jobs of a handle, see
+//! [`ThreadPool::handle_with_limit`].
+//!
+//! [`ThreadPool::handle_with_limit`]: ../struct.ThreadPool.html#method.handle_with_limit
+
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, Weak};
+use thunk::Thunk;
+use {submit_job, Job, ThreadPoolSharedData};
+
+struct State {
+    /// Jobs in the queue of the pool or running.
+    admitted: usize,
+    /// Jobs held back until an admitted one finishes.
+    waiting: VecDeque<Job>,
+}
+
+/// Admits at most `limit` jobs of the handles sharing it to the queue of the pool at once.
+///
+/// Held back jobs are counted as queued by the pool, so that `join` waits for them.
+pub(crate) struct Limiter {
+    limit: usize,
+    state: Mutex<State>,
+}
+
+impl Limiter {
+    pub fn new(limit: usize) -> Limiter {
+        Limiter {
+            limit,
+            state: Mutex::new(State {
+                admitted: 0,
+                waiting: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Queue `job` in the pool, or hold it back if `limit` jobs are admitted already. Hands the
+    /// job back if the pool is shutting down.
+    pub fn submit(
+        self: &Arc<Self>,
+        shared_data: &Arc<ThreadPoolSharedData>,
+        job: Job,
+    ) -> Result<(), Box<Job>> {
+        if shared_data.job_queue.is_closed() {
+            return Err(Box::new(job));
+        }
+        {
+            let mut state = self.state.lock().expect("Limiter unable to lock state");
+            if state.admitted == self.limit {
+                shared_data.queued_count.fetch_add(1, Ordering::SeqCst);
+                state.waiting.push_back(job);
+                return Ok(());
+            }
+            state.admitted += 1;
+        }
+        submit_job(shared_data, self.admit(shared_data, job))
+    }
+
+    /// Wrap `job` so that it gives up its admission once it has run or was dropped.
+    fn admit(self: &Arc<Self>, shared_data: &Arc<ThreadPoolSharedData>, job: Job) -> Job {
+        let admission = Admission {
+            limiter: self.clone(),
+            shared_data: Arc::downgrade(shared_data),
+        };
+        let thunk = job.thunk;
+        Job {
+            thunk: Thunk::new(move || {
+                let _admission = admission;
+                thunk.call();
+            }),
+            ..job
+        }
+    }
+
+    /// Pass the admission of a finished job on to the next held back one.
+    fn release(self: &Arc<Self>, shared_data: Option<Arc<ThreadPoolSharedData>>) {
+        let mut state = self.state.lock().expect("Limiter unable to lock state");
+        let next = match state.waiting.pop_front() {
+            Some(next) => next,
+            None => {
+                state.admitted -= 1;
+                return;
+            }
+        };
+        // The pool is gone, so are the handles which could submit to it.
+        let shared_data = match shared_data {
+            Some(shared_data) => shared_data,
+            None => return,
+        };
+        drop(state);
+
+        let result = submit_job(&shared_data, self.admit(&shared_data, next));
+        shared_data.queued_count.fetch_sub(1, Ordering::SeqCst);
+        if let Err(next) = result {
+            // The pool is shutting down, none of the held back jobs will run. They are dropped
+            // before `next` gives up its admission to keep that from recursing through them.
+            let abandoned = mem::take(
+                &mut self
+                    .state
+                    .lock()
+                    .expect("Limiter unable to lock state")
+                    .waiting,
+            );
+            for job in abandoned {
+                shared_data.queued_count.fetch_sub(1, Ordering::SeqCst);
+                shared_data.release_bytes(job.size);
+            }
+            drop(next);
+        }
+    }
+}
+
+/// Moves along with an admitted job.
+struct Admission {
+    limiter: Arc<Limiter>,
+    shared_data: Weak<ThreadPoolSharedData>,
+}
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        self.limiter.release(self.shared_data.upgrade());
+    }
+}
@@ -0,0 +1,276 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Export of the pool counters in the Prometheus text format.
+
+use std::fmt::Write;
+use std::time::Duration;
+use {PriorityQueueStats, ThreadPool};
+
+struct Metric {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    value: fn(&ThreadPool) -> f64,
+}
+
+const METRICS: &[Metric] = &[
+    Metric {
+        name: "threadpool_queued_jobs",
+        help: "Jobs waiting in the queue.",
+        kind: "gauge",
+        value: |pool| pool.queued_count() as f64,
+    },
+    Metric {
+        name: "threadpool_active_jobs",
+        help: "Jobs being executed by a worker thread.",
+        kind: "gauge",
+        value: |pool| pool.active_count() as f64,
+    },
+    Metric {
+        name: "threadpool_max_threads",
+        help: "Maximum number of worker threads.",
+        kind: "gauge",
+        value: |pool| pool.max_count() as f64,
+    },
+    Metric {
+        name: "threadpool_completed_jobs_total",
+        help: "Jobs which finished, including the ones which panicked.",
+        kind: "counter",
+        value: |pool| pool.completed_count() as f64,
+    },
+    Metric {
+        name: "threadpool_panics_total",
+        help: "Jobs which panicked.",
+        kind: "counter",
+        value: |pool| pool.panic_count() as f64,
+    },
+    Metric {
+        name: "threadpool_rejected_jobs_total",
+        help: "Jobs rejected because their tag was quarantined.",
+        kind: "counter",
+        value: |pool| pool.rejected_count() as f64,
+    },
+    Metric {
+        name: "threadpool_busy_seconds_total",
+        help: "Time the worker threads spent executing jobs.",
+        kind: "counter",
+        value: |pool| seconds(pool.busy_time()),
+    },
+    Metric {
+        name: "threadpool_uptime_seconds",
+        help: "Time since the pool was created.",
+        kind: "gauge",
+        value: |pool| seconds(pool.uptime()),
+    },
+];
+
+struct PriorityMetric {
+    name: &'static str,
+    help: &'static str,
+    value: fn(&PriorityQueueStats) -> f64,
+}
+
+/// Gauges of the jobs not started yet by priority, see `ThreadPool::priority_queue_stats`.
+const PRIORITY_METRICS: &[PriorityMetric] = &[
+    PriorityMetric {
+        name: "threadpool_queued_jobs_by_priority",
+        help: "Jobs not started yet by priority.",
+        value: |stats| stats.queued() as f64,
+    },
+    PriorityMetric {
+        name: "threadpool_oldest_queued_seconds",
+        help: "Time the oldest job not started yet has waited by priority.",
+        value: |stats| seconds(stats.oldest_wait()),
+    },
+];
+
+/// Quantiles of the queue wait reported by `threadpool_queue_wait_seconds`.
+const QUEUE_WAIT_QUANTILES: &[f64] = &[0.5, 0.9, 0.99];
+
+/// Render the counters of `pools` in the [Prometheus text format], ready to be served by a
+/// metrics endpoint.
+///
+/// Every sample is labelled with the name of its pool (see [`Builder::thread_name`]), unnamed
+/// pools get an empty label. The time jobs wait in the queue is rendered as a summary of the
+/// quantiles of [`ThreadPool::queue_wait_stats`], the jobs which are waiting by priority as
+/// gauges labelled with the priority, see [`ThreadPool::priority_queue_stats`].
+///
+/// [Prometheus text format]: https://prometheus.io/docs/instrumenting/exposition_formats/
+/// [`Builder::thread_name`]: struct.Builder.html#method.thread_name
+/// [`ThreadPool::queue_wait_stats`]: struct.ThreadPool.html#method.queue_wait_stats
+/// [`ThreadPool::priority_queue_stats`]: struct.ThreadPool.html#method.priority_queue_stats
+///
+/// # Examples
+///
+/// ```
+/// use threadpool::{prometheus_metrics, ThreadPool};
+///
+/// let decoders = ThreadPool::with_name("decoder".into(), 4);
+/// let encoders = ThreadPool::with_name("encoder".into(), 2);
+///
+/// let text = prometheus_metrics(&[&decoders, &encoders]);
+/// assert!(text.contains("threadpool_max_threads{pool=\"decoder\"} 4\n"));
+/// assert!(text.contains("threadpool_max_threads{pool=\"encoder\"} 2\n"));
+/// ```
+pub fn prometheus_metrics(pools: &[&ThreadPool]) -> String {
+    let mut text = String::new();
+    for metric in METRICS {
+        writeln!(text, "# HELP {} {}", metric.name, metric.help).unwrap();
+        writeln!(text, "# TYPE {} {}", metric.name, metric.kind).unwrap();
+        for pool in pools {
+            let name = pool.name().unwrap_or_default();
+            writeln!(
+                text,
+                "{}{{pool=\"{}\"}} {}",
+                metric.name,
+                escape_label(&name),
+                (metric.value)(pool)
+            )
+            .unwrap();
+        }
+    }
+
+    let name = "threadpool_queue_wait_seconds";
+    writeln!(
+        text,
+        "# HELP {} Time jobs waited in the queue before a worker thread started them.",
+        name
+    )
+    .unwrap();
+    writeln!(text, "# TYPE {} summary", name).unwrap();
+    for pool in pools {
+        let label = escape_label(&pool.name().unwrap_or_default());
+        let waits = pool.queue_wait_stats();
+        for &quantile in QUEUE_WAIT_QUANTILES {
+            let value = waits.quantile(quantile).map_or(f64::NAN, seconds);
+            writeln!(
+                text,
+                "{}{{pool=\"{}\",quantile=\"{}\"}} {}",
+                name, label, quantile, value
+            )
+            .unwrap();
+        }
+        writeln!(
+            text,
+            "{}_sum{{pool=\"{}\"}} {}",
+            name,
+            label,
+            seconds(waits.total())
+        )
+        .unwrap();
+        writeln!(
+            text,
+            "{}_count{{pool=\"{}\"}} {}",
+            name,
+            label,
+            waits.count()
+        )
+        .unwrap();
+    }
+
+    let by_priority: Vec<_> = pools
+        .iter()
+        .map(|pool| {
+            let label = escape_label(&pool.name().unwrap_or_default());
+            (label, pool.priority_queue_stats())
+        })
+        .collect();
+    for metric in PRIORITY_METRICS {
+        writeln!(text, "# HELP {} {}", metric.name, metric.help).unwrap();
+        writeln!(text, "# TYPE {} gauge", metric.name).unwrap();
+        for (label, stats) in &by_priority {
+            for stats in stats {
+                writeln!(
+                    text,
+                    "{}{{pool=\"{}\",priority=\"{}\"}} {}",
+                    metric.name,
+                    label,
+                    stats.priority(),
+                    (metric.value)(stats)
+                )
+                .unwrap();
+            }
+        }
+    }
+    text
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{prometheus_metrics, QUEUE_WAIT_QUANTILES};
+    use std::sync::mpsc::channel;
+    use {Builder, ThreadPool};
+
+    #[test]
+    fn test_format() {
+        let pool = Builder::new()
+            .num_threads(3)
+            .thread_name("a \"quoted\"\\name".into())
+            .build();
+        pool.execute(|| panic!("Ignore this panic, it must!"));
+        pool.join();
+
+        let text = prometheus_metrics(&[&pool]);
+        assert!(text.starts_with(
+            "# HELP threadpool_queued_jobs Jobs waiting in the queue.\n\
+             # TYPE threadpool_queued_jobs gauge\n\
+             threadpool_queued_jobs{pool=\"a \\\"quoted\\\"\\\\name\"} 0\n"
+        ));
+        assert!(text.contains("threadpool_panics_total{pool=\"a \\\"quoted\\\"\\\\name\"} 1\n"));
+        assert!(
+            text.contains("threadpool_completed_jobs_total{pool=\"a \\\"quoted\\\"\\\\name\"} 1\n")
+        );
+        assert!(
+            text.contains("threadpool_rejected_jobs_total{pool=\"a \\\"quoted\\\"\\\\name\"} 0\n")
+        );
+        assert!(text.contains("# TYPE threadpool_busy_seconds_total counter\n"));
+        assert!(text.contains("# TYPE threadpool_uptime_seconds gauge\n"));
+        assert!(text.contains("# TYPE threadpool_queue_wait_seconds summary\n"));
+        assert!(text.contains(
+            "threadpool_queue_wait_seconds_count{pool=\"a \\\"quoted\\\"\\\\name\"} 1\n"
+        ));
+        assert_eq!(
+            text.lines().count(),
+            8 * 3 + 2 + QUEUE_WAIT_QUANTILES.len() + 2 + 2 * 2
+        );
+    }
+
+    #[test]
+    fn test_priority_gauges() {
+        let pool = ThreadPool::with_name("p".into(), 1);
+        let (tx, rx) = channel::<()>();
+        let (started_tx, started_rx) = channel();
+        pool.execute(move || {
+            started_tx.send(()).unwrap();
+            rx.recv().unwrap();
+        });
+        started_rx.recv().unwrap();
+        pool.execute_with_priority(3, || ());
+        pool.execute_with_priority(3, || ());
+
+        let text = prometheus_metrics(&[&pool]);
+        assert!(text.contains("# TYPE threadpool_queued_jobs_by_priority gauge\n"));
+        assert!(text.contains("threadpool_queued_jobs_by_priority{pool=\"p\",priority=\"3\"} 2\n"));
+        assert!(text.contains("threadpool_oldest_queued_seconds{pool=\"p\",priority=\"3\"} "));
+        tx.send(()).unwrap();
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Placement of the worker threads on the NUMA nodes of the machine.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How the worker threads are placed on the NUMA nodes of the machine, see
+/// [`Builder::numa_placement`].
+///
+/// Placement is only supported on Linux, where the nodes are read from
+/// `/sys/devices/system/node`. On other platforms, and for nodes which do not exist, the worker
+/// threads run wherever the operating system puts them.
+///
+/// [`Builder::numa_placement`]: struct.Builder.html#method.numa_placement
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NumaPlacement {
+    /// Worker threads are bound to the nodes in turn, so that every node runs about the same
+    /// number of them. Jobs preferably run on a worker thread bound to the node of the thread
+    /// which submitted them, see [`ThreadPool::execute_with_affinity`] for how this preference
+    /// is kept.
+    ///
+    /// [`ThreadPool::execute_with_affinity`]: struct.ThreadPool.html#method.execute_with_affinity
+    Spread,
+    /// All worker threads are bound to the node with the given number.
+    Node(usize),
+}
+
+/// The NUMA nodes of the machine and how the worker threads of a pool are placed on them.
+pub(crate) struct Numa {
+    placement: NumaPlacement,
+    /// The CPUs of every node, indexed by node number.
+    nodes: Vec<Vec<usize>>,
+    /// Rotates the jobs submitted from a node between the worker threads bound to it.
+    next_worker: AtomicUsize,
+}
+
+impl Numa {
+    /// Returns `None` if the NUMA nodes of the machine are not known.
+    pub fn new(placement: NumaPlacement) -> Option<Numa> {
+        let nodes = imp::nodes()?;
+        Some(Numa {
+            placement,
+            nodes,
+            next_worker: AtomicUsize::new(0),
+        })
+    }
+
+    /// Bind the calling worker thread numbered `worker` to its node. Returns `false` if the
+    /// node does not exist or the platform refused the change.
+    pub fn bind(&self, worker: usize) -> bool {
+        let node = match self.placement {
+            NumaPlacement::Spread => worker % self.nodes.len(),
+            NumaPlacement::Node(node) => node,
+        };
+        match self.nodes.get(node) {
+            Some(cpus) if !cpus.is_empty() => imp::bind(cpus),
+            _ => false,
+        }
+    }
+
+    /// Returns the worker thread which should preferably run a job submitted from the calling
+    /// thread, in a pool of `workers` worker threads.
+    pub fn preferred_worker(&self, workers: usize) -> Option<usize> {
+        let nodes = self.nodes.len();
+        if self.placement != NumaPlacement::Spread || nodes < 2 {
+            return None;
+        }
+        let cpu = imp::current_cpu()?;
+        let node = self.nodes.iter().position(|cpus| cpus.contains(&cpu))?;
+        // Workers node, node + nodes, node + 2 * nodes, ... are bound to the node.
+        let on_node = (workers + nodes - 1 - node) / nodes;
+        if on_node == 0 {
+            return None;
+        }
+        let turn = self.next_worker.fetch_add(1, Ordering::Relaxed) % on_node;
+        Some(node + turn * nodes)
+    }
+}
+
+/// Parse a list of CPUs in the format of `/sys/devices/system/node/node*/cpulist`, e.g.
+/// `0-3,8-11`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let mut bounds = range.splitn(2, '-');
+        let first: usize = bounds.next()?.parse().ok()?;
+        let last = match bounds.next() {
+            Some(last) => last.parse().ok()?,
+            None => first,
+        };
+        cpus.extend(first..=last);
+    }
+    Some(cpus)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::parse_cpu_list;
+    use libc;
+    use std::fs;
+    use std::mem;
+
+    const NODE_DIR: &str = "/sys/devices/system/node";
+
+    pub fn nodes() -> Option<Vec<Vec<usize>>> {
+        let mut nodes = Vec::new();
+        for entry in fs::read_dir(NODE_DIR).ok()? {
+            let name = entry.ok()?.file_name();
+            let node = match name.to_str().and_then(|name| name.strip_prefix("node")) {
+                Some(node) => node.parse::<usize>().ok()?,
+                None => continue,
+            };
+            let list = fs::read_to_string(format!("{}/node{}/cpulist", NODE_DIR, node)).ok()?;
+            if nodes.len() <= node {
+                nodes.resize(node + 1, Vec::new());
+            }
+            nodes[node] = parse_cpu_list(&list)?;
+        }
+        if nodes.is_empty() {
+            None
+        } else {
+            Some(nodes)
+        }
+    }
+
+    pub fn bind(cpus: &[usize]) -> bool {
+        unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            for &cpu in cpus.iter().filter(|&&cpu| cpu < libc::CPU_SETSIZE as usize) {
+                libc::CPU_SET(cpu, &mut set);
+            }
+            libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) == 0
+        }
+    }
+
+    pub fn current_cpu() -> Option<usize> {
+        let cpu = unsafe { libc::sched_getcpu() };
+        if cpu < 0 {
+            None
+        } else {
+            Some(cpu as usize)
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    pub fn nodes() -> Option<Vec<Vec<usize>>> {
+        None
+    }
+
+    pub fn bind(_cpus: &[usize]) -> bool {
+        false
+    }
+
+    pub fn current_cpu() -> Option<usize> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_cpu_list, Numa, NumaPlacement};
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(
+            parse_cpu_list("0-3,8-9,12\n"),
+            Some(vec![0, 1, 2, 3, 8, 9, 12])
+        );
+        assert_eq!(parse_cpu_list("\n"), Some(vec![]));
+        assert_eq!(parse_cpu_list("0-x"), None);
+    }
+
+    #[test]
+    fn test_node_out_of_range() {
+        let numa = Numa {
+            placement: NumaPlacement::Node(2),
+            nodes: vec![vec![0], vec![1]],
+            next_worker: AtomicUsize::new(0),
+        };
+        assert!(!numa.bind(0));
+        assert_eq!(numa.preferred_worker(4), None);
+    }
+}
@@ -0,0 +1,88 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Records of panicking jobs.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+/// Returns the message of a panic payload, if it is a string.
+pub(crate) fn payload_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    payload
+        .downcast_ref::<&'static str>()
+        .cloned()
+        .or_else(|| payload.downcast_ref::<String>().map(|s| &**s))
+}
+
+/// A job which panicked, see [`ThreadPool::last_panic`].
+///
+/// [`ThreadPool::last_panic`]: struct.ThreadPool.html#method.last_panic
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobPanic {
+    message: String,
+    tag: Option<Arc<str>>,
+}
+
+impl JobPanic {
+    pub(crate) fn new(payload: &(dyn Any + Send), tag: Option<Arc<str>>) -> JobPanic {
+        JobPanic {
+            message: payload_message(payload)
+                .unwrap_or("Box<dyn Any>")
+                .to_owned(),
+            tag,
+        }
+    }
+
+    /// The message the job panicked with, or `"Box<dyn Any>"` if the panic payload was not a
+    /// string.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The tag of the job, if it was submitted with [`ThreadPool::execute_tagged`].
+    ///
+    /// [`ThreadPool::execute_tagged`]: struct.ThreadPool.html#method.execute_tagged
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+}
+
+impl fmt::Display for JobPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.tag {
+            Some(ref tag) => write!(f, "job '{}' panicked: {}", tag, self.message),
+            None => write!(f, "job panicked: {}", self.message),
+        }
+    }
+}
+
+/// What a [`ThreadPool`] does when one of its jobs panics, see [`Builder::panic_policy`].
+///
+/// [`ThreadPool`]: struct.ThreadPool.html
+/// [`Builder::panic_policy`]: struct.Builder.html#method.panic_policy
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// The panic is counted by [`ThreadPool::panic_count`] and the worker thread goes on with the
+    /// next job. This is the default.
+    ///
+    /// [`ThreadPool::panic_count`]: struct.ThreadPool.html#method.panic_count
+    #[default]
+    Continue,
+    /// The whole process is aborted with [`std::process::abort`], after printing the panic to
+    /// standard error.
+    ///
+    /// This also applies to jobs whose panic is delivered to a [`JobHandle`] or completion
+    /// callback, the handle never sees the panic.
+    ///
+    /// [`std::process::abort`]: https://doc.rust-lang.org/std/process/fn.abort.html
+    /// [`JobHandle`]: struct.JobHandle.html
+    Abort,
+}
@@ -0,0 +1,262 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The jobs waiting to be started, see [`ThreadPool::pending_jobs`].
+//!
+//! [`ThreadPool::pending_jobs`]: ../struct.ThreadPool.html#method.pending_jobs
+
+use cancel::tag_matches;
+use stats::PriorityQueueStats;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// A snapshot of a job which has not started yet, see [`ThreadPool::pending_jobs`].
+///
+/// [`ThreadPool::pending_jobs`]: struct.ThreadPool.html#method.pending_jobs
+#[derive(Clone, Debug)]
+pub struct JobInfo {
+    tag: Option<Arc<str>>,
+    enqueued_at: Instant,
+    priority: i8,
+}
+
+impl JobInfo {
+    pub(crate) fn new(tag: Option<Arc<str>>, priority: i8) -> JobInfo {
+        JobInfo {
+            tag,
+            enqueued_at: Instant::now(),
+            priority,
+        }
+    }
+
+    /// The tag of the job, if it was submitted with [`ThreadPool::execute_tagged`].
+    ///
+    /// [`ThreadPool::execute_tagged`]: struct.ThreadPool.html#method.execute_tagged
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    /// When the job was submitted to the pool.
+    pub fn enqueued_at(&self) -> Instant {
+        self.enqueued_at
+    }
+
+    /// The priority the job was submitted with, see [`ThreadPool::execute_with_priority`].
+    ///
+    /// [`ThreadPool::execute_with_priority`]: struct.ThreadPool.html#method.execute_with_priority
+    pub fn priority(&self) -> i8 {
+        self.priority
+    }
+}
+
+struct Table {
+    next_id: u64,
+    // Keyed by submission order, with the flag telling the job it was cancelled.
+    jobs: BTreeMap<u64, (JobInfo, Arc<AtomicBool>)>,
+}
+
+/// The jobs of a pool which have been submitted but not started.
+pub(crate) struct PendingJobs {
+    table: Mutex<Table>,
+}
+
+impl PendingJobs {
+    pub fn new() -> PendingJobs {
+        PendingJobs {
+            table: Mutex::new(Table {
+                next_id: 0,
+                jobs: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Record a newly submitted job, until the returned entry is dropped.
+    pub fn enter(pending: &Arc<PendingJobs>, info: JobInfo) -> Pending {
+        let mut table = pending
+            .table
+            .lock()
+            .expect("PendingJobs::enter unable to lock jobs");
+        let id = table.next_id;
+        table.next_id += 1;
+        let enqueued_at = info.enqueued_at;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        table.jobs.insert(id, (info, cancelled.clone()));
+        Pending {
+            pending: pending.clone(),
+            id,
+            enqueued_at,
+            cancelled,
+        }
+    }
+
+    /// Returns the recorded jobs, oldest first.
+    pub fn snapshot(&self) -> Vec<JobInfo> {
+        self.table
+            .lock()
+            .expect("PendingJobs::snapshot unable to lock jobs")
+            .jobs
+            .values()
+            .map(|(info, _)| info.clone())
+            .collect()
+    }
+
+    /// Update the priority recorded for the job with the given id, if it is still recorded.
+    pub fn set_priority(&self, id: u64, priority: i8) {
+        let mut table = self
+            .table
+            .lock()
+            .expect("PendingJobs::set_priority unable to lock jobs");
+        if let Some(&mut (ref mut info, _)) = table.jobs.get_mut(&id) {
+            info.priority = priority;
+        }
+    }
+
+    /// Returns the number of recorded jobs and the wait of the oldest one by priority, highest
+    /// priority first.
+    pub fn by_priority(&self) -> Vec<PriorityQueueStats> {
+        let table = self
+            .table
+            .lock()
+            .expect("PendingJobs::by_priority unable to lock jobs");
+        let now = Instant::now();
+        let mut priorities: BTreeMap<i8, (usize, Instant)> = BTreeMap::new();
+        for (info, _) in table.jobs.values() {
+            // Jobs are visited oldest first, so the first one of a priority is its oldest.
+            priorities
+                .entry(info.priority)
+                .or_insert((0, info.enqueued_at))
+                .0 += 1;
+        }
+        priorities
+            .into_iter()
+            .rev()
+            .map(|(priority, (queued, oldest))| {
+                PriorityQueueStats::new(priority, queued, now.saturating_duration_since(oldest))
+            })
+            .collect()
+    }
+
+    /// Returns when the oldest recorded job was submitted.
+    pub fn oldest(&self) -> Option<Instant> {
+        self.table
+            .lock()
+            .expect("PendingJobs::oldest unable to lock jobs")
+            .jobs
+            .values()
+            .next()
+            .map(|(info, _)| info.enqueued_at)
+    }
+
+    /// Cancel the recorded jobs whose tag matches `pattern`, returning how many there were.
+    /// They are forgotten right away, but stay in the queue until a worker thread drops them.
+    pub fn cancel_tagged(&self, pattern: &str) -> usize {
+        let mut table = self
+            .table
+            .lock()
+            .expect("PendingJobs::cancel_tagged unable to lock jobs");
+        let before = table.jobs.len();
+        table
+            .jobs
+            .retain(|_, &mut (ref info, ref cancelled)| match info.tag() {
+                Some(tag) if tag_matches(pattern, tag) => {
+                    cancelled.store(true, Ordering::SeqCst);
+                    false
+                }
+                _ => true,
+            });
+        before - table.jobs.len()
+    }
+}
+
+/// Moves along with a job until it starts or is dropped without running.
+pub(crate) struct Pending {
+    pending: Arc<PendingJobs>,
+    id: u64,
+    enqueued_at: Instant,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Pending {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn enqueued_at(&self) -> Instant {
+        self.enqueued_at
+    }
+
+    /// Returns `true` if the job was cancelled with `PendingJobs::cancel_tagged`.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.pending
+            .table
+            .lock()
+            .expect("Pending::drop unable to lock jobs")
+            .jobs
+            .remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{JobInfo, PendingJobs};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_oldest_first() {
+        let pending = Arc::new(PendingJobs::new());
+        let first = PendingJobs::enter(&pending, JobInfo::new(Some("first".into()), 0));
+        let second = PendingJobs::enter(&pending, JobInfo::new(None, 5));
+        let third = PendingJobs::enter(&pending, JobInfo::new(Some("third".into()), -1));
+
+        let jobs = pending.snapshot();
+        let tags: Vec<_> = jobs.iter().map(|job| job.tag()).collect();
+        assert_eq!(tags, vec![Some("first"), None, Some("third")]);
+        assert_eq!(jobs[1].priority(), 5);
+        assert!(jobs[0].enqueued_at() <= jobs[2].enqueued_at());
+
+        drop(second);
+        let tags: Vec<_> = pending
+            .snapshot()
+            .iter()
+            .map(|job| job.tag().map(String::from))
+            .collect();
+        assert_eq!(
+            tags,
+            vec![Some("first".to_owned()), Some("third".to_owned())]
+        );
+        drop(first);
+        drop(third);
+        assert!(pending.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_cancel_tagged() {
+        let pending = Arc::new(PendingJobs::new());
+        let export = PendingJobs::enter(&pending, JobInfo::new(Some("export-1".into()), 0));
+        let import = PendingJobs::enter(&pending, JobInfo::new(Some("import".into()), 0));
+        let untagged = PendingJobs::enter(&pending, JobInfo::new(None, 0));
+
+        assert_eq!(pending.cancel_tagged("export-*"), 1);
+        assert!(export.is_cancelled());
+        assert!(!import.is_cancelled());
+        assert!(!untagged.is_cancelled());
+        assert_eq!(pending.snapshot().len(), 2);
+        assert_eq!(pending.cancel_tagged("export-*"), 0);
+        assert_eq!(pending.cancel_tagged("import"), 1);
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Chains of processing stages running on a pool, connected by bounded queues.
+
+use std::sync::mpsc::{self, Receiver, SendError, SyncSender};
+use std::sync::{Arc, Mutex};
+use ThreadPool;
+
+type Connect<I, O> = Box<dyn FnOnce(Receiver<I>) -> Receiver<O>>;
+
+/// [`Pipeline`] factory, which is used to define the stages of the pipeline.
+///
+/// [`Pipeline`]: struct.Pipeline.html
+pub struct PipelineBuilder<I, O> {
+    pool: ThreadPool,
+    capacity: usize,
+    connect: Connect<I, O>,
+}
+
+impl<I: Send + 'static, O: Send + 'static> PipelineBuilder<I, O> {
+    /// Append a stage which applies `f` to every output of the previous stage, running on
+    /// `concurrency` threads of the pool at once.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if `concurrency` is 0.
+    pub fn stage<U, F>(self, concurrency: usize, f: F) -> PipelineBuilder<I, U>
+    where
+        U: Send + 'static,
+        F: Fn(O) -> U + Send + Sync + 'static,
+    {
+        assert!(concurrency > 0);
+        let PipelineBuilder {
+            pool,
+            capacity,
+            connect,
+        } = self;
+        let stage_pool = pool.clone();
+        PipelineBuilder {
+            pool,
+            capacity,
+            connect: Box::new(move |input| {
+                let input = connect(input);
+                start_stage(&stage_pool, capacity, concurrency, input, f)
+            }),
+        }
+    }
+
+    /// Start the stages on the pool and return the running [`Pipeline`].
+    ///
+    /// [`Pipeline`]: struct.Pipeline.html
+    pub fn build(self) -> Pipeline<I, O> {
+        let (input, receiver) = mpsc::sync_channel(self.capacity);
+        Pipeline {
+            input,
+            output: (self.connect)(receiver),
+        }
+    }
+}
+
+/// Spawn `concurrency` loops on `pool` which feed the values of `input` through `f`.
+fn start_stage<T, U, F>(
+    pool: &ThreadPool,
+    capacity: usize,
+    concurrency: usize,
+    input: Receiver<T>,
+    f: F,
+) -> Receiver<U>
+where
+    T: Send + 'static,
+    U: Send + 'static,
+    F: Fn(T) -> U + Send + Sync + 'static,
+{
+    let (output, receiver) = mpsc::sync_channel(capacity);
+    let input = Arc::new(Mutex::new(input));
+    let f = Arc::new(f);
+    for _ in 0..concurrency {
+        let (input, output, f) = (input.clone(), output.clone(), f.clone());
+        pool.execute(move || loop {
+            let value = match input
+                .lock()
+                .expect("Pipeline unable to lock stage input")
+                .recv()
+            {
+                Ok(value) => value,
+                // The previous stage is done.
+                Err(_) => break,
+            };
+            if output.send(f(value)).is_err() {
+                // Nobody is interested in the results anymore.
+                break;
+            }
+        });
+    }
+    receiver
+}
+
+/// A chain of processing stages running on a [`ThreadPool`], connected by bounded queues.
+///
+/// Values sent into the pipeline pass through every stage in order. Each stage runs on a fixed
+/// number of pool threads, which are occupied for as long as the pipeline is alive, so the pool
+/// must have at least as many threads as all stages together. Full queues block the stage in
+/// front of them, which bounds the memory used by a pipeline whose consumer falls behind.
+///
+/// Stages running on more than one thread may reorder values.
+///
+/// [`ThreadPool`]: struct.ThreadPool.html
+///
+/// # Examples
+///
+/// ```
+/// use threadpool::{Pipeline, ThreadPool};
+/// use std::thread;
+///
+/// let pool = ThreadPool::new(3);
+/// let pipeline = Pipeline::builder(&pool, 4)
+///     .stage(2, |n: u64| n * n)
+///     .stage(1, |n| format!("<{}>", n))
+///     .build();
+///
+/// let input = pipeline.sender();
+/// thread::spawn(move || {
+///     for n in 0..10 {
+///         input.send(n).unwrap();
+///     }
+/// });
+///
+/// let mut outputs: Vec<String> = pipeline.into_iter().collect();
+/// outputs.sort();
+/// assert_eq!(10, outputs.len());
+/// assert_eq!("<0>", outputs[0]);
+/// ```
+pub struct Pipeline<I, O> {
+    input: SyncSender<I>,
+    output: Receiver<O>,
+}
+
+impl<I: Send + 'static> Pipeline<I, I> {
+    /// Start defining a pipeline whose stages run on `pool`, with queues holding up to
+    /// `capacity` values between the stages.
+    pub fn builder(pool: &ThreadPool, capacity: usize) -> PipelineBuilder<I, I> {
+        PipelineBuilder {
+            pool: pool.clone(),
+            capacity,
+            connect: Box::new(|input| input),
+        }
+    }
+}
+
+impl<I, O> Pipeline<I, O> {
+    /// Send `value` into the first stage, blocking while its queue is full.
+    ///
+    /// Fails if the first stage is not running anymore, which happens if its function panicked
+    /// on all of its threads.
+    pub fn send(&self, value: I) -> Result<(), SendError<I>> {
+        self.input.send(value)
+    }
+
+    /// Returns a sender for the first stage, so that the pipeline can be fed from other threads.
+    ///
+    /// The pipeline only finishes once all senders have been dropped.
+    pub fn sender(&self) -> SyncSender<I> {
+        self.input.clone()
+    }
+
+    /// Block until the last stage produces its next value.
+    ///
+    /// Note that the pipeline can not finish, and thus this method can not return `None`, while
+    /// the pipeline itself still accepts values; use [`into_iter`](#method.into_iter) to drain it.
+    pub fn recv(&self) -> Option<O> {
+        self.output.recv().ok()
+    }
+}
+
+impl<I, O> IntoIterator for Pipeline<I, O> {
+    type Item = O;
+    type IntoIter = mpsc::IntoIter<O>;
+
+    /// Stop accepting values through [`send`](#method.send) and iterate over the remaining
+    /// outputs, until every sender has been dropped and all values have passed the pipeline.
+    fn into_iter(self) -> mpsc::IntoIter<O> {
+        let Pipeline { input, output } = self;
+        drop(input);
+        output.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Pipeline;
+    use std::thread;
+    use ThreadPool;
+
+    #[test]
+    fn test_stages_in_order() {
+        let pool = ThreadPool::new(4);
+        let pipeline = Pipeline::builder(&pool, 2)
+            .stage(2, |n: u32| n + 1)
+            .stage(1, |n| n * 10)
+            .stage(1, |n| n.to_string())
+            .build();
+
+        let input = pipeline.sender();
+        let feeder = thread::spawn(move || {
+            for n in 0..100 {
+                input.send(n).unwrap();
+            }
+        });
+
+        let mut outputs: Vec<u32> = pipeline.into_iter().map(|s| s.parse().unwrap()).collect();
+        feeder.join().unwrap();
+        outputs.sort();
+        assert_eq!(outputs, (1..101).map(|n| n * 10).collect::<Vec<u32>>());
+
+        pool.join();
+        assert_eq!(pool.active_count(), 0);
+    }
+
+    #[test]
+    fn test_send_and_recv() {
+        let pool = ThreadPool::new(1);
+        let pipeline = Pipeline::builder(&pool, 1).stage(1, |n: u8| n * 2).build();
+        for n in 0..5 {
+            pipeline.send(n).unwrap();
+            assert_eq!(pipeline.recv(), Some(n * 2));
+        }
+        assert_eq!(pipeline.into_iter().next(), None);
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Operating system scheduling priority of the worker threads.
+
+/// Scheduling priority of the worker threads relative to the other threads of the process, see
+/// [`Builder::thread_priority`].
+///
+/// The levels are mapped to the platform's notion of priority: the nice value of the thread on
+/// Linux, `pthread_setschedparam` on other Unix platforms and `SetThreadPriority` on Windows.
+///
+/// [`Builder::thread_priority`]: struct.Builder.html#method.thread_priority
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ThreadPriority {
+    Lowest,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+    Highest,
+}
+
+impl ThreadPriority {
+    /// Apply the priority to the calling thread. Returns `false` if the platform refused the
+    /// change, e.g. because raising the priority requires privileges.
+    pub(crate) fn apply(self) -> bool {
+        imp::apply(self)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::ThreadPriority;
+    use libc;
+
+    pub fn apply(priority: ThreadPriority) -> bool {
+        let nice = match priority {
+            ThreadPriority::Lowest => 19,
+            ThreadPriority::BelowNormal => 10,
+            ThreadPriority::Normal => 0,
+            ThreadPriority::AboveNormal => -5,
+            ThreadPriority::Highest => -10,
+        };
+        // On Linux the nice value is a per-thread attribute when addressed by thread id.
+        unsafe {
+            let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+            libc::setpriority(libc::PRIO_PROCESS, tid, nice) == 0
+        }
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+mod imp {
+    use super::ThreadPriority;
+    use libc;
+    use std::mem;
+
+    pub fn apply(priority: ThreadPriority) -> bool {
+        let level = match priority {
+            ThreadPriority::Lowest => 0,
+            ThreadPriority::BelowNormal => 1,
+            ThreadPriority::Normal => 2,
+            ThreadPriority::AboveNormal => 3,
+            ThreadPriority::Highest => 4,
+        };
+        unsafe {
+            let policy = libc::SCHED_OTHER;
+            let min = libc::sched_get_priority_min(policy);
+            let max = libc::sched_get_priority_max(policy);
+            let mut param: libc::sched_param = mem::zeroed();
+            param.sched_priority = min + (max - min) * level / 4;
+            libc::pthread_setschedparam(libc::pthread_self(), policy, &param) == 0
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::ThreadPriority;
+    use std::os::raw::{c_int, c_void};
+
+    extern "system" {
+        fn GetCurrentThread() -> *mut c_void;
+        fn SetThreadPriority(thread: *mut c_void, priority: c_int) -> c_int;
+    }
+
+    pub fn apply(priority: ThreadPriority) -> bool {
+        let priority = match priority {
+            ThreadPriority::Lowest => -2,
+            ThreadPriority::BelowNormal => -1,
+            ThreadPriority::Normal => 0,
+            ThreadPriority::AboveNormal => 1,
+            ThreadPriority::Highest => 2,
+        };
+        unsafe { SetThreadPriority(GetCurrentThread(), priority) != 0 }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    use super::ThreadPriority;
+
+    pub fn apply(_priority: ThreadPriority) -> bool {
+        false
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::ThreadPriority;
+    use libc;
+    use std::thread;
+
+    fn current_nice() -> libc::c_int {
+        unsafe {
+            let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+            libc::getpriority(libc::PRIO_PROCESS, tid)
+        }
+    }
+
+    #[test]
+    fn test_lowest_sets_nice_value() {
+        let nice = thread::spawn(|| {
+            assert!(ThreadPriority::Lowest.apply());
+            current_nice()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(nice, 19);
+        // Only the spawned thread was affected.
+        assert_ne!(current_nice(), 19);
+    }
+}
@@ -0,0 +1,190 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Execution counts and times of the jobs by tag, see [`Builder::profile_jobs`].
+//!
+//! [`Builder::profile_jobs`]: ../struct.Builder.html#method.profile_jobs
+
+use nanos_to_duration;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The jobs of one tag in a [`ProfileReport`].
+///
+/// [`ProfileReport`]: struct.ProfileReport.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobProfile {
+    tag: Option<Arc<str>>,
+    count: u64,
+    panics: u64,
+    total_time: Duration,
+}
+
+impl JobProfile {
+    /// The tag the jobs were submitted with by [`ThreadPool::execute_tagged`], `None` for the
+    /// untagged jobs.
+    ///
+    /// [`ThreadPool::execute_tagged`]: struct.ThreadPool.html#method.execute_tagged
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    /// The number of jobs which have finished, including the ones which panicked.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The number of jobs which panicked.
+    pub fn panics(&self) -> u64 {
+        self.panics
+    }
+
+    /// The time the jobs spent executing.
+    pub fn total_time(&self) -> Duration {
+        self.total_time
+    }
+
+    /// The average time a job spent executing.
+    pub fn mean_time(&self) -> Duration {
+        match self.count {
+            0 => Duration::from_secs(0),
+            count => nanos_to_duration((self.total_time.as_nanos() / u128::from(count)) as u64),
+        }
+    }
+}
+
+/// The execution counts and times of the jobs of a pool by tag, see
+/// [`ThreadPool::profile_report`].
+///
+/// The `Display` implementation renders a table, one line per tag.
+///
+/// [`ThreadPool::profile_report`]: struct.ThreadPool.html#method.profile_report
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfileReport {
+    profiles: Vec<JobProfile>,
+}
+
+impl ProfileReport {
+    /// The jobs of every tag, the ones which took the most time in total first.
+    pub fn profiles(&self) -> &[JobProfile] {
+        &self.profiles
+    }
+
+    /// The jobs submitted with `tag`, or the untagged jobs for `None`. Returns `None` if no such
+    /// job has finished.
+    pub fn get(&self, tag: Option<&str>) -> Option<&JobProfile> {
+        self.profiles.iter().find(|profile| profile.tag() == tag)
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<24} {:>10} {:>8} {:>14} {:>14}",
+            "tag", "count", "panics", "total", "mean"
+        )?;
+        for profile in &self.profiles {
+            writeln!(
+                f,
+                "{:<24} {:>10} {:>8} {:>14} {:>14}",
+                profile.tag().unwrap_or("<untagged>"),
+                profile.count,
+                profile.panics,
+                format!("{:?}", profile.total_time),
+                format!("{:?}", profile.mean_time()),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Aggregates the jobs of a pool by tag.
+pub(crate) struct Profiler {
+    by_tag: Mutex<HashMap<Option<Arc<str>>, JobProfile>>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            by_tag: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, tag: &Option<Arc<str>>, time: Duration, panicked: bool) {
+        let mut by_tag = self
+            .by_tag
+            .lock()
+            .expect("Profiler::record unable to lock profiles");
+        let profile = by_tag.entry(tag.clone()).or_insert_with(|| JobProfile {
+            tag: tag.clone(),
+            count: 0,
+            panics: 0,
+            total_time: Duration::from_secs(0),
+        });
+        profile.count += 1;
+        if panicked {
+            profile.panics += 1;
+        }
+        profile.total_time += time;
+    }
+
+    pub fn reset(&self) {
+        self.by_tag
+            .lock()
+            .expect("Profiler::reset unable to lock profiles")
+            .clear();
+    }
+
+    pub fn report(&self) -> ProfileReport {
+        let mut profiles: Vec<_> = self
+            .by_tag
+            .lock()
+            .expect("Profiler::report unable to lock profiles")
+            .values()
+            .cloned()
+            .collect();
+        profiles.sort_by(|a, b| b.total_time.cmp(&a.total_time).then(a.tag.cmp(&b.tag)));
+        ProfileReport { profiles }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Profiler;
+    use std::time::Duration;
+
+    #[test]
+    fn test_report() {
+        let profiler = Profiler::new();
+        let parse = Some("parse".into());
+        profiler.record(&parse, Duration::from_millis(10), false);
+        profiler.record(&None, Duration::from_millis(5), false);
+        profiler.record(&parse, Duration::from_millis(20), true);
+
+        let report = profiler.report();
+        let tags: Vec<_> = report.profiles().iter().map(|p| p.tag()).collect();
+        assert_eq!(tags, vec![Some("parse"), None]);
+
+        let parse = report.get(Some("parse")).unwrap();
+        assert_eq!(parse.count(), 2);
+        assert_eq!(parse.panics(), 1);
+        assert_eq!(parse.total_time(), Duration::from_millis(30));
+        assert_eq!(parse.mean_time(), Duration::from_millis(15));
+        assert_eq!(report.get(None).unwrap().count(), 1);
+        assert!(report.get(Some("render")).is_none());
+
+        let table = report.to_string();
+        assert_eq!(table.lines().count(), 3);
+        assert!(table.lines().nth(2).unwrap().starts_with("<untagged>"));
+    }
+}
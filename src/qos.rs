@@ -0,0 +1,107 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Quality of service class of the worker threads on Apple platforms.
+
+/// Quality of service class of the worker threads, see [`Builder::thread_qos`].
+///
+/// On macOS, iOS and the other Apple platforms the scheduler decides by the QoS class of a thread
+/// rather than by its priority, both how much CPU time it gets and whether it runs on the
+/// efficiency or the performance cores. A pool doing background work should therefore use
+/// [`Utility`](#variant.Utility) or [`Background`](#variant.Background), so that it does not
+/// compete with the user interface. On other platforms the class is ignored.
+///
+/// [`Builder::thread_qos`]: struct.Builder.html#method.thread_qos
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum QosClass {
+    /// Work the user is waiting for to see the next frame, e.g. animations.
+    UserInteractive,
+    /// Work the user started and waits for, e.g. opening a document.
+    UserInitiated,
+    /// The class of threads which were not given one.
+    Default,
+    /// Long running work the user is aware of, e.g. a download with a progress bar.
+    Utility,
+    /// Work the user does not see, e.g. indexing or backups.
+    Background,
+}
+
+impl QosClass {
+    /// Apply the class to the calling thread. Returns `false` if the platform has no QoS classes
+    /// or refused the change.
+    pub(crate) fn apply(self) -> bool {
+        imp::apply(self)
+    }
+}
+
+#[cfg(target_vendor = "apple")]
+mod imp {
+    use super::QosClass;
+    use std::os::raw::{c_int, c_uint};
+
+    // From `pthread/qos.h` and `sys/qos.h`.
+    extern "C" {
+        fn pthread_set_qos_class_self_np(class: c_uint, relative_priority: c_int) -> c_int;
+    }
+
+    pub fn class(class: QosClass) -> c_uint {
+        match class {
+            QosClass::UserInteractive => 0x21,
+            QosClass::UserInitiated => 0x19,
+            QosClass::Default => 0x15,
+            QosClass::Utility => 0x11,
+            QosClass::Background => 0x09,
+        }
+    }
+
+    pub fn apply(qos: QosClass) -> bool {
+        unsafe { pthread_set_qos_class_self_np(class(qos), 0) == 0 }
+    }
+}
+
+#[cfg(not(target_vendor = "apple"))]
+mod imp {
+    use super::QosClass;
+
+    pub fn apply(_qos: QosClass) -> bool {
+        false
+    }
+}
+
+#[cfg(all(test, target_vendor = "apple"))]
+mod test {
+    use super::{imp, QosClass};
+    use libc;
+    use std::os::raw::{c_int, c_uint};
+    use std::thread;
+
+    extern "C" {
+        fn pthread_get_qos_class_np(
+            thread: libc::pthread_t,
+            class: *mut c_uint,
+            relative_priority: *mut c_int,
+        ) -> c_int;
+    }
+
+    #[test]
+    fn test_background_sets_qos_class() {
+        let class = thread::spawn(|| {
+            assert!(QosClass::Background.apply());
+            let (mut class, mut relative_priority) = (0, 0);
+            unsafe {
+                pthread_get_qos_class_np(libc::pthread_self(), &mut class, &mut relative_priority)
+            };
+            class
+        })
+        .join()
+        .unwrap();
+        assert_eq!(class, imp::class(QosClass::Background));
+    }
+}
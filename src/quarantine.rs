@@ -0,0 +1,161 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Rejection of the jobs of tags which keep panicking, see [`Builder::quarantine_tags`].
+//!
+//! [`Builder::quarantine_tags`]: ../struct.Builder.html#method.quarantine_tags
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The recent panics of the jobs of a tag.
+struct TagPanics {
+    /// When the jobs panicked, oldest first and within the window.
+    panics: VecDeque<Instant>,
+    quarantined: bool,
+}
+
+/// Quarantines a tag once its jobs have panicked `threshold` times within `window`.
+pub(crate) struct Quarantine {
+    threshold: usize,
+    window: Duration,
+    tags: Mutex<HashMap<Arc<str>, TagPanics>>,
+    // Lets submissions skip the lock while no tag is quarantined.
+    quarantined: AtomicUsize,
+    // Jobs rejected so far.
+    rejected: AtomicUsize,
+}
+
+impl Quarantine {
+    pub fn new(threshold: usize, window: Duration) -> Quarantine {
+        Quarantine {
+            threshold,
+            window,
+            tags: Mutex::new(HashMap::new()),
+            quarantined: AtomicUsize::new(0),
+            rejected: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns `true` if the jobs tagged `tag` are rejected, counting one rejected job if so.
+    pub fn reject(&self, tag: &str) -> bool {
+        if !self.is_quarantined(tag) {
+            return false;
+        }
+        self.rejected.fetch_add(1, Ordering::SeqCst);
+        true
+    }
+
+    /// Returns the number of jobs rejected so far.
+    pub fn rejected(&self) -> usize {
+        self.rejected.load(Ordering::SeqCst)
+    }
+
+    /// Count a panic of a job tagged `tag`.
+    pub fn record(&self, tag: &Arc<str>) {
+        let now = Instant::now();
+        let mut tags = self.tags.lock().expect("Quarantine unable to lock tags");
+        // Forget the tags which have not panicked for a while.
+        let window = self.window;
+        tags.retain(|_, tag| {
+            while tag
+                .panics
+                .front()
+                .is_some_and(|&panicked| now.duration_since(panicked) > window)
+            {
+                tag.panics.pop_front();
+            }
+            tag.quarantined || !tag.panics.is_empty()
+        });
+        let entry = tags.entry(tag.clone()).or_insert_with(|| TagPanics {
+            panics: VecDeque::new(),
+            quarantined: false,
+        });
+        if entry.quarantined {
+            return;
+        }
+        entry.panics.push_back(now);
+        if entry.panics.len() >= self.threshold {
+            entry.quarantined = true;
+            entry.panics.clear();
+            self.quarantined.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Returns `true` if the jobs tagged `tag` are rejected.
+    pub fn is_quarantined(&self, tag: &str) -> bool {
+        if self.quarantined.load(Ordering::SeqCst) == 0 {
+            return false;
+        }
+        self.tags
+            .lock()
+            .expect("Quarantine unable to lock tags")
+            .get(tag)
+            .is_some_and(|tag| tag.quarantined)
+    }
+
+    /// Returns the quarantined tags, sorted.
+    pub fn tags(&self) -> Vec<String> {
+        let mut quarantined: Vec<_> = self
+            .tags
+            .lock()
+            .expect("Quarantine unable to lock tags")
+            .iter()
+            .filter(|&(_, tag)| tag.quarantined)
+            .map(|(name, _)| name.to_string())
+            .collect();
+        quarantined.sort();
+        quarantined
+    }
+
+    /// Accept the jobs tagged `tag` again, with a clean slate. Returns `false` if the tag was not
+    /// quarantined.
+    pub fn release(&self, tag: &str) -> bool {
+        let mut tags = self.tags.lock().expect("Quarantine unable to lock tags");
+        if !tags.get(tag).is_some_and(|tag| tag.quarantined) {
+            return false;
+        }
+        tags.remove(tag);
+        self.quarantined.fetch_sub(1, Ordering::SeqCst);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Quarantine;
+    use std::sync::Arc;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    #[test]
+    fn test_window() {
+        let quarantine = Quarantine::new(2, Duration::from_millis(50));
+        let tag = Arc::from("flaky");
+        quarantine.record(&tag);
+        sleep(Duration::from_millis(100));
+        // The first panic is out of the window.
+        quarantine.record(&tag);
+        assert!(!quarantine.is_quarantined("flaky"));
+        quarantine.record(&tag);
+        assert!(quarantine.is_quarantined("flaky"));
+        assert!(!quarantine.is_quarantined("other"));
+        assert!(quarantine.reject("flaky"));
+        assert!(!quarantine.reject("other"));
+        assert_eq!(quarantine.rejected(), 1);
+
+        assert!(quarantine.release("flaky"));
+        assert!(!quarantine.release("flaky"));
+        quarantine.record(&tag);
+        assert!(!quarantine.is_quarantined("flaky"));
+    }
+}
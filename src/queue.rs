@@ -0,0 +1,655 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The job queue shared by all handles of a pool and its worker threads.
+
+use std::cell::UnsafeCell;
+use std::cmp;
+use std::collections::VecDeque;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{self, AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Instant;
+use wait::WaitStrategy;
+
+/// Number of slots in the lock-free ring. Must be a power of two.
+const RING_CAPACITY: usize = 1024;
+
+struct Slot<T> {
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Bounded lock-free multi-producer multi-consumer queue.
+///
+/// This is Dmitry Vyukov's bounded MPMC queue: every slot carries a sequence number which tells
+/// producers and consumers whether it is their turn to write or to read the slot, so neither
+/// side ever has to take a lock.
+pub struct Ring<T> {
+    buffer: Box<[Slot<T>]>,
+    mask: usize,
+    enqueue_pos: AtomicUsize,
+    dequeue_pos: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T> Ring<T> {
+    pub fn with_capacity(capacity: usize) -> Ring<T> {
+        assert!(capacity.is_power_of_two());
+        let buffer = (0..capacity)
+            .map(|i| Slot {
+                sequence: AtomicUsize::new(i),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+
+        Ring {
+            buffer,
+            mask: capacity - 1,
+            enqueue_pos: AtomicUsize::new(0),
+            dequeue_pos: AtomicUsize::new(0),
+        }
+    }
+
+    /// Push `value` into the ring, handing it back if the ring is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.enqueue_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.buffer[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let diff = sequence.wrapping_sub(pos) as isize;
+
+            if diff == 0 {
+                match self.enqueue_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // The successful CAS gives us exclusive write access to the slot.
+                        unsafe { (*slot.value.get()).as_mut_ptr().write(value) };
+                        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                return Err(value);
+            } else {
+                pos = self.enqueue_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Pop the oldest value out of the ring, if there is one.
+    pub fn pop(&self) -> Option<T> {
+        let mut pos = self.dequeue_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.buffer[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let diff = sequence.wrapping_sub(pos.wrapping_add(1)) as isize;
+
+            if diff == 0 {
+                match self.dequeue_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // The successful CAS gives us exclusive read access to the slot.
+                        let value = unsafe { (*slot.value.get()).as_ptr().read() };
+                        slot.sequence
+                            .store(pos.wrapping_add(self.mask + 1), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                return None;
+            } else {
+                pos = self.dequeue_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Returns `true` if no push has been started that was not popped yet.
+    fn is_empty(&self) -> bool {
+        self.enqueue_pos.load(Ordering::SeqCst) == self.dequeue_pos.load(Ordering::SeqCst)
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// The order in which a [`JobQueue`] hands out its values.
+///
+/// Implementations only store values, blocking and waking up consumers is done by the
+/// [`JobQueue`] wrapping them.
+pub trait Discipline<T>: Send + Sync {
+    fn push(&self, value: T);
+
+    /// Pop the next value without blocking.
+    fn try_pop(&self) -> Option<T>;
+
+    /// Pop the next value for the consumer numbered `consumer`, which may be handed values routed
+    /// to it before all others.
+    fn try_pop_for(&self, consumer: usize) -> Option<T> {
+        let _ = consumer;
+        self.try_pop()
+    }
+
+    /// Pop up to `max` values for the consumer numbered `consumer` into `batch`, in the order
+    /// they would be popped one by one, and return how many were popped. Disciplines behind a
+    /// lock should take them with a single acquisition.
+    fn try_pop_batch_for(&self, consumer: usize, max: usize, batch: &mut VecDeque<T>) -> usize {
+        let mut count = 0;
+        while count < max {
+            match self.try_pop_for(consumer) {
+                Some(value) => batch.push_back(value),
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Returns `true` if there is no value to pop. Must be sequentially consistent with `push`.
+    fn is_empty(&self) -> bool;
+
+    /// Hand the queued values to `f` until it returns `true` for one of them, which may have
+    /// been changed and is put back into place. Returns `false` if there was no such value, or
+    /// the discipline can not look at its queued values.
+    fn update(&self, f: &mut dyn FnMut(&mut T) -> bool) -> bool {
+        let _ = f;
+        false
+    }
+}
+
+/// Unbounded first-in first-out discipline.
+///
+/// Values go into a lock-free [`Ring`] as long as it has room. Only once the ring is full does
+/// the queue fall back to a mutex protected overflow list, which keeps taking values until it has
+/// been drained again, so a single producer's values are still popped in the order they were
+/// pushed.
+pub struct Fifo<T> {
+    ring: Ring<T>,
+    overflow: Mutex<VecDeque<T>>,
+    overflow_len: AtomicUsize,
+}
+
+impl<T> Fifo<T> {
+    pub fn new() -> Fifo<T> {
+        Fifo::with_capacity(RING_CAPACITY)
+    }
+
+    /// A queue whose ring has `capacity` slots, which must be a power of two.
+    fn with_capacity(capacity: usize) -> Fifo<T> {
+        Fifo {
+            ring: Ring::with_capacity(capacity),
+            overflow: Mutex::new(VecDeque::new()),
+            overflow_len: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T: Send> Discipline<T> for Fifo<T> {
+    fn push(&self, value: T) {
+        let value = if self.overflow_len.load(Ordering::Acquire) == 0 {
+            self.ring.push(value).err()
+        } else {
+            Some(value)
+        };
+        if let Some(value) = value {
+            let mut overflow = self
+                .overflow
+                .lock()
+                .expect("Fifo::push unable to lock overflow");
+            overflow.push_back(value);
+            self.overflow_len.store(overflow.len(), Ordering::Release);
+        }
+    }
+
+    fn try_pop(&self) -> Option<T> {
+        if let Some(value) = self.ring.pop() {
+            return Some(value);
+        }
+        if self.overflow_len.load(Ordering::Acquire) == 0 {
+            return None;
+        }
+        let mut overflow = self
+            .overflow
+            .lock()
+            .expect("Fifo::try_pop unable to lock overflow");
+        let value = overflow.pop_front();
+        self.overflow_len.store(overflow.len(), Ordering::Release);
+        value
+    }
+
+    fn try_pop_batch_for(&self, _consumer: usize, max: usize, batch: &mut VecDeque<T>) -> usize {
+        let mut count = 0;
+        while count < max {
+            match self.ring.pop() {
+                Some(value) => batch.push_back(value),
+                None => break,
+            }
+            count += 1;
+        }
+        if count == max || self.overflow_len.load(Ordering::Acquire) == 0 {
+            return count;
+        }
+        let mut overflow = self
+            .overflow
+            .lock()
+            .expect("Fifo::try_pop_batch_for unable to lock overflow");
+        let taken = cmp::min(max - count, overflow.len());
+        batch.extend(overflow.drain(..taken));
+        self.overflow_len.store(overflow.len(), Ordering::Release);
+        count + taken
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ring.is_empty() && self.overflow_len.load(Ordering::SeqCst) == 0
+    }
+}
+
+/// First-in first-out discipline split into several [`Fifo`] shards, so that producers on
+/// different threads do not contend on the same ring.
+///
+/// Every producing thread pushes into one shard, picked round-robin the first time the thread
+/// pushes, so the values of a single thread are still popped in the order they were pushed. A
+/// consumer first drains the shard matching its number and then steals from the others.
+pub struct Sharded<T> {
+    shards: Vec<Fifo<T>>,
+}
+
+impl<T> Sharded<T> {
+    pub fn new(shards: usize) -> Sharded<T> {
+        assert!(shards >= 1);
+        // The shards split the slots of a single ring, so that sharding does not multiply the
+        // memory of the queue and the time it takes to set it up.
+        let capacity = (RING_CAPACITY / shards).next_power_of_two();
+        Sharded {
+            shards: (0..shards).map(|_| Fifo::with_capacity(capacity)).collect(),
+        }
+    }
+}
+
+static NEXT_PRODUCER: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // Numbers the threads pushing into a `Sharded` queue.
+    static PRODUCER: usize = NEXT_PRODUCER.fetch_add(1, Ordering::Relaxed);
+}
+
+impl<T: Send> Discipline<T> for Sharded<T> {
+    fn push(&self, value: T) {
+        let shard = PRODUCER.with(|producer| *producer % self.shards.len());
+        self.shards[shard].push(value);
+    }
+
+    fn try_pop(&self) -> Option<T> {
+        self.try_pop_for(0)
+    }
+
+    fn try_pop_for(&self, consumer: usize) -> Option<T> {
+        let n = self.shards.len();
+        (0..n).find_map(|i| self.shards[(consumer + i) % n].try_pop())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.is_empty())
+    }
+}
+
+/// Why a blocking pop returned without a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PopError {
+    /// The queue has been closed and drained.
+    Closed,
+    /// The deadline of the pop has passed.
+    TimedOut,
+}
+
+/// Unbounded multi-producer multi-consumer queue with blocking pops.
+///
+/// The order of the values is decided by a [`Discipline`], the queue itself takes care of
+/// closing and of putting consumers which find it empty to sleep on a condition variable.
+/// Producers only touch that mutex when somebody is actually sleeping.
+pub struct JobQueue<T> {
+    discipline: Box<dyn Discipline<T>>,
+    closed: AtomicBool,
+    // Consumers blocked in `pop`, whether spinning or sleeping.
+    waiting: AtomicUsize,
+    sleepers: AtomicUsize,
+    sleep_lock: Mutex<()>,
+    sleep_condvar: Condvar,
+    wait_strategy: WaitStrategy,
+}
+
+impl<T> JobQueue<T> {
+    pub fn with_discipline(discipline: Box<dyn Discipline<T>>) -> JobQueue<T> {
+        JobQueue {
+            discipline,
+            closed: AtomicBool::new(false),
+            waiting: AtomicUsize::new(0),
+            sleepers: AtomicUsize::new(0),
+            sleep_lock: Mutex::new(()),
+            sleep_condvar: Condvar::new(),
+            wait_strategy: WaitStrategy::park(),
+        }
+    }
+
+    /// Let consumers which find the queue empty spin and yield as told by `wait_strategy` before
+    /// they go to sleep.
+    pub fn with_wait_strategy(mut self, wait_strategy: WaitStrategy) -> JobQueue<T> {
+        self.wait_strategy = wait_strategy;
+        self
+    }
+
+    /// Push `value` into the queue, handing it back if the queue has been closed.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(value);
+        }
+        self.discipline.push(value);
+
+        // Pairs with the fence in `pop`: either the sleeper sees our value or we see the sleeper.
+        atomic::fence(Ordering::SeqCst);
+        if self.sleepers.load(Ordering::SeqCst) > 0 {
+            let _guard = self
+                .sleep_lock
+                .lock()
+                .expect("JobQueue::push unable to lock sleep_lock");
+            self.sleep_condvar.notify_one();
+        }
+        Ok(())
+    }
+
+    /// Pop a value without blocking.
+    pub fn try_pop(&self) -> Option<T> {
+        self.discipline.try_pop()
+    }
+
+    /// Pop a value for the consumer numbered `consumer`, blocking until one is available.
+    ///
+    /// Returns `None` once the queue has been closed and drained.
+    pub fn pop(&self, consumer: usize) -> Option<T> {
+        self.pop_until(consumer, None).ok()
+    }
+
+    /// Pop a value for the consumer numbered `consumer`, blocking until one is available or
+    /// `deadline` has passed.
+    pub fn pop_until(&self, consumer: usize, deadline: Option<Instant>) -> Result<T, PopError> {
+        if let Some(value) = self.discipline.try_pop_for(consumer) {
+            return Ok(value);
+        }
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+        let value = self.wait_pop(consumer, deadline);
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+        value
+    }
+
+    /// Pop up to `max` values for the consumer numbered `consumer` into `batch`, blocking until
+    /// at least one is available or `deadline` has passed.
+    pub fn pop_batch(
+        &self,
+        consumer: usize,
+        max: usize,
+        batch: &mut VecDeque<T>,
+        deadline: Option<Instant>,
+    ) -> Result<(), PopError> {
+        if self.discipline.try_pop_batch_for(consumer, max, batch) > 0 {
+            return Ok(());
+        }
+        let value = self.pop_until(consumer, deadline)?;
+        batch.push_back(value);
+        self.discipline.try_pop_batch_for(consumer, max - 1, batch);
+        Ok(())
+    }
+
+    /// Returns the number of consumers blocked in `pop` because the queue is empty.
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+
+    fn wait_pop(&self, consumer: usize, deadline: Option<Instant>) -> Result<T, PopError> {
+        let mut attempt = 0;
+        loop {
+            if let Some(value) = self.discipline.try_pop_for(consumer) {
+                return Ok(value);
+            }
+            if self.closed.load(Ordering::SeqCst) {
+                return self
+                    .discipline
+                    .try_pop_for(consumer)
+                    .ok_or(PopError::Closed);
+            }
+            if self.wait_strategy.back_off(attempt) {
+                attempt += 1;
+                continue;
+            }
+            let now = Instant::now();
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                return Err(PopError::TimedOut);
+            }
+
+            let guard = self
+                .sleep_lock
+                .lock()
+                .expect("JobQueue::pop unable to lock sleep_lock");
+            self.sleepers.fetch_add(1, Ordering::SeqCst);
+            atomic::fence(Ordering::SeqCst);
+            // Re-check while holding the lock, a push which missed our sleeper registration has
+            // to be visible by now and a push which saw it can not notify before we wait.
+            if self.is_empty() && !self.closed.load(Ordering::SeqCst) {
+                let _guard = match deadline {
+                    Some(deadline) => {
+                        self.sleep_condvar
+                            .wait_timeout(guard, deadline - now)
+                            .expect("JobQueue::pop unable to wait on sleep_condvar")
+                            .0
+                    }
+                    None => self
+                        .sleep_condvar
+                        .wait(guard)
+                        .expect("JobQueue::pop unable to wait on sleep_condvar"),
+                };
+            }
+            self.sleepers.fetch_sub(1, Ordering::SeqCst);
+            attempt = 0;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.discipline.is_empty()
+    }
+
+    /// See `Discipline::update`.
+    pub fn update(&self, f: &mut dyn FnMut(&mut T) -> bool) -> bool {
+        self.discipline.update(f)
+    }
+
+    /// Returns `true` once the queue has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Wake up all sleeping consumers and let them return `None` once the queue is drained.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let _guard = self
+            .sleep_lock
+            .lock()
+            .expect("JobQueue::close unable to lock sleep_lock");
+        self.sleep_condvar.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Discipline, Fifo, JobQueue, PopError, Ring, Sharded, RING_CAPACITY};
+    use std::collections::VecDeque;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use wait::WaitStrategy;
+
+    #[test]
+    fn test_ring_full_and_empty() {
+        let ring = Ring::with_capacity(4);
+        for i in 0..4 {
+            assert_eq!(ring.push(i), Ok(()));
+        }
+        assert_eq!(ring.push(4), Err(4));
+        for i in 0..4 {
+            assert_eq!(ring.pop(), Some(i));
+        }
+        assert_eq!(ring.pop(), None);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_overflow_keeps_order() {
+        let queue = JobQueue::with_discipline(Box::new(Fifo::new()));
+        for i in 0..3 * RING_CAPACITY {
+            queue.push(i).unwrap();
+        }
+        for i in 0..3 * RING_CAPACITY {
+            assert_eq!(queue.try_pop(), Some(i));
+        }
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_pop_batch() {
+        let queue = JobQueue::with_discipline(Box::new(Fifo::with_capacity(4)));
+        for i in 0..10 {
+            queue.push(i).unwrap();
+        }
+        // The batch spans the ring and its overflow.
+        let mut batch = VecDeque::new();
+        assert_eq!(queue.pop_batch(0, 6, &mut batch, None), Ok(()));
+        assert_eq!(batch, [0, 1, 2, 3, 4, 5]);
+        batch.clear();
+        assert_eq!(queue.pop_batch(0, 6, &mut batch, None), Ok(()));
+        assert_eq!(batch, [6, 7, 8, 9]);
+
+        batch.clear();
+        let deadline = Instant::now() + Duration::from_millis(20);
+        assert_eq!(
+            queue.pop_batch(0, 6, &mut batch, Some(deadline)),
+            Err(PopError::TimedOut)
+        );
+        assert!(Instant::now() >= deadline);
+        queue.close();
+        assert_eq!(
+            queue.pop_batch(0, 6, &mut batch, None),
+            Err(PopError::Closed)
+        );
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_push_pop() {
+        let n_producers = 4;
+        let n_values = 100_000;
+        let queue = Arc::new(JobQueue::with_discipline(Box::new(Fifo::new())));
+
+        let consumers = (0..4)
+            .map(|consumer| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let mut sum = 0;
+                    while let Some(value) = queue.pop(consumer) {
+                        sum += value;
+                    }
+                    sum
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let producers = (0..n_producers)
+            .map(|_| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for i in 0..n_values {
+                        queue.push(i).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        queue.close();
+
+        let sum: usize = consumers.into_iter().map(|c| c.join().unwrap()).sum();
+        assert_eq!(sum, n_producers * n_values * (n_values - 1) / 2);
+    }
+
+    #[test]
+    fn test_spinning_consumer() {
+        let queue = Arc::new(
+            JobQueue::with_discipline(Box::new(Fifo::new()))
+                .with_wait_strategy(WaitStrategy::park().spin(100).yield_now(10)),
+        );
+        let consumer = {
+            let queue = queue.clone();
+            thread::spawn(move || (queue.pop(0), queue.pop(0)))
+        };
+        queue.push(1).unwrap();
+        queue.close();
+        assert_eq!(consumer.join().unwrap(), (Some(1), None));
+    }
+
+    #[test]
+    fn test_sharded_keeps_order_per_producer() {
+        let queue = Arc::new(Sharded::new(4));
+        let producers = (0..3)
+            .map(|producer| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for i in 0..3 * RING_CAPACITY {
+                        queue.push((producer, i));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        let mut next = [0; 3];
+        while let Some((producer, i)) = queue.try_pop_for(3) {
+            assert_eq!(i, next[producer]);
+            next[producer] += 1;
+        }
+        assert_eq!(next, [3 * RING_CAPACITY; 3]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_push_after_close() {
+        let queue = JobQueue::with_discipline(Box::new(Fifo::new()));
+        queue.push(1).unwrap();
+        queue.close();
+        assert_eq!(queue.push(2), Err(2));
+        assert_eq!(queue.pop(0), Some(1));
+        assert_eq!(queue.pop(0), None);
+    }
+}
@@ -0,0 +1,714 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The order in which queued jobs are handed to the worker threads.
+
+use queue::{Discipline, Fifo, JobQueue, Sharded};
+use std::cmp::{self, Ordering};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::fmt;
+use std::mem;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use Job;
+
+/// Maximum number of shards of a [`Scheduling::Fifo`] queue.
+const MAX_SHARDS: usize = 8;
+
+/// The order in which a [`ThreadPool`] starts its queued jobs, see [`Builder::scheduling`].
+///
+/// [`ThreadPool`]: struct.ThreadPool.html
+/// [`Builder::scheduling`]: struct.Builder.html#method.scheduling
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheduling {
+    /// Jobs submitted from the same thread are started in the order they were submitted. This
+    /// is the default.
+    ///
+    /// To keep submitting threads from contending with each other, pools with several worker
+    /// threads split their queue into shards and every submitting thread uses one of them. Jobs
+    /// submitted from different threads may therefore not start in the order they were
+    /// submitted.
+    Fifo,
+    /// The queued job with the earliest deadline is started first.
+    ///
+    /// Jobs submitted without a deadline are started after all jobs with a deadline, in the
+    /// order they were submitted.
+    EarliestDeadlineFirst,
+    /// Every [`ThreadPool`] handle gets its own queue and the workers take turns between the
+    /// handles which have queued jobs, so one handle submitting lots of jobs can not starve the
+    /// others.
+    ///
+    /// Each handle created with [`ThreadPool::clone`] is scheduled separately from the handle it
+    /// was cloned from. Jobs of the same handle are started in the order they were submitted.
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`ThreadPool::clone`]: struct.ThreadPool.html#impl-Clone
+    Fair,
+    /// The queued job with the highest priority, as given to
+    /// [`ThreadPool::execute_with_priority`], is started first. Other jobs have priority 0. Jobs
+    /// of the same priority are started in the order they were submitted.
+    ///
+    /// With [`Builder::priority_aging`] the priority of a job grows while it waits in the queue,
+    /// so low priority jobs can not be starved by a steady stream of high priority ones. The
+    /// priority of a job submitted with [`ThreadPool::spawn`] can still be changed while it is
+    /// queued, see [`JobHandle::set_priority`].
+    ///
+    /// [`ThreadPool::execute_with_priority`]: struct.ThreadPool.html#method.execute_with_priority
+    /// [`Builder::priority_aging`]: struct.Builder.html#method.priority_aging
+    /// [`ThreadPool::spawn`]: struct.ThreadPool.html#method.spawn
+    /// [`JobHandle::set_priority`]: struct.JobHandle.html#method.set_priority
+    Priority,
+}
+
+impl Scheduling {
+    /// The discipline of a pool with `num_threads` worker threads.
+    pub(crate) fn discipline(
+        self,
+        num_threads: usize,
+        aging: Option<Duration>,
+    ) -> Box<dyn Discipline<Job>> {
+        match self {
+            Scheduling::Fifo if num_threads > 1 => {
+                Box::new(Sharded::new(cmp::min(num_threads, MAX_SHARDS)))
+            }
+            Scheduling::Fifo => Box::new(Fifo::new()),
+            Scheduling::EarliestDeadlineFirst => Box::new(EarliestDeadlineFirst::new()),
+            Scheduling::Fair => Box::new(Fair::new()),
+            Scheduling::Priority => Box::new(Priority::new(aging)),
+        }
+    }
+}
+
+/// Create the queue of a pool ordering its jobs with `discipline`.
+pub(crate) fn job_queue(discipline: Box<dyn Discipline<Job>>) -> JobQueue<Job> {
+    JobQueue::with_discipline(Box::new(Affinity::new(discipline)))
+}
+
+/// A job handed to a [`Scheduler`], together with the information to order it by.
+///
+/// [`Scheduler`]: trait.Scheduler.html
+pub struct QueuedJob(Job);
+
+impl QueuedJob {
+    /// The deadline the job was submitted with by [`ThreadPool::execute_with_deadline`].
+    ///
+    /// [`ThreadPool::execute_with_deadline`]: struct.ThreadPool.html#method.execute_with_deadline
+    pub fn deadline(&self) -> Option<Instant> {
+        self.0.deadline
+    }
+
+    /// The priority the job was submitted with by [`ThreadPool::execute_with_priority`], 0 for
+    /// other jobs.
+    ///
+    /// [`ThreadPool::execute_with_priority`]: struct.ThreadPool.html#method.execute_with_priority
+    pub fn priority(&self) -> i8 {
+        self.0.priority
+    }
+
+    /// The tag the job was submitted with by [`ThreadPool::execute_tagged`].
+    ///
+    /// [`ThreadPool::execute_tagged`]: struct.ThreadPool.html#method.execute_tagged
+    pub fn tag(&self) -> Option<&str> {
+        self.0.tag.as_deref()
+    }
+
+    /// Identifies the [`ThreadPool`] handle which submitted the job. Every handle created with
+    /// [`ThreadPool::clone`] gets a different number.
+    ///
+    /// [`ThreadPool`]: struct.ThreadPool.html
+    /// [`ThreadPool::clone`]: struct.ThreadPool.html#impl-Clone
+    pub fn submitter(&self) -> usize {
+        self.0.submitter
+    }
+}
+
+impl fmt::Debug for QueuedJob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("QueuedJob")
+            .field("deadline", &self.deadline())
+            .field("priority", &self.priority())
+            .field("tag", &self.tag())
+            .field("submitter", &self.submitter())
+            .finish()
+    }
+}
+
+/// A custom order in which a [`ThreadPool`] starts its queued jobs, see [`Builder::scheduler`].
+///
+/// The scheduler only stores the jobs, the pool takes care of putting idle worker threads to
+/// sleep and waking them up when jobs are pushed. Its methods are called concurrently by the
+/// submitting threads and the worker threads.
+///
+/// [`ThreadPool`]: struct.ThreadPool.html
+/// [`Builder::scheduler`]: struct.Builder.html#method.scheduler
+///
+/// # Examples
+///
+/// A scheduler starting the most recently submitted job first:
+///
+/// ```
+/// use std::sync::Mutex;
+/// use threadpool::{QueuedJob, Scheduler};
+///
+/// #[derive(Default)]
+/// struct Lifo(Mutex<Vec<QueuedJob>>);
+///
+/// impl Scheduler for Lifo {
+///     fn push(&self, job: QueuedJob) {
+///         self.0.lock().unwrap().push(job);
+///     }
+///
+///     fn pop(&self) -> Option<QueuedJob> {
+///         self.0.lock().unwrap().pop()
+///     }
+///
+///     fn len(&self) -> usize {
+///         self.0.lock().unwrap().len()
+///     }
+/// }
+///
+/// let pool = threadpool::Builder::new()
+///     .num_threads(1)
+///     .scheduler(Lifo::default)
+///     .build();
+/// pool.execute(|| println!("hello"));
+/// pool.join();
+/// ```
+pub trait Scheduler: Send + Sync {
+    /// Store a newly submitted job.
+    fn push(&self, job: QueuedJob);
+
+    /// Take the job to start next, without blocking. Returns `None` if there is no job.
+    fn pop(&self) -> Option<QueuedJob>;
+
+    /// The number of stored jobs. A job must be counted as soon as `push` returns, otherwise a
+    /// worker thread may go to sleep although there is a job for it.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if there are no stored jobs.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Drives a user provided [`Scheduler`].
+pub(crate) struct Custom(pub Box<dyn Scheduler>);
+
+impl Discipline<Job> for Custom {
+    fn push(&self, job: Job) {
+        self.0.push(QueuedJob(job));
+    }
+
+    fn try_pop(&self) -> Option<Job> {
+        self.0.pop().map(|job| job.0)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Keeps the jobs submitted with [`ThreadPool::execute_with_affinity`] aside for the worker they
+/// prefer, all other jobs are ordered by the wrapped discipline.
+///
+/// A worker first takes the jobs which prefer it, then the jobs of the wrapped discipline. Only
+/// once both are empty does it steal from the workers which have the most jobs waiting for them,
+/// which are most likely busy.
+///
+/// [`ThreadPool::execute_with_affinity`]: ../struct.ThreadPool.html#method.execute_with_affinity
+struct Affinity {
+    inner: Box<dyn Discipline<Job>>,
+    // Jobs by preferred worker, each list in submission order.
+    preferred: Mutex<HashMap<usize, VecDeque<Job>>>,
+    preferred_len: AtomicUsize,
+}
+
+impl Affinity {
+    fn new(inner: Box<dyn Discipline<Job>>) -> Affinity {
+        Affinity {
+            inner,
+            preferred: Mutex::new(HashMap::new()),
+            preferred_len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Pop a job preferring `worker`, or if there is none and `steal` is set, a job preferring
+    /// the worker with the most jobs waiting.
+    fn pop_preferred(&self, worker: Option<usize>, steal: bool) -> Option<Job> {
+        if self.preferred_len.load(atomic::Ordering::SeqCst) == 0 {
+            return None;
+        }
+        let mut preferred = self
+            .preferred
+            .lock()
+            .expect("Affinity::pop_preferred unable to lock jobs");
+        let worker = match worker.filter(|worker| preferred.contains_key(worker)) {
+            Some(worker) => worker,
+            None if steal => *preferred
+                .iter()
+                .max_by_key(|&(_, jobs)| jobs.len())
+                .map(|(worker, _)| worker)?,
+            None => return None,
+        };
+        let (job, exhausted) = {
+            let jobs = preferred
+                .get_mut(&worker)
+                .expect("Affinity::pop_preferred worker without jobs");
+            (jobs.pop_front(), jobs.is_empty())
+        };
+        if exhausted {
+            preferred.remove(&worker);
+        }
+        self.preferred_len.fetch_sub(1, atomic::Ordering::SeqCst);
+        job
+    }
+}
+
+impl Discipline<Job> for Affinity {
+    fn push(&self, job: Job) {
+        let worker = match job.affinity {
+            Some(worker) => worker,
+            None => return self.inner.push(job),
+        };
+        let mut preferred = self
+            .preferred
+            .lock()
+            .expect("Affinity::push unable to lock jobs");
+        preferred.entry(worker).or_default().push_back(job);
+        self.preferred_len.fetch_add(1, atomic::Ordering::SeqCst);
+    }
+
+    fn try_pop(&self) -> Option<Job> {
+        self.inner
+            .try_pop()
+            .or_else(|| self.pop_preferred(None, true))
+    }
+
+    fn try_pop_for(&self, worker: usize) -> Option<Job> {
+        self.pop_preferred(Some(worker), false)
+            .or_else(|| self.inner.try_pop())
+            .or_else(|| self.pop_preferred(None, true))
+    }
+
+    // Like `try_pop_for`, but takes the jobs of the wrapped discipline in one go.
+    fn try_pop_batch_for(&self, worker: usize, max: usize, batch: &mut VecDeque<Job>) -> usize {
+        let mut count = 0;
+        while count < max {
+            match self.pop_preferred(Some(worker), false) {
+                Some(job) => batch.push_back(job),
+                None => break,
+            }
+            count += 1;
+        }
+        count += self.inner.try_pop_batch_for(worker, max - count, batch);
+        while count < max {
+            match self.pop_preferred(None, true) {
+                Some(job) => batch.push_back(job),
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty() && self.preferred_len.load(atomic::Ordering::SeqCst) == 0
+    }
+
+    fn update(&self, f: &mut dyn FnMut(&mut Job) -> bool) -> bool {
+        self.inner.update(f)
+    }
+}
+
+struct DeadlineEntry {
+    sequence: u64,
+    job: Job,
+}
+
+impl DeadlineEntry {
+    /// `Less` means `self` has to run first.
+    fn urgency(&self, other: &DeadlineEntry) -> Ordering {
+        match (self.job.deadline, other.job.deadline) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+        .then(self.sequence.cmp(&other.sequence))
+    }
+}
+
+impl PartialEq for DeadlineEntry {
+    fn eq(&self, other: &DeadlineEntry) -> bool {
+        self.sequence == other.sequence
+    }
+}
+
+impl Eq for DeadlineEntry {}
+
+impl PartialOrd for DeadlineEntry {
+    fn partial_cmp(&self, other: &DeadlineEntry) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DeadlineEntry {
+    // `BinaryHeap` pops the greatest entry, which has to be the most urgent one.
+    fn cmp(&self, other: &DeadlineEntry) -> Ordering {
+        other.urgency(self)
+    }
+}
+
+struct DeadlineHeap {
+    heap: BinaryHeap<DeadlineEntry>,
+    next_sequence: u64,
+}
+
+/// Earliest deadline first discipline, see [`Scheduling::EarliestDeadlineFirst`].
+struct EarliestDeadlineFirst {
+    inner: Mutex<DeadlineHeap>,
+    len: AtomicUsize,
+}
+
+impl EarliestDeadlineFirst {
+    fn new() -> EarliestDeadlineFirst {
+        EarliestDeadlineFirst {
+            inner: Mutex::new(DeadlineHeap {
+                heap: BinaryHeap::new(),
+                next_sequence: 0,
+            }),
+            len: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Discipline<Job> for EarliestDeadlineFirst {
+    fn push(&self, job: Job) {
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("EarliestDeadlineFirst::push unable to lock heap");
+        let sequence = inner.next_sequence;
+        inner.next_sequence += 1;
+        inner.heap.push(DeadlineEntry { sequence, job });
+        self.len.store(inner.heap.len(), atomic::Ordering::SeqCst);
+    }
+
+    fn try_pop(&self) -> Option<Job> {
+        if self.len.load(atomic::Ordering::SeqCst) == 0 {
+            return None;
+        }
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("EarliestDeadlineFirst::try_pop unable to lock heap");
+        let entry = inner.heap.pop();
+        self.len.store(inner.heap.len(), atomic::Ordering::SeqCst);
+        entry.map(|entry| entry.job)
+    }
+
+    fn try_pop_batch_for(&self, _worker: usize, max: usize, batch: &mut VecDeque<Job>) -> usize {
+        if self.len.load(atomic::Ordering::SeqCst) == 0 {
+            return 0;
+        }
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("EarliestDeadlineFirst::try_pop_batch_for unable to lock heap");
+        let count = cmp::min(max, inner.heap.len());
+        for _ in 0..count {
+            batch.extend(inner.heap.pop().map(|entry| entry.job));
+        }
+        self.len.store(inner.heap.len(), atomic::Ordering::SeqCst);
+        count
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len.load(atomic::Ordering::SeqCst) == 0
+    }
+}
+
+struct PriorityEntry {
+    /// The job runs before all jobs with a greater key.
+    key: i128,
+    /// Nanoseconds since the discipline was created when the job was pushed, 0 without aging.
+    enqueued: i128,
+    sequence: u64,
+    job: Job,
+}
+
+impl PartialEq for PriorityEntry {
+    fn eq(&self, other: &PriorityEntry) -> bool {
+        self.sequence == other.sequence
+    }
+}
+
+impl Eq for PriorityEntry {}
+
+impl PartialOrd for PriorityEntry {
+    fn partial_cmp(&self, other: &PriorityEntry) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PriorityEntry {
+    // `BinaryHeap` pops the greatest entry, which has to be the one with the smallest key.
+    fn cmp(&self, other: &PriorityEntry) -> Ordering {
+        other
+            .key
+            .cmp(&self.key)
+            .then(other.sequence.cmp(&self.sequence))
+    }
+}
+
+struct PriorityHeap {
+    heap: BinaryHeap<PriorityEntry>,
+    next_sequence: u64,
+}
+
+/// Highest priority first discipline, see [`Scheduling::Priority`].
+///
+/// With aging by one level per `interval`, the effective priority of a job at time `now` is
+/// `priority + (now - enqueued) / interval`. Comparing two jobs, `now` cancels out, so the order
+/// is fixed at push time: a job is treated as if it had been enqueued `priority` intervals
+/// earlier, and jobs are started in the order of those virtual enqueue times.
+struct Priority {
+    inner: Mutex<PriorityHeap>,
+    len: AtomicUsize,
+    aging: Option<Duration>,
+    created_at: Instant,
+}
+
+impl Priority {
+    fn new(aging: Option<Duration>) -> Priority {
+        Priority {
+            inner: Mutex::new(PriorityHeap {
+                heap: BinaryHeap::new(),
+                next_sequence: 0,
+            }),
+            len: AtomicUsize::new(0),
+            aging,
+            created_at: Instant::now(),
+        }
+    }
+
+    fn key(&self, priority: i8, enqueued: i128) -> i128 {
+        match self.aging {
+            Some(interval) => enqueued - i128::from(priority) * interval.as_nanos() as i128,
+            None => -i128::from(priority),
+        }
+    }
+}
+
+impl Discipline<Job> for Priority {
+    fn push(&self, job: Job) {
+        let enqueued = match self.aging {
+            Some(_) => self.created_at.elapsed().as_nanos() as i128,
+            None => 0,
+        };
+        let key = self.key(job.priority, enqueued);
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("Priority::push unable to lock heap");
+        let sequence = inner.next_sequence;
+        inner.next_sequence += 1;
+        inner.heap.push(PriorityEntry {
+            key,
+            enqueued,
+            sequence,
+            job,
+        });
+        self.len.store(inner.heap.len(), atomic::Ordering::SeqCst);
+    }
+
+    fn try_pop(&self) -> Option<Job> {
+        if self.len.load(atomic::Ordering::SeqCst) == 0 {
+            return None;
+        }
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("Priority::try_pop unable to lock heap");
+        let entry = inner.heap.pop();
+        self.len.store(inner.heap.len(), atomic::Ordering::SeqCst);
+        entry.map(|entry| entry.job)
+    }
+
+    fn try_pop_batch_for(&self, _worker: usize, max: usize, batch: &mut VecDeque<Job>) -> usize {
+        if self.len.load(atomic::Ordering::SeqCst) == 0 {
+            return 0;
+        }
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("Priority::try_pop_batch_for unable to lock heap");
+        let count = cmp::min(max, inner.heap.len());
+        for _ in 0..count {
+            batch.extend(inner.heap.pop().map(|entry| entry.job));
+        }
+        self.len.store(inner.heap.len(), atomic::Ordering::SeqCst);
+        count
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len.load(atomic::Ordering::SeqCst) == 0
+    }
+
+    // A changed job keeps the time it was enqueued at, so it keeps what it has aged so far.
+    fn update(&self, f: &mut dyn FnMut(&mut Job) -> bool) -> bool {
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("Priority::update unable to lock heap");
+        let mut entries = mem::take(&mut inner.heap).into_vec();
+        let mut found = false;
+        for entry in &mut entries {
+            if f(&mut entry.job) {
+                entry.key = self.key(entry.job.priority, entry.enqueued);
+                found = true;
+                break;
+            }
+        }
+        inner.heap = BinaryHeap::from(entries);
+        found
+    }
+}
+
+struct FairQueues {
+    queues: HashMap<usize, VecDeque<Job>>,
+    /// Submitters with queued jobs, the one in front is served next.
+    turns: VecDeque<usize>,
+}
+
+impl FairQueues {
+    /// Pop the next job of the submitter whose turn it is.
+    fn pop_turn(&mut self) -> Option<Job> {
+        let submitter = self.turns.pop_front()?;
+        let (job, exhausted) = {
+            let queue = self
+                .queues
+                .get_mut(&submitter)
+                .expect("Fair::try_pop submitter without queue");
+            (queue.pop_front(), queue.is_empty())
+        };
+        if exhausted {
+            self.queues.remove(&submitter);
+        } else {
+            self.turns.push_back(submitter);
+        }
+        job
+    }
+}
+
+/// Round robin between submitting handles, see [`Scheduling::Fair`].
+struct Fair {
+    inner: Mutex<FairQueues>,
+    len: AtomicUsize,
+}
+
+impl Fair {
+    fn new() -> Fair {
+        Fair {
+            inner: Mutex::new(FairQueues {
+                queues: HashMap::new(),
+                turns: VecDeque::new(),
+            }),
+            len: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Discipline<Job> for Fair {
+    fn push(&self, job: Job) {
+        let mut inner = self.inner.lock().expect("Fair::push unable to lock queues");
+        let submitter = job.submitter;
+        let queue = inner.queues.entry(submitter).or_default();
+        queue.push_back(job);
+        if queue.len() == 1 {
+            inner.turns.push_back(submitter);
+        }
+        self.len.fetch_add(1, atomic::Ordering::SeqCst);
+    }
+
+    fn try_pop(&self) -> Option<Job> {
+        if self.len.load(atomic::Ordering::SeqCst) == 0 {
+            return None;
+        }
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("Fair::try_pop unable to lock queues");
+        let job = inner.pop_turn()?;
+        self.len.fetch_sub(1, atomic::Ordering::SeqCst);
+        Some(job)
+    }
+
+    fn try_pop_batch_for(&self, _worker: usize, max: usize, batch: &mut VecDeque<Job>) -> usize {
+        if self.len.load(atomic::Ordering::SeqCst) == 0 {
+            return 0;
+        }
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("Fair::try_pop_batch_for unable to lock queues");
+        let mut count = 0;
+        while count < max {
+            match inner.pop_turn() {
+                Some(job) => batch.push_back(job),
+                None => break,
+            }
+            count += 1;
+        }
+        self.len.fetch_sub(count, atomic::Ordering::SeqCst);
+        count
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len.load(atomic::Ordering::SeqCst) == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Affinity;
+    use queue::{Discipline, Fifo};
+    use thunk::Thunk;
+    use Job;
+
+    fn job(affinity: Option<usize>, submitter: usize) -> Job {
+        Job {
+            affinity,
+            ..Job::new(Thunk::new(|| ()), submitter)
+        }
+    }
+
+    #[test]
+    fn test_affinity() {
+        let affinity = Affinity::new(Box::new(Fifo::new()));
+        affinity.push(job(Some(1), 0));
+        affinity.push(job(None, 1));
+        affinity.push(job(Some(0), 2));
+        affinity.push(job(Some(1), 3));
+        affinity.push(job(Some(1), 4));
+
+        let submitter = |job: Option<Job>| job.map(|job| job.submitter);
+        assert_eq!(submitter(affinity.try_pop_for(0)), Some(2));
+        assert_eq!(submitter(affinity.try_pop_for(0)), Some(1));
+        // Worker 0 has nothing left and steals from worker 1.
+        assert_eq!(submitter(affinity.try_pop_for(0)), Some(0));
+        assert_eq!(submitter(affinity.try_pop_for(1)), Some(3));
+        assert!(!affinity.is_empty());
+        assert_eq!(submitter(affinity.try_pop()), Some(4));
+        assert!(affinity.is_empty());
+        assert_eq!(submitter(affinity.try_pop_for(1)), None);
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! One set of worker threads serving several logical pools.
+
+use scheduling::Scheduling;
+use {available_parallelism, Builder, ThreadPool};
+
+/// A set of worker threads from which several logical pools are created, see
+/// [`pool`](#method.pool).
+///
+/// Libraries which each create their own pool easily end up with many times more threads than
+/// the machine has cores. Handing them pools of one `SharedWorkers` instead keeps the number of
+/// threads fixed, while every pool still has its own queue and at most its share of the
+/// threads.
+///
+/// The pools are handles of a single [`ThreadPool`], scheduled with [`Scheduling::Fair`] so
+/// that the workers take turns between them. Methods which concern the whole pool, like
+/// [`join`], [`shutdown_now`] or [`set_num_threads`], therefore act on the shared workers and
+/// the jobs of all pools.
+///
+/// [`ThreadPool`]: struct.ThreadPool.html
+/// [`Scheduling::Fair`]: enum.Scheduling.html#variant.Fair
+/// [`join`]: struct.ThreadPool.html#method.join
+/// [`shutdown_now`]: struct.ThreadPool.html#method.shutdown_now
+/// [`set_num_threads`]: struct.ThreadPool.html#method.set_num_threads
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::mpsc::channel;
+/// use threadpool::SharedWorkers;
+///
+/// let workers = SharedWorkers::new(4);
+/// let images = workers.pool(3);
+/// let indexing = workers.pool(1);
+///
+/// let (tx, rx) = channel();
+/// for i in 0..8 {
+///     let tx = tx.clone();
+///     images.execute(move || tx.send(i).unwrap());
+/// }
+/// indexing.execute(move || tx.send(8).unwrap());
+///
+/// let mut done: Vec<i32> = rx.iter().take(9).collect();
+/// done.sort();
+/// assert_eq!(done, (0..9).collect::<Vec<_>>());
+/// ```
+#[derive(Clone, Debug)]
+pub struct SharedWorkers {
+    pool: ThreadPool,
+}
+
+impl SharedWorkers {
+    /// Creates `num_threads` worker threads to share between pools.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `num_threads` is 0.
+    pub fn new(num_threads: usize) -> SharedWorkers {
+        SharedWorkers {
+            pool: Builder::new()
+                .num_threads(num_threads)
+                .scheduling(Scheduling::Fair)
+                .build(),
+        }
+    }
+
+    /// Returns a new pool with its own queue, whose jobs never occupy more than `max_share` of
+    /// the worker threads at once. Jobs beyond that wait in the queue of the pool, they are
+    /// counted by [`ThreadPool::queued_count`] in the meantime.
+    ///
+    /// See [`ThreadPool::handle_with_limit`] for the caveats of limiting a pool.
+    ///
+    /// [`ThreadPool::queued_count`]: struct.ThreadPool.html#method.queued_count
+    /// [`ThreadPool::handle_with_limit`]: struct.ThreadPool.html#method.handle_with_limit
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `max_share` is 0.
+    pub fn pool(&self, max_share: usize) -> ThreadPool {
+        self.pool.handle_with_limit(max_share)
+    }
+
+    /// Returns the number of worker threads.
+    pub fn num_threads(&self) -> usize {
+        self.pool.max_count()
+    }
+}
+
+impl Default for SharedWorkers {
+    fn default() -> Self {
+        SharedWorkers::new(available_parallelism())
+    }
+}
@@ -0,0 +1,315 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Resizing pools when the process receives a signal, see [`ThreadPool::resize_on_signal`].
+//!
+//! [`ThreadPool::resize_on_signal`]: ../struct.ThreadPool.html#method.resize_on_signal
+
+use libc;
+use std::io;
+use std::mem;
+use std::os::raw::c_int;
+use std::panic;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use {resize, ThreadPoolSharedData};
+
+type TargetSize = Arc<dyn Fn() -> Option<usize> + Send + Sync>;
+
+struct Registration {
+    signal: c_int,
+    shared_data: Weak<ThreadPoolSharedData>,
+    target: TargetSize,
+}
+
+struct Registry {
+    registrations: Vec<Registration>,
+    /// The signals a handler has been installed for.
+    handled: Vec<c_int>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    registrations: Vec::new(),
+    handled: Vec::new(),
+});
+
+/// The write end of the pipe the signal handler reports to, -1 until the dispatching thread
+/// has been started.
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn handle(signal: c_int) {
+    // Only async-signal-safe calls are allowed here, so the signal is passed on to the
+    // dispatching thread. `write` may set `errno`, which the interrupted code may be about to
+    // read, so it is restored before returning.
+    let byte = signal as u8;
+    unsafe {
+        let errno = errno_location();
+        let saved = errno.as_ref().copied();
+        // The result is ignored on purpose: the write end does not block, and the only failure
+        // which can happen, a full pipe, leaves a resize pending anyway. Nothing else could be
+        // done about an error from within a signal handler.
+        libc::write(
+            PIPE.load(Ordering::Relaxed),
+            &byte as *const u8 as *const libc::c_void,
+            1,
+        );
+        if let Some(saved) = saved {
+            *errno = saved;
+        }
+    }
+}
+
+/// Returns where the `errno` of the calling thread lives, or null on platforms for which it is
+/// not known.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "emscripten",
+    target_os = "dragonfly",
+    target_os = "redox",
+    target_os = "hurd"
+))]
+unsafe fn errno_location() -> *mut c_int {
+    libc::__errno_location()
+}
+
+#[cfg(any(target_vendor = "apple", target_os = "freebsd"))]
+unsafe fn errno_location() -> *mut c_int {
+    libc::__error()
+}
+
+#[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+unsafe fn errno_location() -> *mut c_int {
+    libc::__errno()
+}
+
+#[cfg(any(target_os = "solaris", target_os = "illumos"))]
+unsafe fn errno_location() -> *mut c_int {
+    libc::___errno()
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "emscripten",
+    target_os = "dragonfly",
+    target_os = "redox",
+    target_os = "hurd",
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "android",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "solaris",
+    target_os = "illumos"
+)))]
+unsafe fn errno_location() -> *mut c_int {
+    ptr::null_mut()
+}
+
+/// Resize the pool to the size returned by `target` whenever the process receives `signal`.
+pub(crate) fn register(
+    shared_data: &Arc<ThreadPoolSharedData>,
+    signal: c_int,
+    target: TargetSize,
+) -> io::Result<()> {
+    let mut registry = REGISTRY
+        .lock()
+        .expect("resize_on_signal unable to lock registry");
+    if PIPE.load(Ordering::SeqCst) == -1 {
+        PIPE.store(spawn_dispatcher()?, Ordering::SeqCst);
+    }
+    if !registry.handled.contains(&signal) {
+        install_handler(signal)?;
+        registry.handled.push(signal);
+    }
+    registry.registrations.push(Registration {
+        signal,
+        shared_data: Arc::downgrade(shared_data),
+        target,
+    });
+    Ok(())
+}
+
+/// Install the handler for `signal`, unless another handler than the default action or
+/// ignoring the signal is installed already. That handler is left in place in that case, as it
+/// would never be called again otherwise.
+fn install_handler(signal: c_int) -> io::Result<()> {
+    unsafe {
+        // Look first, so that no signal meant for the other handler ever reaches ours.
+        let mut previous: libc::sigaction = mem::zeroed();
+        if libc::sigaction(signal, ptr::null(), &mut previous) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if previous.sa_sigaction != libc::SIG_DFL && previous.sa_sigaction != libc::SIG_IGN {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("another handler is installed for signal {}", signal),
+            ));
+        }
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = handle as extern "C" fn(c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signal, &action, ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Create the pipe and the thread reading the signals from it, returning the write end.
+fn spawn_dispatcher() -> io::Result<c_int> {
+    let mut fds = [0; 2];
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        for &fd in &fds {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        let flags = libc::fcntl(fds[1], libc::F_GETFL);
+        libc::fcntl(fds[1], libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+    let read_end = fds[0];
+    let spawned = thread::Builder::new()
+        .name("threadpool-signal".into())
+        .spawn(move || loop {
+            let mut byte = 0u8;
+            let read =
+                unsafe { libc::read(read_end, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+            match read {
+                1 => dispatch(c_int::from(byte)),
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+                _ => return,
+            }
+        });
+    if let Err(error) = spawned {
+        unsafe {
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+        }
+        return Err(error);
+    }
+    Ok(fds[1])
+}
+
+/// Resize the pools registered for `signal`, forgetting the ones which have shut down.
+fn dispatch(signal: c_int) {
+    let targets: Vec<_> = {
+        let mut registry = REGISTRY
+            .lock()
+            .expect("resize_on_signal unable to lock registry");
+        registry.registrations.retain(|registration| {
+            registration
+                .shared_data
+                .upgrade()
+                .is_some_and(|shared_data| !shared_data.job_queue.is_closed())
+        });
+        registry
+            .registrations
+            .iter()
+            .filter(|registration| registration.signal == signal)
+            .map(|registration| {
+                (
+                    registration.shared_data.clone(),
+                    registration.target.clone(),
+                )
+            })
+            .collect()
+    };
+    // The callbacks run without the lock, so that they may register further pools.
+    for (shared_data, target) in targets {
+        let target = panic::catch_unwind(panic::AssertUnwindSafe(|| target()));
+        if let (Some(shared_data), Ok(Some(num_threads))) = (shared_data.upgrade(), target) {
+            if num_threads >= 1 {
+                resize(&shared_data, num_threads);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{errno_location, handle};
+    use libc;
+    use std::io;
+    use std::mem;
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use ThreadPool;
+
+    #[test]
+    fn test_resize_on_signal() {
+        // The handler for `SIGUSR2` stays installed for the rest of the process, no other test
+        // may use that signal.
+        let pool = ThreadPool::new(2);
+        let target = Arc::new(AtomicUsize::new(0));
+        {
+            let target = target.clone();
+            pool.resize_on_signal(libc::SIGUSR2, move || match target.load(Ordering::SeqCst) {
+                0 => None,
+                n => Some(n),
+            })
+            .unwrap();
+        }
+
+        // Without a target size the pool keeps its size.
+        unsafe { libc::raise(libc::SIGUSR2) };
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(pool.max_count(), 2);
+
+        target.store(5, Ordering::SeqCst);
+        unsafe { libc::raise(libc::SIGUSR2) };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.max_count() != 5 {
+            assert!(Instant::now() < deadline, "pool was not resized");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_errno_preserved() {
+        unsafe {
+            *errno_location() = libc::EINTR;
+            handle(libc::SIGUSR2);
+            assert_eq!(*errno_location(), libc::EINTR);
+        }
+    }
+
+    #[test]
+    fn test_foreign_handler_kept() {
+        extern "C" fn foreign(_: libc::c_int) {}
+
+        let mut previous: libc::sigaction = unsafe { mem::zeroed() };
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = foreign as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            assert_eq!(libc::sigaction(libc::SIGURG, &action, &mut previous), 0);
+        }
+        let pool = ThreadPool::new(1);
+        let error = pool.resize_on_signal(libc::SIGURG, || None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        unsafe {
+            let mut current: libc::sigaction = mem::zeroed();
+            libc::sigaction(libc::SIGURG, ptr::null(), &mut current);
+            assert_eq!(
+                current.sa_sigaction,
+                foreign as extern "C" fn(libc::c_int) as libc::sighandler_t
+            );
+            // Leave the process as it was for the other tests.
+            assert_eq!(libc::sigaction(libc::SIGURG, &previous, ptr::null_mut()), 0);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Statistics of the pool and its individual worker threads.
+
+use nanos_to_duration;
+use std::cmp;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Number of queue waits kept for computing quantiles.
+const WAITS_RETAINED: usize = 1024;
+
+/// A snapshot of the statistics of one worker thread, see [`ThreadPool::worker_stats`].
+///
+/// [`ThreadPool::worker_stats`]: struct.ThreadPool.html#method.worker_stats
+#[derive(Clone, Debug)]
+pub struct WorkerStats {
+    pub(crate) index: usize,
+    pub(crate) jobs_executed: u64,
+    pub(crate) panics: u64,
+    pub(crate) busy_time: Duration,
+    pub(crate) current_job_time: Option<Duration>,
+    pub(crate) last_tag: Option<Arc<str>>,
+}
+
+impl WorkerStats {
+    /// The number of the worker, unique among the live workers of the pool. A worker which
+    /// exits frees its number for a later worker.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The number of jobs the worker has finished, including the ones which panicked.
+    pub fn jobs_executed(&self) -> u64 {
+        self.jobs_executed
+    }
+
+    /// The number of jobs which panicked on the worker.
+    pub fn panics(&self) -> u64 {
+        self.panics
+    }
+
+    /// The time the worker has spent executing jobs, including the job it is running right now.
+    pub fn busy_time(&self) -> Duration {
+        self.busy_time
+    }
+
+    /// How long the worker has been running its current job, or `None` if it is idle.
+    pub fn current_job_time(&self) -> Option<Duration> {
+        self.current_job_time
+    }
+
+    /// The tag of the job the worker started last, if it was submitted with
+    /// [`ThreadPool::execute_tagged`].
+    ///
+    /// [`ThreadPool::execute_tagged`]: struct.ThreadPool.html#method.execute_tagged
+    pub fn last_tag(&self) -> Option<&str> {
+        self.last_tag.as_deref()
+    }
+}
+
+/// A snapshot of how long jobs waited in the queue before a worker thread started them, see
+/// [`ThreadPool::queue_wait_stats`].
+///
+/// Long waits tell that the pool has too few threads for its load, even if every job runs
+/// quickly once started.
+///
+/// [`ThreadPool::queue_wait_stats`]: struct.ThreadPool.html#method.queue_wait_stats
+#[derive(Clone, Debug)]
+pub struct QueueWaitStats {
+    count: u64,
+    total: Duration,
+    // The most recent waits, sorted.
+    recent: Vec<Duration>,
+}
+
+impl QueueWaitStats {
+    /// The number of jobs which have been started.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The sum of the waits of all started jobs.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// The average wait of all started jobs, or `None` if no job has been started yet.
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(nanos_to_duration(
+            (self.total.as_nanos() / u128::from(self.count)) as u64,
+        ))
+    }
+
+    /// The wait which the fraction `q` of the 1024 most recently started jobs did not exceed,
+    /// e.g. `quantile(0.99)` for the 99th percentile. Returns `None` if no job has been started
+    /// yet.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `q` is not between 0 and 1.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        assert!((0.0..=1.0).contains(&q), "quantile must be between 0 and 1");
+        if self.recent.is_empty() {
+            return None;
+        }
+        let rank = (q * self.recent.len() as f64).ceil() as usize;
+        Some(self.recent[rank.saturating_sub(1)])
+    }
+}
+
+/// The statistics of the pool since [`ThreadPool::reset_stats`] was last called, or since the
+/// pool was created, see [`ThreadPool::interval_stats`].
+///
+/// [`ThreadPool::reset_stats`]: struct.ThreadPool.html#method.reset_stats
+/// [`ThreadPool::interval_stats`]: struct.ThreadPool.html#method.interval_stats
+#[derive(Clone, Debug)]
+pub struct IntervalStats {
+    pub(crate) elapsed: Duration,
+    pub(crate) completed_count: usize,
+    pub(crate) panic_count: usize,
+    pub(crate) respawn_count: usize,
+    pub(crate) busy_time: Duration,
+}
+
+impl IntervalStats {
+    /// The length of the interval.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// The number of jobs which have finished in the interval, including the ones which
+    /// panicked.
+    pub fn completed_count(&self) -> usize {
+        self.completed_count
+    }
+
+    /// The number of jobs which panicked in the interval.
+    pub fn panic_count(&self) -> usize {
+        self.panic_count
+    }
+
+    /// The number of worker threads which died and were replaced in the interval.
+    pub fn respawn_count(&self) -> usize {
+        self.respawn_count
+    }
+
+    /// The time the worker threads spent executing jobs in the interval.
+    pub fn busy_time(&self) -> Duration {
+        self.busy_time
+    }
+}
+
+/// A snapshot of the jobs of one priority which have not been started yet, see
+/// [`ThreadPool::priority_queue_stats`].
+///
+/// [`ThreadPool::priority_queue_stats`]: struct.ThreadPool.html#method.priority_queue_stats
+#[derive(Clone, Debug)]
+pub struct PriorityQueueStats {
+    priority: i8,
+    queued: usize,
+    oldest_wait: Duration,
+}
+
+impl PriorityQueueStats {
+    pub(crate) fn new(priority: i8, queued: usize, oldest_wait: Duration) -> PriorityQueueStats {
+        PriorityQueueStats {
+            priority,
+            queued,
+            oldest_wait,
+        }
+    }
+
+    /// The priority of the jobs, see [`ThreadPool::execute_with_priority`].
+    ///
+    /// [`ThreadPool::execute_with_priority`]: struct.ThreadPool.html#method.execute_with_priority
+    pub fn priority(&self) -> i8 {
+        self.priority
+    }
+
+    /// The number of jobs of the priority which have not been started yet.
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+
+    /// How long the oldest of these jobs has been waiting.
+    pub fn oldest_wait(&self) -> Duration {
+        self.oldest_wait
+    }
+}
+
+/// Records the queue waits of the jobs of a pool.
+pub(crate) struct QueueWaits {
+    count: AtomicU64,
+    total_nanos: AtomicU64,
+    recent: Mutex<VecDeque<Duration>>,
+}
+
+impl QueueWaits {
+    pub fn new() -> QueueWaits {
+        QueueWaits {
+            count: AtomicU64::new(0),
+            total_nanos: AtomicU64::new(0),
+            recent: Mutex::new(VecDeque::with_capacity(WAITS_RETAINED)),
+        }
+    }
+
+    pub fn record(&self, wait: Duration) {
+        let nanos = cmp::min(wait.as_nanos(), u128::from(u64::MAX)) as u64;
+        self.total_nanos.fetch_add(nanos, Ordering::SeqCst);
+        self.count.fetch_add(1, Ordering::SeqCst);
+        let mut recent = self
+            .recent
+            .lock()
+            .expect("QueueWaits::record unable to lock recent waits");
+        if recent.len() == WAITS_RETAINED {
+            recent.pop_front();
+        }
+        recent.push_back(wait);
+    }
+
+    pub fn reset(&self) {
+        let mut recent = self
+            .recent
+            .lock()
+            .expect("QueueWaits::reset unable to lock recent waits");
+        recent.clear();
+        self.count.store(0, Ordering::SeqCst);
+        self.total_nanos.store(0, Ordering::SeqCst);
+    }
+
+    pub fn snapshot(&self) -> QueueWaitStats {
+        let mut recent: Vec<_> = self
+            .recent
+            .lock()
+            .expect("QueueWaits::snapshot unable to lock recent waits")
+            .iter()
+            .cloned()
+            .collect();
+        recent.sort();
+        QueueWaitStats {
+            count: self.count.load(Ordering::SeqCst),
+            total: nanos_to_duration(self.total_nanos.load(Ordering::SeqCst)),
+            recent,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{QueueWaits, WAITS_RETAINED};
+    use std::time::Duration;
+
+    #[test]
+    fn test_queue_waits() {
+        let waits = QueueWaits::new();
+        let stats = waits.snapshot();
+        assert_eq!(stats.count(), 0);
+        assert_eq!(stats.mean(), None);
+        assert_eq!(stats.quantile(0.5), None);
+
+        for millis in (1..=100).rev() {
+            waits.record(Duration::from_millis(millis));
+        }
+        let stats = waits.snapshot();
+        assert_eq!(stats.count(), 100);
+        assert_eq!(stats.mean(), Some(Duration::from_micros(50_500)));
+        assert_eq!(stats.quantile(0.0), Some(Duration::from_millis(1)));
+        assert_eq!(stats.quantile(0.5), Some(Duration::from_millis(50)));
+        assert_eq!(stats.quantile(0.99), Some(Duration::from_millis(99)));
+        assert_eq!(stats.quantile(1.0), Some(Duration::from_millis(100)));
+
+        for _ in 0..WAITS_RETAINED {
+            waits.record(Duration::from_secs(1));
+        }
+        let stats = waits.snapshot();
+        assert_eq!(stats.count(), 100 + WAITS_RETAINED as u64);
+        assert_eq!(stats.quantile(0.0), Some(Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Status of the jobs submitted with [`ThreadPool::execute_tracked`].
+//!
+//! [`ThreadPool::execute_tracked`]: ../struct.ThreadPool.html#method.execute_tracked
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Number of finished jobs whose status is remembered.
+const FINISHED_RETAINED: usize = 1024;
+
+/// Identifies a job submitted with [`ThreadPool::execute_tracked`].
+///
+/// [`ThreadPool::execute_tracked`]: struct.ThreadPool.html#method.execute_tracked
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JobId(u64);
+
+/// The status of a job, see [`ThreadPool::job_status`].
+///
+/// [`ThreadPool::job_status`]: struct.ThreadPool.html#method.job_status
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobStatus {
+    /// The job is waiting in the queue.
+    Queued,
+    /// A worker thread is executing the job.
+    Running,
+    /// The job has returned.
+    Completed,
+    /// The job has panicked.
+    Panicked,
+    /// The job was dropped without running, e.g. by [`ThreadPool::shutdown_now`].
+    ///
+    /// [`ThreadPool::shutdown_now`]: struct.ThreadPool.html#method.shutdown_now
+    Cancelled,
+    /// The job is not known to the pool: it belongs to another pool, or it finished so long ago
+    /// that its status has been forgotten.
+    Unknown,
+}
+
+impl JobStatus {
+    fn is_finished(self) -> bool {
+        match self {
+            JobStatus::Completed | JobStatus::Panicked | JobStatus::Cancelled => true,
+            JobStatus::Queued | JobStatus::Running | JobStatus::Unknown => false,
+        }
+    }
+}
+
+struct Table {
+    next_id: u64,
+    statuses: HashMap<JobId, JobStatus>,
+    // Finished jobs, oldest first, so that their statuses can be forgotten in order.
+    finished: VecDeque<JobId>,
+}
+
+/// The statuses of the tracked jobs of a pool.
+pub(crate) struct StatusTable {
+    table: Mutex<Table>,
+}
+
+impl StatusTable {
+    pub fn new() -> StatusTable {
+        StatusTable {
+            table: Mutex::new(Table {
+                next_id: 0,
+                statuses: HashMap::new(),
+                finished: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Returns a tracker for a newly queued job.
+    pub fn track(table: &Arc<StatusTable>) -> Tracker {
+        let mut inner = table
+            .table
+            .lock()
+            .expect("StatusTable::track unable to lock statuses");
+        let id = JobId(inner.next_id);
+        inner.next_id += 1;
+        inner.statuses.insert(id, JobStatus::Queued);
+        Tracker {
+            table: table.clone(),
+            id,
+            started: false,
+            finished: false,
+        }
+    }
+
+    pub fn get(&self, id: JobId) -> JobStatus {
+        let inner = self
+            .table
+            .lock()
+            .expect("StatusTable::get unable to lock statuses");
+        inner
+            .statuses
+            .get(&id)
+            .cloned()
+            .unwrap_or(JobStatus::Unknown)
+    }
+
+    fn set(&self, id: JobId, status: JobStatus) {
+        let mut inner = self
+            .table
+            .lock()
+            .expect("StatusTable::set unable to lock statuses");
+        inner.statuses.insert(id, status);
+        if status.is_finished() {
+            inner.finished.push_back(id);
+            if inner.finished.len() > FINISHED_RETAINED {
+                let forgotten = inner.finished.pop_front().unwrap();
+                inner.statuses.remove(&forgotten);
+            }
+        }
+    }
+}
+
+/// Moves along with a tracked job and updates its status.
+///
+/// Dropping it reports the job as panicked if it has started, or cancelled otherwise.
+pub(crate) struct Tracker {
+    table: Arc<StatusTable>,
+    id: JobId,
+    started: bool,
+    finished: bool,
+}
+
+impl Tracker {
+    pub fn id(&self) -> JobId {
+        self.id
+    }
+
+    pub fn start(&mut self) {
+        self.started = true;
+        self.table.set(self.id, JobStatus::Running);
+    }
+
+    pub fn complete(mut self) {
+        self.finished = true;
+        self.table.set(self.id, JobStatus::Completed);
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        if !self.finished {
+            let status = if self.started {
+                JobStatus::Panicked
+            } else {
+                JobStatus::Cancelled
+            };
+            self.table.set(self.id, status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{JobStatus, StatusTable, FINISHED_RETAINED};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_lifecycle() {
+        let table = Arc::new(StatusTable::new());
+        let mut tracker = StatusTable::track(&table);
+        let id = tracker.id();
+        assert_eq!(table.get(id), JobStatus::Queued);
+        tracker.start();
+        assert_eq!(table.get(id), JobStatus::Running);
+        tracker.complete();
+        assert_eq!(table.get(id), JobStatus::Completed);
+
+        let cancelled = StatusTable::track(&table);
+        let id = cancelled.id();
+        drop(cancelled);
+        assert_eq!(table.get(id), JobStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_finished_are_forgotten() {
+        let table = Arc::new(StatusTable::new());
+        let first = StatusTable::track(&table).id();
+        let running = {
+            let mut tracker = StatusTable::track(&table);
+            tracker.start();
+            tracker
+        };
+        for _ in 0..FINISHED_RETAINED {
+            drop(StatusTable::track(&table));
+        }
+
+        assert_eq!(table.get(first), JobStatus::Unknown);
+        assert_eq!(table.get(running.id()), JobStatus::Running);
+    }
+}
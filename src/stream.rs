@@ -0,0 +1,155 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Results of a sequence of jobs in completion order, see [`ThreadPool::execute_stream`].
+//!
+//! [`ThreadPool::execute_stream`]: ../struct.ThreadPool.html#method.execute_stream
+
+use handle::JoinError;
+use std::panic;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use ThreadPool;
+
+/// An iterator over the results of jobs submitted with [`ThreadPool::execute_stream`], in the
+/// order the jobs finish.
+///
+/// Jobs are taken from the underlying iterator as results are consumed, so that only a bounded
+/// number of them is queued or running at any time.
+///
+/// [`ThreadPool::execute_stream`]: struct.ThreadPool.html#method.execute_stream
+pub struct JobStream<I, T> {
+    pool: ThreadPool,
+    jobs: I,
+    // Number of submitted jobs whose result has not been received yet.
+    in_flight: usize,
+    window: usize,
+    sender: Sender<Result<T, JoinError>>,
+    receiver: Receiver<Result<T, JoinError>>,
+}
+
+impl<I, F, T> JobStream<I, T>
+where
+    I: Iterator<Item = F>,
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    pub(crate) fn new(pool: ThreadPool, jobs: I, window: usize) -> JobStream<I, T> {
+        let (sender, receiver) = channel();
+        JobStream {
+            pool,
+            jobs,
+            in_flight: 0,
+            window,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Submit jobs until the window is full or the jobs are exhausted.
+    fn fill(&mut self) {
+        while self.in_flight < self.window {
+            let job = match self.jobs.next() {
+                Some(job) => job,
+                None => return,
+            };
+            let sender = self.sender.clone();
+            self.pool.execute_with_callback(job, move |result| {
+                // The stream may have been dropped already.
+                let _ = sender.send(result);
+            });
+            self.in_flight += 1;
+        }
+    }
+}
+
+impl<I, F, T> Iterator for JobStream<I, T>
+where
+    I: Iterator<Item = F>,
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    type Item = T;
+
+    /// Block until the next job finishes and return its result.
+    ///
+    /// If the job panicked, the panic is resumed on the calling thread. Jobs dropped by
+    /// [`ThreadPool::shutdown_now`] are skipped.
+    ///
+    /// [`ThreadPool::shutdown_now`]: struct.ThreadPool.html#method.shutdown_now
+    fn next(&mut self) -> Option<T> {
+        self.fill();
+        while self.in_flight > 0 {
+            // Every submitted job reports exactly once, and `self.sender` keeps the channel open.
+            let result = self
+                .receiver
+                .recv()
+                .expect("JobStream::next unable to receive job result");
+            self.in_flight -= 1;
+            self.fill();
+            match result {
+                Ok(value) => return Some(value),
+                Err(error) => {
+                    if let Ok(payload) = error.try_into_panic() {
+                        panic::resume_unwind(payload);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::panic;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use ThreadPool;
+
+    #[test]
+    fn test_all_results() {
+        let pool = ThreadPool::new(4);
+        let mut squares: Vec<u64> = pool
+            .execute_stream((0..1000u64).map(|n| move || n * n))
+            .collect();
+        squares.sort();
+        assert_eq!(squares, (0..1000u64).map(|n| n * n).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_bounded_window() {
+        let pool = ThreadPool::new(2);
+        let submitted = Arc::new(AtomicUsize::new(0));
+        let jobs = {
+            let submitted = submitted.clone();
+            (0..100).map(move |n| {
+                submitted.fetch_add(1, Ordering::SeqCst);
+                move || n
+            })
+        };
+
+        let mut stream = pool.execute_stream(jobs);
+        assert!(stream.next().is_some());
+        assert!(submitted.load(Ordering::SeqCst) <= 2 * 2 + 1);
+        assert_eq!(stream.count(), 99);
+    }
+
+    #[test]
+    fn test_panic_is_resumed() {
+        let pool = ThreadPool::new(2);
+        let mut stream =
+            pool.execute_stream(vec![|| -> u32 { panic!("Ignore this panic, it must!") }]);
+        let payload = panic::catch_unwind(panic::AssertUnwindSafe(|| stream.next())).unwrap_err();
+        assert_eq!(
+            payload.downcast_ref::<&str>(),
+            Some(&"Ignore this panic, it must!")
+        );
+    }
+}
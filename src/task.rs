@@ -0,0 +1,225 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Futures polled by the worker threads, see [`ThreadPool::spawn_future`].
+//!
+//! [`ThreadPool::spawn_future`]: ../struct.ThreadPool.html#method.spawn_future
+
+use handle::{self, Completer, JobHandle, JoinError};
+use panics;
+use std::future::Future;
+use std::panic;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Wake, Waker};
+use thunk::Thunk;
+use {submit_job, Job, ThreadPoolSharedData};
+
+type Slot<F> = Option<(Pin<Box<F>>, Completer<<F as Future>::Output>)>;
+
+/// A spawned future together with the sender for its result.
+///
+/// Every wake-up submits one job which polls the future once.
+pub(crate) struct Task<F: Future> {
+    // The pool must not be kept alive by wakers stored in the future itself.
+    shared_data: Weak<ThreadPoolSharedData>,
+    submitter: usize,
+    // `None` once the future has completed or was cancelled.
+    slot: Mutex<Slot<F>>,
+    scheduled: AtomicBool,
+}
+
+impl<F> Task<F>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    pub fn spawn(
+        shared_data: &Arc<ThreadPoolSharedData>,
+        submitter: usize,
+        future: F,
+    ) -> JobHandle<F::Output> {
+        let (completer, handle) = handle::pair();
+        let task = Arc::new(Task {
+            shared_data: Arc::downgrade(shared_data),
+            submitter,
+            slot: Mutex::new(Some((Box::pin(future), completer))),
+            scheduled: AtomicBool::new(false),
+        });
+        task.schedule();
+        handle
+    }
+
+    /// Submit a job polling the future, unless one is queued already.
+    fn schedule(self: Arc<Self>) {
+        if self.scheduled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let shared_data = match self.shared_data.upgrade() {
+            Some(shared_data) => shared_data,
+            None => return self.cancel(),
+        };
+        let task = self.clone();
+        let job = Job::new(Thunk::new(move || task.run()), self.submitter);
+        if submit_job(&shared_data, job).is_err() {
+            // The pool is shutting down, the future will never make progress.
+            self.cancel();
+        }
+    }
+
+    fn run(self: Arc<Self>) {
+        // Wake-ups from here on need another poll.
+        self.scheduled.store(false, Ordering::SeqCst);
+        let mut slot = self.slot.lock().expect("Task::run unable to lock future");
+        let poll = match *slot {
+            Some((ref mut future, _)) => {
+                let waker = Waker::from(self.clone());
+                let mut context = Context::from_waker(&waker);
+                panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                    future.as_mut().poll(&mut context)
+                }))
+            }
+            None => return,
+        };
+        let (result, message) = match poll {
+            Ok(Poll::Pending) => return,
+            Ok(Poll::Ready(value)) => (Ok(value), None),
+            Err(payload) => {
+                let message = panics::payload_message(&*payload)
+                    .unwrap_or("future panicked, see its handle")
+                    .to_owned();
+                (Err(JoinError::panic(payload)), Some(message))
+            }
+        };
+        let (future, completer) = slot.take().expect("Task::run future vanished");
+        drop(slot);
+        drop(future);
+        completer.complete(result);
+        if let Some(message) = message {
+            // The payload now belongs to the handle, unwind with its message so the panic is
+            // accounted for like any other job panic.
+            panic::resume_unwind(Box::new(message));
+        }
+    }
+
+    /// Drop the future, resolving its handle as cancelled.
+    fn cancel(&self) {
+        let slot = self
+            .slot
+            .lock()
+            .expect("Task::cancel unable to lock future")
+            .take();
+        drop(slot);
+    }
+}
+
+impl<F> Wake for Task<F>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn wake(self: Arc<Self>) {
+        self.schedule();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.clone().schedule();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Waker};
+    use std::thread;
+    use std::time::Duration;
+    use ThreadPool;
+
+    /// A future for a value which is set from another thread.
+    #[derive(Clone, Default)]
+    struct Shared {
+        state: Arc<Mutex<(Option<u32>, Option<Waker>)>>,
+    }
+
+    impl Shared {
+        fn set(&self, value: u32) {
+            let mut state = self.state.lock().unwrap();
+            state.0 = Some(value);
+            if let Some(waker) = state.1.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    impl Future for Shared {
+        type Output = u32;
+
+        fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<u32> {
+            let mut state = self.state.lock().unwrap();
+            match state.0 {
+                Some(value) => Poll::Ready(value),
+                None => {
+                    state.1 = Some(context.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_woken_from_other_thread() {
+        let pool = ThreadPool::new(1);
+        let slot = Shared::default();
+        let handle = pool.spawn_future(slot.clone());
+
+        // The pending future does not block the only worker.
+        assert_eq!(pool.spawn(|| 1).join().unwrap(), 1);
+        assert!(!handle.is_finished());
+
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            slot.set(42);
+        });
+        assert_eq!(handle.join().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_panic() {
+        struct Panicking;
+
+        impl Future for Panicking {
+            type Output = ();
+
+            fn poll(self: Pin<&mut Self>, _: &mut Context) -> Poll<()> {
+                panic!("Ignore this panic, it must!");
+            }
+        }
+
+        let pool = ThreadPool::new(1);
+        assert!(pool.spawn_future(Panicking).join().unwrap_err().is_panic());
+        pool.join();
+        assert_eq!(pool.panic_count(), 1);
+    }
+
+    #[test]
+    fn test_cancelled_on_shutdown() {
+        let pool = ThreadPool::new(1);
+        let slot = Shared::default();
+        let handle = pool.spawn_future(slot.clone());
+        pool.join();
+        drop(pool);
+
+        slot.set(42);
+        assert!(handle.join().unwrap_err().is_cancelled());
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Type erased `FnOnce()` closures which do not allocate when they are small.
+
+use queue::Ring;
+use std::mem::{self, ManuallyDrop, MaybeUninit};
+use std::ptr;
+use std::sync::OnceLock;
+
+/// Number of machine words a closure may occupy to be stored inline.
+const INLINE_WORDS: usize = 4;
+
+type Storage = [MaybeUninit<usize>; INLINE_WORDS];
+
+/// Size in bytes of the recycled blocks for closures which do not fit inline.
+const BLOCK_BYTES: usize = 128;
+
+/// Maximum number of free blocks kept for reuse. Must be a power of two.
+const RECYCLED_BLOCKS: usize = 256;
+
+#[repr(C, align(16))]
+struct Block([MaybeUninit<u8>; BLOCK_BYTES]);
+
+/// Blocks which are free for the next medium sized closure, shared by all pools.
+fn free_blocks() -> &'static Ring<Box<Block>> {
+    static FREE_BLOCKS: OnceLock<Ring<Box<Block>>> = OnceLock::new();
+    FREE_BLOCKS.get_or_init(|| Ring::with_capacity(RECYCLED_BLOCKS))
+}
+
+/// Return `block` to the free list, or deallocate it if the list is full.
+fn recycle(block: Box<Block>) {
+    let _ = free_blocks().push(block);
+}
+
+/// A type erased `FnOnce() + Send` closure.
+///
+/// Closures which fit into [`INLINE_WORDS`] words (and need no stricter alignment than a word)
+/// are stored right inside the `Thunk`. Closures of up to [`BLOCK_BYTES`] bytes are stored in a
+/// block which is recycled once the closure has been called or dropped, so that submitting lots of
+/// them does not hit the allocator for every job. Larger ones are boxed. Either way only the
+/// pointer is stored inline.
+pub struct Thunk {
+    storage: Storage,
+    call: unsafe fn(*mut u8),
+    drop: unsafe fn(*mut u8),
+}
+
+// A `Thunk` can only be created from `Send` closures.
+unsafe impl Send for Thunk {}
+
+impl Thunk {
+    pub fn new<F>(job: F) -> Thunk
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if Thunk::fits_inline::<F>() {
+            unsafe { Thunk::new_inline(job) }
+        } else if Thunk::fits_block::<F>() {
+            unsafe { Thunk::new_block(job) }
+        } else {
+            unsafe { Thunk::new_inline(Box::new(job)) }
+        }
+    }
+
+    /// Returns `true` if a closure of type `F` is stored in a recycled block.
+    fn fits_block<F>() -> bool {
+        mem::size_of::<F>() <= mem::size_of::<Block>()
+            && mem::align_of::<F>() <= mem::align_of::<Block>()
+    }
+
+    /// Caller must make sure `F` fits into a `Block`.
+    unsafe fn new_block<F>(job: F) -> Thunk
+    where
+        F: FnOnce(),
+    {
+        debug_assert!(Thunk::fits_block::<F>());
+        let mut block = free_blocks()
+            .pop()
+            .unwrap_or_else(|| Box::new(Block([MaybeUninit::uninit(); BLOCK_BYTES])));
+        ptr::write(block.0.as_mut_ptr() as *mut F, job);
+
+        let mut storage: Storage = [MaybeUninit::uninit(); INLINE_WORDS];
+        ptr::write(storage.as_mut_ptr() as *mut Box<Block>, block);
+        Thunk {
+            storage,
+            call: call_block::<F>,
+            drop: drop_block::<F>,
+        }
+    }
+
+    /// Returns `true` if a closure of type `F` is stored without allocating.
+    fn fits_inline<F>() -> bool {
+        mem::size_of::<F>() <= mem::size_of::<Storage>()
+            && mem::align_of::<F>() <= mem::align_of::<Storage>()
+    }
+
+    /// Caller must make sure `F` fits into `Storage`.
+    unsafe fn new_inline<F>(job: F) -> Thunk
+    where
+        F: FnOnce(),
+    {
+        debug_assert!(Thunk::fits_inline::<F>());
+        let mut storage: Storage = [MaybeUninit::uninit(); INLINE_WORDS];
+        ptr::write(storage.as_mut_ptr() as *mut F, job);
+        Thunk {
+            storage,
+            call: call_inline::<F>,
+            drop: drop_inline::<F>,
+        }
+    }
+
+    /// Run the closure, consuming the `Thunk`.
+    pub fn call(self) {
+        // The closure is moved out by `call`, so it must not be dropped again, even on panic.
+        let mut this = ManuallyDrop::new(self);
+        unsafe { (this.call)(this.storage.as_mut_ptr() as *mut u8) }
+    }
+}
+
+impl Drop for Thunk {
+    fn drop(&mut self) {
+        unsafe { (self.drop)(self.storage.as_mut_ptr() as *mut u8) }
+    }
+}
+
+unsafe fn call_inline<F: FnOnce()>(storage: *mut u8) {
+    ptr::read(storage as *mut F)()
+}
+
+unsafe fn drop_inline<F>(storage: *mut u8) {
+    ptr::drop_in_place(storage as *mut F)
+}
+
+unsafe fn call_block<F: FnOnce()>(storage: *mut u8) {
+    let mut block = ptr::read(storage as *mut Box<Block>);
+    let job = ptr::read(block.0.as_mut_ptr() as *mut F);
+    // Recycle the block before calling, the closure may panic.
+    recycle(block);
+    job()
+}
+
+unsafe fn drop_block<F>(storage: *mut u8) {
+    let mut block = ptr::read(storage as *mut Box<Block>);
+    ptr::drop_in_place(block.0.as_mut_ptr() as *mut F);
+    recycle(block);
+}
+
+#[cfg(test)]
+mod test {
+    use super::Thunk;
+    use std::panic;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn fits_inline<F>(_: &F) -> bool {
+        Thunk::fits_inline::<F>()
+    }
+
+    fn fits_block<F>(_: &F) -> bool {
+        Thunk::fits_block::<F>()
+    }
+
+    #[test]
+    fn test_small_closure_is_inline() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let job = {
+            let counter = counter.clone();
+            move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        };
+        assert!(fits_inline(&job));
+
+        Thunk::new(job).call();
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_boxed_closure_is_inline() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let job: Box<dyn FnOnce() + Send> = {
+            let counter = counter.clone();
+            Box::new(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+        assert!(fits_inline(&job));
+
+        Thunk::new(job).call();
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_large_closure_is_boxed() {
+        let data = [7u64; 32];
+        let (tx, rx) = ::std::sync::mpsc::channel();
+        let job = move || tx.send(data.iter().sum::<u64>()).unwrap();
+        assert!(!fits_inline(&job));
+        assert!(!fits_block(&job));
+
+        Thunk::new(job).call();
+        assert_eq!(rx.recv().unwrap(), 7 * 32);
+    }
+
+    #[test]
+    fn test_medium_closure_uses_block() {
+        let captured = Arc::new(AtomicUsize::new(0));
+        let medium = |captured: &Arc<AtomicUsize>| {
+            let (captured, data) = (captured.clone(), [3usize; 8]);
+            move || {
+                captured.fetch_add(data.iter().sum::<usize>(), Ordering::SeqCst);
+            }
+        };
+        assert!(!fits_inline(&medium(&captured)));
+        assert!(fits_block(&medium(&captured)));
+
+        for _ in 0..2 * super::RECYCLED_BLOCKS {
+            Thunk::new(medium(&captured)).call();
+            drop(Thunk::new(medium(&captured)));
+        }
+        assert_eq!(
+            captured.load(Ordering::SeqCst),
+            2 * super::RECYCLED_BLOCKS * 24
+        );
+        assert_eq!(Arc::strong_count(&captured), 1);
+
+        let thunk = {
+            let captured = (captured.clone(), [0u8; 64]);
+            Thunk::new(move || {
+                let _captured = captured;
+                panic!("Ignore this panic, it must!");
+            })
+        };
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| thunk.call())).is_err());
+        assert_eq!(Arc::strong_count(&captured), 1);
+    }
+
+    #[test]
+    fn test_drop_without_call() {
+        let small = Arc::new(());
+        let large = Arc::new(());
+        {
+            let small = small.clone();
+            let _thunk = Thunk::new(move || drop(small));
+        }
+        {
+            let large = (large.clone(), [0u8; 128]);
+            let _thunk = Thunk::new(move || drop(large));
+        }
+        assert_eq!(Arc::strong_count(&small), 1);
+        assert_eq!(Arc::strong_count(&large), 1);
+    }
+
+    #[test]
+    fn test_panic_drops_captures_once() {
+        let captured = Arc::new(());
+        let thunk = {
+            let captured = captured.clone();
+            Thunk::new(move || {
+                let _captured = captured;
+                panic!("Ignore this panic, it must!");
+            })
+        };
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| thunk.call())).is_err());
+        assert_eq!(Arc::strong_count(&captured), 1);
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! How idle worker threads wait for the next job.
+
+use std::hint;
+use std::thread;
+
+/// How an idle worker thread waits for the next job, see [`Builder::wait_strategy`].
+///
+/// A worker thread which finds the queue empty first busy-spins for a number of attempts, then
+/// yields its time slice for a number of attempts, and finally parks until a job is submitted.
+/// Spinning and yielding avoid the latency of waking up a parked thread, at the cost of burning
+/// CPU time while the pool is idle.
+///
+/// The default parks right away.
+///
+/// [`Builder::wait_strategy`]: struct.Builder.html#method.wait_strategy
+///
+/// # Examples
+///
+/// ```
+/// use threadpool::WaitStrategy;
+///
+/// let strategy = WaitStrategy::park().spin(1000).yield_now(10);
+/// assert_eq!(strategy.spins(), 1000);
+/// assert_eq!(strategy.yields(), 10);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WaitStrategy {
+    spins: u32,
+    yields: u32,
+}
+
+impl WaitStrategy {
+    /// Park as soon as the queue is found empty.
+    pub fn park() -> WaitStrategy {
+        WaitStrategy::default()
+    }
+
+    /// Busy-spin for `attempts` attempts before yielding.
+    pub fn spin(mut self, attempts: u32) -> WaitStrategy {
+        self.spins = attempts;
+        self
+    }
+
+    /// Yield the time slice for `attempts` attempts after spinning and before parking.
+    pub fn yield_now(mut self, attempts: u32) -> WaitStrategy {
+        self.yields = attempts;
+        self
+    }
+
+    /// Returns the number of busy-spinning attempts.
+    pub fn spins(&self) -> u32 {
+        self.spins
+    }
+
+    /// Returns the number of yielding attempts.
+    pub fn yields(&self) -> u32 {
+        self.yields
+    }
+
+    /// Back off before the next attempt numbered `attempt`, returning `false` once the caller
+    /// should park instead.
+    pub(crate) fn back_off(&self, attempt: u32) -> bool {
+        if attempt < self.spins {
+            hint::spin_loop();
+            true
+        } else if attempt - self.spins < self.yields {
+            thread::yield_now();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::WaitStrategy;
+
+    #[test]
+    fn test_back_off() {
+        let strategy = WaitStrategy::park().spin(2).yield_now(1);
+        assert!((0..3).all(|attempt| strategy.back_off(attempt)));
+        assert!(!strategy.back_off(3));
+        assert!(!WaitStrategy::park().back_off(0));
+    }
+}